@@ -0,0 +1,319 @@
+//! Best-first chart parsing.
+//!
+//! Instead of filling the chart left-to-right like `earley::parse_chart`,
+//! states are kept on an agenda ordered by score, and the best one is moved
+//! into the chart at each step. A state's score is the product of the weights
+//! (`@weight(x)`) of the rules used to build it, so with the default weight of
+//! 1.0 everywhere this degrades into an exhaustive parse that prefers states
+//! further to the right, which still tends to find a complete parse early.
+//!
+//! Weights are probabilities, from 0 to 1, so a state never scores higher
+//! than the states it was built from. That's what makes the first complete
+//! parse the best one: anything still on the agenda can only score lower.
+//!
+//! A beam (`ParseOptions::beam`) keeps the highest-scoring states of each
+//! chart cell, like it does for `earley::parse_chart_with`. Since states are
+//! mostly added in score order, the beam rarely has to drop a state it's
+//! already added.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::earley::{Chart, State, LR0};
+use crate::options::ParseOptions;
+use crate::rules::{Grammar, GrammarError};
+
+/// A state waiting on the agenda to be added to the chart at `k`
+#[derive(Debug)]
+struct Item {
+  state: State,
+  k: usize,
+  score: f64,
+  /// insertion order, used to break ties so parsing is deterministic
+  seq: usize,
+}
+
+impl PartialEq for Item {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for Item {}
+
+impl PartialOrd for Item {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Item {
+  /// Higher scores first, then states further into the input, then older states
+  fn cmp(&self, other: &Self) -> Ordering {
+    self
+      .score
+      .total_cmp(&other.score)
+      .then(self.k.cmp(&other.k))
+      .then(other.seq.cmp(&self.seq))
+  }
+}
+
+/// Incremental best-first parser. Call `next_complete` to run until the next
+/// complete parse is found, then inspect or take the chart.
+pub struct BestFirstParser<'a> {
+  g: &'a Grammar,
+  input: &'a [&'a str],
+  chart: Chart,
+  agenda: BinaryHeap<Item>,
+  seq: usize,
+  /// For the cancel flag, deadline and start symbol
+  options: ParseOptions,
+}
+
+impl<'a> BestFirstParser<'a> {
+  pub fn new(g: &'a Grammar, input: &'a [&'a str]) -> Self {
+    // the grammar's own start symbol was checked when it was built
+    Self::with_options(g, input, &ParseOptions::default()).expect("grammar missing start rules")
+  }
+
+  /// Like `new`, with options. Fails with `GrammarError::UndefinedStart` if
+  /// `ParseOptions::start` has no rules.
+  pub fn with_options(
+    g: &'a Grammar,
+    input: &'a [&'a str],
+    options: &ParseOptions,
+  ) -> Result<Self, GrammarError> {
+    let start = options.start_symbol(g);
+    g.check_start(start)?;
+    let mut this = Self {
+      g,
+      input,
+      chart: Chart::new(input.len() + 1)
+        .with_tokens(input)
+        .with_beam(options.beam)
+        .with_observer(options.observer.clone()),
+      agenda: BinaryHeap::new(),
+      seq: 0,
+      options: options.clone(),
+    };
+
+    for rule in &g.rules[start] {
+      this.push(State::new(LR0::new(rule), 0), 0, rule.weight);
+    }
+
+    Ok(this)
+  }
+
+  /// The chart built so far
+  pub fn chart(&self) -> &Chart {
+    &self.chart
+  }
+
+  pub fn into_chart(self) -> Chart {
+    self.chart
+  }
+
+  /// Processes the agenda until a complete start-symbol state spanning the
+  /// whole input is added to the chart, and returns its score. Returns None
+  /// once the agenda is exhausted, or if the parse is cancelled or its
+  /// deadline has passed.
+  pub fn next_complete(&mut self) -> Option<f64> {
+    while let Some(Item {
+      state, k, score, ..
+    }) = self.agenda.pop()
+    {
+      if self.options.is_cancelled() {
+        return None;
+      }
+      if !self.chart.add_scored(k, state.clone(), score) {
+        // already added with a better (or equal) score, or outside the beam
+        continue;
+      }
+      // a worse state this one pushed out of its cell
+      self.chart.compact(k);
+
+      match state.lr0.next_production() {
+        Some(production) if production.is_nonterminal() => self.predict(k, &state, score),
+        Some(_) => self.scan(k, &state, score),
+        None => {
+          self.complete(k, &state, score);
+          if state.origin == 0
+            && k == self.input.len()
+            && state.lr0.rule.symbol == self.options.start_symbol(self.g)
+          {
+            trace_event!(
+              debug,
+              score,
+              agenda = self.agenda.len(),
+              "found complete parse"
+            );
+            return Some(score);
+          }
+        }
+      }
+    }
+    None
+  }
+
+  fn push(&mut self, state: State, k: usize, score: f64) {
+    if !self.chart.has(k, &state) {
+      self.seq += 1;
+      self.agenda.push(Item {
+        state,
+        k,
+        score,
+        seq: self.seq,
+      });
+    }
+  }
+
+  fn predict(&mut self, k: usize, state: &State, score: f64) {
+    let g = self.g;
+    let needed_symbol = &state.lr0.next_production().unwrap().symbol;
+    for wanted_rule in g
+      .rules
+      .get(&**needed_symbol)
+      .unwrap_or_else(|| panic!("missing rules for production {}", needed_symbol))
+    {
+      self.push(State::new(LR0::new(wanted_rule), k), k, wanted_rule.weight);
+    }
+
+    if g.is_nullable(needed_symbol) && state.lr0.rule.allows_span(Some(state.lr0.pos), 0) {
+      self.push(state.advance(), k, score);
+    }
+
+    // unlike the exhaustive parser, states can arrive in any order, so
+    // constituents this state is waiting for may have already been completed
+    let mut found = Vec::new();
+    for end in k..self.chart.len() {
+      for (other, other_score) in self.chart.scored_at(end) {
+        if !other.lr0.is_active()
+          && other.origin == k
+          && other.lr0.rule.symbol == **needed_symbol
+          && state.lr0.rule.allows_span(Some(state.lr0.pos), end - k)
+        {
+          found.push((end, other_score));
+        }
+      }
+    }
+    for (end, other_score) in found {
+      self.push(state.advance(), end, score * other_score);
+    }
+  }
+
+  fn scan(&mut self, k: usize, state: &State, score: f64) {
+    let needed_symbol = &state.lr0.next_production().unwrap().symbol;
+    if k < self.input.len()
+      && self.g.terminal_matches(needed_symbol, self.input[k])
+      && state.lr0.rule.allows_span(Some(state.lr0.pos), 1)
+    {
+      self.chart.observe(|o| o.scanned(k, state, self.input[k]));
+      self.push(state.advance(), k + 1, score);
+    }
+  }
+
+  fn complete(&mut self, k: usize, state: &State, score: f64) {
+    self.chart.observe(|o| o.completed(k, state));
+    let mut found = Vec::new();
+    for (other, other_score) in self.chart.scored_at(state.origin) {
+      if let Some(np) = other.lr0.next_production() {
+        if *np.symbol == state.lr0.rule.symbol
+          && other
+            .lr0
+            .rule
+            .allows_span(Some(other.lr0.pos), k - state.origin)
+        {
+          found.push((other.advance(), other_score));
+        }
+      }
+    }
+    for (advanced, other_score) in found {
+      self.push(advanced, k, other_score * score);
+    }
+  }
+}
+
+/// Parses best-first, stopping as soon as there's a complete parse in the chart
+pub fn parse_chart_best_first(g: &Grammar, input: &[&str]) -> Chart {
+  let mut parser = BestFirstParser::new(g, input);
+  parser.next_complete();
+  parser.into_chart()
+}
+
+#[test]
+fn test_best_first_prefers_weighted_rules() {
+  let g: Grammar = r#"
+    @weight(0.9)
+    S -> A
+    @weight(0.1)
+    S -> B
+    A -> x
+    B -> x
+  "#
+  .parse()
+  .unwrap();
+
+  let input = ["x"];
+  let forest = crate::Forest::from(parse_chart_best_first(&g, &input));
+  let trees = forest.trees(&g);
+  assert_eq!(trees.len(), 1);
+  let (_, children) = trees[0].get_branch().unwrap();
+  assert_eq!(children[0].get_branch().unwrap().0.value.symbol, "A");
+
+  let mut parser = BestFirstParser::new(&g, &input);
+  assert_eq!(parser.next_complete(), Some(0.9));
+  assert_eq!(parser.next_complete(), Some(0.1));
+  assert_eq!(parser.next_complete(), None);
+}
+
+#[test]
+fn test_best_first_finds_the_best_parse_first() {
+  // the best parse goes through more rules, but still scores higher
+  let g: Grammar = r#"
+    S -> NP
+    @weight(0.5)
+    NP -> x
+    @weight(0.9)
+    NP -> A
+    @weight(0.9)
+    A -> x
+  "#
+  .parse()
+  .unwrap();
+
+  let trees = g.parse_best_first(["x"]);
+  assert_eq!(trees.len(), 1);
+  assert_eq!(trees[0].0.to_sexpr(), "(S (NP (A x)))");
+
+  let mut parser = BestFirstParser::new(&g, &["x"]);
+  assert_eq!(parser.next_complete(), Some(0.9 * 0.9));
+}
+
+#[test]
+fn test_best_first_deadline() {
+  use std::time::{Duration, Instant};
+
+  let g: Grammar = "S -> x".parse().unwrap();
+  let options = ParseOptions {
+    deadline: Some(Instant::now() - Duration::from_millis(1)),
+    ..ParseOptions::default()
+  };
+  let mut parser = BestFirstParser::with_options(&g, &["x"], &options).unwrap();
+  assert_eq!(parser.next_complete(), None);
+  assert_eq!(BestFirstParser::new(&g, &["x"]).next_complete(), Some(1.0));
+}
+
+#[test]
+fn test_best_first_undefined_start() {
+  let g: Grammar = "S -> NP\nNP -> x".parse().unwrap();
+  let options = |start: &str| ParseOptions {
+    start: Some(start.to_string()),
+    ..ParseOptions::default()
+  };
+  let mut parser = BestFirstParser::with_options(&g, &["x"], &options("NP")).unwrap();
+  assert_eq!(parser.next_complete(), Some(1.0));
+  assert!(matches!(
+    BestFirstParser::with_options(&g, &["x"], &options("Np")),
+    Err(GrammarError::UndefinedStart(s)) if s == "Np"
+  ));
+}
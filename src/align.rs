@@ -0,0 +1,215 @@
+//! Keeping track of where each token came from in the original text, for
+//! highlighting parses in it, when the tokens the grammar sees aren't one to
+//! one with what was typed: after normalizing (like lowercasing), splitting
+//! clitics (`don't` into `do not`), or merging multi-word terminals
+//! (`New York` into `new_york`).
+//!
+//! ```
+//! use treebender::align::Alignment;
+//! use treebender::tokenize::Whitespace;
+//!
+//! let a = Alignment::new(&Whitespace, "I don't like New York")
+//!   .normalize(|t| t.to_lowercase())
+//!   .split(|t| match t {
+//!     "don't" => vec!["do".to_string(), "not".to_string()],
+//!     t => vec![t.to_string()],
+//!   })
+//!   .merge(4, 6, "new_york");
+//! assert_eq!(a.tokens, ["i", "do", "not", "like", "new_york"]);
+//! // "new_york" is the 4th and 5th tokens of the original
+//! assert_eq!(a.origins[4].tokens, (3, 5));
+//! assert_eq!(a.text((4, 5)), Some("New York"));
+//! ```
+
+use crate::syntree::SynTree;
+use crate::tokenize::Tokenizer;
+
+/// Where a token came from in the original text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Origin {
+  /// The range of the tokenizer's tokens it came from: one token, or more
+  /// for merged tokens. Tokens split from the same one share it.
+  pub tokens: (usize, usize),
+  /// Its byte range in the text
+  pub bytes: (usize, usize),
+}
+
+impl Origin {
+  /// The smallest origin covering both
+  fn union(self, other: Self) -> Self {
+    Self {
+      tokens: (
+        self.tokens.0.min(other.tokens.0),
+        self.tokens.1.max(other.tokens.1),
+      ),
+      bytes: (
+        self.bytes.0.min(other.bytes.0),
+        self.bytes.1.max(other.bytes.1),
+      ),
+    }
+  }
+}
+
+/// Tokens to parse, each with its `Origin` in `source`
+#[derive(Debug, Clone, Default)]
+pub struct Alignment {
+  pub source: String,
+  pub tokens: Vec<String>,
+  /// One for each token
+  pub origins: Vec<Origin>,
+}
+
+impl Alignment {
+  /// Tokenizes `s`, one to one with the tokenizer's tokens
+  pub fn new(tokenizer: &impl Tokenizer, s: &str) -> Self {
+    let spans = tokenizer.token_spans(s);
+    Self {
+      source: s.to_string(),
+      tokens: tokenizer
+        .tokenize(s)
+        .into_iter()
+        .map(str::to_string)
+        .collect(),
+      origins: spans
+        .into_iter()
+        .enumerate()
+        .map(|(idx, bytes)| Origin {
+          tokens: (idx, idx + 1),
+          bytes,
+        })
+        .collect(),
+    }
+  }
+
+  /// Replaces every token with `f` of it, keeping its origin
+  pub fn normalize(mut self, mut f: impl FnMut(&str) -> String) -> Self {
+    for token in self.tokens.iter_mut() {
+      *token = f(token);
+    }
+    self
+  }
+
+  /// Replaces every token with the tokens `f` splits it into. A piece that's
+  /// in the original text of its token (in order) gets that part of it as
+  /// its byte range, and otherwise gets the whole token's, like `not` from
+  /// `don't`.
+  pub fn split(self, mut f: impl FnMut(&str) -> Vec<String>) -> Self {
+    let mut tokens = Vec::with_capacity(self.tokens.len());
+    let mut origins = Vec::with_capacity(self.origins.len());
+    for (token, origin) in self.tokens.iter().zip(&self.origins) {
+      let text = &self.source[origin.bytes.0..origin.bytes.1];
+      let mut next = 0;
+      for piece in f(token) {
+        let bytes = match text[next..].find(piece.as_str()) {
+          Some(idx) if !piece.is_empty() => {
+            let start = origin.bytes.0 + next + idx;
+            next += idx + piece.len();
+            (start, start + piece.len())
+          }
+          _ => origin.bytes,
+        };
+        tokens.push(piece);
+        origins.push(Origin { bytes, ..*origin });
+      }
+    }
+    Self {
+      source: self.source,
+      tokens,
+      origins,
+    }
+  }
+
+  /// Replaces the tokens in `start..end` with `token`, whose origin covers
+  /// theirs. Panics if the range is empty or out of bounds.
+  pub fn merge(mut self, start: usize, end: usize, token: impl Into<String>) -> Self {
+    assert!(start < end && end <= self.tokens.len(), "bad merge range");
+    let origin = self.origins[start..end]
+      .iter()
+      .copied()
+      .reduce(Origin::union)
+      .unwrap();
+    self.tokens.splice(start..end, [token.into()]);
+    self.origins.splice(start..end, [origin]);
+    self
+  }
+
+  /// The origin of a span of tokens, like a constituent's, or None if it's
+  /// empty or out of bounds
+  pub fn origin(&self, span: (usize, usize)) -> Option<Origin> {
+    self
+      .origins
+      .get(span.0..span.1)?
+      .iter()
+      .copied()
+      .reduce(Origin::union)
+  }
+
+  /// The original text of a span of tokens
+  pub fn text(&self, span: (usize, usize)) -> Option<&str> {
+    let Origin { bytes, .. } = self.origin(span)?;
+    self.source.get(bytes.0..bytes.1)
+  }
+
+  /// Each leaf of `tree` (a parse of `tokens`), with its origin
+  pub fn leaves<'t, T>(&self, tree: &'t SynTree<T, String>) -> Vec<(&'t str, Option<Origin>)> {
+    let mut out = Vec::new();
+    self.collect_leaves(tree, &mut out);
+    out
+  }
+
+  fn collect_leaves<'t, T>(
+    &self,
+    tree: &'t SynTree<T, String>,
+    out: &mut Vec<(&'t str, Option<Origin>)>,
+  ) {
+    match tree {
+      SynTree::Leaf(w) => out.push((w.value.as_str(), self.origin(w.span))),
+      SynTree::Branch(_, children) => {
+        for child in children {
+          self.collect_leaves(child, out);
+        }
+      }
+    }
+  }
+}
+
+#[test]
+fn test_alignment() {
+  use crate::tokenize::UnicodeWords;
+  use crate::Grammar;
+
+  let g: Grammar = r#"
+    S -> N Aux Neg V N
+    N -> i
+    N -> new_york
+    Aux -> do
+    Neg -> not
+    V -> like
+  "#
+  .parse()
+  .unwrap();
+
+  let a = Alignment::new(&UnicodeWords, "I don't like New York")
+    .normalize(|t| t.to_lowercase())
+    .split(|t| match t {
+      "don't" => vec!["do".to_string(), "not".to_string()],
+      t => vec![t.to_string()],
+    })
+    .merge(4, 6, "new_york");
+  let (tree, _) = g.parse_one(&a.tokens).unwrap();
+
+  let origin = |tokens, bytes| Some(Origin { tokens, bytes });
+  assert_eq!(
+    a.leaves(&tree),
+    [
+      ("i", origin((0, 1), (0, 1))),
+      ("do", origin((1, 2), (2, 4))),
+      ("not", origin((1, 2), (2, 7))),
+      ("like", origin((2, 3), (8, 12))),
+      ("new_york", origin((3, 5), (13, 21))),
+    ]
+  );
+  assert_eq!(a.text((1, 3)), Some("don't"));
+  assert_eq!(a.text(tree.span()), Some("I don't like New York"));
+  assert_eq!(a.origin((5, 5)), None);
+}
@@ -0,0 +1,266 @@
+//! Warnings for sentences with more parses than expected, which is usually
+//! the first sign that a new rule or lexical entry interacts badly with the
+//! rest of the grammar. Set `ParseOptions::max_ambiguity` to get them through
+//! the observer (and `tracing`, if it's enabled).
+//!
+//! Not all ambiguity is worth warning about: parses that only differ in
+//! their bracketing, like the two of "cats and dogs and birds", often mean the
+//! same thing. `Grammar::ambiguity_classes` groups parses by their features,
+//! to count the readings instead of the derivations.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::featurestructure::{NodeRef, Normalized};
+use crate::packing::Restrictor;
+use crate::projection::{is_child_label, Projected};
+use crate::rules::Grammar;
+use crate::syntree::SynTree;
+use crate::ParsedTree;
+
+/// A sentence had more than `limit` parses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmbiguityWarning {
+  pub parses: usize,
+  pub limit: usize,
+  /// The shortest constituent the parses disagree on, and its span. None if
+  /// they only differ in their roots' features.
+  pub span: Option<(String, (usize, usize))>,
+}
+
+impl fmt::Display for AmbiguityWarning {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{} parses, more than the limit of {}",
+      self.parses, self.limit
+    )?;
+    if let Some((symbol, (start, end))) = &self.span {
+      write!(
+        f,
+        "; the shortest ambiguous span is {} over {}..{}",
+        symbol, start, end
+      )?;
+    }
+    Ok(())
+  }
+}
+
+impl Grammar {
+  /// The shortest constituent (the leftmost, of those as short) where some
+  /// parse differs from the first: in its children, or in its own features
+  /// if its children are the same. None if there are fewer than two parses,
+  /// or they're all the same.
+  pub fn shortest_ambiguity(&self, trees: &[ParsedTree]) -> Option<(String, (usize, usize))> {
+    let (first, rest) = trees.split_first()?;
+    let first = self.project(first);
+    let mut spans = Vec::new();
+    for tree in rest {
+      differences(&first, &self.project(tree), &mut spans);
+    }
+    spans
+      .into_iter()
+      .min_by_key(|(_, (start, end))| (end - start, *start))
+  }
+}
+
+/// Parses whose roots have the same features, once the child features (and
+/// the restricted ones) are left out: one reading, with every derivation of it
+#[derive(Debug, Clone)]
+pub struct AmbiguityClass {
+  /// The features the parses share, in canonical form
+  pub features: Normalized,
+  /// In the order they were given
+  pub trees: Vec<ParsedTree>,
+}
+
+impl Grammar {
+  /// Groups `trees` into `AmbiguityClass`es, leaving the features in
+  /// `restrictor` out of the comparison, like the bookkeeping features a
+  /// grammar uses to pass gaps around. The classes are in the order of their
+  /// first trees, so the first parse's reading comes first.
+  pub fn ambiguity_classes(
+    &self,
+    trees: Vec<ParsedTree>,
+    restrictor: &Restrictor,
+  ) -> Vec<AmbiguityClass> {
+    let mut classes: Vec<AmbiguityClass> = Vec::new();
+    let mut by_features: HashMap<String, usize> = HashMap::new();
+    for tree in trees {
+      let features = self.reading(&tree.1, restrictor);
+      match by_features.get(&features.canonical) {
+        Some(&idx) => classes[idx].trees.push(tree),
+        None => {
+          by_features.insert(features.canonical.clone(), classes.len());
+          classes.push(AmbiguityClass {
+            features,
+            trees: vec![tree],
+          });
+        }
+      }
+    }
+    classes
+  }
+
+  /// How many readings `trees` have, see `ambiguity_classes`
+  pub fn count_readings(&self, trees: &[ParsedTree], restrictor: &Restrictor) -> usize {
+    let mut seen = trees
+      .iter()
+      .map(|(_, fs)| self.reading(fs, restrictor).canonical)
+      .collect::<Vec<_>>();
+    seen.sort();
+    seen.dedup();
+    seen.len()
+  }
+
+  /// A parse's root features without its children's or the restricted ones
+  fn reading(&self, fs: &NodeRef, restrictor: &Restrictor) -> Normalized {
+    let fs = fs.clone().dereference();
+    let edges = fs.borrow().edged().map(|edges| {
+      edges
+        .iter()
+        .filter(|(label, _)| {
+          !is_child_label(label, &self.child_prefix) && !restrictor.features.contains(*label)
+        })
+        .map(|(l, v)| (l.clone(), v.clone()))
+        .collect::<Vec<_>>()
+    });
+    match edges {
+      Some(edges) => NodeRef::new_with_edges(edges).unwrap().normalize(),
+      None => fs.normalize(),
+    }
+  }
+}
+
+/// Pushes the deepest constituents where `a` and `b` differ
+fn differences(
+  a: &SynTree<Projected, String>,
+  b: &SynTree<Projected, String>,
+  out: &mut Vec<(String, (usize, usize))>,
+) {
+  let (Some((ca, children_a)), Some((cb, children_b))) = (a.get_branch(), b.get_branch()) else {
+    return;
+  };
+  // the children's symbols (or words) and spans
+  fn outline(children: &[SynTree<Projected, String>]) -> Vec<(&str, (usize, usize))> {
+    children
+      .iter()
+      .map(|c| match c {
+        SynTree::Branch(c, _) => (c.value.symbol.as_str(), c.span),
+        SynTree::Leaf(w) => (w.value.as_str(), w.span),
+      })
+      .collect()
+  }
+  let symbol_and_span = (ca.value.symbol.clone(), ca.span);
+  if ca.value.symbol != cb.value.symbol || outline(children_a) != outline(children_b) {
+    out.push(symbol_and_span);
+    return;
+  }
+
+  let before = out.len();
+  for (child_a, child_b) in children_a.iter().zip(children_b) {
+    differences(child_a, child_b, out);
+  }
+  if out.len() == before && !NodeRef::equivalent(&ca.value.features, &cb.value.features) {
+    out.push(symbol_and_span);
+  }
+}
+
+#[test]
+fn test_ambiguity_warning() {
+  use std::sync::{Arc, Mutex};
+
+  use crate::observer::ParseObserver;
+  use crate::ParseOptions;
+
+  let g: Grammar = r#"
+    S -> NP VP
+    VP -> V NP
+    VP -> VP PP
+    NP -> NP PP
+    NP -> Det N[ num: #1 ]
+    NP -> N
+    PP -> P NP
+    Det -> the
+    N -> i
+    N -> man
+    N[ num: sg ] -> fish
+    N[ num: pl ] -> fish
+    V -> saw
+    P -> with
+  "#
+  .parse()
+  .unwrap();
+
+  // attachment: "with the man" goes on the VP or the object NP
+  let trees = g.parse(["i", "saw", "the", "man", "with", "the", "man"]);
+  assert_eq!(trees.len(), 2);
+  assert_eq!(
+    g.shortest_ambiguity(&trees),
+    Some(("VP".to_string(), (1, 7)))
+  );
+  // features only: "fish" is sg or pl
+  let trees = g.parse(["i", "saw", "the", "fish"]);
+  assert_eq!(
+    g.shortest_ambiguity(&trees),
+    Some(("N".to_string(), (3, 4)))
+  );
+  assert_eq!(g.shortest_ambiguity(&trees[..1]), None);
+
+  #[derive(Default)]
+  struct Recorder(Mutex<Vec<String>>);
+
+  impl ParseObserver for Recorder {
+    fn ambiguity_exceeded(&self, warning: &AmbiguityWarning) {
+      self.0.lock().unwrap().push(warning.to_string());
+    }
+  }
+
+  let recorder = Arc::new(Recorder::default());
+  let options = ParseOptions {
+    observer: Some(recorder.clone()),
+    max_ambiguity: Some(1),
+    ..ParseOptions::default()
+  };
+  g.parse_with(["i", "saw", "the", "man"], &options);
+  g.parse_with(["i", "saw", "the", "fish", "with", "the", "fish"], &options);
+  assert_eq!(
+    *recorder.0.lock().unwrap(),
+    ["8 parses, more than the limit of 1; the shortest ambiguous span is N over 3..4"]
+  );
+}
+
+#[test]
+fn test_ambiguity_classes() {
+  let g: Grammar = r#"
+    S[ num: #1 ] -> NP[ num: #1 ] V
+    NP[ num: pl ] -> NP and NP
+    NP[ num: sg ] -> fish
+    NP[ num: pl ] -> fish
+    V -> sleep
+  "#
+  .parse()
+  .unwrap();
+  let restrictor = Restrictor::default();
+
+  // two bracketings, and every fish sg or pl, but always pl overall
+  let trees = g.parse(["fish", "and", "fish", "and", "fish", "sleep"]);
+  assert_eq!(trees.len(), 16);
+  let classes = g.ambiguity_classes(trees.clone(), &restrictor);
+  assert_eq!(classes.len(), 1);
+  assert_eq!(classes[0].trees.len(), 16);
+  assert_eq!(classes[0].features.canonical, "[ num: pl ]");
+  assert_eq!(g.count_readings(&trees, &restrictor), 1);
+
+  let trees = g.parse(["fish", "sleep"]);
+  let classes = g.ambiguity_classes(trees.clone(), &restrictor);
+  assert_eq!(
+    classes
+      .iter()
+      .map(|c| (c.features.canonical.as_str(), c.trees.len()))
+      .collect::<Vec<_>>(),
+    [("[ num: sg ]", 1), ("[ num: pl ]", 1)]
+  );
+  assert_eq!(g.count_readings(&trees, &Restrictor::new(["num"])), 1);
+  assert!(g.ambiguity_classes(Vec::new(), &restrictor).is_empty());
+}
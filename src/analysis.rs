@@ -0,0 +1,183 @@
+//! Static analyses of a grammar's symbols, for tools built on the crate, like
+//! linters, editors, and test generators. The parser only needs
+//! `Grammar::is_nullable`; these are computed on request.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::rules::{Grammar, Production};
+
+/// Stands for the end of the input in `Grammar::follow_sets`. Not a valid
+/// terminal name, so it can't clash with one.
+pub const END_OF_INPUT: &str = "$";
+
+impl Grammar {
+  /// Nonterminals that can appear in a derivation from the start symbol,
+  /// including it
+  pub fn reachable(&self) -> HashSet<String> {
+    let mut reachable = HashSet::new();
+    let mut stack = vec![self.start.clone()];
+    while let Some(symbol) = stack.pop() {
+      if !reachable.insert(symbol.clone()) {
+        continue;
+      }
+      for rule in self.rules.get(&symbol).into_iter().flatten() {
+        for p in rule.productions.iter() {
+          if p.is_nonterminal() && !reachable.contains(&*p.symbol) {
+            stack.push(p.symbol.to_string());
+          }
+        }
+      }
+    }
+    reachable
+  }
+
+  /// Nonterminals that derive at least one (possibly empty) string of
+  /// terminals
+  pub fn productive(&self) -> HashSet<String> {
+    let mut productive: HashSet<String> = HashSet::new();
+
+    let mut last_length = 1;
+    while last_length != productive.len() {
+      last_length = productive.len();
+      for r in self.rules.values().flatten() {
+        if !productive.contains(&r.symbol)
+          && r
+            .productions
+            .iter()
+            .all(|p| p.is_terminal() || productive.contains(&*p.symbol))
+        {
+          productive.insert(r.symbol.clone());
+        }
+      }
+    }
+
+    productive
+  }
+
+  /// For each nonterminal, the terminals its constituents can start with.
+  /// Features aren't considered, so some may not lead to a parse.
+  pub fn first_sets(&self) -> HashMap<String, HashSet<String>> {
+    let mut first: HashMap<String, HashSet<String>> = self
+      .rules
+      .keys()
+      .map(|symbol| (symbol.clone(), HashSet::new()))
+      .collect();
+
+    let mut changed = true;
+    while changed {
+      changed = false;
+      for rule in self.rules.values().flatten() {
+        let (found, _) = self.first_of(&first, &rule.productions);
+        let set = first.get_mut(&rule.symbol).unwrap();
+        let before = set.len();
+        set.extend(found);
+        changed |= set.len() != before;
+      }
+    }
+    first
+  }
+
+  /// For each nonterminal, the terminals that can come right after its
+  /// constituents, and `END_OF_INPUT` if they can end the sentence. Like
+  /// `first_sets`, features aren't considered.
+  pub fn follow_sets(&self) -> HashMap<String, HashSet<String>> {
+    let first = self.first_sets();
+    let mut follow: HashMap<String, HashSet<String>> = self
+      .rules
+      .keys()
+      .map(|symbol| (symbol.clone(), HashSet::new()))
+      .collect();
+    if let Some(set) = follow.get_mut(&self.start) {
+      set.insert(END_OF_INPUT.to_string());
+    }
+
+    let mut changed = true;
+    while changed {
+      changed = false;
+      for rule in self.rules.values().flatten() {
+        for (idx, p) in rule.productions.iter().enumerate() {
+          if !p.is_nonterminal() {
+            continue;
+          }
+          let (mut found, rest_nullable) = self.first_of(&first, &rule.productions[idx + 1..]);
+          if rest_nullable {
+            found.extend(follow[&rule.symbol].iter().cloned());
+          }
+          let set = follow.get_mut(&*p.symbol).unwrap();
+          let before = set.len();
+          set.extend(found);
+          changed |= set.len() != before;
+        }
+      }
+    }
+    follow
+  }
+
+  /// The terminals `productions` can start with, given the first sets so
+  /// far, and whether they can all be empty
+  fn first_of(
+    &self,
+    first: &HashMap<String, HashSet<String>>,
+    productions: &[Production],
+  ) -> (HashSet<String>, bool) {
+    let mut found = HashSet::new();
+    for p in productions {
+      if p.is_terminal() {
+        found.insert(p.symbol.to_string());
+        return (found, false);
+      }
+      found.extend(first[&*p.symbol].iter().cloned());
+      if !self.is_nullable(&p.symbol) {
+        return (found, false);
+      }
+    }
+    (found, true)
+  }
+}
+
+#[test]
+fn test_analyses() {
+  let g: Grammar = r#"
+    S -> NP VP
+    NP -> Det N
+    Det -> the
+    Det ->
+    N -> cat
+    VP -> V
+    VP -> V NP
+    V -> sees
+    Adj -> big
+    Loop -> Loop x
+    S -> Loop
+  "#
+  .parse()
+  .unwrap();
+
+  fn sorted(set: &HashSet<String>) -> Vec<&str> {
+    let mut v = set.iter().map(String::as_str).collect::<Vec<_>>();
+    v.sort();
+    v
+  }
+
+  assert_eq!(sorted(g.nullables()), ["Det"]);
+  assert_eq!(
+    sorted(&g.reachable()),
+    ["Det", "Loop", "N", "NP", "S", "V", "VP"]
+  );
+  assert!(!g.productive().contains("Loop"));
+  assert!(g.productive().contains("Det"));
+
+  let first = g.first_sets();
+  // Loop never starts with anything, since it never finishes
+  assert_eq!(sorted(&first["S"]), ["cat", "the"]);
+  assert_eq!(sorted(&first["Det"]), ["the"]);
+  assert_eq!(sorted(&first["VP"]), ["sees"]);
+
+  let follow = g.follow_sets();
+  assert_eq!(sorted(&follow["S"]), [END_OF_INPUT]);
+  assert_eq!(sorted(&follow["Det"]), ["cat"]);
+  assert_eq!(sorted(&follow["NP"]), [END_OF_INPUT, "sees"]);
+  assert_eq!(sorted(&follow["V"]), [END_OF_INPUT, "cat", "the"]);
+  assert_eq!(sorted(&follow["Loop"]), [END_OF_INPUT, "x"]);
+  assert!(follow["Adj"].is_empty());
+}
@@ -0,0 +1,204 @@
+//! Parsing within a time budget, for interactive applications that would
+//! rather have a partial answer now than every parse later. When the budget
+//! runs out, the parses found so far are returned, and if there aren't any,
+//! the largest constituents the parser could find.
+
+use std::cmp::Reverse;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::earley::{parse_chart_everywhere, parse_chart_with};
+use crate::forest::{Forest, ForestError};
+use crate::options::ParseOptions;
+use crate::rules::{Grammar, GrammarError, Rule};
+use crate::syntree::SynTree;
+use crate::utils::Err;
+use crate::{with_tokens, ParsedTree};
+
+/// What `Grammar::parse_anytime` found in its budget
+#[derive(Debug, Clone)]
+pub struct AnytimeParse {
+  /// The parses found, in the order `Grammar::parse` would give them
+  pub trees: Vec<ParsedTree>,
+  /// Whether the parse finished in time, so `trees` has every parse
+  pub complete: bool,
+  /// If there are no `trees`: the longest constituents that unify, left to
+  /// right without overlapping, skipping words nothing covers. If the parse
+  /// finished without any trees, these are looked for in the rest of the
+  /// budget, over every span, and otherwise in the part of the sentence's
+  /// chart that was built in time.
+  pub chunks: Vec<ParsedTree>,
+}
+
+impl Grammar {
+  /// Parses `input`, stopping after `budget` with what was found so far, see
+  /// `AnytimeParse`
+  pub fn parse_anytime<I>(&self, input: I, budget: Duration) -> AnytimeParse
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    self
+      .parse_anytime_with(input, budget, &ParseOptions::default())
+      .expect("grammar missing start rules")
+  }
+
+  /// Like `parse_anytime`, with options. `best_first` and `packing` are
+  /// ignored, and trees that grow past the `max_fs_*` limits are dropped
+  /// instead of ending the parse. Fails with `GrammarError::UndefinedStart`
+  /// if `ParseOptions::start` has no rules.
+  pub fn parse_anytime_with<I>(
+    &self,
+    input: I,
+    budget: Duration,
+    options: &ParseOptions,
+  ) -> Result<AnytimeParse, GrammarError>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    let options = ParseOptions {
+      deadline: Some(Instant::now() + budget),
+      ..options.clone()
+    };
+    with_tokens(input, |input| {
+      let forest = Forest::from(parse_chart_with(self, input, &options)?);
+      let mut trees = Vec::new();
+      let mut complete = !options.is_cancelled();
+      if complete {
+        for tree in forest.try_iter_trees_with(self, &options) {
+          let tree = match tree {
+            Ok(tree) if !options.is_cancelled() => tree,
+            Err(ForestError::Cancelled) | Ok(_) => {
+              complete = false;
+              break;
+            }
+            Err(_e) => {
+              trace_event!(warn, error = %_e, "stopped building trees");
+              break;
+            }
+          };
+          if let Ok(tree) = self.unify_anytime(tree, &options) {
+            trees.push(tree);
+          }
+        }
+      }
+      let chunks = if trees.is_empty() && complete {
+        let everywhere = Forest::from(parse_chart_everywhere(self, input, &options)?);
+        self.chunks(&everywhere, &options)
+      } else if trees.is_empty() {
+        self.chunks(&forest, &options)
+      } else {
+        Vec::new()
+      };
+      trace_event!(
+        debug,
+        trees = trees.len(),
+        complete,
+        chunks = chunks.len(),
+        "anytime parse"
+      );
+      Ok(AnytimeParse {
+        trees,
+        complete,
+        chunks,
+      })
+    })
+  }
+
+  /// Unifies a tree like `unify_forest` does
+  fn unify_anytime(
+    &self,
+    tree: SynTree<Arc<Rule>, Arc<str>>,
+    options: &ParseOptions,
+  ) -> Result<ParsedTree, Err> {
+    let tree = Self::unify_tree_with(tree, options)?;
+    Ok(self.rename_children(Self::unify_root(tree, options)?))
+  }
+
+  /// See `AnytimeParse::chunks`
+  fn chunks(&self, forest: &Forest, options: &ParseOptions) -> Vec<ParsedTree> {
+    let mut chunks = Vec::new();
+    let mut pos = 0;
+    while pos < forest.len() {
+      let mut states = forest
+        .states_from(pos)
+        .iter()
+        .filter(|s| s.span().1 > pos)
+        .collect::<Vec<_>>();
+      // longest first, then in rule order
+      states.sort_by_key(|s| (Reverse(s.span().1), s.rule().index));
+      let chunk = states.into_iter().find_map(|state| {
+        forest
+          .iter_state_trees(state)
+          .find_map(|tree| self.unify_anytime(tree, options).ok())
+      });
+      match chunk {
+        Some(chunk) => {
+          pos = chunk.0.span().1;
+          chunks.push(chunk);
+        }
+        None => pos += 1,
+      }
+    }
+    chunks
+  }
+}
+
+#[test]
+fn test_parse_anytime() {
+  let g: Grammar = r#"
+    S -> NP VP
+    VP -> V NP
+    NP -> Det N[ num: #n ]
+    Det -> the
+    N[ num: sg ] -> cat
+    N[ num: sg ] -> dog
+    V -> saw
+  "#
+  .parse()
+  .unwrap();
+  let budget = Duration::from_secs(10);
+
+  let parse = g.parse_anytime(["the", "cat", "saw", "the", "dog"], budget);
+  assert!(parse.complete);
+  assert_eq!(parse.trees.len(), 1);
+  assert!(parse.chunks.is_empty());
+
+  // no parse, so the pieces
+  let parse = g.parse_anytime(["the", "cat", "the", "dog", "saw"], budget);
+  assert!(parse.complete);
+  assert!(parse.trees.is_empty());
+  assert_eq!(
+    parse
+      .chunks
+      .iter()
+      .map(|(t, _)| t.to_sexpr())
+      .collect::<Vec<_>>(),
+    [
+      "(NP (Det the) (N cat))",
+      "(NP (Det the) (N dog))",
+      "(V saw)"
+    ]
+  );
+
+  let parse = g.parse_anytime(["the", "cat", "saw", "the", "dog"], Duration::ZERO);
+  assert!(!parse.complete);
+  assert!(parse.trees.is_empty());
+}
+
+#[test]
+fn test_parse_anytime_undefined_start() {
+  let g: Grammar = "S -> NP\nNP -> x".parse().unwrap();
+  let options = |start: &str| ParseOptions {
+    start: Some(start.to_string()),
+    ..ParseOptions::default()
+  };
+  let budget = Duration::from_secs(10);
+  let parse = g.parse_anytime_with(["x"], budget, &options("NP")).unwrap();
+  assert_eq!(parse.trees.len(), 1);
+  assert!(matches!(
+    g.parse_anytime_with(["x"], budget, &options("Np")),
+    Err(GrammarError::UndefinedStart(s)) if s == "Np"
+  ));
+}
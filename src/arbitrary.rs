@@ -0,0 +1,243 @@
+//! [proptest](https://docs.rs/proptest) strategies for grammars, feature
+//! structures and sentences, plus checks for invariants that should hold for
+//! any of them. Needs the `proptest` feature.
+//!
+//! ```no_run
+//! use proptest::prelude::*;
+//! use treebender::arbitrary::unify_commutes;
+//! use treebender::NodeRef;
+//!
+//! proptest! {
+//!   #[test]
+//!   fn my_test(a: NodeRef, b: NodeRef) {
+//!     unify_commutes(&a, &b)?;
+//!   }
+//! }
+//! ```
+
+use proptest::prelude::*;
+use proptest::sample::select;
+use proptest::test_runner::TestCaseError;
+
+use crate::featurestructure::{Feature, NodeRef};
+use crate::rules::Grammar;
+
+/// Nonterminals of generated grammars, in the order they can refer to each other
+const NONTERMINALS: [&str; 4] = ["S", "A", "B", "C"];
+const TERMINALS: [&str; 3] = ["a", "b", "c"];
+const LABELS: [&str; 3] = ["a", "b", "c"];
+const VALUES: [&str; 2] = ["x", "y"];
+
+/// Feature structures with up to 6 features, on paths up to 3 deep, with
+/// some values shared through tags
+pub fn feature_structure() -> impl Strategy<Value = NodeRef> {
+  let path = prop::collection::vec(select(&LABELS[..]), 1..=3).prop_map(|labels| labels.join("."));
+  let tag = prop_oneof![Just(None), Just(Some("1")), Just(Some("2"))];
+  // None is top
+  let value = prop_oneof![Just(None), select(&VALUES[..]).prop_map(Some)];
+
+  prop::collection::vec((path, tag, value), 0..6).prop_filter_map(
+    "features must not conflict or make a cycle",
+    |features| {
+      // a tag on both a path and its prefix would make a cycle, and building
+      // it would deadlock
+      let cyclic = features.iter().any(|(p1, t1, _)| {
+        features
+          .iter()
+          .any(|(p2, t2, _)| t1.is_some() && t1 == t2 && p2.starts_with(&format!("{}.", p1)))
+      });
+      if cyclic {
+        return None;
+      }
+      let fs = NodeRef::new_from_paths(features.into_iter().map(|(path, tag, value)| Feature {
+        path,
+        tag: tag.map(str::to_string),
+        value: match value {
+          Some(s) => NodeRef::new_str(s.to_string()),
+          None => NodeRef::new_top(),
+        },
+      }))
+      .ok()?;
+      // tags can still make one together, like a: #1, b: #1 and a.c: #1
+      if is_cyclic(&fs, &mut Vec::new()) {
+        None
+      } else {
+        Some(fs)
+      }
+    },
+  )
+}
+
+/// Whether a node is its own descendant
+fn is_cyclic(nref: &NodeRef, ancestors: &mut Vec<NodeRef>) -> bool {
+  let nref = nref.clone().dereference();
+  if ancestors.contains(&nref) {
+    return true;
+  }
+  let children = nref
+    .borrow()
+    .edged()
+    .map(|edges| edges.values().cloned().collect::<Vec<_>>())
+    .unwrap_or_default();
+  ancestors.push(nref);
+  let cyclic = children.iter().any(|child| is_cyclic(child, ancestors));
+  ancestors.pop();
+  cyclic
+}
+
+impl Arbitrary for NodeRef {
+  type Parameters = ();
+  type Strategy = BoxedStrategy<NodeRef>;
+
+  fn arbitrary_with(_: ()) -> Self::Strategy {
+    feature_structure().boxed()
+  }
+}
+
+/// A `num` feature, or nothing
+fn num_feature() -> impl Strategy<Value = &'static str> {
+  prop_oneof![Just(""), Just("[ num: sg ]"), Just("[ num: pl ]")]
+}
+
+/// A rule for `NONTERMINALS[lhs]`, which can only use later nonterminals
+fn rule(lhs: usize) -> impl Strategy<Value = String> {
+  let symbols = TERMINALS
+    .iter()
+    .chain(NONTERMINALS[lhs + 1..].iter())
+    .copied()
+    .collect::<Vec<_>>();
+  let productions = prop::collection::vec((select(symbols), num_feature()), 0..=3);
+
+  (num_feature(), productions).prop_map(move |(features, productions)| {
+    let productions = productions
+      .into_iter()
+      .map(|(symbol, features)| {
+        if TERMINALS.contains(&symbol) {
+          symbol.to_string()
+        } else {
+          format!("{}{}", symbol, features)
+        }
+      })
+      .collect::<Vec<_>>();
+    format!(
+      "{}{} -> {}",
+      NONTERMINALS[lhs],
+      features,
+      productions.join(" ")
+    )
+  })
+}
+
+/// Grammars over the nonterminals S, A, B and C and the terminals a, b and c,
+/// with a few rules each and `num` features that don't always agree. Rules
+/// only refer to nonterminals after their own, so the grammars aren't
+/// recursive and every sentence has finitely many parses.
+pub fn grammar() -> impl Strategy<Value = Grammar> {
+  let rules = |lhs| prop::collection::vec(rule(lhs), 1..=3);
+  (rules(0), rules(1), rules(2), rules(3)).prop_filter_map("grammar must load", |(s, a, b, c)| {
+    [s, a, b, c].concat().join("\n").parse::<Grammar>().ok()
+  })
+}
+
+impl Arbitrary for Grammar {
+  type Parameters = ();
+  type Strategy = BoxedStrategy<Grammar>;
+
+  fn arbitrary_with(_: ()) -> Self::Strategy {
+    grammar().boxed()
+  }
+}
+
+/// The terminals used in a grammar's rules, for building sentences
+pub fn terminals(g: &Grammar) -> Vec<String> {
+  let mut terminals = g
+    .rules
+    .values()
+    .flatten()
+    .flat_map(|rule| rule.productions.iter())
+    .filter(|p| p.is_terminal())
+    .map(|p| p.symbol.to_string())
+    .collect::<Vec<_>>();
+  terminals.sort();
+  terminals.dedup();
+  terminals
+}
+
+/// Sentences of up to `max_len` words from `vocabulary`, which can't be empty
+/// unless `max_len` is 0
+pub fn sentence(vocabulary: Vec<String>, max_len: usize) -> impl Strategy<Value = Vec<String>> {
+  let word = if vocabulary.is_empty() {
+    Just(String::new()).boxed()
+  } else {
+    select(vocabulary).boxed()
+  };
+  prop::collection::vec(word, 0..=max_len)
+}
+
+/// A grammar, and a sentence of up to `max_len` of its terminals
+pub fn grammar_and_sentence(max_len: usize) -> impl Strategy<Value = (Grammar, Vec<String>)> {
+  grammar().prop_flat_map(move |g| {
+    let terminals = terminals(&g);
+    let max_len = if terminals.is_empty() { 0 } else { max_len };
+    (Just(g), sentence(terminals, max_len))
+  })
+}
+
+/// Checks that unifying `a` with `b` gives the same result as `b` with `a`.
+/// Works on copies, so `a` and `b` aren't changed.
+pub fn unify_commutes(a: &NodeRef, b: &NodeRef) -> Result<(), TestCaseError> {
+  let (a1, b1) = (a.deep_clone(), b.deep_clone());
+  let (a2, b2) = (a.deep_clone(), b.deep_clone());
+  let ab = NodeRef::unify(a1.clone(), b1);
+  let ba = NodeRef::unify(b2, a2.clone());
+
+  prop_assert_eq!(ab.is_ok(), ba.is_ok(), "{} & {}", a, b);
+  if ab.is_ok() {
+    prop_assert!(
+      NodeRef::equivalent(&a1, &a2),
+      "{} & {}: {} vs {}",
+      a,
+      b,
+      a1,
+      a2
+    );
+  }
+  Ok(())
+}
+
+/// Checks that unifying `a` with a copy of itself succeeds and changes nothing
+pub fn unify_idempotent(a: &NodeRef) -> Result<(), TestCaseError> {
+  let a1 = a.deep_clone();
+  prop_assert!(NodeRef::unify(a1.clone(), a.deep_clone()).is_ok(), "{}", a);
+  prop_assert!(NodeRef::equivalent(&a1, a), "{} vs {}", a, a1);
+  Ok(())
+}
+
+/// Checks that `parse_one` finds a tree exactly when `parse` does
+pub fn parse_one_agrees(g: &Grammar, sentence: &[String]) -> Result<(), TestCaseError> {
+  prop_assert_eq!(
+    g.parse_one(sentence).is_some(),
+    !g.parse(sentence).is_empty(),
+    "{:?}",
+    sentence
+  );
+  Ok(())
+}
+
+#[cfg(test)]
+proptest! {
+  #[test]
+  fn test_unify_commutes(a: NodeRef, b: NodeRef) {
+    unify_commutes(&a, &b)?;
+  }
+
+  #[test]
+  fn test_unify_idempotent(a: NodeRef) {
+    unify_idempotent(&a)?;
+  }
+
+  #[test]
+  fn test_parse_one_agrees((g, sentence) in grammar_and_sentence(4)) {
+    parse_one_agrees(&g, &sentence)?;
+  }
+}
@@ -0,0 +1,101 @@
+//! Parser backends turn a grammar and a sentence into a `Forest`, which
+//! everything downstream (tree building, unification) works from. Earley is
+//! the default, but other algorithms can be plugged into
+//! `Grammar::parse_with_backend` by implementing `ParserBackend`.
+
+use crate::earley::parse_chart_with;
+use crate::forest::Forest;
+use crate::options::ParseOptions;
+use crate::rules::{Grammar, GrammarError};
+
+pub trait ParserBackend {
+  /// Builds a forest containing every completed constituent that can be
+  /// found for `input`, indexed by where it starts. Fails with
+  /// `GrammarError::UndefinedStart` if `ParseOptions::start` has no rules.
+  fn parse_forest(
+    &self,
+    g: &Grammar,
+    input: &[&str],
+    options: &ParseOptions,
+  ) -> Result<Forest, GrammarError>;
+}
+
+/// The default backend, see `earley.rs`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Earley;
+
+impl ParserBackend for Earley {
+  fn parse_forest(
+    &self,
+    g: &Grammar,
+    input: &[&str],
+    options: &ParseOptions,
+  ) -> Result<Forest, GrammarError> {
+    parse_chart_with(g, input, options).map(Forest::from)
+  }
+}
+
+#[test]
+fn test_custom_backend() {
+  use crate::forest::ForestState;
+
+  /// Only knows how to build `S -> x` over the first token
+  struct Lexical;
+
+  impl ParserBackend for Lexical {
+    fn parse_forest(
+      &self,
+      g: &Grammar,
+      input: &[&str],
+      _: &ParseOptions,
+    ) -> Result<Forest, GrammarError> {
+      let mut forest = Forest::new(input.len());
+      for rule in g.rules[&g.start].iter() {
+        if rule.len() == 1 && *rule.productions[0].symbol == *input[0] {
+          forest.push(ForestState::new(rule, 0, 1));
+        }
+      }
+      Ok(forest)
+    }
+  }
+
+  let g: Grammar = r#"
+    S -> x
+    S -> S S
+  "#
+  .parse()
+  .unwrap();
+
+  let options = ParseOptions::default();
+  let parse = |backend: &dyn ParserBackend, input: &[&str]| {
+    g.parse_with_backend(backend, input, &options)
+      .unwrap()
+      .len()
+  };
+  assert_eq!(parse(&Lexical, &["x"]), 1);
+  assert_eq!(parse(&Lexical, &["y"]), 0);
+  assert_eq!(parse(&Earley, &["x", "x"]), 1);
+}
+
+#[test]
+fn test_backend_undefined_start() {
+  let g: Grammar = "S -> NP\nNP -> x".parse().unwrap();
+  let options = |start: &str| ParseOptions {
+    start: Some(start.to_string()),
+    ..ParseOptions::default()
+  };
+  assert_eq!(
+    Earley
+      .parse_forest(&g, &["x"], &options("NP"))
+      .unwrap()
+      .len(),
+    1
+  );
+  assert!(matches!(
+    Earley.parse_forest(&g, &["x"], &options("Np")),
+    Err(GrammarError::UndefinedStart(s)) if s == "Np"
+  ));
+  assert!(g
+    .parse_with_backend(&Earley, &["x"], &options("Np"))
+    .is_err());
+}
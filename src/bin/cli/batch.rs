@@ -0,0 +1,174 @@
+//! Batch mode: parses every line of stdin, printing one row (or JSON object)
+//! per sentence instead of the REPL's trees.
+
+use std::io;
+use std::io::BufRead;
+
+use treebender::featurestructure::DisplayOptions;
+use treebender::report::ParseReport;
+use treebender::rules::Grammar;
+use treebender::{Err, NodeRef, ParseOptions, ParsedTree};
+
+use crate::progress::Reporter;
+use crate::select::Selection;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+  Csv,
+  Tsv,
+  /// One JSON object per line, printed as soon as each sentence is parsed
+  Jsonl,
+}
+
+pub struct BatchOptions {
+  pub format: Format,
+  /// Feature paths to print the values of, from the first selected tree
+  pub extract: Vec<String>,
+  pub expect: Option<usize>,
+  /// Which parses to print. The parse count is still the total.
+  pub selection: Selection,
+  /// Input lines to skip, to resume an interrupted run
+  pub skip: usize,
+  /// Show progress on stderr. This reads all of the input before starting.
+  pub progress: bool,
+}
+
+impl Format {
+  fn field(self, s: &str) -> String {
+    match self {
+      Self::Csv if s.contains([',', '"', '\n', '\r']) => format!("\"{}\"", s.replace('"', "\"\"")),
+      Self::Csv => s.to_string(),
+      // TSV has no quoting, so separators just become spaces
+      Self::Tsv => s.replace(['\t', '\n', '\r'], " "),
+      Self::Jsonl => unreachable!("JSON lines aren't rows"),
+    }
+  }
+
+  fn row(self, fields: &[String]) -> String {
+    let sep = match self {
+      Self::Tsv => "\t",
+      _ => ",",
+    };
+    fields
+      .iter()
+      .map(|f| self.field(f))
+      .collect::<Vec<_>>()
+      .join(sep)
+  }
+}
+
+/// The value at `path` as a single field: strings as themselves, anything
+/// else (like a whole structure) in its compact form, and nothing for **top**
+/// or a missing path
+fn extract(fs: &NodeRef, path: &str) -> String {
+  match fs.get_path(path) {
+    Some(node) if node.is_top() => String::new(),
+    Some(node) => match node.get_str() {
+      Some(s) => s.to_string(),
+      None => node
+        .display_with(&DisplayOptions {
+          compact: true,
+          ..DisplayOptions::default()
+        })
+        .to_string(),
+    },
+    None => String::new(),
+  }
+}
+
+/// The report in the `treebender::report` format, plus the input line
+fn json_line(line_no: usize, input: &str, report: &ParseReport) -> String {
+  let mut json = report.to_json();
+  json["line"] = line_no.into();
+  json["input"] = input.into();
+  json.to_string()
+}
+
+fn csv_row(
+  opts: &BatchOptions,
+  input: &str,
+  total: usize,
+  trees: &[ParsedTree],
+  ms: f64,
+) -> String {
+  let first = trees.first();
+  let mut row = vec![
+    input.to_string(),
+    total.to_string(),
+    first.map(|(t, _)| t.to_sexpr()).unwrap_or_default(),
+  ];
+  for path in opts.extract.iter() {
+    row.push(first.map(|(_, fs)| extract(fs, path)).unwrap_or_default());
+  }
+  row.push(format!("{:.3}", ms));
+  opts.format.row(&row)
+}
+
+/// Parses each non-blank line of stdin, and returns how many sentences didn't
+/// parse as expected (see `Repl::expect`)
+pub fn run(g: &Grammar, opts: &BatchOptions) -> Result<usize, Err> {
+  if opts.format != Format::Jsonl {
+    let mut header = vec![
+      "input".to_string(),
+      "parses".to_string(),
+      "tree".to_string(),
+    ];
+    header.extend(opts.extract.iter().cloned());
+    header.push("ms".to_string());
+    println!("{}", opts.format.row(&header));
+  }
+
+  // (line number, line) for each sentence, streamed unless we need the total
+  let lines = io::stdin()
+    .lock()
+    .lines()
+    .enumerate()
+    .skip(opts.skip)
+    .filter(|(_, line)| line.as_ref().map_or(true, |l| !l.trim().is_empty()));
+  let (lines, mut reporter): (Box<dyn Iterator<Item = _>>, _) = if opts.progress {
+    let lines = lines.collect::<Vec<_>>();
+    let reporter = Reporter::new(lines.len());
+    (Box::new(lines.into_iter()), Some(reporter))
+  } else {
+    (Box::new(lines), None)
+  };
+
+  let mut failures = 0;
+  for (idx, line) in lines {
+    let line = line?;
+    let input = line.trim();
+    let tokens = input.split_whitespace().collect::<Vec<_>>();
+    let (tokens, options) = g.fold_case(&tokens, &ParseOptions::default());
+
+    let mut report = ParseReport::new_with(g, &tokens, &options)?;
+    let total = report.parses;
+    let mut count = 0;
+    report.retain_trees(|trees| {
+      let (n, trees) = opts.selection.apply(trees, |t| t);
+      count = n;
+      trees
+    });
+    let ok = match opts.expect {
+      Some(n) => count == n,
+      None => count > 0,
+    };
+    if !ok {
+      failures += 1;
+    }
+
+    if opts.format == Format::Jsonl {
+      println!("{}", json_line(idx + 1, input, &report));
+    } else {
+      println!("{}", csv_row(opts, input, total, report.trees(), report.ms));
+    }
+
+    if let Some(reporter) = &mut reporter {
+      reporter.tick();
+    }
+  }
+
+  if let Some(reporter) = reporter {
+    reporter.finish();
+  }
+  Ok(failures)
+}
@@ -0,0 +1,151 @@
+//! `FILE fuzz [TESTFILE]`: looks for over-generation by generating random
+//! sentences that parse and asking whether they're actually grammatical.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use treebender::generate::{sentences, XorShiftRng};
+use treebender::rules::Grammar;
+use treebender::testsuite::{inline_tests, parse_test_file, TestCase};
+use treebender::Err;
+
+const MAX_DEPTH: usize = 12;
+const MAX_ATTEMPTS: usize = 1000;
+/// How many sentences we've already seen can be generated in a row before
+/// giving up, since a finite grammar may not have `count` different sentences
+const MAX_DUPLICATES: usize = 1000;
+
+pub struct FuzzOptions {
+  pub count: usize,
+  pub test_file: Option<String>,
+  pub report_file: Option<String>,
+  /// Seeds the sentence generator, to reproduce an earlier run. Defaults to
+  /// the time.
+  pub seed: Option<u64>,
+}
+
+/// Asks whether a sentence is grammatical. Returns None once there's no more
+/// input (or on q), after which sentences are only checked against the tests.
+fn ask(sentence: &str) -> io::Result<Option<bool>> {
+  loop {
+    print!("{}\n  ok? [y/n/q] ", sentence);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer)? == 0 {
+      println!();
+      return Ok(None);
+    }
+    match answer.trim() {
+      "y" | "Y" | "" => return Ok(Some(true)),
+      "n" | "N" => return Ok(Some(false)),
+      "q" | "Q" => return Ok(None),
+      _ => continue,
+    }
+  }
+}
+
+/// Up to `count` different random sentences that parse. Ends early once
+/// `MAX_DUPLICATES` sentences in a row were ones it had already generated.
+fn unique_sentences<'a>(
+  g: &'a Grammar,
+  rng: &'a mut XorShiftRng,
+  count: usize,
+) -> impl Iterator<Item = String> + 'a {
+  let mut seen = HashSet::new();
+  let mut generated = sentences(g, rng, MAX_DEPTH, MAX_ATTEMPTS);
+  std::iter::from_fn(move || {
+    if seen.len() == count {
+      return None;
+    }
+    for _ in 0..MAX_DUPLICATES {
+      let sentence = generated.next()?.join(" ");
+      if seen.insert(sentence.clone()) {
+        return Some(sentence);
+      }
+    }
+    None
+  })
+}
+
+/// Runs the fuzzer, and returns false if any sentence was flagged
+pub fn fuzz(g: &Grammar, grammar_file: &str, src: &str, opts: &FuzzOptions) -> Result<bool, Err> {
+  // known sentences, from the grammar's inline tests and the test file
+  let mut tests: Vec<(String, TestCase)> = inline_tests(src)
+    .into_iter()
+    .map(|t| (grammar_file.to_string(), t))
+    .collect();
+  if let Some(test_file) = &opts.test_file {
+    tests.extend(
+      parse_test_file(&fs::read_to_string(test_file)?)
+        .into_iter()
+        .map(|t| (test_file.clone(), t)),
+    );
+  }
+  let known = tests
+    .iter()
+    .map(|(file, t)| (t.tokens().join(" "), (file, t)))
+    .collect::<HashMap<_, _>>();
+
+  let seed = match opts.seed {
+    Some(seed) => seed,
+    None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as u64,
+  };
+  let mut rng = XorShiftRng::new(seed);
+
+  let mut asking = true;
+  let mut generated = 0;
+  let mut flagged: Vec<(String, String)> = Vec::new();
+
+  for sentence in unique_sentences(g, &mut rng, opts.count) {
+    generated += 1;
+    match known.get(&sentence) {
+      Some((file, test)) if !test.should_parse => {
+        println!(
+          "{}\n  flagged: negative test at {}:{}",
+          sentence, file, test.line
+        );
+        flagged.push((sentence, format!("negative test at {}:{}", file, test.line)));
+      }
+      Some(_) => println!("{}\n  ok: in tests", sentence),
+      None if asking => match ask(&sentence)? {
+        Some(true) => {}
+        Some(false) => flagged.push((sentence, "marked wrong".to_string())),
+        None => asking = false,
+      },
+      None => println!("{}", sentence),
+    }
+  }
+
+  let mut report = format!(
+    "// over-generation report: {} of {} generated sentences flagged\n// generated with --seed {}\n",
+    flagged.len(),
+    generated,
+    seed
+  );
+  for (sentence, reason) in flagged.iter() {
+    report += &format!("// {}\n*{}\n", reason, sentence);
+  }
+
+  println!("\n{}", report);
+  if let Some(report_file) = &opts.report_file {
+    fs::write(report_file, &report)?;
+  }
+
+  Ok(flagged.is_empty())
+}
+
+#[test]
+fn test_unique_sentences_of_finite_grammar() {
+  let g: Grammar = "S -> x\nS -> y".parse().unwrap();
+  let mut rng = XorShiftRng::new(1);
+  let mut found = unique_sentences(&g, &mut rng, 3).collect::<Vec<_>>();
+  found.sort();
+  assert_eq!(found, vec!["x", "y"]);
+
+  let mut rng = XorShiftRng::new(1);
+  assert_eq!(unique_sentences(&g, &mut rng, 1).count(), 1);
+}
@@ -0,0 +1,565 @@
+mod batch;
+mod fuzz;
+mod progress;
+mod repl;
+mod select;
+mod testrun;
+
+use std::env;
+use std::fs;
+use std::process;
+
+use treebender::featurestructure::DisplayOptions;
+use treebender::htmldoc;
+use treebender::rules::Grammar;
+use treebender::testsuite::inline_tests;
+use treebender::{Err, GrammarOptions};
+
+/// Exit codes, so scripts can tell what went wrong without reading the output
+const EXIT_GRAMMAR_ERROR: i32 = 1;
+const EXIT_PARSE_FAILED: i32 = 2;
+const EXIT_ARGUMENT_ERROR: i32 = 255;
+
+fn usage(prog_name: &str) -> String {
+  format!(
+    r"Usage: {0} FILE [MORE_FILES...] [options]
+       {0} FILE test TESTFILE [--watch]
+       {0} FILE fuzz [TESTFILE] [--count N] [--report FILE]
+       {0} FILE doc [OUTFILE]
+
+The first form starts a REPL that parses each sentence you type, with FILE's
+grammar until you switch to one of MORE_FILES with :use. The second
+runs a test file, with one sentence per line, and sentences that shouldn't
+parse starred (*like this). The third generates random sentences that parse,
+and asks whether each is actually grammatical (unless it's starred in TESTFILE
+or the grammar's `// test:` comments), then prints a report of the bad ones.
+The fourth writes an HTML reference for the grammar to OUTFILE (or stdout),
+with its rules and their doc comments, features and lexicon.
+
+Options:
+  -h, --help    Print this message
+  -v, -vv, -vvv Log what the parser is doing to stderr, with more detail for
+                more v's (needs the cli-tracing feature)
+  --strict      Fail to load a grammar that has warnings, instead of printing
+                them, for CI
+  --check       Print warnings about the grammar and run its `// test:`
+                comments, then exit instead of starting the REPL
+  -c, --chart   Print the parse chart a position at a time, as it's built
+                (defaults to not printing)
+  -n, --no-fs   Don't print feature structures (defaults to printing)
+  -r, --show-rejected
+                Also print the trees that failed unification, and where
+  -d, --derivation
+                Print the rules used to build each tree, with their lines in
+                FILE
+  --igt         Print each parse as interlinear glossed text, from the gloss
+                and morph features of its words (see treebender::igt)
+  --compact     Print feature structures on one line
+  --width N     Print parts of feature structures on one line when they fit
+                in N columns
+  --hide PATH   Don't print features matching PATH, like child-*.word (can be
+                repeated)
+  --filter QUERY
+                Only print parses matching QUERY, like child-1.tense=past or
+                'child-0.num=#x & child-1.num=#x' (can be repeated)
+  --tree N      Only print the Nth parse (from 1) that matches the filters
+  --transcript FILE
+                Write the session's inputs and output to FILE
+  --expect N    Expect every sentence to have N parses matching the filters
+                (by default, at least one)
+  --csv, --tsv  Parse each line of stdin and print a table with the input,
+                number of parses, first tree, extracted features (see
+                --extract) and parse time in milliseconds, instead of starting
+                the REPL
+  --json, --jsonl
+                Like --csv, but print a JSON object per sentence, with every
+                tree (as an S-expression and as JSON), its features, and why
+                the sentence was rejected if it was, as soon as it's parsed.
+                See treebender::report for the format.
+  --skip N      With --csv, --tsv or --jsonl, skip the first N lines of input,
+                to resume an interrupted run
+  --progress    With --csv, --tsv or --jsonl, show progress on stderr. The
+                whole input is read before parsing starts.
+  --extract PATH
+                With --csv or --tsv, add a column with the value at PATH, like
+                child-0.num, in the first parse (can be repeated)
+  --watch       With test, re-run the tests whenever FILE or TESTFILE changes,
+                printing which tests started or stopped passing
+  --count N     With fuzz, how many sentences to generate (defaults to 20)
+  --report FILE With fuzz, also write the report to FILE
+  --seed N      With fuzz, seed the sentence generator with N, to generate the
+                same sentences as an earlier run (the report says which seed
+                it used)
+
+Exit status is 0 if every sentence parsed as expected, {1} if the grammar
+failed to load, {2} if some sentence didn't parse as expected, and {3} for bad
+arguments.
+
+Type :help at the prompt for REPL commands",
+    prog_name, EXIT_GRAMMAR_ERROR, EXIT_PARSE_FAILED, EXIT_ARGUMENT_ERROR
+  )
+}
+
+struct Args {
+  filename: String,
+  /// More grammars for the REPL to load
+  more_files: Vec<String>,
+  print_fs: bool,
+  print_chart: bool,
+  print_derivation: bool,
+  print_igt: bool,
+  print_rejected: bool,
+  display: DisplayOptions,
+  transcript: Option<String>,
+  expect: Option<usize>,
+  check: bool,
+  grammar_options: GrammarOptions,
+  test_file: Option<String>,
+  watch: bool,
+  fuzz: Option<fuzz::FuzzOptions>,
+  /// Set for doc, with the file to write to, if any
+  doc: Option<Option<String>>,
+  batch_format: Option<batch::Format>,
+  extract: Vec<String>,
+  skip: usize,
+  selection: select::Selection,
+  verbosity: usize,
+  progress: bool,
+}
+
+impl Args {
+  fn make_error_message(msg: &str, prog_name: impl AsRef<str>) -> String {
+    format!("argument error: {}.\n\n{}", msg, usage(prog_name.as_ref()))
+  }
+
+  fn parse(v: Vec<String>) -> Result<Self, String> {
+    if v.is_empty() {
+      return Err(Self::make_error_message(
+        "bad argument vector",
+        "treebender",
+      ));
+    }
+
+    let args_len = v.len();
+    let mut iter = v.into_iter();
+    let prog_name = iter.next().unwrap();
+
+    if args_len < 2 {
+      return Err(Self::make_error_message("not enough arguments", prog_name));
+    }
+
+    let mut filename: Option<String> = None;
+    let mut more_files = Vec::new();
+    let mut print_fs = true; // default to printing feature structures
+    let mut print_chart = false; // default to *not* printing the chart
+    let mut print_derivation = false;
+    let mut print_igt = false;
+    let mut print_rejected = false;
+    let mut display = DisplayOptions::default();
+    let mut transcript = None;
+    let mut expect = None;
+    let mut check = false;
+    let mut grammar_options = GrammarOptions::default();
+    let mut test_file = None;
+    let mut watch = false;
+    let mut fuzz: Option<fuzz::FuzzOptions> = None;
+    let mut doc: Option<Option<String>> = None;
+    let mut count = 20;
+    let mut report_file = None;
+    let mut seed = None;
+    let mut batch_format = None;
+    let mut extract = Vec::new();
+    let mut skip = 0;
+    let mut selection = select::Selection::default();
+    let mut verbosity = 0;
+    let mut progress = false;
+
+    while let Some(o) = iter.next() {
+      if o == "-h" || o == "--help" {
+        println!("{}", usage(&prog_name));
+        process::exit(0);
+      } else if o.len() > 1 && o.starts_with('-') && o[1..].chars().all(|c| c == 'v') {
+        verbosity += o.len() - 1;
+      } else if o == "--check" {
+        check = true;
+      } else if o == "--strict" {
+        grammar_options.strict = true;
+      } else if o == "--watch" {
+        watch = true;
+      } else if o == "--csv" {
+        batch_format = Some(batch::Format::Csv);
+      } else if o == "--tsv" {
+        batch_format = Some(batch::Format::Tsv);
+      } else if o == "--filter" {
+        match iter.next().as_deref().map(select::Selection::parse_filter) {
+          Some(Ok(filter)) => selection.filters.push(filter),
+          Some(Err(e)) => return Err(Self::make_error_message(&e, prog_name)),
+          None => {
+            return Err(Self::make_error_message(
+              "--filter needs a query, like PATH=VALUE",
+              prog_name,
+            ))
+          }
+        }
+      } else if o == "--tree" {
+        match iter.next().and_then(|n| n.parse().ok()) {
+          Some(n) if n > 0 => selection.index = Some(n),
+          _ => {
+            return Err(Self::make_error_message(
+              "--tree needs a number, from 1",
+              prog_name,
+            ))
+          }
+        }
+      } else if o == "--progress" {
+        progress = true;
+      } else if o == "--jsonl" || o == "--json" {
+        batch_format = Some(batch::Format::Jsonl);
+      } else if o == "--skip" {
+        match iter.next().and_then(|n| n.parse().ok()) {
+          Some(n) => skip = n,
+          None => return Err(Self::make_error_message("--skip needs a number", prog_name)),
+        }
+      } else if o == "--extract" {
+        match iter.next() {
+          Some(path) => extract.push(path),
+          None => {
+            return Err(Self::make_error_message(
+              "--extract needs a path",
+              prog_name,
+            ))
+          }
+        }
+      } else if o == "--count" {
+        match iter.next().and_then(|n| n.parse().ok()) {
+          Some(n) => count = n,
+          None => {
+            return Err(Self::make_error_message(
+              "--count needs a number",
+              prog_name,
+            ))
+          }
+        }
+      } else if o == "--report" {
+        match iter.next() {
+          Some(path) => report_file = Some(path),
+          None => {
+            return Err(Self::make_error_message(
+              "--report needs a filename",
+              prog_name,
+            ))
+          }
+        }
+      } else if o == "--seed" {
+        match iter.next().and_then(|n| n.parse().ok()) {
+          Some(n) => seed = Some(n),
+          None => return Err(Self::make_error_message("--seed needs a number", prog_name)),
+        }
+      } else if o == "fuzz" && filename.is_some() && fuzz.is_none() {
+        fuzz = Some(fuzz::FuzzOptions {
+          count: 0,
+          test_file: None,
+          report_file: None,
+          seed: None,
+        });
+      } else if fuzz.as_ref().is_some_and(|f| f.test_file.is_none()) && !o.starts_with('-') {
+        fuzz.as_mut().unwrap().test_file = Some(o);
+      } else if o == "doc" && filename.is_some() && doc.is_none() {
+        doc = Some(None);
+      } else if doc == Some(None) && !o.starts_with('-') {
+        doc = Some(Some(o));
+      } else if o == "test" && filename.is_some() && test_file.is_none() {
+        match iter.next() {
+          Some(path) => test_file = Some(path),
+          None => return Err(Self::make_error_message("test needs a file", prog_name)),
+        }
+      } else if o == "-n" || o == "--no-fs" {
+        print_fs = false;
+      } else if o == "-r" || o == "--show-rejected" {
+        print_rejected = true;
+      } else if o == "-d" || o == "--derivation" {
+        print_derivation = true;
+      } else if o == "--igt" {
+        print_igt = true;
+      } else if o == "-c" || o == "--chart" {
+        print_chart = true;
+      } else if o == "--compact" {
+        display.compact = true;
+      } else if o == "--width" {
+        let width = iter.next().and_then(|w| w.parse().ok());
+        if width.is_none() {
+          return Err(Self::make_error_message(
+            "--width needs a number",
+            prog_name,
+          ));
+        }
+        display.max_width = width;
+      } else if o == "--hide" {
+        match iter.next() {
+          Some(path) => display.hidden_paths.push(path),
+          None => return Err(Self::make_error_message("--hide needs a path", prog_name)),
+        }
+      } else if o == "--transcript" {
+        match iter.next() {
+          Some(path) => transcript = Some(path),
+          None => {
+            return Err(Self::make_error_message(
+              "--transcript needs a filename",
+              prog_name,
+            ))
+          }
+        }
+      } else if o == "--expect" {
+        expect = iter.next().and_then(|n| n.parse().ok());
+        if expect.is_none() {
+          return Err(Self::make_error_message(
+            "--expect needs a number",
+            prog_name,
+          ));
+        }
+      } else if filename.is_none() {
+        filename = Some(o);
+      } else if !o.starts_with('-') {
+        more_files.push(o);
+      } else {
+        return Err(Self::make_error_message("invalid arguments", prog_name));
+      }
+    }
+
+    let repl_only = test_file.is_some() || fuzz.is_some() || doc.is_some();
+    if !more_files.is_empty() && (repl_only || check || batch_format.is_some()) {
+      return Err(Self::make_error_message(
+        "only the REPL can load more than one grammar",
+        prog_name,
+      ));
+    }
+
+    if watch && test_file.is_none() {
+      return Err(Self::make_error_message(
+        "--watch only works with test",
+        prog_name,
+      ));
+    }
+
+    if let Some(fuzz) = &mut fuzz {
+      fuzz.count = count;
+      fuzz.report_file = report_file;
+      fuzz.seed = seed;
+    }
+
+    if let Some(filename) = filename {
+      Ok(Self {
+        filename,
+        more_files,
+        print_fs,
+        print_chart,
+        print_derivation,
+        print_igt,
+        print_rejected,
+        display,
+        transcript,
+        expect,
+        check,
+        grammar_options,
+        test_file,
+        watch,
+        fuzz,
+        doc,
+        batch_format,
+        extract,
+        skip,
+        selection,
+        verbosity,
+        progress,
+      })
+    } else {
+      Err(Self::make_error_message("missing filename", prog_name))
+    }
+  }
+}
+
+/// Reads a grammar file, returning its source along with the grammar
+fn load_grammar(filename: &str, options: &GrammarOptions) -> Result<(String, Grammar), Err> {
+  let src = fs::read_to_string(filename)?;
+  let options = GrammarOptions {
+    file: Some(filename.to_string()),
+    ..options.clone()
+  };
+  let g = Grammar::from_str_with(&src, &options)?;
+  Ok((src, g))
+}
+
+/// What the REPL calls a grammar file: its name without the directory or
+/// extension
+fn grammar_name(filename: &str) -> String {
+  std::path::Path::new(filename).file_stem().map_or_else(
+    || filename.to_string(),
+    |s| s.to_string_lossy().into_owned(),
+  )
+}
+
+/// Prints lints and runs the inline tests for `--check`. Returns false if any
+/// test failed.
+fn check(filename: &str, g: &Grammar, src: &str) -> bool {
+  let lints = g.warnings();
+  for l in lints.iter() {
+    println!("{}: {}", filename, l);
+  }
+
+  let tests = inline_tests(src);
+  let mut failed = 0;
+  for test in tests.iter() {
+    if !test.passes(g) {
+      failed += 1;
+      let expected = if test.should_parse {
+        "should parse"
+      } else {
+        "shouldn't parse"
+      };
+      println!(
+        "{}:{}: test failed: {} ({})",
+        filename, test.line, test, expected
+      );
+    }
+  }
+
+  println!(
+    "{} warning{}, {} of {} test{} passed",
+    lints.len(),
+    if lints.len() == 1 { "" } else { "s" },
+    tests.len() - failed,
+    tests.len(),
+    if tests.len() == 1 { "" } else { "s" },
+  );
+  failed == 0
+}
+
+#[cfg(feature = "cli-tracing")]
+fn init_tracing(verbosity: usize) {
+  let level = match verbosity {
+    1 => tracing::Level::INFO,
+    2 => tracing::Level::DEBUG,
+    _ => tracing::Level::TRACE,
+  };
+  tracing_subscriber::fmt()
+    .with_max_level(level)
+    .with_writer(std::io::stderr)
+    .init();
+}
+
+#[cfg(not(feature = "cli-tracing"))]
+fn init_tracing(_verbosity: usize) {
+  eprintln!("warning: -v does nothing, as this was built without the cli-tracing feature");
+}
+
+fn main() -> Result<(), Err> {
+  let opts = match Args::parse(env::args().collect()) {
+    Ok(opts) => opts,
+    Err(msg) => {
+      eprintln!("{}", msg);
+      process::exit(EXIT_ARGUMENT_ERROR);
+    }
+  };
+
+  if opts.verbosity > 0 {
+    init_tracing(opts.verbosity);
+  }
+
+  if let Some(test_file) = &opts.test_file {
+    if opts.watch {
+      testrun::watch(&opts.filename, test_file, &opts.grammar_options);
+    }
+    match testrun::run_tests(&opts.filename, test_file, &opts.grammar_options) {
+      Ok(true) => return Ok(()),
+      Ok(false) => process::exit(EXIT_PARSE_FAILED),
+      Err(e) => {
+        eprintln!("error: {}", e);
+        process::exit(EXIT_GRAMMAR_ERROR);
+      }
+    }
+  }
+
+  let (src, g) = match load_grammar(&opts.filename, &opts.grammar_options) {
+    Ok(loaded) => loaded,
+    Err(e) => {
+      eprintln!("error loading {}: {}", opts.filename, e);
+      process::exit(EXIT_GRAMMAR_ERROR);
+    }
+  };
+
+  if let Some(output) = &opts.doc {
+    let html = htmldoc::render(&g, &src);
+    match output {
+      Some(path) => fs::write(path, html)?,
+      None => print!("{}", html),
+    }
+    return Ok(());
+  }
+
+  if let Some(fuzz_opts) = &opts.fuzz {
+    if !fuzz::fuzz(&g, &opts.filename, &src, fuzz_opts)? {
+      process::exit(EXIT_PARSE_FAILED);
+    }
+    return Ok(());
+  }
+
+  if let Some(format) = opts.batch_format {
+    let batch_opts = batch::BatchOptions {
+      format,
+      extract: opts.extract,
+      expect: opts.expect,
+      skip: opts.skip,
+      selection: opts.selection,
+      progress: opts.progress,
+    };
+    if batch::run(&g, &batch_opts)? > 0 {
+      process::exit(EXIT_PARSE_FAILED);
+    }
+    return Ok(());
+  }
+
+  if opts.check {
+    if !check(&opts.filename, &g, &src) {
+      process::exit(EXIT_PARSE_FAILED);
+    }
+    return Ok(());
+  }
+
+  // warnings don't stop the REPL, but it's easy to miss a typo otherwise
+  for warning in g.warnings() {
+    eprintln!("{}: {}", opts.filename, warning);
+  }
+  let mut repl = repl::Repl::new(
+    grammar_name(&opts.filename),
+    g,
+    opts.print_chart,
+    opts.print_fs,
+    opts.display,
+  );
+  for filename in opts.more_files.iter() {
+    let loaded = load_grammar(filename, &opts.grammar_options).and_then(|(_, g)| {
+      for warning in g.warnings() {
+        eprintln!("{}: {}", filename, warning);
+      }
+      repl.add_grammar(grammar_name(filename), g)
+    });
+    if let Err(e) = loaded {
+      eprintln!("error loading {}: {}", filename, e);
+      process::exit(EXIT_GRAMMAR_ERROR);
+    }
+  }
+  repl.print_derivation = opts.print_derivation;
+  repl.print_igt = opts.print_igt;
+  repl.print_rejected = opts.print_rejected;
+  repl.expect = opts.expect;
+  repl.selection = opts.selection;
+  if let Some(transcript) = &opts.transcript {
+    repl.start_transcript(transcript)?;
+  }
+  repl.run()?;
+
+  if repl.failures > 0 {
+    process::exit(EXIT_PARSE_FAILED);
+  }
+  Ok(())
+}
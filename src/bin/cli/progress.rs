@@ -0,0 +1,78 @@
+//! `--progress`: shows how far through its input batch mode is, on stderr.
+//! Uses an indicatif progress bar when built with the `progress` feature, and a
+//! plain status line otherwise.
+
+use std::time::Instant;
+
+use treebender::Progress;
+
+pub struct Reporter {
+  progress: Progress,
+  start: Instant,
+  #[cfg(feature = "progress")]
+  bar: indicatif::ProgressBar,
+  #[cfg(not(feature = "progress"))]
+  last_draw: Option<Instant>,
+}
+
+impl Reporter {
+  pub fn new(total: usize) -> Self {
+    Self {
+      progress: Progress {
+        done: 0,
+        total,
+        elapsed: Default::default(),
+      },
+      start: Instant::now(),
+      #[cfg(feature = "progress")]
+      bar: {
+        let bar = indicatif::ProgressBar::new(total as u64);
+        bar.set_style(
+          indicatif::ProgressStyle::with_template(
+            "{wide_bar} {pos}/{len} sentences, {per_sec}, ETA {eta}",
+          )
+          .expect("progress template is valid"),
+        );
+        bar
+      },
+      #[cfg(not(feature = "progress"))]
+      last_draw: None,
+    }
+  }
+
+  /// Records that another sentence is done
+  pub fn tick(&mut self) {
+    self.progress.done += 1;
+    self.progress.elapsed = self.start.elapsed();
+    self.draw();
+  }
+
+  #[cfg(feature = "progress")]
+  fn draw(&mut self) {
+    self.bar.set_position(self.progress.done as u64);
+  }
+
+  #[cfg(not(feature = "progress"))]
+  fn draw(&mut self) {
+    // redrawing for every sentence would slow down fast grammars
+    let now = Instant::now();
+    if self.progress.is_finished()
+      || self
+        .last_draw
+        .is_none_or(|last| now.duration_since(last).as_millis() >= 100)
+    {
+      self.last_draw = Some(now);
+      eprint!("\r{}\x1b[K", self.progress);
+    }
+  }
+
+  #[cfg(feature = "progress")]
+  pub fn finish(self) {
+    self.bar.finish();
+  }
+
+  #[cfg(not(feature = "progress"))]
+  pub fn finish(self) {
+    eprintln!();
+  }
+}
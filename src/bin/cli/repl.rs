@@ -0,0 +1,646 @@
+//! The interactive loop: reading (possibly multi-line) input, running
+//! `:commands`, and echoing everything to an optional transcript file.
+
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::{Arc, Mutex};
+
+use treebender::earley::{format_position, parse_chart_with, State};
+use treebender::featurestructure::DisplayOptions;
+use treebender::generate::inflect;
+use treebender::igt::Igt;
+use treebender::minpair::minimal_pairs_with;
+use treebender::rules::{Grammar, Rule};
+use treebender::{Err, ParseObserver, ParseOptions, Restrictor};
+
+use crate::select::Selection;
+
+const HELP: &str = r#":add RULE         Add a rule to the grammar, like :add S -> N V
+:remove RULE      Remove a rule from the grammar
+:rules            Print the grammar's rules
+:start SYMBOL     Parse sentences as SYMBOL, like NP, instead of the grammar's
+                  start symbol
+:pairs SENTENCE   Swap each word of SENTENCE for others of its category, and
+                  print the variants that don't parse, and why
+:inflect FEATURES Print the words in the lexicon with FEATURES, like
+                  :inflect [ lex: go, tense: past ]
+:whyno SYMBOL START END SENTENCE
+                  Print which rules for SYMBOL tried to cover START..END of
+                  SENTENCE, and where each got stuck
+:grammars         List the loaded grammars, marking the one in use
+:use NAME         Parse with the grammar NAME (its file name, without .fgr)
+:source FILE      Replay the inputs in FILE as if they were typed
+:transcript FILE  Write this session's inputs and output to FILE
+:transcript off   Stop writing the transcript
+:help             Print this message
+
+End a line with \ to continue it on the next line, or start a sentence with "
+and it'll continue until the closing ". Start a sentence with @NAME to parse it
+with another grammar just this once, or with @* to parse it with all of them."#;
+
+/// Prints each position of the chart as soon as it's finished, keeping what
+/// it printed for the transcript
+struct ChartPrinter {
+  input: Vec<String>,
+  printed: Mutex<String>,
+}
+
+impl ParseObserver for ChartPrinter {
+  fn position_finished(&self, k: usize, states: &[State]) {
+    let input = self.input.iter().map(String::as_str).collect::<Vec<_>>();
+    let mut text = format_position(k, states, &input);
+    text += "\n";
+    print!("{}", text);
+    let _ = io::stdout().flush();
+    self.printed.lock().unwrap().push_str(&text);
+  }
+}
+
+pub struct Repl {
+  /// The loaded grammars, by name. :add and :remove change the current one.
+  pub grammars: Vec<(String, Grammar)>,
+  /// The index of the grammar sentences are parsed with
+  pub current: usize,
+  pub print_chart: bool,
+  pub print_fs: bool,
+  /// Print the rules used to build each tree
+  pub print_derivation: bool,
+  /// Print each tree as interlinear glossed text
+  pub print_igt: bool,
+  /// Print the trees that failed unification, and why
+  pub print_rejected: bool,
+  pub display: DisplayOptions,
+  /// How many parses each sentence should have. When None, sentences should
+  /// have at least one.
+  pub expect: Option<usize>,
+  /// Which parses to print
+  pub selection: Selection,
+  /// The number of sentences that didn't parse as expected
+  pub failures: usize,
+  transcript: Option<File>,
+}
+
+impl Repl {
+  pub fn new(
+    name: String,
+    g: Grammar,
+    print_chart: bool,
+    print_fs: bool,
+    display: DisplayOptions,
+  ) -> Self {
+    Self {
+      grammars: vec![(name, g)],
+      current: 0,
+      print_chart,
+      print_fs,
+      print_derivation: false,
+      print_igt: false,
+      print_rejected: false,
+      display,
+      expect: None,
+      selection: Selection::default(),
+      failures: 0,
+      transcript: None,
+    }
+  }
+
+  /// Adds another grammar, which `:use` can switch to
+  pub fn add_grammar(&mut self, name: String, g: Grammar) -> Result<(), Err> {
+    if self.find_grammar(&name).is_some() {
+      return Err(format!("there's already a grammar named {}", name).into());
+    }
+    self.grammars.push((name, g));
+    Ok(())
+  }
+
+  fn find_grammar(&self, name: &str) -> Option<usize> {
+    self.grammars.iter().position(|(n, _)| n == name)
+  }
+
+  /// Which grammars to parse an input with, and the sentence without any
+  /// `@NAME` prefix
+  fn route<'a>(&self, input: &'a str) -> Result<(Vec<usize>, &'a str), Err> {
+    let Some(prefixed) = input.strip_prefix('@') else {
+      return Ok((vec![self.current], input));
+    };
+    let (name, sentence) = prefixed.split_once(' ').unwrap_or((prefixed, ""));
+    let sentence = sentence.trim_start();
+    if name == "*" {
+      return Ok(((0..self.grammars.len()).collect(), sentence));
+    }
+    match self.find_grammar(name) {
+      Some(idx) => Ok((vec![idx], sentence)),
+      None => Err(format!("no grammar named {} (try :grammars)", name).into()),
+    }
+  }
+
+  /// Starts writing the session to `filename`, replacing any current transcript
+  pub fn start_transcript(&mut self, filename: &str) -> Result<(), Err> {
+    self.transcript = Some(File::create(filename)?);
+    Ok(())
+  }
+
+  /// Prints `s`, and writes it to the transcript if there is one
+  fn emit(&mut self, s: &str) -> Result<(), Err> {
+    print!("{}", s);
+    if let Some(t) = &mut self.transcript {
+      t.write_all(s.as_bytes())?;
+    }
+    Ok(())
+  }
+
+  /// Writes `s` to the transcript only, for output that's already been printed
+  fn record_output(&mut self, s: &str) -> Result<(), Err> {
+    if let Some(t) = &mut self.transcript {
+      t.write_all(s.as_bytes())?;
+    }
+    Ok(())
+  }
+
+  /// Records an input in the transcript, the way it would look on a terminal
+  fn record_input(&mut self, input: &str) -> Result<(), Err> {
+    if let Some(t) = &mut self.transcript {
+      writeln!(t, "> {}", input)?;
+    }
+    Ok(())
+  }
+
+  /// Reads and handles inputs from stdin until EOF
+  pub fn run(&mut self) -> Result<(), Err> {
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+    while let Some(input) = read_input(&mut stdin, true)? {
+      self.record_input(&input)?;
+      self.handle(&input)?;
+    }
+    Ok(())
+  }
+
+  /// Replays the inputs in a script file, echoing each one
+  pub fn source(&mut self, filename: &str) -> Result<(), Err> {
+    let mut reader = BufReader::new(File::open(filename)?);
+    while let Some(input) = read_input(&mut reader, false)? {
+      if input.is_empty() || input.starts_with("//") {
+        continue;
+      }
+      println!("> {}", input);
+      self.record_input(&input)?;
+      self.handle(&input)?;
+    }
+    Ok(())
+  }
+
+  /// Handles a single logical input line, either a `:command` or a sentence
+  pub fn handle(&mut self, input: &str) -> Result<(), Err> {
+    if input.trim().is_empty() {
+      // nothing to parse, and not a failed parse either
+      return Ok(());
+    }
+    if let Some(command) = input.strip_prefix(':') {
+      let (name, arg) = match command.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (command, ""),
+      };
+      // errors from commands are reported, but shouldn't end the session
+      if let Err(e) = self.command(name, arg) {
+        self.emit(&format!("error: {}\n", e))?;
+      }
+      Ok(())
+    } else {
+      let (grammars, sentence) = match self.route(input) {
+        Ok(routed) => routed,
+        Err(e) => return self.emit(&format!("error: {}\n", e)),
+      };
+      let several = grammars.len() > 1;
+      for idx in grammars {
+        if several {
+          self.emit(&format!("[{}]\n", self.grammars[idx].0))?;
+        }
+        if self.print_chart {
+          self.print_chart(idx, &sentence.to_lowercase())?;
+        }
+        let (output, count) = self.parse(&self.grammars[idx].1, sentence);
+        self.emit(&output)?;
+        match self.expect {
+          Some(n) if n != count => {
+            self.failures += 1;
+            self.emit(&format!("error: expected {} parses, got {}\n", n, count))?;
+          }
+          None if count == 0 => self.failures += 1,
+          _ => {}
+        }
+      }
+      Ok(())
+    }
+  }
+
+  fn command(&mut self, name: &str, arg: &str) -> Result<(), Err> {
+    match (name, arg) {
+      ("help", _) => self.emit(&format!("{}\n", HELP)),
+      ("add", rule) => {
+        let rule = self.grammars[self.current].1.add_rule(rule.parse()?)?;
+        self.emit(&format!("added rule #{}: {}\n", rule.index, rule))
+      }
+      ("remove", rule) => {
+        let rule = self.grammars[self.current].1.remove_rule(&rule.parse()?)?;
+        self.emit(&format!("removed rule #{}: {}\n", rule.index, rule))
+      }
+      ("rules", _) => {
+        let rules = self.grammars[self.current]
+          .1
+          .rules_in_order()
+          .iter()
+          .map(|r| format!("#{}: {}\n", r.index, r))
+          .collect::<String>();
+        self.emit(&rules)
+      }
+      ("start", "") => {
+        let start = self.grammars[self.current].1.start.clone();
+        self.emit(&format!("parsing as {}\n", start))
+      }
+      ("start", symbol) => {
+        let g = self.grammars[self.current].1.with_start(symbol)?;
+        self.grammars[self.current].1 = g;
+        self.emit(&format!("parsing as {}\n", symbol))
+      }
+      ("grammars", _) => {
+        let list = self
+          .grammars
+          .iter()
+          .enumerate()
+          .map(|(idx, (name, _))| {
+            let marker = if idx == self.current { '*' } else { ' ' };
+            format!("{} {}\n", marker, name)
+          })
+          .collect::<String>();
+        self.emit(&list)
+      }
+      ("use", "") => Err(":use needs a grammar name (try :grammars)".into()),
+      ("use", name) => {
+        self.current = self
+          .find_grammar(name)
+          .ok_or_else(|| format!("no grammar named {} (try :grammars)", name))?;
+        self.emit(&format!("using {}\n", name))
+      }
+      ("pairs", "") => Err(":pairs needs a sentence".into()),
+      ("pairs", sentence) => {
+        let g = &self.grammars[self.current].1;
+        let sentence = sentence.split(' ').collect::<Vec<_>>();
+        let (sentence, options) = g.fold_case(&sentence, &ParseOptions::default());
+        let pairs = minimal_pairs_with(g, &sentence, &options)?;
+        let mut out = String::new();
+        for pair in pairs.iter().filter(|p| p.is_blocked()) {
+          out += &format!(
+            "*{} ({} -> {})\n{}\n",
+            pair.sentence.join(" "),
+            pair.original,
+            pair.replacement,
+            pair.judgment
+          );
+        }
+        out += &format!(
+          "{} of {} variant{} blocked\n",
+          pairs.iter().filter(|p| p.is_blocked()).count(),
+          pairs.len(),
+          if pairs.len() == 1 { "" } else { "s" }
+        );
+        self.emit(&out)
+      }
+      ("inflect", "") => Err(":inflect needs features, like [ lex: go, tense: past ]".into()),
+      ("inflect", fs) => {
+        let forms = inflect(&self.grammars[self.current].1, &fs.parse()?);
+        if forms.is_empty() {
+          self.emit("no words with those features\n")
+        } else {
+          self.emit(&format!("{}\n", forms.join("\n")))
+        }
+      }
+      ("whyno", args) => {
+        let usage = ":whyno needs a symbol, a start, an end, and a sentence";
+        let mut args = args.splitn(4, ' ');
+        let (Some(symbol), Some(start), Some(end), Some(sentence)) =
+          (args.next(), args.next(), args.next(), args.next())
+        else {
+          return Err(usage.into());
+        };
+        let (start, end) = (start.parse()?, end.parse()?);
+        let g = &self.grammars[self.current].1;
+        // only the chart is looked at, which doesn't depend on capitalization
+        let (sentence, _) = g.fold_case(
+          &sentence.split(' ').collect::<Vec<_>>(),
+          &ParseOptions::default(),
+        );
+        let why = g.why_no(&sentence, symbol, start, end);
+        self.emit(&format!("{}\n", why))
+      }
+      ("source", "") => Err(":source needs a filename".into()),
+      ("source", filename) => self.source(filename),
+      ("transcript", "") => Err(":transcript needs a filename, or off".into()),
+      ("transcript", "off") => {
+        self.transcript = None;
+        Ok(())
+      }
+      ("transcript", filename) => self.start_transcript(filename),
+      _ => Err(format!("unknown command :{} (try :help)", name).into()),
+    }
+  }
+
+  /// Prints the chart for a sentence a position at a time, as the parser
+  /// finishes each, so long sentences show progress
+  fn print_chart(&mut self, idx: usize, sentence: &str) -> Result<(), Err> {
+    let input = sentence.split(' ').collect::<Vec<_>>();
+    let printer = Arc::new(ChartPrinter {
+      input: input.iter().map(|s| s.to_string()).collect(),
+      printed: Mutex::new(String::new()),
+    });
+    let options = ParseOptions {
+      observer: Some(printer.clone()),
+      ..ParseOptions::default()
+    };
+    self.emit("chart:\n")?;
+    parse_chart_with(&self.grammars[idx].1, &input, &options)?;
+    let printed = printer.printed.lock().unwrap().clone();
+    self.record_output(&printed)
+  }
+
+  /// Returns the output for a sentence, and how many parses it had that
+  /// matched the selection
+  /// Parses `sentence` case-insensitively, giving words a `cap` feature for
+  /// how they were written
+  fn parse(&self, g: &Grammar, sentence: &str) -> (String, usize) {
+    let (words, options) = g.fold_case(
+      &sentence.split(' ').collect::<Vec<_>>(),
+      &ParseOptions::default(),
+    );
+    let sentence = words.iter().map(String::as_str).collect::<Vec<_>>();
+    let mut out = String::new();
+
+    // the same as Grammar::parse, but keeping the rules of surviving trees, and
+    // the rejected trees
+    let mut trees = Vec::new();
+    let mut rejected = Vec::new();
+    for t in g.parse_forest(&sentence).iter_trees(g) {
+      let rules = t.derivation().into_iter().cloned().collect::<Vec<_>>();
+      let bare = t.map(|c| c.value.symbol.clone(), |w| w.value.clone());
+      match Grammar::unify_tree_with(t, &options) {
+        Ok(parsed) => trees.push((parsed, rules)),
+        Err(e) => rejected.push((bare, e)),
+      }
+    }
+    let total = trees.len();
+    let parsed = trees.iter().map(|(t, _)| t.clone()).collect::<Vec<_>>();
+    let readings = g.count_readings(&parsed, &Restrictor::default());
+    let (count, trees) = self.selection.apply(trees, |(t, _)| t);
+
+    out += &format!("Parsed {} tree{}", total, if total == 1 { "" } else { "s" });
+    if readings < total {
+      out += &format!(
+        " ({} reading{})",
+        readings,
+        if readings == 1 { "" } else { "s" }
+      );
+    }
+    if !self.selection.filters.is_empty() {
+      out += &format!(", {} matching", count);
+    }
+    if self.selection.index.is_some() {
+      out += &format!(", showing {}", trees.len());
+    }
+    out += "\n";
+    if total == 0 {
+      if let Some(stuck) = g.stuck(&sentence) {
+        out += &format!("{}\n", stuck);
+      }
+      if let Some(correction) = g.corrections(&sentence, 1).first() {
+        out += &format!("{}\n", correction);
+      }
+    }
+
+    for (parsed, rules) in trees {
+      let (t, fs) = &parsed;
+      out += &format!("{}\n", t);
+      if self.print_derivation {
+        out += &format_derivation(&rules);
+      }
+      if self.print_igt {
+        out += &Igt::from(&parsed).to_string();
+      }
+      if self.print_fs {
+        out += &format!("{}\n", fs.display_with(&self.display));
+      }
+      out += "\n";
+    }
+
+    if self.print_rejected && !rejected.is_empty() {
+      out += &format!(
+        "Rejected {} tree{}\n",
+        rejected.len(),
+        if rejected.len() == 1 { "" } else { "s" }
+      );
+      for (t, e) in rejected {
+        out += &format!("{}\nrejected: {}\n\n", t, e);
+      }
+    }
+
+    (out, count)
+  }
+}
+
+fn format_derivation(rules: &[Arc<Rule>]) -> String {
+  let mut out = String::from("derivation:\n");
+  for rule in rules {
+    out += &format!(
+      "  {:<30} {}\n",
+      rule.skeleton(),
+      rule.location().unwrap_or_default()
+    );
+  }
+  out
+}
+
+/// Reads one logical input, joining lines that end with a backslash, or
+/// everything between an opening `"` and a closing `"`, with single spaces.
+/// Returns None at EOF.
+pub fn read_input(reader: &mut impl BufRead, prompt: bool) -> io::Result<Option<String>> {
+  let mut parts: Vec<String> = Vec::new();
+  let mut quoted = false;
+
+  loop {
+    if prompt {
+      print!("{}", if parts.is_empty() { "> " } else { ". " });
+      io::stdout().flush()?;
+    }
+
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+      // ctrl+d, or the end of a script. A dangling continuation is still input
+      if parts.is_empty() {
+        return Ok(None);
+      }
+      break;
+    }
+    let mut line = line.trim();
+
+    if parts.is_empty() && line.starts_with('"') {
+      quoted = true;
+      line = &line[1..];
+    }
+
+    if quoted {
+      if let Some(rest) = line.strip_suffix('"') {
+        parts.push(rest.trim().to_string());
+        break;
+      }
+      parts.push(line.to_string());
+    } else if let Some(rest) = line.strip_suffix('\\') {
+      parts.push(rest.trim().to_string());
+    } else {
+      parts.push(line.to_string());
+      break;
+    }
+  }
+
+  parts.retain(|p| !p.is_empty());
+  Ok(Some(parts.join(" ")))
+}
+
+#[test]
+fn test_read_input() {
+  let script = "mary likes \\\n  herself\n\"mary\nlikes\n herself\"\n\n:help\n";
+  let mut reader = script.as_bytes();
+  let mut inputs = Vec::new();
+  while let Some(input) = read_input(&mut reader, false).unwrap() {
+    inputs.push(input);
+  }
+  assert_eq!(
+    inputs,
+    vec!["mary likes herself", "mary likes herself", "", ":help"]
+  );
+}
+
+#[test]
+fn test_route() {
+  let g = |src: &str| src.parse::<Grammar>().unwrap();
+  let mut repl = Repl::new(
+    "base".to_string(),
+    g("S -> x"),
+    false,
+    false,
+    DisplayOptions::default(),
+  );
+  repl.add_grammar("exp".to_string(), g("S -> x x")).unwrap();
+  assert!(repl.add_grammar("exp".to_string(), g("S -> x")).is_err());
+
+  assert_eq!(repl.route("x x").unwrap(), (vec![0], "x x"));
+  assert_eq!(repl.route("@exp x x").unwrap(), (vec![1], "x x"));
+  assert_eq!(repl.route("@* x").unwrap(), (vec![0, 1], "x"));
+  assert!(repl.route("@other x").is_err());
+
+  repl.handle(":use exp").unwrap();
+  assert_eq!(repl.current, 1);
+  assert_eq!(repl.route("x").unwrap(), (vec![1], "x"));
+  repl.handle("x x").unwrap();
+  repl.handle("@base x").unwrap();
+  assert_eq!(repl.failures, 0);
+  repl.handle("@* x").unwrap();
+  assert_eq!(repl.failures, 1);
+}
+
+#[test]
+fn test_stuck_message() {
+  let g = "S -> mary V\nV -> runs\nV -> sleeps".parse().unwrap();
+  let repl = Repl::new("g".to_string(), g, false, false, DisplayOptions::default());
+  let (out, count) = repl.parse(&repl.grammars[0].1, "mary jumps");
+  assert_eq!(count, 0);
+  assert_eq!(
+    out,
+    "Parsed 0 trees\nparse stuck after token 1 ('mary'); expected one of: runs, sleeps\n\
+     did you mean: 'mary runs'?\n"
+  );
+}
+
+#[test]
+fn test_capitalization() {
+  let g = "S -> N V\nN[ child-0.cap: initial ] -> mary\nN -> it\nV -> runs"
+    .parse()
+    .unwrap();
+  let repl = Repl::new("g".to_string(), g, false, false, DisplayOptions::default());
+  let g = &repl.grammars[0].1;
+  assert_eq!(repl.parse(g, "Mary runs").1, 1);
+  assert_eq!(repl.parse(g, "It RUNS").1, 1);
+  assert_eq!(repl.parse(g, "mary runs").1, 0);
+}
+
+#[test]
+fn test_start_command() {
+  let g = "S -> N V\nN -> mary\nV -> runs".parse().unwrap();
+  let mut repl = Repl::new("g".to_string(), g, false, false, DisplayOptions::default());
+  repl.handle(":start N").unwrap();
+  repl.handle("mary").unwrap();
+  repl.handle(":start X").unwrap();
+  repl.handle("mary runs").unwrap();
+  assert_eq!(repl.failures, 1);
+  assert_eq!(repl.grammars[0].1.start, "N");
+}
+
+#[test]
+fn test_blank_lines_are_not_failures() {
+  let g = "S -> x".parse().unwrap();
+  let mut repl = Repl::new("g".to_string(), g, false, false, DisplayOptions::default());
+  repl.handle("").unwrap();
+  repl.handle("   ").unwrap();
+  assert_eq!(repl.failures, 0);
+}
+
+#[test]
+fn test_documented_transcript() {
+  // keep in sync with the transcripts in the crate docs and README.md
+  let src = std::fs::read_to_string(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/examples/reflexives.fgr"
+  ))
+  .unwrap();
+  let repl = Repl::new(
+    "g".to_string(),
+    src.parse().unwrap(),
+    false,
+    true,
+    DisplayOptions::default(),
+  );
+  let g = &repl.grammars[0].1;
+  assert_eq!(
+    repl.parse(g, "she likes herself").0,
+    "Parsed 1 tree
+(0..3: S
+  (0..1: N (0..1: she))
+  (1..2: TV (1..2: likes))
+  (2..3: N (2..3: herself)))
+[
+  child-0: [
+    case: nom
+    child-0: [ word: she ]
+    num: #2 sg
+    pron: #1 she
+  ]
+  child-1: [
+    child-0: [ word: likes ]
+    num: #2
+    tense: nonpast
+  ]
+  child-2: [
+    case: acc
+    child-0: [ word: herself ]
+    needs_pron: #1
+    num: sg
+    pron: ref
+  ]
+]
+
+"
+  );
+  assert_eq!(
+    repl.parse(g, "she likes himself").0,
+    "Parsed 0 trees\ndid you mean: 'she likes herself'?\n"
+  );
+}
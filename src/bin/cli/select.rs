@@ -0,0 +1,42 @@
+//! `--filter` and `--tree`: choosing which parses get printed.
+
+use treebender::query::Query;
+use treebender::ParsedTree;
+
+#[derive(Debug, Default, Clone)]
+pub struct Selection {
+  /// Queries that a tree's feature structure must all match
+  pub filters: Vec<Query>,
+  /// Only print the nth (from 1) tree that matches the filters
+  pub index: Option<usize>,
+}
+
+impl Selection {
+  /// Parses a filter like `child-1.tense=past`, see `treebender::query`
+  pub fn parse_filter(s: &str) -> Result<Query, String> {
+    s.parse().map_err(|e| format!("--filter: {}", e))
+  }
+
+  pub fn matches(&self, tree: &ParsedTree) -> bool {
+    self.filters.iter().all(|query| query.matches(&tree.1))
+  }
+
+  /// Splits items into how many of their trees matched the filters, and the
+  /// selected items
+  pub fn apply<T>(&self, items: Vec<T>, tree: impl Fn(&T) -> &ParsedTree) -> (usize, Vec<T>) {
+    let matching = items
+      .into_iter()
+      .filter(|t| self.matches(tree(t)))
+      .collect::<Vec<_>>();
+    let count = matching.len();
+    let selected = match self.index {
+      Some(n) => matching
+        .into_iter()
+        .skip(n.saturating_sub(1))
+        .take(1)
+        .collect(),
+      None => matching,
+    };
+    (count, selected)
+  }
+}
@@ -0,0 +1,114 @@
+//! `FILE test TESTFILE`: runs a test file against the grammar, optionally
+//! re-running it whenever either file changes.
+
+use std::collections::HashMap;
+use std::fs;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use treebender::testsuite::{parse_test_file, TestCase};
+use treebender::{Err, GrammarOptions};
+
+use crate::load_grammar;
+
+/// Each test case, and whether it passed
+type Results = Vec<(TestCase, bool)>;
+
+fn run(grammar_file: &str, test_file: &str, options: &GrammarOptions) -> Result<Results, Err> {
+  let (_, g) = load_grammar(grammar_file, options)?;
+  let tests = parse_test_file(&fs::read_to_string(test_file)?);
+  Ok(
+    tests
+      .into_iter()
+      .map(|t| {
+        let passed = t.passes(&g);
+        (t, passed)
+      })
+      .collect(),
+  )
+}
+
+fn describe(test_file: &str, test: &TestCase) -> String {
+  let expected = if test.should_parse {
+    "should parse"
+  } else {
+    "shouldn't parse"
+  };
+  format!("{}:{}: {} ({})", test_file, test.line, test, expected)
+}
+
+fn print_summary(results: &Results) {
+  let passed = results.iter().filter(|(_, p)| *p).count();
+  println!(
+    "{} of {} test{} passed",
+    passed,
+    results.len(),
+    if results.len() == 1 { "" } else { "s" }
+  );
+}
+
+/// Runs the tests once, printing the failures. Returns false if a test
+/// failed.
+pub fn run_tests(
+  grammar_file: &str,
+  test_file: &str,
+  options: &GrammarOptions,
+) -> Result<bool, Err> {
+  let results = run(grammar_file, test_file, options)?;
+  for (test, _) in results.iter().filter(|(_, p)| !p) {
+    println!("FAIL {}", describe(test_file, test));
+  }
+  print_summary(&results);
+  Ok(results.iter().all(|(_, p)| *p))
+}
+
+/// Prints the tests whose results changed since the last run. Tests are
+/// matched up by their text, so moving lines around doesn't count as a change,
+/// and new tests are only shown if they fail.
+fn print_diff(test_file: &str, previous: &Results, results: &Results) {
+  let before = previous
+    .iter()
+    .map(|(t, p)| ((&t.sentence, t.should_parse), *p))
+    .collect::<HashMap<_, _>>();
+
+  for (test, passed) in results.iter() {
+    match (before.get(&(&test.sentence, test.should_parse)), passed) {
+      (Some(false), true) => println!("+ {} now passes", describe(test_file, test)),
+      (Some(true), false) => println!("- {} now fails", describe(test_file, test)),
+      (None, false) => println!("- {} fails", describe(test_file, test)),
+      _ => {}
+    }
+  }
+}
+
+fn modified(files: &[&str]) -> Vec<Option<SystemTime>> {
+  files
+    .iter()
+    .map(|f| fs::metadata(f).and_then(|m| m.modified()).ok())
+    .collect()
+}
+
+/// Re-runs the tests whenever the grammar or test file changes, printing what
+/// changed. Runs until killed.
+pub fn watch(grammar_file: &str, test_file: &str, options: &GrammarOptions) -> ! {
+  let mut previous: Results = Vec::new();
+  let mut last_modified = None;
+
+  loop {
+    let now_modified = modified(&[grammar_file, test_file]);
+    if last_modified.as_ref() != Some(&now_modified) {
+      last_modified = Some(now_modified);
+      match run(grammar_file, test_file, options) {
+        Ok(results) => {
+          print_diff(test_file, &previous, &results);
+          print_summary(&results);
+          previous = results;
+        }
+        // keep watching, the next save will probably fix it
+        Err(e) => println!("error: {}", e),
+      }
+      println!("watching {} and {} for changes...", grammar_file, test_file);
+    }
+    thread::sleep(Duration::from_millis(300));
+  }
+}
@@ -0,0 +1,630 @@
+//! A long-running parse server, so editors and programs in other languages
+//! can keep a grammar loaded instead of starting the CLI for every sentence.
+//! It speaks JSON-RPC 2.0, one request per line on stdin and one response per
+//! line on stdout, or over HTTP POST with `--http`.
+
+use std::env;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+use treebender::generate::{sentences, XorShiftRng};
+use treebender::report::ParseReport;
+use treebender::rules::Grammar;
+use treebender::tokenize::{Tokenizer, UnicodeWords};
+use treebender::ParseOptions;
+
+const MAX_DEPTH: usize = 12;
+const MAX_ATTEMPTS: usize = 1000;
+/// The most sentences one `generate` request returns
+const MAX_GENERATE: usize = 1000;
+/// The largest HTTP request body the server will read
+const MAX_BODY_BYTES: usize = 1 << 20;
+/// The longest line, and most lines, of an HTTP request head it will read
+const MAX_HEADER_LINE_BYTES: usize = 8 << 10;
+const MAX_HEADER_LINES: usize = 100;
+/// How long a client can leave the server waiting on a read
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a parse can take, unless changed with `--timeout`
+const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+
+fn usage(prog_name: &str) -> String {
+  format!(
+    r#"Usage: {0} [GRAMMAR] [--http ADDR] [--timeout MS]
+
+Serves JSON-RPC 2.0 requests, one per line on stdin, answering each with one
+line on stdout. With --http, listens on ADDR (like 127.0.0.1:8080) for
+requests POSTed to any path instead. Requests that take longer than
+--timeout milliseconds ({1} by default, 0 for no limit) are stopped.
+
+Methods:
+  load_grammar {{"path": FILE}} or {{"source": TEXT}}
+      Replaces the loaded grammar. Returns the start symbol, the number of
+      rules, the grammar's metadata (its #name, #version, etc.), and any
+      warnings about the grammar.
+  parse {{"sentence": TEXT}} or {{"tokens": [WORD, ...]}}, optionally
+      "max_trees"
+      Parses a sentence, which is split into words like
+      `tokenize::UnicodeWords` and lowercased like `Grammar::fold_case`.
+      Returns the parse in the format described in `treebender::report`: the
+      number of parses and the trees, each as an S-expression, as JSON with
+      any grammatical functions, and with its features, or why the sentence
+      was rejected. Stops after "max_trees" trees. The grammar's metadata is
+      added as "grammar". Fails if the parse times out.
+  generate {{"count": N, "seed": N, "max_depth": N}}, all optional
+      Returns random sentences that parse, at most {2}, and the seed used
+      (the time, if none was given), which generates the same sentences
+      again. Fails if it times out."#,
+    prog_name, DEFAULT_TIMEOUT_MS, MAX_GENERATE
+  )
+}
+
+/// A JSON-RPC error, with one of the codes from the spec
+struct RpcError {
+  code: i64,
+  message: String,
+}
+
+impl RpcError {
+  const PARSE_ERROR: i64 = -32700;
+  const INVALID_REQUEST: i64 = -32600;
+  const METHOD_NOT_FOUND: i64 = -32601;
+  const INVALID_PARAMS: i64 = -32602;
+  /// Errors from the grammar, like a file that didn't load
+  const SERVER_ERROR: i64 = -32000;
+
+  fn new(code: i64, message: impl Into<String>) -> Self {
+    Self {
+      code,
+      message: message.into(),
+    }
+  }
+
+  fn invalid_params(message: impl Into<String>) -> Self {
+    Self::new(Self::INVALID_PARAMS, message)
+  }
+}
+
+struct Server {
+  grammar: Option<Grammar>,
+  /// How long each parse can take, or None for no limit
+  timeout: Option<Duration>,
+}
+
+impl Default for Server {
+  fn default() -> Self {
+    Self {
+      grammar: None,
+      timeout: Some(Duration::from_millis(DEFAULT_TIMEOUT_MS)),
+    }
+  }
+}
+
+impl Server {
+  /// Handles one request, returning the response to send back, or None for
+  /// a notification (a request without an id)
+  fn handle(&mut self, request: &str) -> Option<Value> {
+    let request = match serde_json::from_str::<Value>(request) {
+      Ok(request) => request,
+      Err(e) => {
+        return Some(error_response(
+          Value::Null,
+          RpcError::new(RpcError::PARSE_ERROR, e.to_string()),
+        ))
+      }
+    };
+    let id = request.get("id").cloned();
+
+    // without a method it's not a notification, just invalid, so it gets an
+    // error even without an id
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+      return Some(error_response(
+        id.unwrap_or(Value::Null),
+        RpcError::new(RpcError::INVALID_REQUEST, "missing method"),
+      ));
+    };
+    let result = self.call(method, request.get("params").unwrap_or(&Value::Null));
+
+    let id = id?;
+    Some(match result {
+      Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+      Err(e) => error_response(id, e),
+    })
+  }
+
+  fn call(&mut self, method: &str, params: &Value) -> Result<Value, RpcError> {
+    match method {
+      "load_grammar" => self.load_grammar(params),
+      "parse" => self.parse(params),
+      "generate" => self.generate(params),
+      _ => Err(RpcError::new(
+        RpcError::METHOD_NOT_FOUND,
+        format!("unknown method {}", method),
+      )),
+    }
+  }
+
+  fn grammar(&self) -> Result<&Grammar, RpcError> {
+    self
+      .grammar
+      .as_ref()
+      .ok_or_else(|| RpcError::new(RpcError::SERVER_ERROR, "no grammar loaded"))
+  }
+
+  fn load_grammar(&mut self, params: &Value) -> Result<Value, RpcError> {
+    let loaded = if let Some(path) = params.get("path").and_then(Value::as_str) {
+      Grammar::read_from_file(path)
+    } else if let Some(source) = params.get("source").and_then(Value::as_str) {
+      source.parse()
+    } else {
+      return Err(RpcError::invalid_params("expected a path or source"));
+    };
+    let g = loaded.map_err(|e| RpcError::new(RpcError::SERVER_ERROR, e.to_string()))?;
+
+    let result = json!({
+      "start": g.start,
+      "rules": g.rules.values().map(Vec::len).sum::<usize>(),
+      "metadata": g.metadata.to_json(),
+      "warnings": g.warnings().iter().map(|l| l.message.clone()).collect::<Vec<_>>(),
+    });
+    self.grammar = Some(g);
+    Ok(result)
+  }
+
+  fn parse(&self, params: &Value) -> Result<Value, RpcError> {
+    let g = self.grammar()?;
+    let (tokens, options) = if let Some(tokens) = params.get("tokens").and_then(Value::as_array) {
+      let tokens = tokens
+        .iter()
+        .map(|t| t.as_str().map(str::to_string))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| RpcError::invalid_params("tokens must be strings"))?;
+      (tokens, ParseOptions::default())
+    } else if let Some(sentence) = params.get("sentence").and_then(Value::as_str) {
+      g.fold_case(&UnicodeWords.tokenize(sentence), &ParseOptions::default())
+    } else {
+      return Err(RpcError::invalid_params("expected a sentence or tokens"));
+    };
+    let options = ParseOptions {
+      max_trees: params
+        .get("max_trees")
+        .and_then(Value::as_u64)
+        .map(|n| n as usize),
+      deadline: self.timeout.map(|timeout| Instant::now() + timeout),
+      ..options
+    };
+
+    let report = ParseReport::new_with(g, &tokens, &options)
+      .map_err(|e| RpcError::new(RpcError::SERVER_ERROR, e.to_string()))?;
+    // the deadline stopped the parse, so what it found is incomplete
+    if options.is_cancelled() {
+      return Err(timed_out("parse", report.ms));
+    }
+    let mut result = report.to_json();
+    result["grammar"] = g.metadata.to_json();
+    Ok(result)
+  }
+
+  fn generate(&self, params: &Value) -> Result<Value, RpcError> {
+    let g = self.grammar()?;
+    let count = params
+      .get("count")
+      .and_then(Value::as_u64)
+      .map_or(1, |n| n.min(MAX_GENERATE as u64) as usize);
+    let max_depth = params
+      .get("max_depth")
+      .and_then(Value::as_u64)
+      .map_or(MAX_DEPTH, |n| n as usize);
+    let seed = match params.get("seed").and_then(Value::as_u64) {
+      Some(seed) => seed,
+      None => SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64),
+    };
+
+    let started = Instant::now();
+    let mut rng = XorShiftRng::new(seed);
+    let mut generated = sentences(g, &mut rng, max_depth, MAX_ATTEMPTS);
+    let mut sentences = Vec::new();
+    while sentences.len() < count {
+      if self
+        .timeout
+        .is_some_and(|timeout| started.elapsed() >= timeout)
+      {
+        return Err(timed_out(
+          "generate",
+          started.elapsed().as_secs_f64() * 1000.0,
+        ));
+      }
+      let Some(sentence) = generated.next() else {
+        break;
+      };
+      sentences.push(sentence.join(" "));
+    }
+    Ok(json!({ "sentences": sentences, "seed": seed }))
+  }
+}
+
+/// The error for a request that ran past the server's timeout
+fn timed_out(method: &str, ms: f64) -> RpcError {
+  RpcError::new(
+    RpcError::SERVER_ERROR,
+    format!("{} timed out after {} ms", method, ms.round()),
+  )
+}
+
+fn error_response(id: Value, e: RpcError) -> Value {
+  json!({
+    "jsonrpc": "2.0",
+    "id": id,
+    "error": { "code": e.code, "message": e.message },
+  })
+}
+
+fn serve_stdio(server: &mut Server) -> io::Result<()> {
+  let stdout = io::stdout();
+  for line in io::stdin().lock().lines() {
+    let line = line?;
+    if line.trim().is_empty() {
+      continue;
+    }
+    if let Some(response) = server.handle(&line) {
+      let mut out = stdout.lock();
+      writeln!(out, "{}", response)?;
+      out.flush()?;
+    }
+  }
+  Ok(())
+}
+
+/// Reads one line of an HTTP request head into `line`, returning false if
+/// it's longer than `MAX_HEADER_LINE_BYTES`
+fn read_head_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<bool> {
+  line.clear();
+  reader
+    .take(MAX_HEADER_LINE_BYTES as u64 + 1)
+    .read_line(line)?;
+  Ok(line.len() <= MAX_HEADER_LINE_BYTES)
+}
+
+/// Handles one HTTP request: just enough HTTP to read a POSTed body
+fn serve_http_request(server: &mut Server, stream: TcpStream) -> io::Result<()> {
+  stream.set_read_timeout(Some(READ_TIMEOUT))?;
+  let mut reader = BufReader::new(stream.try_clone()?);
+  let mut line = String::new();
+  let head_too_large = |stream| {
+    let message = format!("request header lines over {} bytes", MAX_HEADER_LINE_BYTES);
+    write_http_error(stream, "431 Request Header Fields Too Large", &[], message)
+  };
+
+  if !read_head_line(&mut reader, &mut line)? {
+    return head_too_large(stream);
+  }
+  let method = line.split_whitespace().next().unwrap_or("").to_string();
+  let mut content_length = Some(0);
+  for _ in 0..MAX_HEADER_LINES {
+    if !read_head_line(&mut reader, &mut line)? {
+      return head_too_large(stream);
+    }
+    if line.trim().is_empty() {
+      break;
+    }
+    if let Some((name, value)) = line.split_once(':') {
+      if name.eq_ignore_ascii_case("content-length") {
+        content_length = value.trim().parse().ok();
+      }
+    }
+  }
+
+  if method != "POST" {
+    let message = format!("method {} not allowed, requests are POSTed", method);
+    return write_http_error(
+      stream,
+      "405 Method Not Allowed",
+      &[("Allow", "POST")],
+      message,
+    );
+  }
+  let Some(content_length) = content_length else {
+    let message = "bad Content-Length".to_string();
+    return write_http_error(stream, "400 Bad Request", &[], message);
+  };
+  if content_length > MAX_BODY_BYTES {
+    let message = format!("request body over {} bytes", MAX_BODY_BYTES);
+    return write_http_error(stream, "413 Payload Too Large", &[], message);
+  }
+
+  let mut body = vec![0; content_length];
+  reader.read_exact(&mut body)?;
+  let response = server
+    .handle(&String::from_utf8_lossy(&body))
+    .map(|r| r.to_string())
+    .unwrap_or_default();
+  write_http_response(stream, "200 OK", &[], &response)
+}
+
+/// Answers a request that was refused before it was handled, with an
+/// invalid request error
+fn write_http_error(
+  stream: TcpStream,
+  status: &str,
+  headers: &[(&str, &str)],
+  message: String,
+) -> io::Result<()> {
+  let error = RpcError::new(RpcError::INVALID_REQUEST, message);
+  let response = error_response(Value::Null, error).to_string();
+  write_http_response(stream, status, headers, &response)
+}
+
+fn write_http_response(
+  mut stream: TcpStream,
+  status: &str,
+  headers: &[(&str, &str)],
+  body: &str,
+) -> io::Result<()> {
+  write!(stream, "HTTP/1.1 {}\r\n", status)?;
+  for (name, value) in headers {
+    write!(stream, "{}: {}\r\n", name, value)?;
+  }
+  write!(
+    stream,
+    "Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+    body.len(),
+    body
+  )
+}
+
+fn serve_http(server: &mut Server, addr: &str) -> io::Result<()> {
+  let listener = TcpListener::bind(addr)?;
+  eprintln!("listening on {}", listener.local_addr()?);
+  for stream in listener.incoming() {
+    if let Err(e) = stream.and_then(|s| serve_http_request(server, s)) {
+      eprintln!("{}", e);
+    }
+  }
+  Ok(())
+}
+
+fn main() {
+  let args = env::args().collect::<Vec<_>>();
+  let mut server = Server::default();
+  let mut http = None;
+
+  let mut idx = 1;
+  while idx < args.len() {
+    match args[idx].as_str() {
+      "-h" | "--help" => {
+        println!("{}", usage(&args[0]));
+        return;
+      }
+      "--http" if idx + 1 < args.len() => {
+        http = Some(args[idx + 1].clone());
+        idx += 1;
+      }
+      "--timeout" if idx + 1 < args.len() => {
+        let Ok(ms) = args[idx + 1].parse::<u64>() else {
+          eprintln!("bad --timeout {}, expected milliseconds", args[idx + 1]);
+          process::exit(255);
+        };
+        server.timeout = (ms > 0).then(|| Duration::from_millis(ms));
+        idx += 1;
+      }
+      arg if !arg.starts_with('-') && server.grammar.is_none() => {
+        match Grammar::read_from_file(arg) {
+          Ok(g) => server.grammar = Some(g),
+          Err(e) => {
+            eprintln!("error loading {}: {}", arg, e);
+            process::exit(1);
+          }
+        }
+      }
+      arg => {
+        eprintln!("unknown argument {}\n\n{}", arg, usage(&args[0]));
+        process::exit(255);
+      }
+    }
+    idx += 1;
+  }
+
+  let result = match http {
+    Some(addr) => serve_http(&mut server, &addr),
+    None => serve_stdio(&mut server),
+  };
+  if let Err(e) = result {
+    eprintln!("{}", e);
+    process::exit(1);
+  }
+}
+
+#[test]
+fn test_server() {
+  let mut server = Server::default();
+  let mut call = |request: Value| server.handle(&request.to_string()).unwrap();
+
+  let response =
+    call(json!({ "jsonrpc": "2.0", "id": 1, "method": "parse", "params": { "sentence": "x" } }));
+  assert_eq!(response["error"]["message"], "no grammar loaded");
+
+  let response = call(json!({
+    "jsonrpc": "2.0",
+    "id": 2,
+    "method": "load_grammar",
+    "params": { "source": "S -> N IV\nN[ num: sg ] -> mary\nIV -> fell" },
+  }));
+  assert_eq!(
+    response["result"],
+    json!({ "start": "S", "rules": 3, "metadata": {}, "warnings": [] })
+  );
+
+  let mut response = call(
+    json!({ "jsonrpc": "2.0", "id": 3, "method": "parse", "params": { "sentence": "Mary fell" } }),
+  );
+  // the only thing that changes from run to run
+  assert!(response["result"]["ms"].as_f64().is_some());
+  response["result"]["ms"] = 0.into();
+  assert_eq!(
+    response["result"],
+    json!({
+      "version": 1,
+      "grammar": {},
+      "tokens": ["mary", "fell"],
+      "parses": 1,
+      "trees": [{
+        "tree": "(S (N mary) (IV fell))",
+        "structure": {
+          "label": "S",
+          "span": [0, 2],
+          "children": [
+            { "label": "N", "span": [0, 1], "children": [{ "word": "mary", "span": [0, 1] }] },
+            { "label": "IV", "span": [1, 2], "children": [{ "word": "fell", "span": [1, 2] }] },
+          ],
+        },
+        "features": {
+          "child-0": { "num": "sg", "child-0": { "word": "mary" } },
+          "child-1": { "child-0": { "word": "fell" } },
+        },
+        "reentrancies": [],
+      }],
+      "diagnostics": [],
+      "ms": 0,
+    })
+  );
+
+  let response =
+    call(json!({ "jsonrpc": "2.0", "id": 4, "method": "parse", "params": { "tokens": ["mary"] } }));
+  assert_eq!(response["result"]["parses"], 0);
+  assert_eq!(
+    response["result"]["diagnostics"][0]["expected"],
+    json!(["fell"])
+  );
+
+  let response = call(
+    json!({ "jsonrpc": "2.0", "id": 4, "method": "generate", "params": { "count": 2, "seed": 1 } }),
+  );
+  assert_eq!(
+    response["result"]["sentences"],
+    json!(["mary fell", "mary fell"])
+  );
+  assert_eq!(response["result"]["seed"], 1);
+
+  let response = call(json!({ "jsonrpc": "2.0", "id": 5, "method": "fly" }));
+  assert_eq!(response["error"]["code"], RpcError::METHOD_NOT_FOUND);
+  assert_eq!(
+    server.handle("{").unwrap()["error"]["code"],
+    RpcError::PARSE_ERROR
+  );
+  assert!(server
+    .handle(r#"{ "jsonrpc": "2.0", "method": "parse" }"#)
+    .is_none());
+}
+
+#[test]
+fn test_server_invalid_and_unicode() {
+  let mut server = Server::default();
+  let mut call = |request: &str| server.handle(request).unwrap();
+
+  let response = call(r#"{ "jsonrpc": "2.0" }"#);
+  assert_eq!(response["error"]["code"], RpcError::INVALID_REQUEST);
+  assert_eq!(response["id"], Value::Null);
+
+  let load = json!({
+    "jsonrpc": "2.0",
+    "id": 1,
+    "method": "load_grammar",
+    "params": { "source": "S -> N\nN -> x" },
+  });
+  assert!(call(&load.to_string())["result"].is_object());
+
+  let parse =
+    json!({ "jsonrpc": "2.0", "id": 2, "method": "parse", "params": { "sentence": "ÉMILE" } });
+  let response = call(&parse.to_string());
+  // grammar symbols are ASCII, but sentences needn't be
+  assert_eq!(response["result"]["tokens"], json!(["émile"]));
+  assert_eq!(response["result"]["parses"], 0);
+}
+
+#[test]
+fn test_server_limits() {
+  let mut server = Server {
+    grammar: Some("S -> X\nX -> X X\nX -> a".parse().unwrap()),
+    timeout: Some(Duration::from_secs(1)),
+  };
+  let mut parse = |params: Value| {
+    let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "parse", "params": params });
+    server.handle(&request.to_string()).unwrap()
+  };
+
+  let response = parse(json!({ "sentence": "a a a a", "max_trees": 2 }));
+  assert_eq!(response["result"]["parses"], 2);
+  assert_eq!(response["result"]["trees"].as_array().unwrap().len(), 2);
+
+  // millions of trees, but the deadline stops building them
+  let started = Instant::now();
+  let response = parse(json!({ "tokens": vec!["a"; 16] }));
+  assert!(started.elapsed() < Duration::from_secs(5));
+  assert_eq!(response["error"]["code"], RpcError::SERVER_ERROR);
+
+  let mut generate = |params: Value| {
+    let request = json!({ "jsonrpc": "2.0", "id": 2, "method": "generate", "params": params });
+    server.handle(&request.to_string()).unwrap()
+  };
+  let response = generate(json!({ "count": 1_000_000, "seed": 1, "max_depth": 2 }));
+  let sentences = response["result"]["sentences"].as_array().unwrap();
+  assert_eq!(sentences.len(), MAX_GENERATE);
+
+  server.timeout = Some(Duration::ZERO);
+  let response = server
+    .handle(r#"{ "jsonrpc": "2.0", "id": 3, "method": "parse", "params": { "sentence": "a" } }"#)
+    .unwrap();
+  let message = response["error"]["message"].as_str().unwrap();
+  assert!(message.starts_with("parse timed out"));
+  let response = server
+    .handle(r#"{ "jsonrpc": "2.0", "id": 4, "method": "generate", "params": { "count": 2 } }"#)
+    .unwrap();
+  assert_eq!(response["error"]["code"], RpcError::SERVER_ERROR);
+}
+
+#[test]
+fn test_http_request() {
+  use std::thread;
+
+  let send = |request: Vec<u8>| {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+      let mut stream = TcpStream::connect(addr).unwrap();
+      stream.write_all(&request).unwrap();
+      let mut response = String::new();
+      stream.read_to_string(&mut response).unwrap();
+      response
+    });
+    let (stream, _) = listener.accept().unwrap();
+    serve_http_request(&mut Server::default(), stream).unwrap();
+    client.join().unwrap()
+  };
+
+  let body = r#"{ "jsonrpc": "2.0", "id": 1, "method": "fly" }"#;
+  let post = format!(
+    "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+    body.len(),
+    body
+  );
+  let response = send(post.into_bytes());
+  assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+  assert!(response.contains("unknown method fly"));
+
+  let response = send(b"GET / HTTP/1.1\r\n\r\n".to_vec());
+  assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\nAllow: POST\r\n"));
+
+  let mut long = b"POST / HTTP/1.1\r\nX-Long: ".to_vec();
+  long.extend(vec![b'a'; MAX_HEADER_LINE_BYTES]);
+  long.extend(b"\r\n\r\n");
+  let response = send(long);
+  assert!(response.starts_with("HTTP/1.1 431 "));
+
+  let response = send(b"POST / HTTP/1.1\r\nContent-Length: lots\r\n\r\n{}".to_vec());
+  assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+}
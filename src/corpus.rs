@@ -0,0 +1,242 @@
+//! Running a directory of grammars against their test sentences, as a
+//! harness for integration tests. Each `name.fgr` in the directory is paired
+//! with `name.sentences`, if there is one, in the `testsuite` format (starred
+//! sentences shouldn't parse), and the grammar's own `// test:` comments are
+//! run too. This crate runs `examples/` with it, and grammar repos can do the
+//! same from their own tests:
+//!
+//! ```no_run
+//! use treebender::corpus::Corpus;
+//!
+//! let report = Corpus::load_dir("grammars").unwrap().run();
+//! assert!(report.is_ok(), "{}", report);
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::rules::Grammar;
+use crate::testsuite::{inline_tests, parse_test_file, TestCase};
+use crate::Err;
+
+/// A grammar file and the tests for it
+#[derive(Debug, Clone)]
+pub struct CorpusEntry {
+  /// The file's name without `.fgr`
+  pub name: String,
+  pub grammar: PathBuf,
+  /// The `.sentences` file, if there is one
+  pub sentences: Option<PathBuf>,
+  /// The grammar's `// test:` comments
+  pub inline_tests: Vec<TestCase>,
+  /// The sentences file's tests
+  pub sentence_tests: Vec<TestCase>,
+}
+
+/// Every grammar in a directory, see the module docs
+#[derive(Debug, Clone, Default)]
+pub struct Corpus {
+  /// Sorted by name
+  pub entries: Vec<CorpusEntry>,
+}
+
+/// How one test went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestResult {
+  pub test: TestCase,
+  /// Whether it was in the sentences file, instead of the grammar
+  pub from_sentences: bool,
+  /// How many trees survived unification
+  pub parses: usize,
+}
+
+impl TestResult {
+  pub fn passed(&self) -> bool {
+    (self.parses > 0) == self.test.should_parse
+  }
+}
+
+/// How one grammar's tests went
+#[derive(Debug, Clone)]
+pub struct EntryReport {
+  pub name: String,
+  /// Why the grammar couldn't be loaded, in which case no tests were run
+  pub error: Option<String>,
+  pub results: Vec<TestResult>,
+}
+
+impl EntryReport {
+  pub fn failures(&self) -> impl Iterator<Item = &TestResult> {
+    self.results.iter().filter(|r| !r.passed())
+  }
+
+  pub fn is_ok(&self) -> bool {
+    self.error.is_none() && self.failures().next().is_none()
+  }
+}
+
+/// How a whole corpus went
+#[derive(Debug, Clone, Default)]
+pub struct CorpusReport {
+  pub entries: Vec<EntryReport>,
+}
+
+impl CorpusReport {
+  /// Whether every grammar loaded and every test passed
+  pub fn is_ok(&self) -> bool {
+    self.entries.iter().all(EntryReport::is_ok)
+  }
+
+  /// How many tests passed, of how many
+  pub fn passed(&self) -> (usize, usize) {
+    let results = self.entries.iter().flat_map(|e| &e.results);
+    let total = results.clone().count();
+    (results.filter(|r| r.passed()).count(), total)
+  }
+}
+
+impl fmt::Display for CorpusReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for entry in &self.entries {
+      if let Some(e) = &entry.error {
+        writeln!(f, "{}: couldn't load grammar: {}", entry.name, e)?;
+      }
+      for result in entry.failures() {
+        writeln!(
+          f,
+          "{}.{}:{}: {} ({} parse{})",
+          entry.name,
+          if result.from_sentences {
+            "sentences"
+          } else {
+            "fgr"
+          },
+          result.test.line,
+          result.test,
+          result.parses,
+          if result.parses == 1 { "" } else { "s" }
+        )?;
+      }
+    }
+    let (passed, total) = self.passed();
+    write!(
+      f,
+      "{} of {} test{} passed in {} grammar{}",
+      passed,
+      total,
+      if total == 1 { "" } else { "s" },
+      self.entries.len(),
+      if self.entries.len() == 1 { "" } else { "s" }
+    )
+  }
+}
+
+impl Corpus {
+  /// Finds the `.fgr` files in `dir` (not its subdirectories) and reads their
+  /// tests. Grammars aren't loaded until `run`, so one that doesn't load is
+  /// reported there instead of failing the whole corpus.
+  pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Self, Err> {
+    let mut grammars = fs::read_dir(dir)?
+      .map(|entry| entry.map(|e| e.path()))
+      .collect::<Result<Vec<_>, _>>()?;
+    grammars.retain(|p| p.is_file() && p.extension().is_some_and(|e| e == "fgr"));
+    grammars.sort();
+
+    let mut entries = Vec::with_capacity(grammars.len());
+    for grammar in grammars {
+      let name = grammar
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+      let sentences = Some(grammar.with_extension("sentences")).filter(|p| p.is_file());
+      let inline_tests = inline_tests(&fs::read_to_string(&grammar)?);
+      let sentence_tests = match &sentences {
+        Some(sentences) => parse_test_file(&fs::read_to_string(sentences)?),
+        None => Vec::new(),
+      };
+      entries.push(CorpusEntry {
+        name,
+        grammar,
+        sentences,
+        inline_tests,
+        sentence_tests,
+      });
+    }
+    Ok(Self { entries })
+  }
+
+  /// Loads each grammar and parses its tests
+  pub fn run(&self) -> CorpusReport {
+    CorpusReport {
+      entries: self.entries.iter().map(CorpusEntry::run).collect(),
+    }
+  }
+}
+
+impl CorpusEntry {
+  /// Loads the grammar and parses its tests
+  pub fn run(&self) -> EntryReport {
+    let g = match Grammar::read_from_file(&self.grammar) {
+      Ok(g) => g,
+      Err(e) => {
+        return EntryReport {
+          name: self.name.clone(),
+          error: Some(e.to_string()),
+          results: Vec::new(),
+        }
+      }
+    };
+    let inline = self.inline_tests.iter().map(|t| (t, false));
+    let sentences = self.sentence_tests.iter().map(|t| (t, true));
+    let results = inline
+      .chain(sentences)
+      .map(|(test, from_sentences)| TestResult {
+        test: test.clone(),
+        from_sentences,
+        parses: g.parse(test.tokens()).len(),
+      })
+      .collect();
+    EntryReport {
+      name: self.name.clone(),
+      error: None,
+      results,
+    }
+  }
+}
+
+#[test]
+fn test_corpus() {
+  let examples = Corpus::load_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/examples")).unwrap();
+  assert!(examples.entries.iter().any(|e| e.sentences.is_some()));
+  let report = examples.run();
+  assert!(report.is_ok(), "{}", report);
+
+  let dir = std::env::temp_dir().join(format!("treebender-corpus-{}", std::process::id()));
+  fs::create_dir_all(&dir).unwrap();
+  fs::write(
+    dir.join("a.fgr"),
+    "// test: mary runs\nS -> N V\nN -> mary\nV -> runs\n",
+  )
+  .unwrap();
+  fs::write(dir.join("a.sentences"), "runs mary\n*mary runs\n").unwrap();
+  fs::write(dir.join("b.fgr"), "S -> \n[").unwrap();
+  fs::write(dir.join("notes.txt"), "not a grammar").unwrap();
+  let corpus = Corpus::load_dir(&dir).unwrap();
+  let report = corpus.run();
+  fs::remove_dir_all(&dir).unwrap();
+
+  assert_eq!(
+    corpus.entries.iter().map(|e| &*e.name).collect::<Vec<_>>(),
+    ["a", "b"]
+  );
+  assert!(!report.is_ok());
+  assert_eq!(report.passed(), (1, 3));
+  assert!(report.entries[1].error.is_some());
+  let failures = report.entries[0].failures().collect::<Vec<_>>();
+  assert_eq!(failures.len(), 2);
+  assert!(failures.iter().all(|r| r.from_sentences));
+  assert!(report
+    .to_string()
+    .starts_with("a.sentences:1: runs mary (0 parses)\na.sentences:2: *mary runs (1 parse)\n"));
+}
@@ -0,0 +1,154 @@
+//! Coreference across sentences. A `Discourse` remembers the referents that
+//! earlier sentences introduced, and unifies the antecedents of later
+//! pronouns with them, so the pronoun's features end up sharing the
+//! referent's structure.
+//!
+//! The grammar marks which is which with features: a constituent with a
+//! `referent` feature introduces its value (like `N[ referent.name: mary,
+//! referent.gender: fem ] -> mary`), and one with an `antecedent` feature
+//! wants to be unified with an earlier referent (`Pro[ antecedent.gender: fem
+//! ] -> she`). Antecedents are resolved to the most recent referent they unify
+//! with. Referents in the same sentence are left to the grammar, like
+//! reflexives are.
+
+use crate::featurestructure::NodeRef;
+
+/// How a pronoun's antecedent was resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+  /// The path of the antecedent in the sentence's features
+  pub path: String,
+  /// The index in `Discourse::referents` it was unified with, or None if
+  /// none of them would unify
+  pub referent: Option<usize>,
+}
+
+/// The referents introduced so far, see the module docs
+#[derive(Debug, Clone)]
+pub struct Discourse {
+  /// The feature that introduces a referent, `referent` by default
+  pub referent_feature: String,
+  /// The feature that refers back to one, `antecedent` by default
+  pub antecedent_feature: String,
+  /// Every referent so far, oldest first. Resolving antecedents adds to
+  /// these, since they're unified.
+  pub referents: Vec<NodeRef>,
+}
+
+impl Default for Discourse {
+  fn default() -> Self {
+    Self {
+      referent_feature: "referent".to_string(),
+      antecedent_feature: "antecedent".to_string(),
+      referents: Vec::new(),
+    }
+  }
+}
+
+impl Discourse {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The nodes in `fs` at a path ending in `label`, once each, in path order
+  fn find(fs: &NodeRef, label: &str) -> Vec<(String, NodeRef)> {
+    let mut found = fs
+      .walk()
+      .filter(|(path, _)| path.last().is_some_and(|l| l == label))
+      .map(|(path, node)| (path.join("."), node.dereference()))
+      .collect::<Vec<_>>();
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut seen: Vec<NodeRef> = Vec::new();
+    found.retain(|(_, node)| {
+      let new = !seen.contains(node);
+      seen.push(node.clone());
+      new
+    });
+    found
+  }
+
+  /// The most recent referent `antecedent` unifies with, without changing
+  /// either
+  fn antecedent_of(&self, antecedent: &NodeRef) -> Option<usize> {
+    (0..self.referents.len()).rev().find(|&idx| {
+      NodeRef::unify(antecedent.deep_clone(), self.referents[idx].deep_clone()).is_ok()
+    })
+  }
+
+  /// Whether every antecedent in `fs` has a referent it would unify with
+  pub fn can_resolve(&self, fs: &NodeRef) -> bool {
+    Self::find(fs, &self.antecedent_feature)
+      .iter()
+      .all(|(_, node)| self.antecedent_of(node).is_some())
+  }
+
+  /// Resolves the antecedents in `fs` against the earlier referents, unifying
+  /// each with the one it resolves to, then adds the referents `fs`
+  /// introduces
+  pub fn update(&mut self, fs: &NodeRef) -> Vec<Resolution> {
+    let mut resolutions = Vec::new();
+    for (path, node) in Self::find(fs, &self.antecedent_feature) {
+      let referent = self.antecedent_of(&node);
+      if let Some(idx) = referent {
+        NodeRef::unify(node, self.referents[idx].clone()).expect("unified with a copy already");
+      }
+      resolutions.push(Resolution { path, referent });
+    }
+    for (_, node) in Self::find(fs, &self.referent_feature) {
+      if !self.referents.contains(&node) {
+        self.referents.push(node);
+      }
+    }
+    resolutions
+  }
+}
+
+#[test]
+fn test_discourse() {
+  use crate::session::ParseSession;
+  use crate::Grammar;
+
+  let g: Grammar = r#"
+    S -> N V
+    S -> Pro V
+    N[ referent.name: mary, referent.gender: fem ] -> mary
+    N[ referent.name: john, referent.gender: masc ] -> john
+    Pro[ antecedent.gender: fem ] -> she
+    Pro[ antecedent.gender: masc ] -> he
+    Pro[ antecedent.gender: neut ] -> it
+    V -> runs
+  "#
+  .parse()
+  .unwrap();
+
+  let mut session = ParseSession::new(&g);
+  session.discourse = Some(Discourse::new());
+  session.parse(["mary", "runs"]);
+  session.parse(["john", "runs"]);
+  assert_eq!(session.discourse.as_ref().unwrap().referents.len(), 2);
+
+  let (_, fs) = session.parse(["she", "runs"]).remove(0);
+  assert_eq!(
+    fs.get_path("child-0.antecedent.name")
+      .unwrap()
+      .get_str()
+      .as_deref(),
+    Some("mary")
+  );
+  assert_eq!(
+    session.resolutions,
+    [Resolution {
+      path: "child-0.antecedent".to_string(),
+      referent: Some(0)
+    }]
+  );
+
+  session.parse(["it", "runs"]);
+  assert_eq!(session.resolutions[0].referent, None);
+
+  let discourse = session.discourse.as_ref().unwrap();
+  let (_, fs) = g.parse(["he", "runs"]).remove(0);
+  assert!(discourse.can_resolve(&fs));
+  let (_, fs) = g.parse(["it", "runs"]).remove(0);
+  assert!(!discourse.can_resolve(&fs));
+}
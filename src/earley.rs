@@ -1,7 +1,15 @@
+//! Earley chart parsing. `parse_chart` and `Chart` are the interface;
+//! `LR0` and `State` are the parser's own bookkeeping, public so observers
+//! and chart dumps can show them, but they change as the parser does.
+
+use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
 
-use crate::rules::{Grammar, Production, Rule};
+use crate::observer::ParseObserver;
+use crate::options::ParseOptions;
+use crate::rules::{Grammar, GrammarError, Production, Rule};
+use crate::utils::{reset_rows, Err};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LR0 {
@@ -66,41 +74,280 @@ impl State {
   }
 }
 
-#[derive(Debug)]
-pub struct Chart(Vec<Vec<State>>);
+#[derive(Debug, Clone)]
+pub struct Chart {
+  states: Vec<Vec<State>>,
+  /// Each state's score, indexed like `states`: the product of the weights of
+  /// the rules it was built from, the best it's been reached with. None for a
+  /// state the beam has dropped, until `compact` removes it.
+  scores: Vec<Vec<Option<f64>>>,
+  /// Emptied rows from longer inputs, kept by `reset` for reuse
+  spare: Vec<Vec<State>>,
+  spare_scores: Vec<Vec<Option<f64>>>,
+  /// With a beam, the completed states in each cell at each position, by
+  /// origin and symbol, as indexes into `states`
+  cells: Vec<HashMap<(usize, String), Vec<usize>>>,
+  /// The input, for the words `UNKNOWN_WORD` matched
+  tokens: Vec<Arc<str>>,
+  beam: Option<usize>,
+  observer: Option<Arc<dyn ParseObserver>>,
+}
 
 impl Chart {
   pub fn new(length: usize) -> Self {
-    Self(vec![Vec::new(); length])
+    Self {
+      states: vec![Vec::new(); length],
+      scores: vec![Vec::new(); length],
+      spare: Vec::new(),
+      spare_scores: Vec::new(),
+      cells: vec![HashMap::new(); length],
+      tokens: Vec::new(),
+      beam: None,
+      observer: None,
+    }
+  }
+
+  /// Limits the number of completed states kept per chart cell, a cell being
+  /// the completed states for one symbol with the same origin and end. Once a
+  /// cell is full, a completed state only gets in by scoring higher than the
+  /// lowest-scoring state there, which is dropped. With no `@weight`s every
+  /// state scores the same, so it's first come, first served.
+  pub fn with_beam(mut self, beam: Option<usize>) -> Self {
+    self.beam = beam;
+    self
+  }
+
+  /// Remembers the input, so forests built from the chart have the words
+  /// `UNKNOWN_WORD` matched. Parsing sets it.
+  pub fn with_tokens(mut self, input: &[&str]) -> Self {
+    self.tokens = input.iter().map(|&t| Arc::from(t)).collect();
+    self
+  }
+
+  /// The input the chart was parsed from, if it's known
+  pub fn tokens(&self) -> &[Arc<str>] {
+    &self.tokens
+  }
+
+  /// Sends this chart's events, and those of the forest built from it, to
+  /// `observer`
+  pub fn with_observer(mut self, observer: Option<Arc<dyn ParseObserver>>) -> Self {
+    self.observer = observer;
+    self
+  }
+
+  pub fn observer(&self) -> Option<&Arc<dyn ParseObserver>> {
+    self.observer.as_ref()
+  }
+
+  /// Calls `f` with the observer, if there is one
+  pub(crate) fn observe(&self, f: impl FnOnce(&dyn ParseObserver)) {
+    if let Some(observer) = &self.observer {
+      f(observer.as_ref());
+    }
+  }
+
+  /// Empties the chart and resizes it to `length`, keeping its allocations
+  /// for reuse
+  pub fn reset(&mut self, length: usize) {
+    reset_rows(&mut self.states, &mut self.spare, length);
+    reset_rows(&mut self.scores, &mut self.spare_scores, length);
+    for cells in self.cells.iter_mut() {
+      cells.clear();
+    }
+    self.cells.resize_with(length, HashMap::new);
   }
 
   pub fn len(&self) -> usize {
-    self.0.len()
+    self.states.len()
   }
 
   pub fn is_empty(&self) -> bool {
     self.len() == 0
   }
 
+  pub fn states_at(&self, k: usize) -> &[State] {
+    &self.states[k]
+  }
+
   pub fn len_at(&self, k: usize) -> usize {
-    self.0[k].len()
+    self.states[k].len()
   }
 
   pub fn has(&self, k: usize, state: &State) -> bool {
-    self.0[k].contains(state)
+    self.states[k].contains(state)
+  }
+
+  /// Adds a state at k, returning false if it was already present, was
+  /// dropped because its cell is full, or is complete but too long or short
+  /// for its rule's `@span`
+  pub fn add(&mut self, k: usize, state: State) -> bool {
+    self.add_scored(k, state, 1.0)
   }
 
-  pub fn add(&mut self, k: usize, state: State) {
-    if !self.has(k, &state) {
-      self.0[k].push(state);
+  /// Like `add`, with the state's score, which decides what the beam keeps
+  /// (see `with_beam`). A state that's already present keeps the higher of
+  /// its scores.
+  pub(crate) fn add_scored(&mut self, k: usize, state: State, score: f64) -> bool {
+    let complete = !state.lr0.is_active();
+    if complete && !state.lr0.rule.allows_span(None, k - state.origin) {
+      return false;
     }
+    if let Some(idx) = self.states[k].iter().position(|s| *s == state) {
+      if let Some(old) = &mut self.scores[k][idx] {
+        *old = old.max(score);
+      }
+      return false;
+    }
+    match self.beam {
+      Some(beam) if complete => {
+        let idx = self.states[k].len();
+        let scores = &mut self.scores[k];
+        let cell = self.cells[k]
+          .entry((state.origin, state.lr0.rule.symbol.clone()))
+          .or_default();
+        if cell.len() < beam {
+          cell.push(idx);
+        } else {
+          // live states always have scores
+          let worst = (0..cell.len()).min_by(|&a, &b| {
+            let score = |slot: usize| scores[cell[slot]].unwrap_or(f64::NEG_INFINITY);
+            score(a).total_cmp(&score(b))
+          });
+          match worst {
+            Some(slot) if scores[cell[slot]].is_some_and(|worst| score > worst) => {
+              scores[cell[slot]] = None;
+              cell[slot] = idx;
+            }
+            _ => return false,
+          }
+        }
+      }
+      _ => {}
+    }
+    self.observe(|o| o.state_added(k, &state));
+    self.states[k].push(state);
+    self.scores[k].push(Some(score));
+    true
+  }
+
+  /// Removes the states at `k` that the beam has dropped. They're left in
+  /// place until then, so the parser can keep walking the states at `k` by
+  /// index while they're added.
+  pub(crate) fn compact(&mut self, k: usize) {
+    if !self.scores[k].contains(&None) {
+      return;
+    }
+    let mut scores = self.scores[k].iter();
+    self.states[k].retain(|_| scores.next().unwrap().is_some());
+    self.scores[k].retain(Option::is_some);
+    let cells = &mut self.cells[k];
+    cells.clear();
+    for (idx, state) in self.states[k].iter().enumerate() {
+      if !state.lr0.is_active() {
+        cells
+          .entry((state.origin, state.lr0.rule.symbol.clone()))
+          .or_default()
+          .push(idx);
+      }
+    }
+  }
+
+  /// The states at `k` with their scores, leaving out any the beam has
+  /// dropped
+  pub(crate) fn scored_at(&self, k: usize) -> impl Iterator<Item = (&State, f64)> {
+    self.states[k]
+      .iter()
+      .zip(&self.scores[k])
+      .filter_map(|(state, score)| Some((state, (*score)?)))
   }
 
   /// Get an owned state so that passing around &mut chart is more ergonomic
   /// The clone is fairly cheap, only an rc + 2 usize, State would be copy if not
-  /// for the Arc<Rule>
-  fn get_state(&self, k: usize, idx: usize) -> State {
-    self.0[k][idx].clone()
+  /// for the Arc<Rule>. Its score is None if the beam has dropped it.
+  fn get_state(&self, k: usize, idx: usize) -> (State, Option<f64>) {
+    (self.states[k][idx].clone(), self.scores[k][idx])
+  }
+}
+
+impl Chart {
+  /// The chart as JSON, so a parse can be captured (in a bug report, say)
+  /// and replayed with `from_json` without the grammar or input:
+  /// `{ "rules": [...], "states": [[{ "rule", "pos", "origin" }, ...], ...] }`,
+  /// with one list of states per position, and each state's rule an index
+  /// into `rules` (see `Rule::to_json`). The beam, scores and observer aren't
+  /// saved.
+  pub fn to_json(&self) -> serde_json::Value {
+    let mut rules: Vec<&Arc<Rule>> = Vec::new();
+    let states = self
+      .states
+      .iter()
+      .map(|states| {
+        states
+          .iter()
+          .map(|state| {
+            let rule = match rules.iter().position(|r| Arc::ptr_eq(r, &state.lr0.rule)) {
+              Some(idx) => idx,
+              None => {
+                rules.push(&state.lr0.rule);
+                rules.len() - 1
+              }
+            };
+            serde_json::json!({
+              "rule": rule,
+              "pos": state.lr0.pos,
+              "origin": state.origin,
+            })
+          })
+          .collect::<Vec<_>>()
+      })
+      .collect::<Vec<_>>();
+
+    serde_json::json!({
+      "rules": rules.iter().map(|r| r.to_json()).collect::<Vec<_>>(),
+      "states": states,
+    })
+  }
+
+  /// Reads a chart written by `to_json`. States are added as they were, in
+  /// order, without checking that they make sense.
+  pub fn from_json(value: &serde_json::Value) -> Result<Self, Err> {
+    let rules = value["rules"]
+      .as_array()
+      .ok_or("chart is missing its rules")?
+      .iter()
+      .map(|r| Rule::from_json(r).map(Arc::new))
+      .collect::<Result<Vec<_>, _>>()?;
+    let positions = value["states"]
+      .as_array()
+      .ok_or("chart is missing its states")?;
+
+    let mut chart = Chart::new(positions.len());
+    for (k, states) in positions.iter().enumerate() {
+      for state in states.as_array().ok_or("chart states must be lists")? {
+        let number = |name: &str| -> Result<usize, Err> {
+          Ok(
+            state[name]
+              .as_u64()
+              .ok_or_else(|| format!("bad state {}: {}", name, state))? as usize,
+          )
+        };
+        let rule = rules
+          .get(number("rule")?)
+          .ok_or_else(|| format!("state has no such rule: {}", state))?;
+        let pos = number("pos")?;
+        if pos > rule.len() {
+          return Err(format!("state is past the end of its rule: {}", state).into());
+        }
+        let lr0 = LR0 {
+          rule: rule.clone(),
+          pos,
+        };
+        chart.states[k].push(State::new(lr0, number("origin")?));
+        chart.scores[k].push(Some(1.0));
+      }
+    }
+    Ok(chart)
   }
 }
 
@@ -109,15 +356,53 @@ impl IntoIterator for Chart {
   type IntoIter = std::iter::Enumerate<std::vec::IntoIter<Vec<State>>>;
 
   fn into_iter(self) -> Self::IntoIter {
-    self.0.into_iter().enumerate()
+    self.states.into_iter().enumerate()
+  }
+}
+
+impl Chart {
+  /// The chart one position at a time, see `format_position`, for reading
+  /// long sentences' charts
+  pub fn display_grouped(&self, input: &[&str]) -> String {
+    (0..self.len())
+      .map(|k| format_position(k, self.states_at(k), input))
+      .collect::<Vec<_>>()
+      .join("\n")
+  }
+}
+
+/// The states at `k`, under a header with the word before `k`. Each has its
+/// span as an arrow from its origin, and its rule's location, like
+///
+/// ```text
+/// Position 1, after "mary":
+///   0 → 1  N → mary ・  line 3
+///   0 → 1  S → N ・ V   line 2
+///   1 → 1  V → ・ runs  line 4
+/// ```
+pub fn format_position(k: usize, states: &[State], input: &[&str]) -> String {
+  let mut out = match k.checked_sub(1).and_then(|i| input.get(i)) {
+    Some(word) => format!("Position {}, after {:?}:\n", k, word),
+    None => format!("Position {}:\n", k),
+  };
+  let lr0s = states.iter().map(|s| s.lr0.to_string()).collect::<Vec<_>>();
+  let width = lr0s.iter().map(|s| s.chars().count()).max().unwrap_or(0);
+  for (state, lr0) in states.iter().zip(lr0s) {
+    let line = match state.lr0.rule.location() {
+      Some(location) => format!("  {} → {}  {:width$}  {}", state.origin, k, lr0, location),
+      None => format!("  {} → {}  {}", state.origin, k, lr0),
+    };
+    out += line.trim_end();
+    out += "\n";
   }
+  out
 }
 
 impl fmt::Display for Chart {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     for k in 0..self.len() {
       writeln!(f, "State {}:", k)?;
-      for state in self.0[k].iter() {
+      for state in self.states[k].iter() {
         writeln!(f, "  {}..{}: {}", state.origin, k, state.lr0)?;
       }
     }
@@ -126,52 +411,138 @@ impl fmt::Display for Chart {
 }
 
 pub fn parse_chart(g: &Grammar, input: &[&str]) -> Chart {
-  let mut chart = Chart::new(input.len() + 1);
+  // the grammar's own start symbol was checked when it was built
+  parse_chart_with(g, input, &ParseOptions::default()).expect("grammar missing start rules")
+}
+
+/// Like `parse_chart`, with options. Fails with
+/// `GrammarError::UndefinedStart` if `ParseOptions::start` has no rules.
+pub fn parse_chart_with(
+  g: &Grammar,
+  input: &[&str],
+  options: &ParseOptions,
+) -> Result<Chart, GrammarError> {
+  let mut chart = Chart::new(0);
+  parse_chart_into(g, input, options, &mut chart)?;
+  Ok(chart)
+}
+
+/// Like `parse_chart_with`, but fills `chart` (after resetting it) instead of
+/// a new one, so its allocations can be reused across sentences
+pub fn parse_chart_into(
+  g: &Grammar,
+  input: &[&str],
+  options: &ParseOptions,
+  chart: &mut Chart,
+) -> Result<(), GrammarError> {
+  fill_chart(g, input, options, chart, false)
+}
 
-  for rule in g.rules.get(&g.start).expect("grammar missing start rules") {
-    chart.add(0, State::new(LR0::new(rule), 0));
+/// Like `parse_chart_with`, but predicting every rule at every position
+/// instead of only the start symbol's at the start, so the chart has every
+/// constituent over the input, not just those that fit a parse of the whole
+/// sentence
+pub(crate) fn parse_chart_everywhere(
+  g: &Grammar,
+  input: &[&str],
+  options: &ParseOptions,
+) -> Result<Chart, GrammarError> {
+  let mut chart = Chart::new(0);
+  fill_chart(g, input, options, &mut chart, true)?;
+  Ok(chart)
+}
+
+fn fill_chart(
+  g: &Grammar,
+  input: &[&str],
+  options: &ParseOptions,
+  chart: &mut Chart,
+  everywhere: bool,
+) -> Result<(), GrammarError> {
+  let start = options.start_symbol(g);
+  g.check_start(start)?;
+  trace_span!(debug_span, "parse_chart", tokens = input.len());
+  chart.reset(input.len() + 1);
+  chart.tokens.clear();
+  chart.tokens.extend(input.iter().map(|&t| Arc::from(t)));
+  chart.beam = options.beam;
+  chart.observer = options.observer.clone();
+
+  for rule in &g.rules[start] {
+    chart.add_scored(0, State::new(LR0::new(rule), 0), rule.weight);
   }
 
-  for k in 0..chart.len() {
+  'outer: for k in 0..chart.len() {
+    if everywhere {
+      for rule in g.rules.values().flatten() {
+        chart.add_scored(k, State::new(LR0::new(rule), k), rule.weight);
+      }
+    }
     // need to use while loop because the number of states at k can expand during the loop
     let mut idx = 0;
     while idx < chart.len_at(k) {
-      let state = chart.get_state(k, idx);
+      if options.is_cancelled() {
+        // the chart is incomplete, so callers need to check for this too
+        for k in k..chart.len() {
+          chart.compact(k);
+        }
+        break 'outer;
+      }
+      let (state, score) = chart.get_state(k, idx);
       idx += 1;
+      let score = match score {
+        Some(score) => score,
+        // dropped by the beam for a better state in its cell
+        None => continue,
+      };
 
       if let Some(production) = state.lr0.next_production() {
         if production.is_nonterminal() {
-          predictor(g, &mut chart, k, &state);
+          predictor(g, chart, k, &state, score);
         } else {
-          scanner(&mut chart, k, &state, input);
+          scanner(g, chart, k, &state, score, input);
         }
       } else {
-        completer(&mut chart, k, &state);
+        completer(chart, k, &state, score);
       }
     }
+    chart.compact(k);
+    chart.observe(|o| o.position_finished(k, chart.states_at(k)));
   }
 
-  chart
+  trace_event!(
+    debug,
+    states = (0..chart.len()).map(|k| chart.len_at(k)).sum::<usize>(),
+    "parsed chart"
+  );
+  Ok(())
 }
 
-fn completer(chart: &mut Chart, k: usize, state: &State) {
+fn completer(chart: &mut Chart, k: usize, state: &State, score: f64) {
   assert!(!state.lr0.is_active(), "tried to complete active state");
+  chart.observe(|o| o.completed(k, state));
 
   // lr0 has been completed, now look for states in the chart that are waiting for its symbol
   for idx in 0..chart.len_at(state.origin) {
-    let other = chart.get_state(state.origin, idx);
+    let (other, other_score) = chart.get_state(state.origin, idx);
 
     if let Some(np) = other.lr0.next_production() {
-      if np.symbol == state.lr0.rule.symbol {
+      if *np.symbol == state.lr0.rule.symbol
+        && other
+          .lr0
+          .rule
+          .allows_span(Some(other.lr0.pos), k - state.origin)
+      {
         // found one, advance its dot and add the new state to the chart *at k*,
         // because it's now waiting on a token there
-        chart.add(k, other.advance())
+        let other_score = other_score.expect("only complete states are dropped");
+        chart.add_scored(k, other.advance(), other_score * score);
       }
     }
   }
 }
 
-fn predictor(g: &Grammar, chart: &mut Chart, k: usize, state: &State) {
+fn predictor(g: &Grammar, chart: &mut Chart, k: usize, state: &State, score: f64) {
   assert!(state.lr0.is_active(), "tried to predict non-active state");
   assert!(
     state.lr0.next_production().unwrap().is_nonterminal(),
@@ -184,22 +555,33 @@ fn predictor(g: &Grammar, chart: &mut Chart, k: usize, state: &State) {
   let needed_symbol = &state.lr0.next_production().unwrap().symbol;
   for wanted_rule in g
     .rules
-    .get(needed_symbol)
+    .get(&**needed_symbol)
     .unwrap_or_else(|| panic!("missing rules for production {}", needed_symbol))
   {
-    chart.add(k, State::new(LR0::new(wanted_rule), k));
+    chart.add_scored(k, State::new(LR0::new(wanted_rule), k), wanted_rule.weight);
+  }
 
-    if g.is_nullable(needed_symbol) {
-      // automatically complete `state` early, because we know
-      // it will be completable anyways, because its next_production may be produced
-      // by empty input. If we don't do this, nullable rules won't be completed
-      // correctly, because complete() won't run after predict() without a new symbol.
-      chart.add(k, state.advance());
+  if g.is_nullable(needed_symbol) && state.lr0.rule.allows_span(Some(state.lr0.pos), 0) {
+    // automatically complete `state` early, because we know
+    // it will be completable anyways, because its next_production may be produced
+    // by empty input. If we don't do this, nullable rules won't be completed
+    // correctly, because complete() won't run after predict() without a new symbol.
+    chart.add_scored(k, state.advance(), score);
+
+    // the empty constituents under it would be found by predicting and
+    // completing them one by one, but they're the same every time, so add
+    // them all at once
+    for rule in g.empty_completions(needed_symbol) {
+      let lr0 = LR0 {
+        rule: rule.clone(),
+        pos: rule.len(),
+      };
+      chart.add_scored(k, State::new(lr0, k), rule.weight);
     }
   }
 }
 
-fn scanner(chart: &mut Chart, k: usize, state: &State, input: &[&str]) {
+fn scanner(g: &Grammar, chart: &mut Chart, k: usize, state: &State, score: f64, input: &[&str]) {
   assert!(state.lr0.is_active(), "tried to scan non-active state");
   assert!(
     state.lr0.next_production().unwrap().is_terminal(),
@@ -207,9 +589,150 @@ fn scanner(chart: &mut Chart, k: usize, state: &State, input: &[&str]) {
   );
 
   let needed_symbol = &state.lr0.next_production().unwrap().symbol;
-  if k < input.len() && input[k] == needed_symbol {
+  if k < input.len()
+    && g.terminal_matches(needed_symbol, input[k])
+    && state.lr0.rule.allows_span(Some(state.lr0.pos), 1)
+  {
+    chart.observe(|o| o.scanned(k, state, input[k]));
     // advance the state to consume this token, and add to state k + 1, where
     // it will look for the next token
-    chart.add(k + 1, state.advance());
+    chart.add_scored(k + 1, state.advance(), score);
   }
 }
+
+#[test]
+fn test_reset_keeps_allocations() {
+  let g: Grammar = "S -> x S\nS -> x".parse().unwrap();
+  let mut chart = parse_chart(&g, &["x", "x", "x", "x"]);
+  let capacity = |c: &Chart| -> usize {
+    c.states
+      .iter()
+      .chain(c.spare.iter())
+      .map(Vec::capacity)
+      .sum()
+  };
+  let before = capacity(&chart);
+
+  chart.reset(1);
+  assert_eq!(chart.len(), 1);
+  assert_eq!(chart.len_at(0), 0);
+  assert_eq!(capacity(&chart), before);
+  chart.reset(5);
+  assert_eq!(capacity(&chart), before);
+  parse_chart_into(&g, &["x", "x"], &ParseOptions::default(), &mut chart).unwrap();
+  assert_eq!(chart.len(), 3);
+  assert_eq!(capacity(&chart), before);
+}
+
+#[test]
+fn test_chart_json() {
+  let g: Grammar = r#"
+    S -> subj:N[ num: #1 ] V[ num: #1 ]
+    @weight(0.5) N[ num: sg, agr.per: 3 ] -> mary
+    V[ num: sg ] -> runs
+  "#
+  .parse()
+  .unwrap();
+
+  let chart = parse_chart(&g, &["mary", "runs"]);
+  let json = chart.to_json();
+  let replayed = Chart::from_json(&serde_json::from_str(&json.to_string()).unwrap()).unwrap();
+  assert_eq!(replayed.to_string(), chart.to_string());
+  assert_eq!(replayed.to_json(), json);
+
+  // the replayed chart builds the same trees, with the same features
+  let forest = crate::Forest::from(&replayed);
+  let trees = forest.trees(&g);
+  assert_eq!(trees.len(), 1);
+  let (tree, fs) = Grammar::unify_tree(trees[0].clone()).unwrap();
+  assert_eq!(tree.to_sexpr(), "(S (N mary) (V runs))");
+  assert_eq!(
+    fs.get_path("child-0.agr.per").unwrap().get_str().as_deref(),
+    Some("3")
+  );
+  assert_eq!(trees[0].derivation()[1].weight, 0.5);
+  assert_eq!(
+    trees[0].derivation()[0].productions[0].function.as_deref(),
+    Some("subj")
+  );
+
+  assert!(Chart::from_json(
+    &serde_json::json!({ "rules": [], "states": [[{ "rule": 0, "pos": 0, "origin": 0 }]] })
+  )
+  .is_err());
+}
+
+#[test]
+fn test_display_grouped() {
+  use std::sync::Mutex;
+
+  use crate::observer::ParseObserver;
+
+  let g: Grammar = r#"
+    S -> N V
+    N -> mary
+    V -> runs
+  "#
+  .parse()
+  .unwrap();
+  let input = ["mary", "runs"];
+  let chart = parse_chart(&g, &input);
+  assert_eq!(
+    chart.display_grouped(&input),
+    r#"Position 0:
+  0 → 0  S → ・ N V   line 2
+  0 → 0  N → ・ mary  line 3
+
+Position 1, after "mary":
+  0 → 1  N → mary ・  line 3
+  0 → 1  S → N ・ V   line 2
+  1 → 1  V → ・ runs  line 4
+
+Position 2, after "runs":
+  1 → 2  V → runs ・  line 4
+  0 → 2  S → N V ・   line 2
+"#
+  );
+
+  // streamed, a position at a time, it's the same
+  #[derive(Default)]
+  struct Printer(Mutex<Vec<String>>);
+
+  impl ParseObserver for Printer {
+    fn position_finished(&self, k: usize, states: &[State]) {
+      let input = ["mary", "runs"];
+      self
+        .0
+        .lock()
+        .unwrap()
+        .push(format_position(k, states, &input));
+    }
+  }
+
+  let printer = Arc::new(Printer::default());
+  let options = ParseOptions {
+    observer: Some(printer.clone()),
+    ..ParseOptions::default()
+  };
+  parse_chart_with(&g, &input, &options).unwrap();
+  assert_eq!(
+    printer.0.lock().unwrap().join("\n"),
+    chart.display_grouped(&input)
+  );
+}
+
+#[test]
+fn test_parse_chart_undefined_start() {
+  let g: Grammar = "S -> NP\nNP -> x".parse().unwrap();
+  let options = |start: &str| ParseOptions {
+    start: Some(start.to_string()),
+    ..ParseOptions::default()
+  };
+  assert!(parse_chart_with(&g, &["x"], &options("NP")).is_ok());
+  assert!(matches!(
+    parse_chart_with(&g, &["x"], &options("Np")),
+    Err(GrammarError::UndefinedStart(s)) if s == "Np"
+  ));
+  let mut chart = Chart::new(0);
+  assert!(parse_chart_into(&g, &["x"], &options("Np"), &mut chart).is_err());
+}
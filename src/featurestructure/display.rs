@@ -0,0 +1,339 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write};
+
+use super::node::{count_in_pointers, Node, NodeRef};
+use crate::utils::escape_mermaid;
+
+/// Options for printing feature structures, see `NodeRef::display_with`
+#[derive(Debug, Clone, Default)]
+pub struct DisplayOptions {
+  /// Print everything on one line: `[ case: nom, num: sg ]`
+  pub compact: bool,
+  /// Print complex features on one line when they fit in this many columns,
+  /// and over multiple lines when they don't
+  pub max_width: Option<usize>,
+  /// Features to leave out, as dotted paths that are matched against the end
+  /// of each feature's path. `*` matches any part of a name, so `child-*.word`
+  /// (or just `word`) hides the words under every constituent. Complex
+  /// features with nothing left to show are left out too.
+  pub hidden_paths: Vec<String>,
+}
+
+impl DisplayOptions {
+  fn is_hidden(&self, path: &[String]) -> bool {
+    self.hidden_paths.iter().any(|pattern| {
+      let pattern = pattern.split('.').collect::<Vec<_>>();
+      pattern.len() <= path.len()
+        && pattern
+          .iter()
+          .zip(path[path.len() - pattern.len()..].iter())
+          .all(|(p, label)| glob_matches(p, label))
+    })
+  }
+}
+
+/// Matches a label against a pattern where `*` matches any run of characters
+fn glob_matches(pattern: &str, s: &str) -> bool {
+  match pattern.split_once('*') {
+    None => pattern == s,
+    Some((prefix, rest)) => {
+      s.starts_with(prefix)
+        && (prefix.len()..=s.len())
+          .filter(|idx| s.is_char_boundary(*idx))
+          .any(|idx| glob_matches(rest, &s[idx..]))
+    }
+  }
+}
+
+/// A feature structure with display options, made by `NodeRef::display_with`
+pub struct NodeDisplay<'a> {
+  node: &'a NodeRef,
+  options: &'a DisplayOptions,
+}
+
+impl NodeRef {
+  /// Renders this feature structure as a [mermaid](https://mermaid.js.org/)
+  /// flowchart, with complex features as circles and edges labeled with
+  /// feature names. Shared nodes only appear once, so reentrancy shows up as
+  /// several edges pointing at the same node.
+  pub fn to_mermaid(&self) -> String {
+    let mut out = String::from("graph LR\n");
+    let mut ids = HashMap::new();
+    write_mermaid(self.clone(), &mut ids, &mut out);
+    out
+  }
+
+  pub fn display_with<'a>(&'a self, options: &'a DisplayOptions) -> NodeDisplay<'a> {
+    NodeDisplay {
+      node: self,
+      options,
+    }
+  }
+}
+
+/// Writes a node and everything under it, returning the node's id
+fn write_mermaid(nref: NodeRef, ids: &mut HashMap<NodeRef, usize>, out: &mut String) -> usize {
+  let nref = nref.dereference();
+  if let Some(id) = ids.get(&nref) {
+    return *id;
+  }
+  let id = ids.len();
+  ids.insert(nref.clone(), id);
+
+  let r = &*nref.borrow();
+  match r {
+    Node::Top => writeln!(out, "  n{}[\"**top**\"]", id).unwrap(),
+    Node::Str(s) => writeln!(out, "  n{}[\"{}\"]", id, escape_mermaid(s)).unwrap(),
+    Node::Edged(arcs) => {
+      writeln!(out, "  n{}((\" \"))", id).unwrap();
+      let mut arcs = arcs.iter().collect::<Vec<_>>();
+      arcs.sort_by_key(|(label, _)| *label);
+      for (label, value) in arcs {
+        let value_id = write_mermaid(value.clone(), ids, out);
+        writeln!(
+          out,
+          "  n{} -->|{}| n{}",
+          id,
+          escape_mermaid(label),
+          value_id
+        )
+        .unwrap();
+      }
+    }
+    Node::Forwarded(_) => panic!("unexpected forward"),
+  }
+  id
+}
+
+struct Printer<'a> {
+  counts: HashMap<NodeRef, usize>,
+  /// The shared nodes printed with the `#tag` they were written with in the
+  /// grammar, see `claim_tags`
+  tagged: HashMap<NodeRef, String>,
+  options: &'a DisplayOptions,
+}
+
+/// Gives each shared node with a grammar tag that tag, unless a node visited
+/// earlier (in pre-order, with labels sorted) already has it, since nodes from
+/// different rules can have the same tag
+fn claim_tags(
+  nref: NodeRef,
+  counts: &HashMap<NodeRef, usize>,
+  seen: &mut HashSet<NodeRef>,
+  tagged: &mut HashMap<NodeRef, String>,
+) {
+  let nref = nref.dereference();
+  if !seen.insert(nref.clone()) {
+    return;
+  }
+  if let Some(tag) = nref.tag() {
+    if counts[&nref] > 1 && !tagged.values().any(|t| t == tag) {
+      tagged.insert(nref.clone(), tag.to_string());
+    }
+  }
+  let mut arcs = match nref.borrow().edged() {
+    Some(arcs) => arcs.iter().map(|(l, v)| (l.clone(), v.clone())).collect(),
+    None => Vec::new(),
+  };
+  arcs.sort_by(|a, b| a.0.cmp(&b.0));
+  for (_, value) in arcs {
+    claim_tags(value, counts, seen, tagged);
+  }
+}
+
+impl Printer<'_> {
+  /// The tag to print a shared node with the first time: its grammar tag, or
+  /// else the next number that isn't some other node's grammar tag
+  fn tag_for(&self, nref: &NodeRef, has_printed: &HashMap<NodeRef, String>) -> String {
+    if let Some(tag) = self.tagged.get(nref) {
+      return tag.clone();
+    }
+    let numbered = has_printed
+      .keys()
+      .filter(|n| !self.tagged.contains_key(*n))
+      .count();
+    (0..)
+      .map(|n: usize| n.to_string())
+      .filter(|n| !self.tagged.values().any(|t| t == n))
+      .nth(numbered)
+      .unwrap()
+  }
+
+  /// If a node has anything left to print after hiding paths
+  fn is_visible(
+    &self,
+    nref: &NodeRef,
+    path: &mut Vec<String>,
+    ancestors: &mut Vec<NodeRef>,
+  ) -> bool {
+    if self.options.is_hidden(path) {
+      return false;
+    }
+
+    let nref = nref.clone().dereference();
+    if self.options.hidden_paths.is_empty() || ancestors.contains(&nref) {
+      return true;
+    }
+
+    let n = nref.borrow();
+    match n.edged() {
+      Some(arcs) if !arcs.is_empty() => {
+        ancestors.push(nref.clone());
+        let visible = arcs.iter().any(|(label, value)| {
+          path.push(label.clone());
+          let visible = self.is_visible(value, path, ancestors);
+          path.pop();
+          visible
+        });
+        ancestors.pop();
+        visible
+      }
+      _ => true,
+    }
+  }
+
+  #[allow(clippy::too_many_arguments)]
+  fn print(
+    &self,
+    nref: NodeRef,
+    path: &mut Vec<String>,
+    has_printed: &mut HashMap<NodeRef, String>,
+    indent: usize,
+    column: usize,
+    compact: bool,
+    out: &mut String,
+  ) -> fmt::Result {
+    let nref = nref.dereference();
+
+    if self.counts[&nref] > 1 && has_printed.contains_key(&nref) {
+      return write!(out, "#{}", has_printed[&nref]);
+    }
+
+    let start = out.len();
+    if self.counts[&nref] > 1 {
+      let tag = self.tag_for(&nref, has_printed);
+      write!(out, "#{} ", tag)?;
+      has_printed.insert(nref.clone(), tag);
+    }
+    let column = column + out.len() - start;
+
+    let r = &*nref.borrow();
+    match r {
+      Node::Top => write!(out, "**top**"),
+      Node::Str(s) => write!(out, "{}", s),
+      Node::Edged(arcs) => {
+        // sorted, so output doesn't change between runs
+        let mut arcs = arcs
+          .iter()
+          .filter(|(label, value)| {
+            path.push(label.to_string());
+            let visible = self.is_visible(value, path, &mut Vec::new());
+            path.pop();
+            visible
+          })
+          .collect::<Vec<_>>();
+        arcs.sort_by_key(|(label, _)| *label);
+
+        if arcs.is_empty() {
+          return write!(out, "[]");
+        }
+
+        if !compact && arcs.len() > 1 {
+          if let Some(max_width) = self.options.max_width {
+            let mut trial = String::new();
+            let mut trial_printed = has_printed.clone();
+            self.print_arcs(&arcs, path, &mut trial_printed, 0, column, true, &mut trial)?;
+            if column + trial.len() <= max_width {
+              *has_printed = trial_printed;
+              return write!(out, "{}", trial);
+            }
+          }
+        }
+
+        self.print_arcs(&arcs, path, has_printed, indent, column, compact, out)
+      }
+      Node::Forwarded(_) => panic!("unexpected forward"),
+    }
+  }
+}
+
+impl Printer<'_> {
+  #[allow(clippy::too_many_arguments)]
+  fn print_arcs(
+    &self,
+    arcs: &[(&String, &NodeRef)],
+    path: &mut Vec<String>,
+    has_printed: &mut HashMap<NodeRef, String>,
+    indent: usize,
+    column: usize,
+    compact: bool,
+    out: &mut String,
+  ) -> fmt::Result {
+    if compact || arcs.len() == 1 {
+      let start = out.len();
+      write!(out, "[ ")?;
+      for (idx, (label, value)) in arcs.iter().enumerate() {
+        if idx > 0 {
+          write!(out, ", ")?;
+        }
+        write!(out, "{}: ", label)?;
+        path.push(label.to_string());
+        let column = column + out.len() - start;
+        self.print((*value).clone(), path, has_printed, 0, column, compact, out)?;
+        path.pop();
+      }
+      write!(out, " ]")
+    } else {
+      writeln!(out, "[")?;
+      for (label, value) in arcs {
+        write!(out, "{:indent$}{}: ", "", label, indent = indent + 2)?;
+        path.push(label.to_string());
+        let column = indent + 2 + label.len() + 2;
+        self.print(
+          (*value).clone(),
+          path,
+          has_printed,
+          indent + 2,
+          column,
+          compact,
+          out,
+        )?;
+        path.pop();
+        writeln!(out)?;
+      }
+      write!(out, "{:indent$}]", "", indent = indent)
+    }
+  }
+}
+
+impl fmt::Display for NodeDisplay<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut counts = HashMap::new();
+    count_in_pointers(self.node.clone(), &mut counts);
+    let mut tagged = HashMap::new();
+    claim_tags(self.node.clone(), &counts, &mut HashSet::new(), &mut tagged);
+    let printer = Printer {
+      counts,
+      tagged,
+      options: self.options,
+    };
+
+    let mut out = String::new();
+    printer.print(
+      self.node.clone(),
+      &mut Vec::new(),
+      &mut HashMap::new(),
+      0,
+      0,
+      self.options.compact,
+      &mut out,
+    )?;
+    f.write_str(&out)
+  }
+}
+
+impl fmt::Display for NodeRef {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.display_with(&DisplayOptions::default()))
+  }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use super::node::{Node, NodeRef};
+use crate::utils::Err;
+
+#[derive(Debug)]
+enum FrozenKind {
+  Top,
+  Str(Arc<str>),
+  /// Edges point at indices in the node list, so shared nodes are kept shared
+  Edged(Vec<(String, usize)>),
+}
+
+/// An immutable, persistent snapshot of a feature structure. Unlike a
+/// NodeRef, it's never mutated, so one copy can be shared between threads and
+/// parses without locking or deep_clone-ing. Unifying produces a new
+/// FrozenNode and leaves the inputs alone.
+///
+/// Reentrancy is preserved: nodes shared in the original structure are shared
+/// in the snapshot and in every NodeRef thawed from it.
+#[derive(Debug, Clone)]
+pub struct FrozenNode(Arc<Vec<FrozenKind>>);
+
+impl FrozenNode {
+  /// Makes a new mutable NodeRef with the same structure
+  pub fn thaw(&self) -> NodeRef {
+    let refs = self
+      .0
+      .iter()
+      .map(|_| NodeRef::new_top())
+      .collect::<Vec<_>>();
+
+    for (kind, nref) in self.0.iter().zip(refs.iter()) {
+      let node = match kind {
+        FrozenKind::Top => continue,
+        FrozenKind::Str(s) => Node::Str(s.clone()),
+        FrozenKind::Edged(edges) => Node::Edged(
+          edges
+            .iter()
+            .map(|(label, idx)| (label.clone(), refs[*idx].clone()))
+            .collect(),
+        ),
+      };
+      nref.replace(node);
+    }
+
+    // the root is always the first node
+    refs[0].clone()
+  }
+
+  /// Unifies two snapshots, returning the result as a new snapshot
+  pub fn unify(&self, other: &FrozenNode) -> Result<FrozenNode, Err> {
+    let (n1, n2) = (self.thaw(), other.thaw());
+    NodeRef::unify(n1.clone(), n2)?;
+    Ok((&n1).into())
+  }
+
+  fn freeze(
+    nref: &NodeRef,
+    nodes: &mut Vec<FrozenKind>,
+    seen: &mut HashMap<NodeRef, usize>,
+  ) -> usize {
+    let nref = nref.clone().dereference();
+    // interned nodes are frozen once per path, so they aren't shared
+    if let Some(idx) = seen.get(&nref).filter(|_| !nref.is_interned()) {
+      return *idx;
+    }
+
+    let idx = nodes.len();
+    seen.insert(nref.clone(), idx);
+    // placeholder until the children are frozen
+    nodes.push(FrozenKind::Top);
+
+    let kind = match &*nref.borrow() {
+      Node::Top => FrozenKind::Top,
+      Node::Str(s) => FrozenKind::Str(s.clone()),
+      Node::Edged(edges) => {
+        let mut edges = edges.iter().collect::<Vec<_>>();
+        edges.sort_by_key(|(label, _)| *label);
+        FrozenKind::Edged(
+          edges
+            .into_iter()
+            .map(|(label, value)| (label.clone(), Self::freeze(value, nodes, seen)))
+            .collect(),
+        )
+      }
+      Node::Forwarded(_) => unreachable!("dereferenced above"),
+    };
+    nodes[idx] = kind;
+    idx
+  }
+}
+
+impl From<&NodeRef> for FrozenNode {
+  fn from(nref: &NodeRef) -> Self {
+    let mut nodes = Vec::new();
+    Self::freeze(nref, &mut nodes, &mut HashMap::new());
+    Self(Arc::new(nodes))
+  }
+}
+
+impl fmt::Display for FrozenNode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.thaw())
+  }
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use super::node::{Feature, NodeRef};
+use super::serialized::{SerializedDag, SerializedNode};
+use crate::fgr::TOP_STR;
+use crate::utils::Err;
+
+impl NodeRef {
+  /// Builds a feature structure from a JSON object. Objects become complex
+  /// features, strings (and numbers and bools, as strings) become values, and
+  /// `null` or `"**top**"` is **top**. Reentrancy uses the same `#tag`
+  /// convention as grammar files: `"#1 sg"` and `"#1"` anywhere in the object
+  /// refer to the same node, whose value is `sg`.
+  ///
+  /// ```
+  /// let fs = treebender::NodeRef::from_json(&serde_json::json!({
+  ///   "num": "#1 sg",
+  ///   "subj": { "num": "#1" },
+  /// })).unwrap();
+  /// let flat: std::collections::HashMap<String, String> = (&fs).into();
+  /// assert_eq!(flat["subj.num"], "sg");
+  /// ```
+  pub fn from_json(value: &Value) -> Result<NodeRef, Err> {
+    if !value.is_object() {
+      return Err(format!("expected a JSON object, got {}", value).into());
+    }
+
+    let mut features = Vec::new();
+    collect_json_features(value, &mut Vec::new(), &mut features)?;
+    NodeRef::new_from_paths(features)
+  }
+
+  /// The inverse of `from_json`: complex features become objects and values
+  /// become strings, with **top** left out. Shared values are written with
+  /// `#tag`s, but since only values can be tagged, shared complex features
+  /// are copied to each place they appear.
+  pub fn to_json(&self) -> Value {
+    match Option::<SerializedDag>::from(self) {
+      Some(dag) => {
+        let mut tagged = HashMap::new();
+        collect_tagged(&dag, &mut tagged);
+        dag_to_json(&dag, &tagged)
+      }
+      None => Value::Object(Map::new()),
+    }
+  }
+}
+
+fn collect_tagged<'a>(dag: &'a SerializedDag, tagged: &mut HashMap<usize, &'a SerializedDag>) {
+  match dag {
+    SerializedDag::Tagged { id, value } => {
+      tagged.insert(*id, value);
+      collect_tagged(value, tagged);
+    }
+    SerializedDag::Edged(map) => map.values().for_each(|v| collect_tagged(v, tagged)),
+    _ => {}
+  }
+}
+
+fn dag_to_json(dag: &SerializedDag, tagged: &HashMap<usize, &SerializedDag>) -> Value {
+  match dag {
+    SerializedDag::Top => Value::Null,
+    SerializedDag::Str(s) => Value::String(s.clone()),
+    SerializedDag::Edged(map) => Value::Object(
+      map
+        .iter()
+        .map(|(label, value)| (label.clone(), dag_to_json(value, tagged)))
+        .collect(),
+    ),
+    SerializedDag::Tagged { id, value } => match &**value {
+      SerializedDag::Top => Value::String(format!("#{}", id)),
+      SerializedDag::Str(s) => Value::String(format!("#{} {}", id, s)),
+      value => dag_to_json(value, tagged),
+    },
+    SerializedDag::Ref(id) => match tagged[id] {
+      SerializedDag::Top | SerializedDag::Str(_) => Value::String(format!("#{}", id)),
+      value => dag_to_json(value, tagged),
+    },
+  }
+}
+
+fn collect_json_features(
+  value: &Value,
+  path: &mut Vec<String>,
+  features: &mut Vec<Feature>,
+) -> Result<(), Err> {
+  let (tag, value) = match value {
+    Value::Object(map) => {
+      if map.is_empty() && !path.is_empty() {
+        features.push(Feature {
+          path: path.join("."),
+          tag: None,
+          value: NodeRef::new_with_edges(Vec::new())?,
+        });
+      }
+      for (label, value) in map.iter() {
+        if label.is_empty() || label.contains('.') {
+          return Err(format!("bad feature name in JSON: {:?}", label).into());
+        }
+        path.push(label.clone());
+        collect_json_features(value, path, features)?;
+        path.pop();
+      }
+      return Ok(());
+    }
+    Value::Array(_) => return Err(format!("arrays can't be features: {}", value).into()),
+    Value::Null => (None, NodeRef::new_top()),
+    Value::String(s) => parse_tagged(s),
+    Value::Bool(_) | Value::Number(_) => (None, NodeRef::new_str(value.to_string())),
+  };
+
+  features.push(Feature {
+    path: path.join("."),
+    tag,
+    value,
+  });
+  Ok(())
+}
+
+/// Splits "#tag value", "#tag", or "value"
+fn parse_tagged(s: &str) -> (Option<String>, NodeRef) {
+  let (tag, s) = match s.strip_prefix('#') {
+    Some(rest) => {
+      let mut parts = rest.splitn(2, char::is_whitespace);
+      let tag = parts.next().unwrap_or("").to_string();
+      (Some(tag), parts.next().unwrap_or("").trim())
+    }
+    None => (None, s),
+  };
+
+  let value = if (s.is_empty() && tag.is_some()) || s == TOP_STR {
+    NodeRef::new_top()
+  } else {
+    NodeRef::new_str(s.to_string())
+  };
+  (tag, value)
+}
+
+impl From<&SerializedNode> for NodeRef {
+  /// Serialized nodes have no reentrancy, so neither does the result
+  fn from(sn: &SerializedNode) -> Self {
+    match sn {
+      SerializedNode::Str(s) => NodeRef::new_str(s.clone()),
+      SerializedNode::Edged(map) => NodeRef::new_with_edges(
+        map
+          .iter()
+          .map(|(label, value)| (label.clone(), NodeRef::from(value))),
+      )
+      .expect("labels in a map are unique, so this can't fail to unify"),
+    }
+  }
+}
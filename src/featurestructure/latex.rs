@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use super::serialized::{SerializedDag, SerializedNode};
+
+/// Escapes the characters LaTeX treats specially
+fn escape(s: &str) -> String {
+  let mut out = String::with_capacity(s.len());
+  for c in s.chars() {
+    match c {
+      '_' | '&' | '%' | '$' | '#' | '{' | '}' => {
+        out.push('\\');
+        out.push(c);
+      }
+      '\\' => out.push_str("\\textbackslash{}"),
+      '~' => out.push_str("\\textasciitilde{}"),
+      '^' => out.push_str("\\textasciicircum{}"),
+      _ => out.push(c),
+    }
+  }
+  out
+}
+
+/// Tags are 1-based in papers. langsci-avm has shorthands for \1 to \9
+fn tag(id: usize) -> String {
+  if id < 9 {
+    format!("\\{}", id + 1)
+  } else {
+    format!("\\tag{{{}}}", id + 1)
+  }
+}
+
+/// Writes `[ label & value \\ ... ]`, with one feature per line
+fn write_matrix<T>(
+  map: &HashMap<String, T>,
+  indent: usize,
+  out: &mut String,
+  write_value: impl Fn(&T, usize, &mut String),
+) {
+  let mut entries = map.iter().collect::<Vec<_>>();
+  entries.sort_by_key(|(label, _)| *label);
+
+  out.push_str("[ ");
+  for (idx, (label, value)) in entries.iter().enumerate() {
+    if idx > 0 {
+      out.push_str(" \\\\\n");
+      out.push_str(&" ".repeat(indent + 2));
+    }
+    let label = escape(label);
+    out.push_str(&label);
+    out.push_str(" & ");
+    write_value(value, indent + 2 + label.len() + 3, out);
+  }
+  out.push_str(" ]");
+}
+
+fn write_node(node: &SerializedNode, indent: usize, out: &mut String) {
+  match node {
+    SerializedNode::Str(s) => out.push_str(&escape(s)),
+    SerializedNode::Edged(map) => write_matrix(map, indent, out, write_node),
+  }
+}
+
+fn write_dag(dag: &SerializedDag, indent: usize, out: &mut String) {
+  match dag {
+    SerializedDag::Top => out.push_str("[ ]"),
+    SerializedDag::Str(s) => out.push_str(&escape(s)),
+    SerializedDag::Edged(map) => write_matrix(map, indent, out, write_dag),
+    SerializedDag::Tagged { id, value } => {
+      let tag = tag(*id);
+      out.push_str(&tag);
+      if **value != SerializedDag::Top {
+        out.push(' ');
+        write_dag(value, indent + tag.len() + 1, out);
+      }
+    }
+    SerializedDag::Ref(id) => out.push_str(&tag(*id)),
+  }
+}
+
+impl SerializedNode {
+  /// Renders this structure as an attribute-value matrix for the
+  /// [langsci-avm](https://ctan.org/pkg/langsci-avm) LaTeX package. There's no
+  /// reentrancy in a SerializedNode, see SerializedDag::to_latex_avm for tags.
+  pub fn to_latex_avm(&self) -> String {
+    let mut out = String::from("\\avm{\n");
+    write_node(self, 0, &mut out);
+    out.push_str("\n}");
+    out
+  }
+}
+
+impl SerializedDag {
+  /// Renders this structure as an attribute-value matrix for the
+  /// [langsci-avm](https://ctan.org/pkg/langsci-avm) LaTeX package, with
+  /// boxed tags (\1, \2, ...) for shared nodes
+  pub fn to_latex_avm(&self) -> String {
+    let mut out = String::from("\\avm{\n");
+    write_dag(self, 0, &mut out);
+    out.push_str("\n}");
+    out
+  }
+}
@@ -1,8 +1,16 @@
+mod display;
+mod frozen;
+mod json;
+mod latex;
 mod node;
+mod normal;
 mod serialized;
 
-pub use node::{Feature, NodeRef};
-pub use serialized::SerializedNode;
+pub use display::{DisplayOptions, NodeDisplay};
+pub use frozen::FrozenNode;
+pub use node::{Feature, FeatureStructureTooLarge, Interner, NodeRef, UnificationFailure};
+pub use normal::Normalized;
+pub use serialized::{SerializedDag, SerializedNode};
 
 #[cfg(test)]
 mod tests {
@@ -84,4 +92,418 @@ mod tests {
     assert!(Option::<SerializedNode>::from(&fs1) == Some(gold.clone()));
     assert!(Option::<SerializedNode>::from(&fs2) == Some(gold));
   }
+
+  #[test]
+  fn test_walk_and_get_path() {
+    let fs1 = NodeRef::new_from_paths(vec![
+      Feature {
+        path: "a.b".to_string(),
+        tag: Some("1".to_string()),
+        value: NodeRef::new_top(),
+      },
+      Feature {
+        path: "c".to_string(),
+        tag: Some("1".to_string()),
+        value: NodeRef::new_top(),
+      },
+    ])
+    .unwrap();
+    let fs2 = NodeRef::new_from_paths(vec![Feature {
+      path: "c".to_string(),
+      tag: None,
+      value: NodeRef::new_str("foo".to_string()),
+    }])
+    .unwrap();
+    NodeRef::unify(fs1.clone(), fs2.clone()).unwrap();
+
+    let leaves = fs1
+      .walk()
+      .filter_map(|(path, node)| Some((path.join("."), node.get_str()?.to_string())))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      leaves,
+      vec![
+        ("a.b".to_string(), "foo".to_string()),
+        ("c".to_string(), "foo".to_string())
+      ]
+    );
+    assert_eq!(
+      fs2.get_path("a.b").unwrap().get_str().as_deref(),
+      Some("foo")
+    );
+    assert_eq!(fs2.get_path(""), Some(fs2.clone().dereference()));
+    assert!(fs2.get_path("a.c").is_none());
+    assert!(fs2.get_path("c.d").is_none());
+    let flat: std::collections::HashMap<String, String> = (&fs2).into();
+    assert_eq!(flat.len(), 2);
+    assert_eq!(flat["a.b"], "foo");
+
+    let paths = Option::<SerializedNode>::from(&fs1)
+      .unwrap()
+      .walk()
+      .map(|(path, _)| path.join("."))
+      .collect::<Vec<_>>();
+    assert_eq!(paths, vec!["", "a", "a.b", "c"]);
+  }
+
+  #[test]
+  fn test_equivalent() {
+    let shared = |tag: Option<&str>| {
+      NodeRef::new_from_paths(vec![
+        Feature {
+          path: "a".to_string(),
+          tag: tag.map(str::to_string),
+          value: NodeRef::new_str("x".to_string()),
+        },
+        Feature {
+          path: "b".to_string(),
+          tag: tag.map(str::to_string),
+          value: NodeRef::new_str("x".to_string()),
+        },
+      ])
+      .unwrap()
+    };
+
+    let (fs1, fs2, fs3) = (shared(Some("1")), shared(Some("2")), shared(None));
+
+    assert!(NodeRef::equivalent(&fs1, &fs2));
+    assert!(NodeRef::equivalent(&fs1, &fs1.deep_clone()));
+    assert!(NodeRef::equivalent(&fs3, &shared(None)));
+    // same values, different sharing
+    assert!(!NodeRef::equivalent(&fs1, &fs3));
+    assert!(Option::<SerializedNode>::from(&fs1) == Option::<SerializedNode>::from(&fs3));
+  }
+
+  #[test]
+  fn test_intern_strs() {
+    let fs = NodeRef::new_from_paths(["a", "b.c", "d"].iter().zip(["x", "x", "y"]).map(
+      |(path, value)| Feature {
+        path: path.to_string(),
+        tag: None,
+        value: NodeRef::new_str(value.to_string()),
+      },
+    ))
+    .unwrap();
+
+    let mut interner = Interner::default();
+    fs.intern_strs(&mut interner);
+    assert_eq!(interner.len(), 2);
+    assert!(std::sync::Arc::ptr_eq(
+      &interner.intern("x"),
+      &interner.intern("x")
+    ));
+    assert_eq!(
+      Option::<SerializedNode>::from(&fs)
+        .unwrap()
+        .get_path_str(&["b", "c"]),
+      Some("x")
+    );
+  }
+
+  #[test]
+  fn test_intern_nodes() {
+    let g: crate::rules::Grammar = r#"
+      S[ a.num: sg, a.per: 3, b.num: sg, b.per: 3, c: #1, d: #1, c.num: sg ] -> x
+      T[ a.num: sg, a.per: 3 ] -> y
+    "#
+    .parse()
+    .unwrap();
+    let s = &g.rules["S"][0].features;
+    let t = &g.rules["T"][0].features;
+    let path = |fs: &NodeRef, path: &str| fs.get_path(path).unwrap();
+
+    // identical substructures are stored once, within and across rules
+    assert_eq!(path(s, "a"), path(s, "b"));
+    assert_eq!(path(s, "a"), path(t, "a"));
+    assert_eq!(path(s, "a.num"), path(s, "c.num"));
+    // but tagged ones are left alone
+    assert_eq!(path(s, "c"), path(s, "d"));
+    assert_ne!(path(s, "a"), path(s, "c"));
+    assert_eq!(s.to_string().matches("#1").count(), 2);
+
+    // and sharing them doesn't make them reentrant
+    let cloned = s.deep_clone();
+    assert_ne!(path(&cloned, "a"), path(&cloned, "b"));
+    assert_eq!(path(&cloned, "c"), path(&cloned, "d"));
+    assert!(NodeRef::equivalent(s, &cloned));
+    let thawed = FrozenNode::from(s).thaw();
+    assert_ne!(path(&thawed, "a"), path(&thawed, "b"));
+    assert!(NodeRef::equivalent(s, &thawed));
+
+    let extra = NodeRef::new_from_paths(vec![Feature {
+      path: "a.case".to_string(),
+      tag: None,
+      value: NodeRef::new_str("nom".to_string()),
+    }])
+    .unwrap();
+    NodeRef::unify(cloned.clone(), extra).unwrap();
+    assert!(path(&cloned, "b").get_path("case").is_none());
+    assert!(path(s, "a").get_path("case").is_none());
+  }
+
+  #[test]
+  fn test_frozen() {
+    let fs1 = NodeRef::new_from_paths(vec![
+      Feature {
+        path: "a".to_string(),
+        tag: Some("1".to_string()),
+        value: NodeRef::new_top(),
+      },
+      Feature {
+        path: "b".to_string(),
+        tag: Some("1".to_string()),
+        value: NodeRef::new_top(),
+      },
+    ])
+    .unwrap();
+    let fs2 = NodeRef::new_from_paths(vec![Feature {
+      path: "a".to_string(),
+      tag: None,
+      value: NodeRef::new_str("x".to_string()),
+    }])
+    .unwrap();
+
+    let (frozen1, frozen2) = (FrozenNode::from(&fs1), FrozenNode::from(&fs2));
+    assert!(NodeRef::equivalent(&frozen1.thaw(), &fs1));
+
+    let unified = frozen1.unify(&frozen2).unwrap();
+    // the inputs are untouched, and the result kept the reentrancy
+    assert!(NodeRef::equivalent(&frozen1.thaw(), &fs1));
+    let gold = SerializedNode::Edged(
+      vec![("a".into(), "x".into()), ("b".into(), "x".into())]
+        .into_iter()
+        .collect(),
+    );
+    assert!(Option::<SerializedNode>::from(&unified.thaw()) == Some(gold));
+
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+    assert_send_sync(&unified);
+  }
+
+  #[test]
+  fn test_from_json() {
+    let fs = NodeRef::from_json(&serde_json::json!({
+      "a": { "b": "#1" },
+      "c": "#1 foo",
+      "d": null,
+      "e": 3,
+    }))
+    .unwrap();
+
+    let gold = SerializedNode::Edged(
+      vec![
+        (
+          "a".into(),
+          SerializedNode::Edged(vec![("b".into(), "foo".into())].into_iter().collect()),
+        ),
+        ("c".into(), "foo".into()),
+        ("e".into(), "3".into()),
+      ]
+      .into_iter()
+      .collect(),
+    );
+    let serialized = Option::<SerializedNode>::from(&fs).unwrap();
+    assert!(serialized == gold);
+    assert!(Option::<SerializedNode>::from(&NodeRef::from(&serialized)) == Some(gold));
+
+    let json = fs.to_json();
+    assert_eq!(
+      json,
+      serde_json::json!({ "a": { "b": "#0 foo" }, "c": "#0", "e": "3" })
+    );
+    // d is **top**, so it's left out
+    assert_eq!(
+      Option::<SerializedDag>::from(&NodeRef::from_json(&json).unwrap()),
+      Option::<SerializedDag>::from(&fs)
+    );
+    assert_eq!(NodeRef::new_top().to_json(), serde_json::json!({}));
+
+    assert!(NodeRef::from_json(&serde_json::json!({ "a": "#1 x", "b": "#1 y" })).is_err());
+    assert!(NodeRef::from_json(&serde_json::json!({ "a.b": "x" })).is_err());
+    assert!(NodeRef::from_json(&serde_json::json!("x")).is_err());
+  }
+
+  #[test]
+  fn test_display_source_tags() {
+    let compact = DisplayOptions {
+      compact: true,
+      ..DisplayOptions::default()
+    };
+    let a: NodeRef = "[ x: #1 p, y: #1 ]".parse().unwrap();
+    assert_eq!(a.get_path("x").unwrap().tag(), Some("1"));
+    let b: NodeRef = "[ z: #1 q, w: #1 ]".parse().unwrap();
+    let shared = NodeRef::new_str("r".to_string());
+    let c = NodeRef::new_with_edges(vec![
+      ("m".to_string(), shared.clone()),
+      ("n".to_string(), shared),
+    ])
+    .unwrap();
+    let fs = NodeRef::new_with_edges(vec![
+      ("a".to_string(), a),
+      ("b".to_string(), b),
+      ("c".to_string(), c),
+    ])
+    .unwrap();
+    // b's #1 is taken by a's, and numbering skips it
+    assert_eq!(
+      fs.display_with(&compact).to_string(),
+      "[ a: [ x: #1 p, y: #1 ], b: [ w: #0 q, z: #0 ], c: [ m: #2 r, n: #2 ] ]"
+    );
+
+    // tags survive copying and unification
+    let rule: NodeRef = "[ child-0.num: #agr, child-1.num: #agr ]".parse().unwrap();
+    let rule = rule.deep_clone();
+    let child: NodeRef = "[ child-0.num: sg ]".parse().unwrap();
+    NodeRef::unify(rule.clone(), child).unwrap();
+    assert_eq!(
+      rule.display_with(&compact).to_string(),
+      "[ child-0: [ num: #agr sg ], child-1: [ num: #agr ] ]"
+    );
+  }
+
+  #[test]
+  fn test_display_options() {
+    let fs = NodeRef::from_json(&serde_json::json!({
+      "child-0": { "word": "she", "num": "#1 sg" },
+      "child-1": { "word": "likes", "num": "#1" },
+      "tense": "nonpast",
+    }))
+    .unwrap();
+
+    let mut options = DisplayOptions {
+      compact: true,
+      ..DisplayOptions::default()
+    };
+    assert_eq!(
+      fs.display_with(&options).to_string(),
+      "[ child-0: [ num: #1 sg, word: she ], child-1: [ num: #1, word: likes ], tense: nonpast ]"
+    );
+
+    options.hidden_paths = vec!["child-*.word".to_string()];
+    assert_eq!(
+      fs.display_with(&options).to_string(),
+      "[ child-0: [ num: #1 sg ], child-1: [ num: #1 ], tense: nonpast ]"
+    );
+
+    options.compact = false;
+    options.max_width = Some(30);
+    assert_eq!(
+      fs.display_with(&options).to_string(),
+      "[\n  child-0: [ num: #1 sg ]\n  child-1: [ num: #1 ]\n  tense: nonpast\n]"
+    );
+
+    assert_eq!(
+      NodeRef::from_json(&serde_json::json!({ "a": "#1 x", "b": "#1" }))
+        .unwrap()
+        .to_mermaid(),
+      "graph LR\n  n0((\" \"))\n  n1[\"x\"]\n  n0 -->|a| n1\n  n0 -->|b| n1\n"
+    );
+
+    // the default is unchanged
+    assert_eq!(
+      fs.to_string(),
+      fs.display_with(&DisplayOptions::default()).to_string()
+    );
+    assert!(fs.to_string().contains("    word: she\n"));
+  }
+
+  #[test]
+  fn test_latex_avm() {
+    let fs = NodeRef::from_json(&serde_json::json!({
+      "agr": { "num": "#1 sg" },
+      "needs_pron": "#1",
+      "case": "nom",
+    }))
+    .unwrap();
+
+    assert_eq!(
+      Option::<SerializedDag>::from(&fs).unwrap().to_latex_avm(),
+      "\\avm{\n[ agr & [ num & \\1 sg ] \\\\\n  case & nom \\\\\n  needs\\_pron & \\1 ]\n}"
+    );
+    assert_eq!(
+      Option::<SerializedNode>::from(&fs).unwrap().to_latex_avm(),
+      "\\avm{\n[ agr & [ num & sg ] \\\\\n  case & nom \\\\\n  needs\\_pron & sg ]\n}"
+    );
+  }
+
+  #[test]
+  fn test_unify_checks_atomic_values_first() {
+    let deep = |value: &str, num: &str| {
+      NodeRef::from_json(&serde_json::json!({
+        "a": { "b": { "c": { "d": value } } },
+        "e": { "f": value },
+        "num": num,
+      }))
+      .unwrap()
+    };
+
+    // every deep path conflicts too, but the atomic conflict is found first
+    for _ in 0..10 {
+      let err = NodeRef::unify_at(deep("x", "sg"), deep("y", "pl"), &mut Vec::new()).unwrap_err();
+      assert_eq!(err.path, vec!["num".to_string()]);
+    }
+
+    let (a, b) = (deep("x", "sg"), deep("x", "sg"));
+    NodeRef::unify(a.clone(), b.clone()).unwrap();
+    assert!(NodeRef::equivalent(&a, &deep("x", "sg")));
+  }
+
+  #[test]
+  fn test_unify_closing_a_cycle() {
+    // unifying a with c ties c back into itself through a
+    let a = "[ a: #2, a.a: **top**, b.c: #2, c.a: #2, c.b.c: x ]";
+    let b = "[ a: #1, a.c.b: **top**, b: **top**, c: #1 ]";
+    for (a, b) in [(a, b), (b, a)] {
+      NodeRef::unify(a.parse().unwrap(), b.parse().unwrap()).unwrap();
+    }
+  }
+
+  #[test]
+  fn test_normalize() {
+    let shared = NodeRef::from_json(&serde_json::json!({
+      "b": { "num": "#1 sg" },
+      "a": "#1",
+    }))
+    .unwrap();
+    // the same, built by unification, so it's full of forwards
+    let unified: NodeRef = "[ a: #1, b.num: #1 ]".parse().unwrap();
+    NodeRef::unify(unified.clone(), "[ b.num: sg ]".parse().unwrap()).unwrap();
+
+    let normal = shared.normalize();
+    assert_eq!(normal.canonical, "[ a: #0 sg, b: [ num: #0 ] ]");
+    assert_eq!(normal, unified.normalize());
+    assert_eq!(normal.hash, unified.normalize().hash);
+    assert_eq!(normal.hash, 0xbd339c6eda8b6eb6);
+    assert!(NodeRef::equivalent(&normal.fs, &shared));
+
+    let unshared: NodeRef = "[ a: sg, b.num: sg ]".parse().unwrap();
+    assert_ne!(unshared.normalize(), normal);
+    assert_ne!(unshared.normalize().hash, normal.hash);
+
+    let set = [shared, unified, unshared]
+      .iter()
+      .map(NodeRef::normalize)
+      .collect::<std::collections::HashSet<_>>();
+    assert_eq!(set.len(), 2);
+  }
+
+  #[test]
+  fn test_size_limits_count_shared_nodes_once() {
+    // each level points at the one below twice, so there are 2^40 paths
+    let mut fs = NodeRef::new_str("x".to_string());
+    for _ in 0..40 {
+      fs = NodeRef::new_with_edges(vec![("a".to_string(), fs.clone()), ("b".to_string(), fs)])
+        .unwrap();
+    }
+
+    assert!(FeatureStructureTooLarge::check(&fs, Some(41), Some(40)).is_ok());
+    let err = FeatureStructureTooLarge::check(&fs, Some(40), None).unwrap_err();
+    assert_eq!(
+      err,
+      FeatureStructureTooLarge {
+        nodes: 41,
+        depth: 40
+      }
+    );
+  }
 }
@@ -1,10 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::RwLockReadGuard;
 use std::sync::RwLockWriteGuard;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, OnceLock, RwLock};
 
+use super::display::DisplayOptions;
+use crate::rules::Rule;
 use crate::utils::Err;
 
 /// Unpacked representation of a feature, that NodeRef::new_from_paths can turn into a Node
@@ -23,8 +26,9 @@ pub struct Feature {
 pub(crate) enum Node {
   /// Top can unify with anything
   Top,
-  /// A string-valued feature, such as "nom" in [case: nom]. Unifies with eq. Str nodes
-  Str(String),
+  /// A string-valued feature, such as "nom" in [case: nom]. Unifies with eq. Str nodes.
+  /// Shared so that deep_clone doesn't need to copy the string.
+  Str(Arc<str>),
   /// An arc-containing node with arcs to other NodeRefs
   Edged(HashMap<String, NodeRef>),
   /// A node that has been forwarded to another node through unification.
@@ -34,7 +38,7 @@ pub(crate) enum Node {
 
 impl Node {
   fn new_str(s: String) -> Self {
-    Self::Str(s)
+    Self::Str(s.into())
   }
 
   fn new_edged() -> Self {
@@ -56,7 +60,7 @@ impl Node {
     self.str().is_some()
   }
 
-  fn edged(&self) -> Option<&HashMap<String, NodeRef>> {
+  pub(crate) fn edged(&self) -> Option<&HashMap<String, NodeRef>> {
     match self {
       Self::Edged(v) => Some(v),
       _ => None,
@@ -94,9 +98,203 @@ impl Node {
   }
 }
 
+/// The contents of an interned node, with its children already interned, so
+/// identical substructures have identical shapes
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum Shape {
+  Top,
+  Str(Arc<str>),
+  Edged(Vec<(String, NodeRef)>),
+}
+
+/// Deduplicates the strings in Str nodes, and with `NodeRef::intern` whole
+/// substructures, so feature structures that are loaded once and cloned often
+/// (like rule features) share one copy of each value. Shared substructures are
+/// marked as interned, which tells deep_clone, display and `equivalent` to
+/// treat each path to them as its own node, so they don't become reentrant.
+#[derive(Debug, Default)]
+pub struct Interner {
+  strs: HashSet<Arc<str>>,
+  nodes: HashMap<Shape, NodeRef>,
+}
+
+impl Interner {
+  pub fn intern(&mut self, s: &str) -> Arc<str> {
+    if let Some(existing) = self.strs.get(s) {
+      existing.clone()
+    } else {
+      let new: Arc<str> = s.into();
+      self.strs.insert(new.clone());
+      new
+    }
+  }
+
+  /// Number of distinct strings interned so far
+  pub fn len(&self) -> usize {
+    self.strs.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Number of distinct substructures (including atomic values) interned so far
+  pub fn nodes(&self) -> usize {
+    self.nodes.len()
+  }
+
+  /// The interned node shaped like `shape`, or `nref` after interning it
+  fn intern_node(&mut self, shape: Shape, nref: NodeRef) -> NodeRef {
+    self
+      .nodes
+      .entry(shape)
+      .or_insert_with(|| {
+        nref.0.interned.store(true, Ordering::Relaxed);
+        nref
+      })
+      .clone()
+  }
+}
+
+/// Error for when a feature structure grows past the size limits in
+/// `ParseOptions`, usually because of a runaway recursive rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeatureStructureTooLarge {
+  pub nodes: usize,
+  pub depth: usize,
+}
+
+impl FeatureStructureTooLarge {
+  /// Checks a structure against the (optional) limits
+  pub fn check(
+    nref: &NodeRef,
+    max_nodes: Option<usize>,
+    max_depth: Option<usize>,
+  ) -> Result<(), FeatureStructureTooLarge> {
+    if max_nodes.is_none() && max_depth.is_none() {
+      return Ok(());
+    }
+
+    // reentrant nodes are counted once, so this stays linear in the size of
+    // the structure even when walking its paths would be exponential
+    let mut depths = HashMap::new();
+    let depth = Self::depth(nref.clone(), &mut depths);
+    let nodes = depths.len();
+    if max_nodes.map(|max| nodes > max).unwrap_or(false)
+      || max_depth.map(|max| depth > max).unwrap_or(false)
+    {
+      Err(Self { nodes, depth })
+    } else {
+      Ok(())
+    }
+  }
+}
+
+impl FeatureStructureTooLarge {
+  /// The length of the longest path below `nref`, recording every distinct
+  /// node it visits in `depths`. Nodes still being visited are in `depths` as
+  /// 0, so cycles aren't followed.
+  fn depth(nref: NodeRef, depths: &mut HashMap<NodeRef, usize>) -> usize {
+    let nref = nref.dereference();
+    if let Some(&depth) = depths.get(&nref) {
+      return depth;
+    }
+    depths.insert(nref.clone(), 0);
+
+    let children = nref
+      .borrow()
+      .edged()
+      .map(|arcs| arcs.values().cloned().collect::<Vec<_>>())
+      .unwrap_or_default();
+    let depth = children
+      .into_iter()
+      .map(|child| Self::depth(child, depths) + 1)
+      .max()
+      .unwrap_or(0);
+    depths.insert(nref, depth);
+    depth
+  }
+}
+
+impl fmt::Display for FeatureStructureTooLarge {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "feature structure too large: {} nodes, depth {}",
+      self.nodes, self.depth
+    )
+  }
+}
+
+impl std::error::Error for FeatureStructureTooLarge {}
+
+/// Error for when two feature structures conflict. `left` and `right` are the
+/// conflicting values (or whole structures, printed compactly) at `path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnificationFailure {
+  pub path: Vec<String>,
+  pub left: String,
+  pub right: String,
+  /// The rule whose features failed to unify with a child's, and the span the
+  /// rule covered, when the failure happened while unifying a tree
+  pub rule: Option<(Arc<Rule>, (usize, usize))>,
+}
+
+impl UnificationFailure {
+  fn new(path: &[String], n1: &NodeRef, n2: &NodeRef) -> Self {
+    let describe = |n: &NodeRef| match n.get_str() {
+      Some(s) => s.to_string(),
+      None => n
+        .display_with(&DisplayOptions {
+          compact: true,
+          ..DisplayOptions::default()
+        })
+        .to_string(),
+    };
+    Self {
+      path: path.to_vec(),
+      left: describe(n1),
+      right: describe(n2),
+      rule: None,
+    }
+  }
+}
+
+impl fmt::Display for UnificationFailure {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "unification failure")?;
+    if !self.path.is_empty() {
+      write!(f, " at {}", self.path.join("."))?;
+    }
+    write!(f, ": {} & {}", self.left, self.right)?;
+    if let Some((rule, span)) = &self.rule {
+      write!(f, ", in rule {}", rule.skeleton())?;
+      if let Some(location) = rule.location() {
+        write!(f, " ({})", location)?;
+      }
+      write!(f, " over {}..{}", span.0, span.1)?;
+    }
+    Ok(())
+  }
+}
+
+impl std::error::Error for UnificationFailure {}
+
+/// What a NodeRef points to: the node, and the `#tag` it was written with in
+/// the grammar, if any
+#[derive(Debug)]
+struct NodeCell {
+  node: RwLock<Node>,
+  /// Set once, by the first tag to name the node, and passed on to the node
+  /// it's forwarded to in unification, if that has none
+  tag: OnceLock<Arc<str>>,
+  /// Set when an Interner shares this node between structures
+  interned: AtomicBool,
+}
+
 /// An interior-ly mutable ref to a Node.
 #[derive(Debug)]
-pub struct NodeRef(Arc<RwLock<Node>>);
+pub struct NodeRef(Arc<NodeCell>);
 
 impl NodeRef {
   pub fn new_top() -> Self {
@@ -134,6 +332,7 @@ impl NodeRef {
     let mut tags: HashMap<String, NodeRef> = HashMap::new();
     for Feature { value, tag, path } in paths {
       if let Some(tag) = tag {
+        value.set_tag(&tag);
         if tags.contains_key(&tag) {
           let tagged = tags.get(&tag).unwrap();
           NodeRef::unify(value.clone(), tagged.clone())?;
@@ -172,6 +371,184 @@ impl NodeRef {
     map.get(self).unwrap().clone()
   }
 
+  /// True if this node is (or is forwarded to) **top**
+  pub fn is_top(&self) -> bool {
+    self.clone().dereference().borrow().is_top()
+  }
+
+  /// The string value of this node, if it is (or is forwarded to) a Str node
+  pub fn get_str(&self) -> Option<Arc<str>> {
+    match &*self.clone().dereference().borrow() {
+      Node::Str(s) => Some(s.clone()),
+      _ => None,
+    }
+  }
+
+  /// The node at a dotted path like `child-0.num`, following forwarding.
+  /// The empty path is this node.
+  pub fn get_path(&self, path: &str) -> Option<NodeRef> {
+    let mut node = self.clone().dereference();
+    for label in path.split('.').filter(|l| !l.is_empty()) {
+      let next = node.borrow().edged()?.get(label)?.clone();
+      node = next.dereference();
+    }
+    Some(node)
+  }
+
+  /// Every node in this structure with the path of labels leading to it,
+  /// including the root with an empty path, in pre-order with labels sorted.
+  /// Reentrant nodes show up once per path that reaches them, but cycles are
+  /// not followed.
+  pub fn walk(&self) -> impl Iterator<Item = (Vec<String>, NodeRef)> {
+    let mut out = Vec::new();
+    self._walk(&mut Vec::new(), &mut Vec::new(), &mut out);
+    out.into_iter()
+  }
+
+  fn _walk(
+    &self,
+    path: &mut Vec<String>,
+    ancestors: &mut Vec<NodeRef>,
+    out: &mut Vec<(Vec<String>, NodeRef)>,
+  ) {
+    let this = self.clone().dereference();
+    if ancestors.contains(&this) {
+      return;
+    }
+    out.push((path.clone(), this.clone()));
+
+    let edges = this.borrow().edged().map(|edges| {
+      let mut edges = edges
+        .iter()
+        .map(|(label, value)| (label.clone(), value.clone()))
+        .collect::<Vec<_>>();
+      edges.sort_by(|(l1, _), (l2, _)| l1.cmp(l2));
+      edges
+    });
+    if let Some(edges) = edges {
+      ancestors.push(this.clone());
+      for (label, value) in edges {
+        path.push(label);
+        value._walk(path, ancestors, out);
+        path.pop();
+      }
+      ancestors.pop();
+    }
+  }
+
+  /// Replaces every string value in this structure with its interned copy
+  pub fn intern_strs(&self, interner: &mut Interner) {
+    self._intern_strs(interner, &mut HashSet::new());
+  }
+
+  /// Interns the strings in this structure, and replaces every substructure
+  /// below the root that has no shared or tagged nodes with an interned copy,
+  /// so identical ones are stored once. The structure must not be unified
+  /// afterwards, since interned nodes are shared with other structures:
+  /// deep_clone it first, which copies interned nodes into ordinary ones.
+  pub fn intern(&self, interner: &mut Interner) {
+    self.intern_strs(interner);
+    let mut counts = HashMap::new();
+    count_in_pointers(self.clone(), &mut counts);
+    self
+      .clone()
+      .dereference()
+      ._intern_edges(interner, &counts, &mut HashSet::new());
+  }
+
+  /// Interns this (dereferenced) node's substructure if it can be shared,
+  /// returning the interned node, or interns what it can below it and returns
+  /// None
+  fn _intern(
+    &self,
+    interner: &mut Interner,
+    counts: &HashMap<NodeRef, usize>,
+    seen: &mut HashSet<NodeRef>,
+  ) -> Option<NodeRef> {
+    if self.is_interned() {
+      return Some(self.clone());
+    }
+    if counts[self] > 1 || self.tag().is_some() {
+      self._intern_edges(interner, counts, seen);
+      return None;
+    }
+
+    let shape = match &*self.borrow() {
+      Node::Top => Shape::Top,
+      Node::Str(s) => Shape::Str(s.clone()),
+      Node::Edged(_) => Shape::Edged(Vec::new()),
+      Node::Forwarded(_) => unreachable!("dereferenced by the caller"),
+    };
+    let shape = match shape {
+      Shape::Edged(_) => Shape::Edged(self._intern_edges(interner, counts, seen)?),
+      shape => shape,
+    };
+    Some(interner.intern_node(shape, self.clone()))
+  }
+
+  /// Replaces the children of this node with interned copies where it can,
+  /// returning them sorted by label if they all were
+  fn _intern_edges(
+    &self,
+    interner: &mut Interner,
+    counts: &HashMap<NodeRef, usize>,
+    seen: &mut HashSet<NodeRef>,
+  ) -> Option<Vec<(String, NodeRef)>> {
+    if !seen.insert(self.clone()) {
+      return None;
+    }
+
+    // the lock can't be held while visiting the children, in case of cycles
+    let edges = self.borrow().edged().map(|edges| {
+      edges
+        .iter()
+        .map(|(label, value)| (label.clone(), value.clone()))
+        .collect::<Vec<_>>()
+    })?;
+
+    let mut interned = Vec::with_capacity(edges.len());
+    for (label, value) in edges {
+      if let Some(value) = value.dereference()._intern(interner, counts, seen) {
+        interned.push((label, value));
+      }
+    }
+
+    let mut n = self.borrow_mut();
+    let edges = n.edged_mut().expect("was edged above");
+    for (label, value) in interned.iter() {
+      edges.insert(label.clone(), value.clone());
+    }
+    if interned.len() < edges.len() {
+      return None;
+    }
+    interned.sort_by(|(l1, _), (l2, _)| l1.cmp(l2));
+    Some(interned)
+  }
+
+  /// True if this node was shared by `intern`, so every path to it should be
+  /// treated as leading to a separate node
+  pub(crate) fn is_interned(&self) -> bool {
+    self.0.interned.load(Ordering::Relaxed)
+  }
+
+  fn _intern_strs(&self, interner: &mut Interner, seen: &mut HashSet<NodeRef>) {
+    if !seen.insert(self.clone()) {
+      return;
+    }
+
+    let mut n = self.borrow_mut();
+    match &mut *n {
+      Node::Top => {}
+      Node::Str(s) => *s = interner.intern(s),
+      Node::Forwarded(n1) => n1._intern_strs(interner, seen),
+      Node::Edged(edges) => {
+        for v in edges.values() {
+          v._intern_strs(interner, seen);
+        }
+      }
+    }
+  }
+
   pub fn dereference(self: NodeRef) -> NodeRef {
     if let Node::Forwarded(r) = &*self.borrow() {
       return Self::dereference(r.clone());
@@ -179,8 +556,23 @@ impl NodeRef {
     self
   }
 
+  /// True if this is (or is forwarded to) a string value
+  fn is_atomic(&self) -> bool {
+    self.clone().dereference().borrow().is_str()
+  }
+
   /// Unify two feature structures. Both will be mutated. Use deep_clone() if one needs to be preserved.
   pub fn unify(n1: NodeRef, n2: NodeRef) -> Result<(), Err> {
+    Self::unify_at(n1, n2, &mut Vec::new()).map_err(|e| e.into())
+  }
+
+  /// Like unify, but reports the path of the conflicting nodes, which `path`
+  /// is the prefix of
+  pub(crate) fn unify_at(
+    n1: NodeRef,
+    n2: NodeRef,
+    path: &mut Vec<String>,
+  ) -> Result<(), UnificationFailure> {
     let n1 = n1.dereference();
     let n2 = n2.dereference();
 
@@ -191,10 +583,10 @@ impl NodeRef {
 
     // if either is top forward to the other one w/o checking
     if n1.borrow().is_top() {
-      n1.replace(Node::Forwarded(n2));
+      n1.forward_to(n2);
       return Ok(());
     } else if n2.borrow().is_top() {
-      n2.replace(Node::Forwarded(n1));
+      n2.forward_to(n1);
       return Ok(());
     }
 
@@ -206,64 +598,182 @@ impl NodeRef {
         n1.str().unwrap() == n2.str().unwrap()
       };
       if strs_equal {
-        n1.replace(Node::Forwarded(n2));
+        n1.forward_to(n2);
         return Ok(());
       } else {
-        return Err(
-          format!(
-            "unification failure: {} & {}",
-            n1.borrow().str().unwrap(),
-            n2.borrow().str().unwrap()
-          )
-          .into(),
-        );
+        return Err(UnificationFailure::new(path, &n1, &n2));
       }
     }
 
     if n1.borrow().is_edged() && n2.borrow().is_edged() {
-      let n1 = n1.replace(Node::Forwarded(n2.clone()));
-      let n2 = &mut *n2.borrow_mut();
+      let n1 = n1.forward_to(n2.clone());
+      // arcs only n1 has move over to n2 now, and shared ones are unified
+      // after n2 is unlocked, since that can close a cycle back to n2
+      let shared = {
+        let mut n2 = n2.borrow_mut();
+        let n2arcs = n2.edged_mut().unwrap();
+        let mut shared = Vec::new();
+        for (label, value) in n1.edged().unwrap().iter() {
+          match n2arcs.get(label) {
+            Some(other) => shared.push((label.clone(), value.clone(), other.clone())),
+            None => {
+              n2arcs.insert(label.clone(), value.clone());
+            }
+          }
+        }
+        shared
+      };
 
-      let n1arcs = n1.edged().unwrap();
-      let n2arcs = n2.edged_mut().unwrap();
+      // atomic values are cheap to compare, and they're where most
+      // unifications fail (agreement features like num and case), so check
+      // them all before recursing into deeper structures
+      let (atomic, nested): (Vec<_>, Vec<_>) = shared
+        .into_iter()
+        .partition(|(_, value, other)| value.is_atomic() && other.is_atomic());
+      for (label, value, other) in atomic.into_iter().chain(nested) {
+        path.push(label);
+        Self::unify_at(value, other, path)?;
+        path.pop();
+      }
 
-      for (label, value) in n1arcs.iter() {
-        if n2arcs.contains_key(label) {
-          // shared arc
-          let other = n2arcs.get(label).unwrap();
-          Self::unify(value.clone(), other.clone())?;
-        } else {
-          // complement arc
-          n2arcs.insert(label.clone(), value.clone());
+      return Ok(());
+    }
+
+    Err(UnificationFailure::new(path, &n1, &n2))
+  }
+}
+
+impl NodeRef {
+  /// Checks if two feature structures are isomorphic: they have the same
+  /// features and values, *and* the same nodes are shared between paths.
+  /// Unlike comparing `SerializedNode`s, `[a: #1 x, b: #1]` is not equivalent
+  /// to `[a: x, b: x]`.
+  pub fn equivalent(a: &NodeRef, b: &NodeRef) -> bool {
+    Self::_equivalent(a, b, &mut HashMap::new(), &mut HashMap::new())
+  }
+
+  fn _equivalent(
+    a: &NodeRef,
+    b: &NodeRef,
+    a_to_b: &mut HashMap<NodeRef, NodeRef>,
+    b_to_a: &mut HashMap<NodeRef, NodeRef>,
+  ) -> bool {
+    let a = a.clone().dereference();
+    let b = b.clone().dereference();
+
+    // an interned node stands for a separate node on each path, so it's never
+    // paired up, and the node on the other side must not be shared
+    match (a.is_interned(), b.is_interned()) {
+      (true, true) => {}
+      (true, false) if b_to_a.contains_key(&b) => return false,
+      (true, false) => {
+        b_to_a.insert(b.clone(), a.clone());
+      }
+      (false, true) if a_to_b.contains_key(&a) => return false,
+      (false, true) => {
+        a_to_b.insert(a.clone(), b.clone());
+      }
+      (false, false) => {
+        match (a_to_b.get(&a), b_to_a.get(&b)) {
+          (Some(a_seen), Some(b_seen)) => return a_seen == &b && b_seen == &a,
+          (None, None) => {}
+          // one side was already paired up with a different node
+          _ => return false,
         }
+        a_to_b.insert(a.clone(), b.clone());
+        b_to_a.insert(b.clone(), a.clone());
       }
+    }
 
-      return Ok(());
+    let (a, b) = (a.borrow(), b.borrow());
+    match (&*a, &*b) {
+      (Node::Top, Node::Top) => true,
+      (Node::Str(s1), Node::Str(s2)) => s1 == s2,
+      (Node::Edged(e1), Node::Edged(e2)) => {
+        e1.len() == e2.len()
+          && e1.iter().all(|(label, v1)| {
+            e2.get(label)
+              .map(|v2| Self::_equivalent(v1, v2, a_to_b, b_to_a))
+              .unwrap_or(false)
+          })
+      }
+      _ => false,
     }
+  }
+}
 
-    Err(format!("unification failure: {:#?} & {:#?}", n1, n2).into())
+impl NodeRef {
+  /// The features `a` and `b` agree on, as a new structure: values they share
+  /// are kept, and values they disagree on (or that only one has) are left
+  /// out. The opposite of unification. Shared nodes in `a` and `b` aren't
+  /// shared in the result.
+  pub fn generalize(a: &NodeRef, b: &NodeRef) -> NodeRef {
+    let (a, b) = (a.clone().dereference(), b.clone().dereference());
+    let (a, b) = (a.borrow(), b.borrow());
+    match (&*a, &*b) {
+      (Node::Str(s1), Node::Str(s2)) if s1 == s2 => Node::Str(s1.clone()).into(),
+      (Node::Edged(e1), Node::Edged(e2)) => Node::Edged(
+        e1.iter()
+          .filter_map(|(label, v1)| {
+            let shared = Self::generalize(v1, e2.get(label)?);
+            (!shared.is_top()).then(|| (label.clone(), shared))
+          })
+          .collect(),
+      )
+      .into(),
+      _ => NodeRef::new_top(),
+    }
   }
 }
 
 impl NodeRef {
   pub(crate) fn new(n: Node) -> Self {
-    Self(Arc::new(RwLock::new(n)))
+    Self(Arc::new(NodeCell {
+      node: RwLock::new(n),
+      tag: OnceLock::new(),
+      interned: AtomicBool::new(false),
+    }))
   }
 
-  pub(crate) fn borrow(&self) -> RwLockReadGuard<Node> {
-    self.0.read().expect("NodeRef lock poisoned!")
+  pub(crate) fn borrow(&self) -> RwLockReadGuard<'_, Node> {
+    self.0.node.read().expect("NodeRef lock poisoned!")
   }
 
-  fn borrow_mut(&self) -> RwLockWriteGuard<Node> {
-    self.0.write().expect("NodeRef lock poisoned!")
+  fn borrow_mut(&self) -> RwLockWriteGuard<'_, Node> {
+    self.0.node.write().expect("NodeRef lock poisoned!")
   }
 
-  fn replace(&self, n: Node) -> Node {
+  pub(crate) fn replace(&self, n: Node) -> Node {
     let mut write = self.borrow_mut();
     std::mem::replace(&mut *write, n)
   }
 
+  /// Forwards this node to `to`, giving `to` this node's tag if it has none,
+  /// and returns what this node was
+  fn forward_to(&self, to: NodeRef) -> Node {
+    if let Some(tag) = self.0.tag.get() {
+      to.0.tag.get_or_init(|| tag.clone());
+    }
+    self.replace(Node::Forwarded(to))
+  }
+
+  /// The `#tag` this node (not following forwarding) was written with in the
+  /// grammar, like `1` for `#1`, or passed on to it by unification. Display
+  /// prints shared nodes with these when it can.
+  pub fn tag(&self) -> Option<&str> {
+    self.0.tag.get().map(|t| &**t)
+  }
+
+  /// Names this node with `tag`, unless it already has a tag
+  pub fn set_tag(&self, tag: &str) {
+    self.0.tag.get_or_init(|| tag.into());
+  }
+
   fn _deep_clone(&self, seen: &mut HashMap<NodeRef, NodeRef>) -> NodeRef {
+    // interned nodes get a new copy on every path, so they don't become
+    // reentrant in the clone
+    let mut fresh = HashMap::new();
+    let seen = if self.is_interned() { &mut fresh } else { seen };
     if seen.contains_key(self) {
       return seen.get(self).unwrap().clone();
     }
@@ -275,7 +785,7 @@ impl NodeRef {
         Self::new(Node::Forwarded(n1))
       }
       Node::Top => Self::new_top(),
-      Node::Str(s) => Self::new_str(s.to_string()),
+      Node::Str(s) => Self::new(Node::Str(s.clone())),
       Node::Edged(edges) => Self::new(Node::Edged(
         edges
           .iter()
@@ -283,6 +793,9 @@ impl NodeRef {
           .collect(),
       )),
     };
+    if let Some(tag) = self.0.tag.get() {
+      cloned.0.tag.get_or_init(|| tag.clone());
+    }
     seen.insert(self.clone(), cloned.clone());
     cloned
   }
@@ -312,17 +825,31 @@ impl Hash for NodeRef {
   }
 }
 
+/// Flattens a feature structure into dotted paths and their string values,
+/// for when reentrancy doesn't matter. **top** features are left out.
+impl From<&NodeRef> for HashMap<String, String> {
+  fn from(nref: &NodeRef) -> Self {
+    nref
+      .walk()
+      .filter_map(|(path, node)| Some((path.join("."), node.get_str()?.to_string())))
+      .collect()
+  }
+}
+
 impl From<Node> for NodeRef {
   fn from(node: Node) -> Self {
     Self::new(node)
   }
 }
 
-// for fmt::Display impl
-fn count_in_pointers(nref: NodeRef, seen: &mut HashMap<NodeRef, usize>) {
+/// Counts how many pointers point at each node, for finding reentrant nodes
+pub(crate) fn count_in_pointers(nref: NodeRef, seen: &mut HashMap<NodeRef, usize>) {
   let nref = nref.dereference();
   if seen.contains_key(&nref) {
-    seen.entry(nref).and_modify(|cnt| *cnt += 1);
+    // every path to an interned node counts as its own node
+    if !nref.is_interned() {
+      seen.entry(nref).and_modify(|cnt| *cnt += 1);
+    }
   } else {
     seen.insert(nref.clone(), 1);
     if let Some(arcs) = nref.borrow().edged() {
@@ -332,58 +859,3 @@ fn count_in_pointers(nref: NodeRef, seen: &mut HashMap<NodeRef, usize>) {
     }
   }
 }
-
-// for fmt::Display impl
-fn format_noderef(
-  self_: NodeRef,
-  counts: &HashMap<NodeRef, usize>,
-  has_printed: &mut HashMap<NodeRef, usize>,
-  indent: usize,
-  f: &mut fmt::Formatter<'_>,
-) -> fmt::Result {
-  let self_ = self_.dereference();
-
-  if counts[&self_] > 1 && has_printed.contains_key(&self_) {
-    return write!(f, "#{}", has_printed[&self_]);
-  }
-
-  if counts[&self_] > 1 {
-    let id = has_printed.len();
-    has_printed.insert(self_.clone(), id);
-    write!(f, "#{} ", id)?;
-  }
-
-  let r = &*self_.borrow();
-  match r {
-    Node::Top => write!(f, "**top**"),
-    Node::Str(s) => write!(f, "{}", s),
-    Node::Edged(arcs) => {
-      if arcs.is_empty() {
-        write!(f, "[]")
-      } else if arcs.len() == 1 {
-        let (label, value) = arcs.iter().next().unwrap();
-        write!(f, "[ {}: ", label)?;
-        format_noderef(value.clone(), counts, has_printed, 0, f)?;
-        write!(f, " ]")
-      } else {
-        writeln!(f, "[")?;
-        for (label, value) in arcs.iter() {
-          write!(f, "{:indent$}{}: ", "", label, indent = indent + 2)?;
-          format_noderef(value.clone(), counts, has_printed, indent + 2, f)?;
-          writeln!(f)?;
-        }
-        write!(f, "{:indent$}]", "", indent = indent)
-      }
-    }
-    Node::Forwarded(_) => panic!("unexpected forward"),
-  }
-}
-
-impl fmt::Display for NodeRef {
-  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    let mut counts = HashMap::new();
-    count_in_pointers(self.clone(), &mut counts);
-    let mut has_printed = HashMap::new();
-    format_noderef(self.clone(), &counts, &mut has_printed, 0, f)
-  }
-}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use super::display::DisplayOptions;
+use super::node::{Node, NodeRef};
+
+/// A feature structure in canonical form, from `NodeRef::normalize`. Equal
+/// (and hashed) by `canonical`, so parses can be deduplicated with a
+/// `HashSet`, and `hash` can key caches shared between processes.
+#[derive(Debug, Clone)]
+pub struct Normalized {
+  /// A copy without forwarded nodes, sharing the same nodes the original did
+  pub fs: NodeRef,
+  /// The structure on one line, with features sorted and shared nodes tagged
+  /// `#0`, `#1`, ... in the order they first appear. Structures have the same
+  /// canonical string exactly when they're `NodeRef::equivalent`.
+  pub canonical: String,
+  /// The 64-bit FNV-1a hash of `canonical`, which is the same on every
+  /// platform and in every version
+  pub hash: u64,
+}
+
+impl PartialEq for Normalized {
+  fn eq(&self, other: &Self) -> bool {
+    self.canonical == other.canonical
+  }
+}
+
+impl Eq for Normalized {}
+
+impl Hash for Normalized {
+  fn hash<H: Hasher>(&self, hasher: &mut H) {
+    self.canonical.hash(hasher)
+  }
+}
+
+impl NodeRef {
+  /// The canonical form of this structure, see `Normalized`
+  pub fn normalize(&self) -> Normalized {
+    let fs = compress(self, &mut HashMap::new());
+    let options = DisplayOptions {
+      compact: true,
+      ..DisplayOptions::default()
+    };
+    let canonical = fs.display_with(&options).to_string();
+    let hash = fnv1a(canonical.as_bytes());
+    Normalized {
+      fs,
+      canonical,
+      hash,
+    }
+  }
+}
+
+/// Copies `nref` with every forward followed, keeping shared nodes shared
+fn compress(nref: &NodeRef, copies: &mut HashMap<NodeRef, NodeRef>) -> NodeRef {
+  let nref = nref.clone().dereference();
+  if let Some(copy) = copies.get(&nref) {
+    return copy.clone();
+  }
+  let copy = NodeRef::new_top();
+  copies.insert(nref.clone(), copy.clone());
+  let node = match &*nref.borrow() {
+    Node::Top => Node::Top,
+    Node::Str(s) => Node::Str(s.clone()),
+    Node::Edged(edges) => Node::Edged(
+      edges
+        .iter()
+        .map(|(label, value)| (label.clone(), compress(value, copies)))
+        .collect(),
+    ),
+    Node::Forwarded(_) => panic!("unexpected forward"),
+  };
+  copy.replace(node);
+  copy
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+    (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+  })
+}
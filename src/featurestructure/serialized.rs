@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use super::node::{Node, NodeRef};
+use super::node::{count_in_pointers, Node, NodeRef};
 
 /// A noderef that's been serialized into a tree structure. Nodes with multiple
 /// in-pointers are duplicated.
@@ -54,6 +54,31 @@ impl SerializedNode {
   pub fn get_path_str(&self, path: &[&str]) -> Option<&str> {
     self.get_path(path).and_then(Self::as_str)
   }
+
+  /// Every node in this tree with the path of labels leading to it, including
+  /// the root with an empty path, in pre-order with labels sorted
+  pub fn walk(&self) -> impl Iterator<Item = (Vec<&str>, &SerializedNode)> {
+    let mut out = Vec::new();
+    self._walk(&mut Vec::new(), &mut out);
+    out.into_iter()
+  }
+
+  fn _walk<'a>(
+    &'a self,
+    path: &mut Vec<&'a str>,
+    out: &mut Vec<(Vec<&'a str>, &'a SerializedNode)>,
+  ) {
+    out.push((path.clone(), self));
+    if let Self::Edged(map) = self {
+      let mut edges = map.iter().collect::<Vec<_>>();
+      edges.sort_by_key(|(label, _)| *label);
+      for (label, value) in edges {
+        path.push(label);
+        value._walk(path, out);
+        path.pop();
+      }
+    }
+  }
 }
 
 impl From<&str> for SerializedNode {
@@ -110,8 +135,86 @@ impl PartialEq for SerializedNode {
           return false;
         }
 
-        return m1.iter().all(|(k, v)| m2.get(k) == Some(v));
+        m1.iter().all(|(k, v)| m2.get(k) == Some(v))
+      }
+    }
+  }
+}
+
+/// Like SerializedNode, but keeps reentrancy: a node reachable by more than one
+/// path is serialized once, as `Tagged`, where it's first reached (visiting
+/// features in sorted order), and as a `Ref` to its id everywhere else. Ids
+/// count up from 0 in that order, ignoring the grammar's `#tag`s, which
+/// NodeRef's Display prints where it can.
+/// **top** is stripped out like in SerializedNode, except for shared nodes,
+/// as those still record a binding.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SerializedDag {
+  Top,
+  Str(String),
+  Edged(HashMap<String, SerializedDag>),
+  Tagged {
+    id: usize,
+    value: Box<SerializedDag>,
+  },
+  Ref(usize),
+}
+
+impl SerializedDag {
+  fn serialize(
+    nr: NodeRef,
+    counts: &HashMap<NodeRef, usize>,
+    ids: &mut HashMap<NodeRef, usize>,
+  ) -> Option<Self> {
+    let nr = nr.dereference();
+    let shared = counts[&nr] > 1;
+    if shared {
+      if let Some(id) = ids.get(&nr) {
+        return Some(Self::Ref(*id));
       }
+      let id = ids.len();
+      ids.insert(nr.clone(), id);
     }
+
+    let value = match &*nr.borrow() {
+      Node::Top => None,
+      Node::Str(s) => Some(Self::Str(s.to_string())),
+      Node::Edged(edges) => {
+        let mut edges = edges.iter().collect::<Vec<_>>();
+        edges.sort_by_key(|(label, _)| *label);
+        let map = edges
+          .into_iter()
+          .filter_map(|(label, value)| {
+            Some((
+              label.to_string(),
+              Self::serialize(value.clone(), counts, ids)?,
+            ))
+          })
+          .collect::<HashMap<_, _>>();
+        if map.is_empty() {
+          None
+        } else {
+          Some(Self::Edged(map))
+        }
+      }
+      Node::Forwarded(_) => unreachable!("dereferenced above"),
+    };
+
+    if shared {
+      Some(Self::Tagged {
+        id: ids[&nr],
+        value: Box::new(value.unwrap_or(Self::Top)),
+      })
+    } else {
+      value
+    }
+  }
+}
+
+impl From<&NodeRef> for Option<SerializedDag> {
+  fn from(nr: &NodeRef) -> Self {
+    let mut counts = HashMap::new();
+    count_in_pointers(nr.clone(), &mut counts);
+    SerializedDag::serialize(nr.clone(), &counts, &mut HashMap::new())
   }
 }
@@ -1,10 +1,11 @@
 /// Simple recursive-descent parsing of grammar files
 use std::str::FromStr;
+use std::sync::Arc;
 
 use regex::Regex;
 
 use crate::featurestructure::{Feature, NodeRef};
-use crate::rules::{Grammar, Production, Rule};
+use crate::rules::{Grammar, GrammarMetadata, Production, Rule, SpanConstraint, UNKNOWN_WORD};
 use crate::utils::Err;
 
 pub const TOP_STR: &str = "**top**";
@@ -17,17 +18,90 @@ impl FromStr for Grammar {
   /// Parses a grammar from a string. Assumes the first rule's symbol
   /// is the start symbol.
   fn from_str(s: &str) -> Result<Self, Self::Err> {
-    let (rules, s) = parse_rules(s)?;
-    assert!(s.is_empty());
+    parse_grammar(s, None)
+  }
+}
+
+/// Parses a grammar, recording `file` as the file each rule came from
+pub(crate) fn parse_grammar(s: &str, file: Option<&str>) -> Result<Grammar, Err> {
+  trace_span!(info_span, "load_grammar", bytes = s.len());
+  let (metadata, rules, warnings) = parse_grammar_rules(s, file)?;
+  let mut g = Grammar::new(rules)?;
+  g.metadata = metadata;
+  // rules are indexed in the order they were parsed
+  let in_order = g.rules_in_order().into_iter().cloned().collect::<Vec<_>>();
+  for (rule, messages) in in_order.into_iter().zip(warnings) {
+    g.add_parse_warnings(&rule, messages);
+  }
+  trace_event!(
+    info,
+    rules = g.rules.values().map(Vec::len).sum::<usize>(),
+    nonterminals = g.rules.len(),
+    "loaded grammar"
+  );
+  Ok(g)
+}
+
+/// Parses the metadata and rules of a grammar file without making a
+/// `Grammar` of them, so they don't have to be complete on their own. Returns
+/// each rule's parse warnings alongside the rules.
+#[allow(clippy::type_complexity)]
+pub(crate) fn parse_grammar_rules(
+  s: &str,
+  file: Option<&str>,
+) -> Result<(GrammarMetadata, Vec<Rule>, Vec<Vec<String>>), Err> {
+  let ((metadata, declarations), rest) = parse_metadata(s)?;
+  let header_lines = s[..s.len() - rest.len()].matches('\n').count();
+  let (rules, s) = parse_rules(rest)?;
+  assert!(s.is_empty());
+  let (mut rules, warnings): (Vec<Rule>, Vec<Vec<String>>) = rules.into_iter().unzip();
+  let file: Option<Arc<str>> = file.map(Arc::from);
+  for rule in rules.iter_mut() {
+    rule.line += header_lines;
+    rule.file = file.clone();
+    for percolation in declarations.percolations.iter() {
+      percolation.apply(rule)?;
+    }
+    if let Some(feature) = &declarations.lemma {
+      add_lemma(feature, rule)?;
+    }
+  }
+  Ok((metadata, rules, warnings))
+}
+
+/// Parses a single rule, like `S -> N V`
+impl FromStr for Rule {
+  type Err = Err;
 
-    if rules.is_empty() {
-      Err("empty ruleset".into())
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let ((rule, _), s) = parse_rule(skip_whitespace(s))?;
+    if s.is_empty() {
+      Ok(rule)
     } else {
-      Self::new(rules)
+      Err(format!("expected a single rule, found more at {}", s).into())
+    }
+  }
+}
+
+/// Parses a feature structure on its own, like `[ num: pl, agr: #1, subj.agr: #1 ]`
+impl FromStr for NodeRef {
+  type Err = Err;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (features, s) = parse_features(s)?;
+    if !skip_whitespace(s).is_empty() {
+      return Err(format!("expected a single feature structure, found more at {}", s).into());
     }
+    NodeRef::new_from_paths(features)
   }
 }
 
+/// Parses the features of a structure like `[ num: pl, agr: #1 ]` without
+/// building it, for callers that combine several, returning the rest of `s`
+pub(crate) fn parse_features(s: &str) -> Result<(Vec<Feature>, &str), Err> {
+  parse_featurestructure(skip_whitespace(s))
+}
+
 type Infallible<'a, T> = (T, &'a str);
 type ParseResult<'a, T> = Result<(T, &'a str), Err>;
 
@@ -64,7 +138,7 @@ fn needed_re<'a>(re: &'static Regex, s: &'a str) -> ParseResult<'a, &'a str> {
 }
 
 /// Try to consume a char, returning None if it doesn't match
-fn optional_char(c: char, s: &str) -> Infallible<Option<char>> {
+fn optional_char(c: char, s: &str) -> Infallible<'_, Option<char>> {
   let mut iter = s.char_indices().peekable();
   if let Some((_, c1)) = iter.next() {
     if c == c1 {
@@ -80,7 +154,7 @@ fn optional_char(c: char, s: &str) -> Infallible<Option<char>> {
 }
 
 /// Try to consume a char, failing if it doesn't match
-fn needed_char(c: char, s: &str) -> ParseResult<char> {
+fn needed_char(c: char, s: &str) -> ParseResult<'_, char> {
   if let (Some(c), rest) = optional_char(c, s) {
     Ok((c, rest))
   } else {
@@ -101,19 +175,19 @@ fn skip_whitespace_nonnewline(s: &str) -> &str {
 }
 
 /// Tries to parse a name made of letters, numbers, - and _
-fn parse_name(s: &str) -> ParseResult<&str> {
+fn parse_name(s: &str) -> ParseResult<'_, &str> {
   regex_static!(NAME, r"[a-zA-Z0-9\-_]+");
   needed_re(&NAME, s).map_err(|err| format!("name: {}", err).into())
 }
 
 /// Tries to parse a name made of dotted segments (foo.bar.c.d)
-fn parse_dotted(s: &str) -> ParseResult<&str> {
+fn parse_dotted(s: &str) -> ParseResult<'_, &str> {
   regex_static!(DOTTED, r"[a-zA-Z0-9\-_]+(\.[a-zA-Z0-9\-_]+)*");
   needed_re(&DOTTED, s).map_err(|e| format!("dotted name: {}", e).into())
 }
 
 /// Parses an optional #tag
-fn parse_tag(s: &str) -> ParseResult<Option<String>> {
+fn parse_tag(s: &str) -> ParseResult<'_, Option<String>> {
   let (hash, s) = optional_char('#', s);
   if hash.is_none() {
     Ok((None, s))
@@ -124,12 +198,14 @@ fn parse_tag(s: &str) -> ParseResult<Option<String>> {
   }
 }
 
-/// Parses a value with an optional tag: #tag value
-fn parse_feature_value(s: &str) -> ParseResult<(Option<String>, NodeRef)> {
+/// Parses a value with an optional tag: #tag value. Glosses (see `igt`) can
+/// also have `.` and `=` in them, like 3SG.PRS
+fn parse_feature_value(s: &str, gloss: bool) -> ParseResult<'_, (Option<String>, NodeRef)> {
   regex_static!(VALUE, r"[a-zA-Z0-9\-_\*]+");
+  regex_static!(GLOSS_VALUE, r"[a-zA-Z0-9\-_\*\.=]+");
   let (tag, s) = parse_tag(s)?;
   let s = skip_whitespace(s);
-  let (name, s) = optional_re(&VALUE, s);
+  let (name, s) = optional_re(if gloss { &GLOSS_VALUE } else { &VALUE }, s);
   let value = if let Some(name) = name {
     if name == TOP_STR {
       NodeRef::new_top()
@@ -144,12 +220,13 @@ fn parse_feature_value(s: &str) -> ParseResult<(Option<String>, NodeRef)> {
   Ok(((tag, value), s))
 }
 
-fn parse_feature(s: &str) -> ParseResult<Feature> {
+fn parse_feature(s: &str) -> ParseResult<'_, Feature> {
   let (name, s) = parse_dotted(s).map_err(|e| format!("feature name: {}", e))?;
   let s = skip_whitespace(s);
   let (_, s) = needed_char(':', s)?;
   let s = skip_whitespace(s);
-  let (value, s) = parse_feature_value(s).map_err(|e| format!("feature value: {}", e))?;
+  let gloss = matches!(name.rsplit('.').next(), Some("gloss" | "morph"));
+  let (value, s) = parse_feature_value(s, gloss).map_err(|e| format!("feature value: {}", e))?;
   let s = skip_whitespace(s);
   let (_, s) = optional_char(',', s);
 
@@ -163,7 +240,7 @@ fn parse_feature(s: &str) -> ParseResult<Feature> {
   ))
 }
 
-fn parse_featurestructure(s: &str) -> ParseResult<Vec<Feature>> {
+fn parse_featurestructure(s: &str) -> ParseResult<'_, Vec<Feature>> {
   let mut pairs = Vec::new();
   let mut rem = needed_char('[', s)?.1;
   loop {
@@ -177,8 +254,15 @@ fn parse_featurestructure(s: &str) -> ParseResult<Vec<Feature>> {
   }
 }
 
-fn parse_production(s: &str) -> ParseResult<(Production, Vec<Feature>)> {
+fn parse_production(s: &str) -> ParseResult<'_, (Production, Vec<Feature>)> {
   let (name, s) = parse_name(s).map_err(|e| -> Err { format!("symbol: {}", e).into() })?;
+  // a grammatical function, like subj:NP
+  let (function, name, s) = if let (Some(_), s) = optional_char(':', s) {
+    let (symbol, s) = parse_name(s).map_err(|e| -> Err { format!("symbol: {}", e).into() })?;
+    (Some(name.to_string()), symbol, s)
+  } else {
+    (None, name, s)
+  };
   let s = skip_whitespace_nonnewline(s);
   let (features, s) = if s.starts_with('[') {
     parse_featurestructure(s)?
@@ -187,9 +271,22 @@ fn parse_production(s: &str) -> ParseResult<(Production, Vec<Feature>)> {
   };
 
   if name.chars().next().unwrap().is_uppercase() {
-    Ok(((Production::new_nonterminal(name.to_string()), features), s))
+    let mut prod = Production::new_nonterminal(name.to_string());
+    prod.function = function;
+    Ok(((prod, features), s))
+  } else if function.is_some() {
+    Err(
+      format!(
+        "terminal (lower-case) cannot have a function: {} {}",
+        name, s
+      )
+      .into(),
+    )
   } else if !features.is_empty() {
     Err(format!("terminal (lower-case) cannot have features: {} {}", name, s).into())
+  } else if name == UNKNOWN_WORD {
+    // its word is only known once it's parsed, see `Grammar::leaf_features`
+    Ok(((Production::new_terminal(name.to_string()), Vec::new()), s))
   } else {
     // annotate terminals with their matching string
     Ok((
@@ -206,20 +303,81 @@ fn parse_production(s: &str) -> ParseResult<(Production, Vec<Feature>)> {
   }
 }
 
-fn parse_nonterminal(s: &str) -> ParseResult<(String, Vec<Feature>)> {
+fn parse_nonterminal(s: &str) -> ParseResult<'_, (String, Vec<Feature>)> {
   let ((prod, features), s) = parse_production(s)?;
-  if prod.is_nonterminal() {
-    Ok(((prod.symbol, features), s))
+  if prod.function.is_some() {
+    Err(format!("rule symbol cannot have a function: {}", prod).into())
+  } else if prod.is_nonterminal() {
+    Ok(((prod.symbol.to_string(), features), s))
   } else {
     Err(format!("expected nonterminal, got terminal {}: {}", prod.symbol, s).into())
   }
 }
 
-/// Symbol, productions, terminated by final newline
-fn parse_rule(s: &str) -> ParseResult<Rule> {
+/// Parses any number of `@name` or `@name(arg)` annotations before a rule
+fn parse_annotations(s: &str) -> ParseResult<'_, Vec<(String, Option<String>)>> {
+  regex_static!(ARG, r"[^)\n]*");
+
+  let mut annotations = Vec::new();
+  let mut rem = s;
+  while let (Some(_), s) = optional_char('@', rem) {
+    let (name, s) = parse_name(s).map_err(|e| -> Err { format!("annotation: {}", e).into() })?;
+    let s = skip_whitespace_nonnewline(s);
+    let (arg, s) = if let (Some(_), s) = optional_char('(', s) {
+      let (arg, s) = optional_re(&ARG, s);
+      let (_, s) = needed_char(')', s)?;
+      (arg.map(|a| a.trim().to_string()), s)
+    } else {
+      (None, s)
+    };
+    annotations.push((name.to_string(), arg));
+    rem = skip_whitespace(s);
+  }
+  Ok((annotations, rem))
+}
+
+fn parse_weight(arg: Option<String>) -> Result<f64, Err> {
+  let arg = arg.ok_or("@weight needs an argument, like @weight(0.5)")?;
+  match arg.parse::<f64>() {
+    Ok(w) if (0.0..=1.0).contains(&w) => Ok(w),
+    _ => Err(
+      format!(
+        "bad rule weight: {} (weights are probabilities, from 0 to 1)",
+        arg
+      )
+      .into(),
+    ),
+  }
+}
+
+fn parse_spans(arg: Option<String>) -> Result<Vec<SpanConstraint>, Err> {
+  let arg = arg.ok_or("@span needs an argument, like @span(0: <=3)")?;
+  arg.split(',').map(str::parse).collect()
+}
+
+/// Annotations, symbol, productions, terminated by final newline. Also returns
+/// warnings about things in the rule that are allowed but probably mistakes.
+fn parse_rule(s: &str) -> ParseResult<'_, (Rule, Vec<String>)> {
   #![allow(clippy::trivial_regex)]
   regex_static!(ARROW, "->");
 
+  let (annotations, s) = parse_annotations(s)?;
+  let mut weight = 1.0;
+  let mut coordination = false;
+  let mut spans = Vec::new();
+  let mut hidden = false;
+  for (name, arg) in annotations {
+    match name.as_str() {
+      "weight" => weight = parse_weight(arg)?,
+      "span" => spans.extend(parse_spans(arg)?),
+      "coord" if arg.is_none() => coordination = true,
+      "coord" => return Err("@coord doesn't take an argument".into()),
+      "hidden" if arg.is_none() => hidden = true,
+      "hidden" => return Err("@hidden doesn't take an argument".into()),
+      _ => return Err(format!("unknown rule annotation: @{}", name).into()),
+    }
+  }
+
   let ((symbol, features), s) =
     parse_nonterminal(s).map_err(|e| -> Err { format!("rule symbol: {}", e).into() })?;
   let s = skip_whitespace(s);
@@ -243,19 +401,61 @@ fn parse_rule(s: &str) -> ParseResult<Rule> {
     rem = s;
   }
 
+  if let Some(child) = spans
+    .iter()
+    .filter_map(|c| c.child)
+    .find(|&c| c >= prods_features.len())
+  {
+    return Err(
+      format!(
+        "@span on child {}, but the rule has {}",
+        child,
+        prods_features.len()
+      )
+      .into(),
+    );
+  }
   let (features, productions) = adopt_child_features(features, prods_features);
+  let warnings = tag_warnings(&features);
   let features = NodeRef::new_from_paths(features)?;
 
   Ok((
-    Rule {
-      symbol,
-      features,
-      productions,
-    },
+    (
+      Rule {
+        symbol,
+        features,
+        productions,
+        weight,
+        index: 0,
+        line: 0,
+        file: None,
+        coordination,
+        spans,
+        hidden,
+      },
+      warnings,
+    ),
     rem,
   ))
 }
 
+/// Tags are for sharing a value between paths, so one that's only used once is
+/// probably a typo for another tag
+fn tag_warnings(features: &[Feature]) -> Vec<String> {
+  let mut counts: Vec<(&str, usize)> = Vec::new();
+  for tag in features.iter().filter_map(|f| f.tag.as_deref()) {
+    match counts.iter_mut().find(|(t, _)| *t == tag) {
+      Some((_, count)) => *count += 1,
+      None => counts.push((tag, 1)),
+    }
+  }
+  counts
+    .into_iter()
+    .filter(|(_, count)| *count == 1)
+    .map(|(tag, _)| format!("tag #{} is only used once", tag))
+    .collect()
+}
+
 /// We want rules to be able to access their child features, and to be able to
 /// unify between them
 /// So we have the rule symbol "adopt" the features of its children, copying the
@@ -284,16 +484,191 @@ fn adopt_child_features(
   (rule_features, productions)
 }
 
-fn parse_rules(s: &str) -> ParseResult<Vec<Rule>> {
+/// A `#percolate num, case from head` declaration: every rule with a child
+/// whose grammatical function is `from` (like `VP -> head:V NP`) shares the
+/// features with it, as if the rule had been written
+/// `VP[ num: #1, case: #2 ] -> head:V[ num: #1, case: #2 ] NP`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Percolation {
+  /// Dotted feature paths
+  pub features: Vec<String>,
+  pub from: String,
+}
+
+impl FromStr for Percolation {
+  type Err = Err;
+
+  /// Parses the value of a `#percolate` line, like `num, case from head`
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    regex_static!(DOTTED, r"^[a-zA-Z0-9\-_]+(\.[a-zA-Z0-9\-_]+)*$");
+    regex_static!(NAME, r"^[a-zA-Z0-9\-_]+$");
+
+    let usage = || -> Err {
+      format!(
+        "bad #percolate {}, expected features and a function, like #percolate num, case from head",
+        s
+      )
+      .into()
+    };
+    let (features, from) = s.rsplit_once(" from ").ok_or_else(usage)?;
+    let features = features
+      .split(',')
+      .map(|f| f.trim().to_string())
+      .collect::<Vec<_>>();
+    let from = from.trim().to_string();
+    if !NAME.is_match(&from) || !features.iter().all(|f| DOTTED.is_match(f)) {
+      return Err(usage());
+    }
+    Ok(Self { features, from })
+  }
+}
+
+impl Percolation {
+  /// Shares the features between `rule`'s symbol and each of its children
+  /// with the function `from`
+  fn apply(&self, rule: &mut Rule) -> Result<(), Err> {
+    let children = rule
+      .productions
+      .iter()
+      .enumerate()
+      .filter(|(_, p)| p.function.as_deref() == Some(self.from.as_str()))
+      .map(|(idx, _)| idx)
+      .collect::<Vec<_>>();
+    for idx in children {
+      for feature in self.features.iter() {
+        // tags from the grammar can't contain spaces, so these won't clash
+        let tag = Some(format!("percolate {}", feature));
+        let shared = NodeRef::new_from_paths(vec![
+          Feature {
+            path: feature.clone(),
+            tag: tag.clone(),
+            value: NodeRef::new_top(),
+          },
+          Feature {
+            path: format!("child-{}.{}", idx, feature),
+            tag,
+            value: NodeRef::new_top(),
+          },
+        ])?;
+        NodeRef::unify(rule.features.clone(), shared).map_err(|e| -> Err {
+          format!(
+            "{}: can't percolate {} from {}: {}",
+            rule.location().unwrap_or_default(),
+            feature,
+            self.from,
+            e
+          )
+          .into()
+        })?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// A `#lemma lex` declaration gives every lexical entry (a rule with only
+/// words on the right) its words as `lex`, so semantics can refer to the
+/// lexeme instead of the form it was written as. Entries that set `lex`
+/// themselves keep theirs, like `V[ lex: go ] -> went`, and an entry for
+/// `UNKNOWN_WORD` gets the word it matched.
+fn add_lemma(feature: &str, rule: &mut Rule) -> Result<(), Err> {
+  let lexical = !rule.productions.is_empty() && rule.productions.iter().all(|p| p.is_terminal());
+  if !lexical || rule.features.get_path(feature).is_some() {
+    return Ok(());
+  }
+  let lemma = match &rule.productions[..] {
+    [p] if p.is_unknown_word() => {
+      let tag = Some("lemma".to_string());
+      NodeRef::new_from_paths(vec![
+        Feature {
+          path: feature.to_string(),
+          tag: tag.clone(),
+          value: NodeRef::new_top(),
+        },
+        Feature {
+          path: "child-0.word".to_string(),
+          tag,
+          value: NodeRef::new_top(),
+        },
+      ])?
+    }
+    productions if productions.iter().any(|p| p.is_unknown_word()) => return Ok(()),
+    productions => {
+      let words = productions
+        .iter()
+        .map(|p| &*p.symbol)
+        .collect::<Vec<_>>()
+        .join(" ");
+      NodeRef::new_from_paths(vec![Feature {
+        path: feature.to_string(),
+        tag: None,
+        value: NodeRef::new_str(words),
+      }])?
+    }
+  };
+  NodeRef::unify(rule.features.clone(), lemma)
+}
+
+/// The declarations among the `#key value` lines, which change the rules
+/// instead of being metadata
+#[derive(Debug, Default)]
+struct Declarations {
+  percolations: Vec<Percolation>,
+  /// The feature from `#lemma`, see `add_lemma`
+  lemma: Option<String>,
+}
+
+/// Any number of `#key value` lines, before the first rule. `#percolate`
+/// and `#lemma` lines are declarations rather than metadata, so they're
+/// returned separately.
+fn parse_metadata(s: &str) -> ParseResult<'_, (GrammarMetadata, Declarations)> {
+  regex_static!(VALUE, r"[^\n]*");
+  regex_static!(DOTTED, r"^[a-zA-Z0-9\-_]+(\.[a-zA-Z0-9\-_]+)*$");
+
+  let mut metadata = GrammarMetadata::default();
+  let mut declarations = Declarations::default();
+  let mut rem = skip_whitespace(s);
+  while let (Some(_), s) = optional_char('#', rem) {
+    let (key, s) = parse_name(s).map_err(|e| -> Err { format!("metadata key: {}", e).into() })?;
+    let (value, s) = optional_re(&VALUE, s);
+    let value = value.unwrap_or_default();
+    let value = value.split("//").next().unwrap_or_default().trim();
+    if value.is_empty() {
+      return Err(format!("metadata #{} needs a value", key).into());
+    }
+    if key == "percolate" {
+      declarations.percolations.push(value.parse()?);
+    } else if key == "lemma" {
+      if declarations.lemma.is_some() {
+        return Err("duplicate #lemma".into());
+      }
+      if !DOTTED.is_match(value) {
+        return Err(format!("bad #lemma {}, expected a feature, like #lemma lex", value).into());
+      }
+      declarations.lemma = Some(value.to_string());
+    } else {
+      metadata.insert(key, value.to_string())?;
+    }
+    rem = skip_whitespace(s);
+  }
+  Ok(((metadata, declarations), rem))
+}
+
+fn parse_rules(s: &str) -> ParseResult<'_, Vec<(Rule, Vec<String>)>> {
   let mut rules = Vec::new();
   let mut rem = s;
+  // count lines incrementally, from the end of the last rule
+  let (mut line, mut counted) = (1, s);
   loop {
     rem = skip_whitespace(rem);
     if rem.is_empty() {
       return Ok((rules, rem));
     }
-    let (rule, s) = parse_rule(rem)?;
-    rules.push(rule);
-    rem = s;
+    line += counted[..counted.len() - rem.len()].matches('\n').count();
+    let ((mut rule, warnings), rest) = parse_rule(rem)?;
+    rule.line = line;
+    rules.push((rule, warnings));
+    counted = rem;
+    rem = rest;
   }
 }
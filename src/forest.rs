@@ -1,10 +1,12 @@
+use std::error::Error;
 use std::fmt;
 use std::sync::Arc;
 
 use crate::earley::Chart;
-use crate::rules::{Grammar, Rule};
+use crate::options::{Cancelled, ParseOptions};
+use crate::rules::{Grammar, Production, Rule};
 use crate::syntree::{Constituent, SynTree, Word};
-use crate::utils::combinations;
+use crate::utils::{reset_rows, Combinations};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ForestState {
@@ -19,6 +21,19 @@ impl ForestState {
       span: (start, end),
     }
   }
+
+  pub fn rule(&self) -> &Arc<Rule> {
+    &self.rule
+  }
+
+  pub fn span(&self) -> (usize, usize) {
+    self.span
+  }
+
+  /// States with the same origin are kept sorted by this, see `Forest::trees`
+  fn order_key(&self) -> (usize, usize) {
+    (self.span.1, self.rule.index)
+  }
 }
 
 impl fmt::Display for ForestState {
@@ -32,25 +47,156 @@ impl From<&ForestState> for Constituent<Arc<Rule>> {
     Self {
       value: fs.rule.clone(),
       span: fs.span,
+      function: None,
     }
   }
 }
 
+/// How deeply trees can nest before `Forest` gives up on building them, by
+/// default. Well past any sentence's tree, but shallow enough not to
+/// overflow the stack.
+pub const MAX_TREE_DEPTH: usize = 500;
+
+/// Why a forest couldn't be expanded into trees
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Forest(Vec<Vec<ForestState>>);
+pub enum ForestError {
+  /// A constituent is its own descendant over the same span, so it has
+  /// infinitely many trees. `Grammar::validate` rules out the grammars that
+  /// can do this, but forests from other backends or edited grammars can
+  /// still have cycles.
+  Cycle(ForestState),
+  /// Trees nest deeper than the forest's maximum depth, which is
+  /// `MAX_TREE_DEPTH` unless it was changed with `Forest::with_max_depth`
+  TooDeep { depth: usize, state: ForestState },
+  /// The parse the trees were being built for was cancelled, see
+  /// `Forest::try_iter_trees_with`
+  Cancelled,
+}
+
+impl fmt::Display for ForestError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let (message, state) = match self {
+      Self::Cycle(state) => ("constituent contains itself".to_string(), state),
+      Self::TooDeep { depth, state } => (format!("trees nest more than {} deep", depth), state),
+      Self::Cancelled => return write!(f, "{}", Cancelled),
+    };
+    write!(
+      f,
+      "{}: {} over {}..{}",
+      message,
+      state.rule.skeleton(),
+      state.span.0,
+      state.span.1
+    )
+  }
+}
+
+impl Error for ForestError {}
+
+impl From<Cancelled> for ForestError {
+  fn from(_: Cancelled) -> Self {
+    Self::Cancelled
+  }
+}
+
+/// Fails with `ForestError::Cancelled` if there are options and the parse
+/// they're for has been cancelled
+fn check_cancelled(options: Option<&ParseOptions>) -> Result<(), ForestError> {
+  match options {
+    Some(options) => Ok(options.check_cancelled()?),
+    None => Ok(()),
+  }
+}
+
+type Tree = SynTree<Arc<Rule>, Arc<str>>;
+
+/// A child found by `Forest::find_children`: a state, by its start and index
+/// in `Forest::states`, or a word at a position
+#[derive(Debug, Clone, Copy)]
+enum Step {
+  State(usize, usize),
+  Word(usize),
+}
+
+/// Reusable buffers for `Forest::single_tree_with`
+#[derive(Debug, Clone, Default)]
+pub struct TreeScratch {
+  /// The sequence of children being searched
+  path: Vec<Step>,
+  /// The children found for each constituent being built, innermost last
+  chosen: Vec<Step>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Forest {
+  states: Vec<Vec<ForestState>>,
+  /// Emptied rows from longer inputs, kept by `fill_from` for reuse
+  spare: Vec<Vec<ForestState>>,
+  /// The input, if it's known, for the words `UNKNOWN_WORD` matched
+  tokens: Vec<Arc<str>>,
+  max_depth: usize,
+}
 
 impl Forest {
+  /// Makes an empty forest for an input of `length` tokens
+  pub fn new(length: usize) -> Self {
+    // empty constituents can start at the end of the input
+    Self {
+      states: vec![Vec::new(); length + 1],
+      spare: Vec::new(),
+      tokens: Vec::new(),
+      max_depth: MAX_TREE_DEPTH,
+    }
+  }
+
+  /// Sets how deeply trees can nest before building them fails with
+  /// `ForestError::TooDeep`
+  pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+    self.max_depth = max_depth;
+    self
+  }
+
+  /// Remembers the input, so trees have the words `UNKNOWN_WORD` matched
+  /// instead of the placeholder. Forests from a chart get the chart's.
+  pub fn with_tokens(mut self, input: &[&str]) -> Self {
+    self.tokens = input.iter().map(|&t| Arc::from(t)).collect();
+    self
+  }
+
+  /// The word at `pos` for a terminal `production`
+  pub(crate) fn word(&self, production: &Production, pos: usize) -> Arc<str> {
+    match self.tokens.get(pos) {
+      Some(token) if production.is_unknown_word() => token.clone(),
+      _ => production.symbol.clone(),
+    }
+  }
+
+  /// Adds a completed state. Used by parser backends that don't go through a
+  /// `Chart`.
+  pub fn push(&mut self, state: ForestState) {
+    let states = &mut self.states[state.span.0];
+    if let Err(idx) = states.binary_search_by(|s| s.order_key().cmp(&state.order_key())) {
+      states.insert(idx, state);
+    }
+  }
+
+  /// The length of the input
   pub fn len(&self) -> usize {
-    self.0.len()
+    self.states.len() - 1
   }
 
   pub fn is_empty(&self) -> bool {
     self.len() == 0
   }
 
+  /// The completed states that start at `start`, shortest first
+  pub fn states_from(&self, start: usize) -> &[ForestState] {
+    &self.states[start]
+  }
+
   /// Checks if a subtree has already been completed by make_trees(),
   /// or if it is a leaf and doesn't need to be completed
-  fn subtree_is_complete(node: &SynTree<Arc<Rule>, String>) -> bool {
+  fn subtree_is_complete(node: &SynTree<Arc<Rule>, Arc<str>>) -> bool {
     if let Some((cons, children)) = node.get_branch() {
       cons.value.productions.len() == children.len()
     } else {
@@ -93,25 +239,30 @@ impl Forest {
     prod_idx: usize,
     search_start: usize,
     search_end: usize,
-  ) -> Vec<Vec<SynTree<Arc<Rule>, String>>> {
-    if prod_idx == rule.len() && search_start == search_end {
-      // base case, we consumed the whole rule and the whole span together.
-      // provide a single empty sequence as a base for prepending onto as we unwind the stack
-      return vec![Vec::new()];
-    } else if prod_idx == rule.len() || search_start == search_end {
-      // we either ran out of productions before consuming everything, or ran out of stuff to consume before
-      // satisfying all the productions. bail with 0 possible sequences.
-      return Vec::new();
+  ) -> Vec<Vec<SynTree<Arc<Rule>, Arc<str>>>> {
+    if prod_idx == rule.len() {
+      return if search_start == search_end {
+        // base case, we consumed the whole rule and the whole span together.
+        // provide a single empty sequence as a base for prepending onto as we unwind the stack
+        vec![Vec::new()]
+      } else {
+        // we ran out of productions before consuming everything. bail with 0 possible sequences.
+        Vec::new()
+      };
     }
 
     let next_production = &rule.productions[prod_idx];
     if next_production.is_nonterminal() {
       let wanted_symbol = &next_production.symbol;
       // look for potential next states to produce this production at the search start
-      self.0[search_start]
+      self.states[search_start]
         .iter()
         // only consider states that are contained within the search range, and have our wanted symbol
-        .filter(|s| s.span.1 <= search_end && wanted_symbol == &s.rule.symbol)
+        .filter(|s| {
+          s.span.1 <= search_end
+            && **wanted_symbol == s.rule.symbol
+            && rule.allows_span(Some(prod_idx), s.span.1 - s.span.0)
+        })
         .flat_map(|state| {
           // recursively find possible sequences that start directly after this state
           // TODO: this is probably easily amenable to some dynamic programming to reduce repeated work
@@ -125,11 +276,15 @@ impl Forest {
             })
         })
         .collect()
+    } else if search_start == search_end || !rule.allows_span(Some(prod_idx), 1) {
+      // we ran out of stuff to consume before satisfying all the productions. nonterminals can
+      // still be nullable, but terminals can't
+      Vec::new()
     } else {
       // similar to the nonterminal case, but we don't have to search for multiple potential states --
       // all terminals with the same symbol_str are identical.
       let leaf = SynTree::Leaf(Word {
-        value: next_production.symbol.to_string(),
+        value: self.word(next_production, search_start),
         span: (search_start, search_start + 1),
       });
 
@@ -146,79 +301,443 @@ impl Forest {
     }
   }
 
+  /// Records that `cons` is being expanded, under the constituents in
+  /// `ancestors`, failing if that would never end or go too deep
+  fn enter(
+    &self,
+    cons: &Constituent<Arc<Rule>>,
+    ancestors: &mut Vec<Constituent<Arc<Rule>>>,
+  ) -> Result<(), ForestError> {
+    let state = || ForestState::new(&cons.value, cons.span.0, cons.span.1);
+    let repeated = ancestors
+      .iter()
+      .any(|a| a.span == cons.span && Arc::ptr_eq(&a.value, &cons.value));
+    if repeated {
+      return Err(ForestError::Cycle(state()));
+    }
+    if ancestors.len() >= self.max_depth {
+      return Err(ForestError::TooDeep {
+        depth: self.max_depth,
+        state: state(),
+      });
+    }
+    ancestors.push(cons.clone());
+    Ok(())
+  }
+
   /// Takes a possibly-uncompleted tree, and returns all possible trees it describes.
   /// An uncompleted tree is a non-nullable constituent with 0 children. It needs to be passed
-  /// into extend_out, and then glued onto
-  fn make_trees(&self, tree: SynTree<Arc<Rule>, String>) -> Vec<SynTree<Arc<Rule>, String>> {
+  /// into extend_out, and then glued onto. `ancestors` are the constituents being expanded
+  /// above it, to catch cycles. Stops as soon as the parse `options` are for is cancelled.
+  fn make_trees(
+    &self,
+    tree: Tree,
+    ancestors: &mut Vec<Constituent<Arc<Rule>>>,
+    options: Option<&ParseOptions>,
+  ) -> Result<Vec<Tree>, ForestError> {
     if Self::subtree_is_complete(&tree) {
-      vec![tree]
-    } else {
-      let (cons, _) = tree.get_branch().unwrap();
-      self
-        .extend_out(&cons.value, 0, cons.span.0, cons.span.1)
+      return Ok(vec![tree]);
+    }
+    let (cons, _) = tree.into_branch().unwrap();
+    self.enter(&cons, ancestors)?;
+    let mut trees = Vec::new();
+    for children in self.extend_out(&cons.value, 0, cons.span.0, cons.span.1) {
+      check_cancelled(options)?;
+      let child_sets = children
         .into_iter()
-        .flat_map(|children| {
-          let child_sets = children
-            .into_iter()
-            .map(|child| self.make_trees(child))
-            .collect::<Vec<_>>();
-          combinations(&child_sets)
-            .into_iter()
-            .map(|set| SynTree::Branch(cons.clone(), set))
+        .map(|child| self.make_trees(child, ancestors, options))
+        .collect::<Result<Vec<_>, _>>()?;
+      Self::push_combinations(&cons, child_sets, &mut trees, options)?;
+    }
+    ancestors.pop();
+    Ok(trees)
+  }
+
+  /// Pushes a tree headed by `cons` for every combination of `child_sets`.
+  /// Kept out of `make_trees`, which recurses as deep as the trees do, so its
+  /// stack frame stays small.
+  #[inline(never)]
+  fn push_combinations(
+    cons: &Constituent<Arc<Rule>>,
+    child_sets: Vec<Vec<Tree>>,
+    trees: &mut Vec<Tree>,
+    options: Option<&ParseOptions>,
+  ) -> Result<(), ForestError> {
+    for set in Combinations::new(child_sets) {
+      check_cancelled(options)?;
+      trees.push(SynTree::Branch(cons.clone(), set));
+    }
+    Ok(())
+  }
+
+  /// Like make_trees, but lazy over the possible sequences of children, so
+  /// callers that only want some of the trees don't pay for building the rest
+  fn iter_made_trees<'a>(
+    &'a self,
+    tree: Tree,
+    options: Option<&'a ParseOptions>,
+  ) -> Box<dyn Iterator<Item = Result<Tree, ForestError>> + 'a> {
+    if Self::subtree_is_complete(&tree) {
+      return Box::new(std::iter::once(Ok(tree)));
+    }
+    let (cons, _) = tree.into_branch().unwrap();
+    let mut ancestors = Vec::new();
+    if let Err(e) = self.enter(&cons, &mut ancestors) {
+      return Box::new(std::iter::once(Err(e)));
+    }
+    let sequences = self.extend_out(&cons.value, 0, cons.span.0, cons.span.1);
+    Box::new(sequences.into_iter().flat_map(move |children| {
+      let mut ancestors = ancestors.clone();
+      let child_sets = check_cancelled(options).and_then(|()| {
+        children
+          .into_iter()
+          .map(|child| self.make_trees(child, &mut ancestors, options))
+          .collect::<Result<Vec<_>, _>>()
+      });
+      let cons = cons.clone();
+      let trees: Box<dyn Iterator<Item = _>> = match child_sets {
+        Ok(child_sets) => Box::new(Combinations::new(child_sets).map(move |set| {
+          check_cancelled(options)?;
+          Ok(SynTree::Branch(cons.clone(), set))
+        })),
+        Err(e) => Box::new(std::iter::once(Err(e))),
+      };
+      trees
+    }))
+  }
+
+  /// Lazily generates the trees headed by `state`, in the same order as
+  /// `trees`, or the error that stopped them, after which there are no more
+  pub fn try_iter_state_trees<'a>(
+    &'a self,
+    state: &ForestState,
+  ) -> impl Iterator<Item = Result<Tree, ForestError>> + 'a {
+    stop_after_error(self.iter_made_trees(SynTree::Branch(state.into(), Vec::new()), None))
+  }
+
+  /// Like `try_iter_state_trees`, stopping quietly at an error
+  pub fn iter_state_trees<'a>(&'a self, state: &ForestState) -> impl Iterator<Item = Tree> + 'a {
+    ok_until_error(self.try_iter_state_trees(state))
+  }
+
+  /// Lazily generates the trees in this forest, see `trees`, or the error
+  /// that stopped them, after which there are no more
+  pub fn try_iter_trees<'a>(
+    &'a self,
+    g: &'a Grammar,
+  ) -> impl Iterator<Item = Result<Tree, ForestError>> + 'a {
+    self.iter_roots(g, None)
+  }
+
+  /// Like `try_iter_trees`, but fails with `ForestError::Cancelled` once the
+  /// parse `options` are for is cancelled, even in the middle of building a
+  /// tree
+  pub fn try_iter_trees_with<'a>(
+    &'a self,
+    g: &'a Grammar,
+    options: &'a ParseOptions,
+  ) -> impl Iterator<Item = Result<Tree, ForestError>> + 'a {
+    self.iter_roots(g, Some(options))
+  }
+
+  fn iter_roots<'a>(
+    &'a self,
+    g: &'a Grammar,
+    options: Option<&'a ParseOptions>,
+  ) -> impl Iterator<Item = Result<Tree, ForestError>> + 'a {
+    // seed our search with all LR0s that started at position 0, span to
+    // the end of the string, and are named by the start symbol
+    let start = options.map_or(g.start.as_str(), |options| options.start_symbol(g));
+    let trees = self
+      .states
+      .first()
+      .into_iter()
+      .flatten()
+      .filter(move |state| state.span.1 == self.len() && state.rule.symbol == start)
+      .flat_map(move |state| {
+        self.iter_made_trees(SynTree::Branch(state.into(), Vec::new()), options)
+      });
+    stop_after_error(trees)
+  }
+
+  /// Like `try_iter_trees`, stopping quietly at an error
+  pub fn iter_trees<'a>(&'a self, g: &'a Grammar) -> impl Iterator<Item = Tree> + 'a {
+    ok_until_error(self.try_iter_trees(g))
+  }
+
+  /// Like `try_iter_trees_with`, stopping quietly at an error
+  pub fn iter_trees_with<'a>(
+    &'a self,
+    g: &'a Grammar,
+    options: &'a ParseOptions,
+  ) -> impl Iterator<Item = Tree> + 'a {
+    ok_until_error(self.try_iter_trees_with(g, options))
+  }
+
+  /// Builds every tree in the forest. Trees come out in a stable order that
+  /// only depends on the grammar and the input: wherever there's a choice of
+  /// constituent, shorter constituents are tried first, then constituents in
+  /// the order their rules are defined in the grammar. Stops at the first
+  /// tree that can't be built, see `try_trees`.
+  pub fn trees(&self, g: &Grammar) -> Vec<Tree> {
+    self.iter_trees(g).collect()
+  }
+
+  /// Like `trees`, failing with a `ForestError` if some tree can't be built
+  pub fn try_trees(&self, g: &Grammar) -> Result<Vec<Tree>, ForestError> {
+    self.try_iter_trees(g).collect()
+  }
+
+  /// The forest's only tree, if it has exactly one, built directly instead of
+  /// through the general enumeration, which is much cheaper for the common
+  /// case of an unambiguous input. None if there are no trees, or several,
+  /// or the tree can't be built, in which case `try_iter_trees` says which.
+  pub fn single_tree(&self, g: &Grammar) -> Option<Tree> {
+    self.single_tree_with(g, &mut TreeScratch::default())
+  }
+
+  /// Whether some symbol was completed over the same span by more than one
+  /// state, like an ambiguous root. Such a forest usually has more than one
+  /// tree, so `single_tree` isn't worth trying.
+  pub fn has_shared_cell(&self) -> bool {
+    // rows are sorted by end, so states over the same span are adjacent
+    self.states.iter().any(|row| {
+      row.chunk_by(|a, b| a.span.1 == b.span.1).any(|cell| {
+        cell.iter().enumerate().any(|(idx, a)| {
+          cell[idx + 1..]
+            .iter()
+            .any(|b| a.rule.symbol == b.rule.symbol)
         })
-        .collect::<Vec<_>>()
+      })
+    })
+  }
+
+  /// Like `single_tree`, with buffers that can be reused between forests, so
+  /// nothing is allocated but the tree itself
+  pub fn single_tree_with(&self, g: &Grammar, scratch: &mut TreeScratch) -> Option<Tree> {
+    self.single_tree_as(g, None, scratch)
+  }
+
+  /// Like `single_tree_with`, for a tree of the start symbol in `options`.
+  /// Gives up, returning None, as soon as the parse they're for is cancelled.
+  pub(crate) fn single_tree_as(
+    &self,
+    g: &Grammar,
+    options: Option<&ParseOptions>,
+    scratch: &mut TreeScratch,
+  ) -> Option<Tree> {
+    let start = options.map_or(g.start.as_str(), |options| options.start_symbol(g));
+    scratch.path.clear();
+    scratch.chosen.clear();
+    let mut roots = self
+      .states
+      .first()?
+      .iter()
+      .filter(|state| state.span.1 == self.len() && state.rule.symbol == start);
+    let root = roots.next()?;
+    if roots.next().is_some() {
+      return None;
     }
+    self.build_single(root, 0, options, scratch)
   }
 
-  pub fn trees(&self, g: &Grammar) -> Vec<SynTree<Arc<Rule>, String>> {
-    if self.is_empty() {
-      Vec::new()
-    } else {
-      // seed our search with all LR0s that started at position 0, span to
-      // the end of the string, and are named by the grammar's start symbol
-      let root_states = self.0[0]
-        .iter()
-        .filter(|state| state.span.1 == self.len() && state.rule.symbol == g.start)
-        .map(|state| SynTree::Branch(state.into(), Vec::new()));
-      // use make_trees to generate all possible filled-in trees from each seed tree
-      root_states.fold(
-        Vec::<SynTree<Arc<Rule>, String>>::new(),
-        |mut prev, tree| {
-          let mut trees = self.make_trees(tree);
-          prev.append(&mut trees);
-          prev
-        },
-      )
+  /// Builds the tree headed by `state`, if there's only one, `depth` deep in
+  /// the tree. Its children are found first and left at the end of
+  /// `scratch.chosen`, then built in turn.
+  fn build_single(
+    &self,
+    state: &ForestState,
+    depth: usize,
+    options: Option<&ParseOptions>,
+    scratch: &mut TreeScratch,
+  ) -> Option<Tree> {
+    // a cycle would have a second tree somewhere, but still has to end
+    if depth >= self.max_depth || options.is_some_and(ParseOptions::is_cancelled) {
+      return None;
+    }
+    let base = scratch.chosen.len();
+    scratch.path.clear();
+    let mut found = 0;
+    self.find_children(
+      &state.rule,
+      0,
+      state.span.0,
+      state.span.1,
+      scratch,
+      &mut found,
+    );
+    if found != 1 {
+      return None;
     }
+    let mut children = Vec::with_capacity(state.rule.len());
+    for (idx, production) in state.rule.productions.iter().enumerate() {
+      children.push(match scratch.chosen[base + idx] {
+        Step::State(start, idx) => {
+          self.build_single(&self.states[start][idx], depth + 1, options, scratch)?
+        }
+        Step::Word(pos) => SynTree::Leaf(Word {
+          value: self.word(production, pos),
+          span: (pos, pos + 1),
+        }),
+      });
+    }
+    scratch.chosen.truncate(base);
+    Some(SynTree::Branch(state.into(), children))
+  }
+
+  /// Like `extend_out`, but only counts the sequences of children, stopping
+  /// at two, and appends the first to `scratch.chosen` instead of building it
+  fn find_children(
+    &self,
+    rule: &Rule,
+    prod_idx: usize,
+    search_start: usize,
+    search_end: usize,
+    scratch: &mut TreeScratch,
+    found: &mut usize,
+  ) {
+    if *found > 1 {
+      return;
+    }
+    if prod_idx == rule.len() {
+      if search_start == search_end {
+        *found += 1;
+        if *found == 1 {
+          scratch.chosen.extend_from_slice(&scratch.path);
+        }
+      }
+      return;
+    }
+
+    let next_production = &rule.productions[prod_idx];
+    if next_production.is_nonterminal() {
+      let wanted_symbol = &next_production.symbol;
+      for (idx, state) in self.states[search_start].iter().enumerate() {
+        if state.span.1 <= search_end
+          && **wanted_symbol == state.rule.symbol
+          && rule.allows_span(Some(prod_idx), state.span.1 - state.span.0)
+        {
+          scratch.path.push(Step::State(search_start, idx));
+          self.find_children(rule, prod_idx + 1, state.span.1, search_end, scratch, found);
+          scratch.path.pop();
+        }
+      }
+    } else if search_start < search_end && rule.allows_span(Some(prod_idx), 1) {
+      scratch.path.push(Step::Word(search_start));
+      self.find_children(
+        rule,
+        prod_idx + 1,
+        search_start + 1,
+        search_end,
+        scratch,
+        found,
+      );
+      scratch.path.pop();
+    }
+  }
+}
+
+/// Ends `trees` after its first error
+fn stop_after_error<I>(trees: I) -> impl Iterator<Item = Result<Tree, ForestError>>
+where
+  I: Iterator<Item = Result<Tree, ForestError>>,
+{
+  let mut failed = false;
+  trees.map_while(move |tree| {
+    if failed {
+      return None;
+    }
+    failed = tree.is_err();
+    Some(tree)
+  })
+}
+
+/// The trees before the first error in `trees`
+fn ok_until_error<I>(trees: I) -> impl Iterator<Item = Tree>
+where
+  I: Iterator<Item = Result<Tree, ForestError>>,
+{
+  trees.map_while(|tree| {
+    if let Err(_e) = &tree {
+      trace_event!(warn, error = %_e, "stopped building trees");
+    }
+    tree.ok()
+  })
+}
+
+impl PartialEq for Forest {
+  /// Compares the states and tokens, not the spare storage
+  fn eq(&self, other: &Self) -> bool {
+    self.states == other.states && self.tokens == other.tokens && self.max_depth == other.max_depth
   }
 }
 
+impl Eq for Forest {}
+
 impl From<Chart> for Forest {
   fn from(chart: Chart) -> Self {
-    // the new chart will be indexed by origin location, and no rule can have
-    // its origin at the end of the string, so len is chart.len - 1
-    let mut v = vec![Vec::new(); chart.len() - 1];
+    Self::from(&chart)
+  }
+}
+
+impl From<&Chart> for Forest {
+  fn from(chart: &Chart) -> Self {
+    let mut forest = Self {
+      states: Vec::new(),
+      spare: Vec::new(),
+      tokens: Vec::new(),
+      max_depth: MAX_TREE_DEPTH,
+    };
+    forest.fill_from(chart);
+    forest
+  }
+}
+
+impl Forest {
+  /// Replaces this forest with the one built from `chart`, keeping its
+  /// allocations for reuse
+  pub fn fill_from(&mut self, chart: &Chart) {
+    trace_span!(debug_span, "build_forest");
+    // the new chart will be indexed by origin location. only empty
+    // constituents can have their origin at the end of the string, but they
+    // still need a place
+    self.tokens.clear();
+    self.tokens.extend_from_slice(chart.tokens());
+    reset_rows(&mut self.states, &mut self.spare, chart.len());
+    let v = &mut self.states;
+    let observer = chart.observer();
 
-    for (k, states) in chart.into_iter() {
-      for state in states {
+    for k in 0..chart.len() {
+      for state in chart.states_at(k) {
         // exclude unfinished rules that can't contribute to a tree
         if !state.lr0.is_active() {
-          v.get_mut(state.origin)
+          let state = ForestState::new(&state.lr0.rule, state.origin, k);
+          if let Some(observer) = &observer {
+            observer.forest_node_created(&state);
+          }
+          v.get_mut(state.span.0)
             .expect("origin > input len")
-            .push(ForestState::new(&state.lr0.rule, state.origin, k));
+            .push(state);
         }
       }
     }
 
-    Self(v)
+    for states in v.iter_mut() {
+      states.sort_by_key(ForestState::order_key);
+    }
+    trace_event!(
+      debug,
+      states = v.iter().map(Vec::len).sum::<usize>(),
+      "built forest"
+    );
   }
 }
 
 impl fmt::Display for Forest {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    for k in 0..self.len() {
+    for k in 0..=self.len() {
       writeln!(f, "Origin {}:", k)?;
-      for fs in self.0[k].iter() {
+      for fs in self.states[k].iter() {
         writeln!(f, "  {}", fs)?;
       }
     }
@@ -251,8 +770,8 @@ fn test_parse_chart() {
   let forest: Forest = crate::earley::parse_chart(&g, &["x", "x", "x"]).into();
 
   assert_eq!(
-    forest,
-    Forest(vec![
+    forest.states,
+    vec![
       vec![
         ForestState::new(rule1, 0, 1),
         ForestState::new(rule2, 0, 2),
@@ -260,7 +779,8 @@ fn test_parse_chart() {
       ],
       vec![ForestState::new(rule1, 1, 2), ForestState::new(rule2, 1, 3),],
       vec![ForestState::new(rule1, 2, 3)],
-    ])
+      vec![],
+    ]
   );
 
   println!("{}", forest);
@@ -292,3 +812,167 @@ fn test_tree_generation() {
 
   assert_eq!(trees.len(), 2);
 }
+
+#[test]
+fn test_tree_order() {
+  let g = r#"
+      S -> x
+      S -> S S
+    "#
+  .parse()
+  .unwrap();
+
+  let forest: Forest = crate::earley::parse_chart(&g, &["x", "x", "x"]).into();
+  let trees = forest.trees(&g);
+  assert_eq!(trees.len(), 2);
+
+  // [x][xx] sorts before [xx][x], as its first child is shorter
+  let first_child_span =
+    |t: &SynTree<Arc<Rule>, Arc<str>>| t.get_branch().unwrap().1[0].get_branch().unwrap().0.span;
+  assert_eq!(first_child_span(&trees[0]), (0, 1));
+  assert_eq!(first_child_span(&trees[1]), (0, 2));
+
+  let lines = trees[0]
+    .derivation()
+    .iter()
+    .map(|r| r.line)
+    .collect::<Vec<_>>();
+  assert_eq!(lines, vec![3, 2, 3, 2, 2]);
+}
+
+#[test]
+fn test_empty_constituents_at_end() {
+  let g: Grammar = r#"
+    S -> x B
+    S -> B
+    B ->
+  "#
+  .parse()
+  .unwrap();
+
+  let trees = g.parse(["x"]);
+  assert_eq!(trees.len(), 1);
+  assert_eq!(trees[0].0.to_sexpr_with_spans(), "(S@0..1 x@0..1 (B@1..1))");
+
+  let trees = g.parse(Vec::<String>::new());
+  assert_eq!(trees.len(), 1);
+  assert_eq!(trees[0].0.to_sexpr_with_spans(), "(S@0..0 (B@0..0))");
+}
+
+#[test]
+fn test_tree_expansion_limits() {
+  use crate::output::ParseError;
+
+  // A -> B -> A over the same span has no end. Grammar::new rejects this, so
+  // the forest is built by hand
+  let rule = |s: &str, index| {
+    let mut rule = s.parse::<Rule>().unwrap();
+    rule.index = index;
+    Arc::new(rule)
+  };
+  let (a, b_a, b_x) = (rule("A -> B", 0), rule("B -> A", 1), rule("B -> x", 2));
+  let mut forest = Forest::new(1);
+  for r in [&a, &b_a, &b_x] {
+    forest.push(ForestState::new(r, 0, 1));
+  }
+  let root = ForestState::new(&a, 0, 1);
+  let trees = forest.try_iter_state_trees(&root).collect::<Vec<_>>();
+  assert!(matches!(
+    trees.last(),
+    Some(Err(ForestError::Cycle(state))) if Arc::ptr_eq(state.rule(), &a)
+  ));
+  assert_eq!(
+    trees.last().unwrap().as_ref().unwrap_err().to_string(),
+    "constituent contains itself: A -> B over 0..1"
+  );
+  assert!(forest.iter_state_trees(&root).count() < trees.len());
+
+  let g: Grammar = "S -> x S\nS -> x".parse().unwrap();
+  let input = vec!["x"; 30];
+  let forest = Forest::from(crate::earley::parse_chart(&g, &input));
+  assert_eq!(forest.try_trees(&g).unwrap().len(), 1);
+  let forest = forest.with_max_depth(10);
+  assert!(matches!(
+    forest.try_trees(&g),
+    Err(ForestError::TooDeep { depth: 10, .. })
+  ));
+  assert!(forest.trees(&g).is_empty());
+  assert_eq!(
+    forest.try_trees(&g).unwrap_err().to_string(),
+    "trees nest more than 10 deep: S -> x S over 10..30"
+  );
+
+  // past the default depth, without overflowing the stack
+  let input = vec!["x"; MAX_TREE_DEPTH + 10];
+  assert!(matches!(g.try_parse(&input), Err(ParseError::Forest(_))));
+  assert!(g.parse(&input).is_empty());
+}
+
+#[test]
+fn test_single_tree() {
+  let g: Grammar = r#"
+    S -> NP VP
+    NP -> Det N
+    NP -> N
+    VP -> V NP
+    VP -> V
+    Det -> the
+    Det ->
+    N -> dog
+    N -> cat
+    V -> sees
+  "#
+  .parse()
+  .unwrap();
+
+  let forest = |input: &[&str]| Forest::from(crate::earley::parse_chart(&g, input));
+  let mut scratch = TreeScratch::default();
+  for input in [
+    &["the", "dog", "sees", "the", "cat"][..],
+    &["the", "dog", "sees"],
+  ] {
+    let forest = forest(input);
+    let tree = forest.single_tree_with(&g, &mut scratch).unwrap();
+    assert_eq!(forest.trees(&g), [tree]);
+  }
+  // "dog" is an NP with or without the empty Det
+  assert_eq!(forest(&["dog", "sees"]).trees(&g).len(), 2);
+  assert!(forest(&["dog", "sees"]).single_tree(&g).is_none());
+  assert!(forest(&["dog", "sees"]).has_shared_cell());
+  assert!(forest(&["sees", "dog"]).single_tree(&g).is_none());
+
+  let g: Grammar = "S -> x\nS -> S S".parse().unwrap();
+  let forest = Forest::from(crate::earley::parse_chart(&g, &["x", "x"]));
+  assert!(!forest.has_shared_cell());
+  assert_eq!(forest.single_tree(&g), forest.trees(&g).pop());
+  let forest = Forest::from(crate::earley::parse_chart(&g, &["x", "x", "x"]));
+  assert!(forest.single_tree(&g).is_none());
+  let forest = Forest::from(crate::earley::parse_chart(&g, &["x", "x"]));
+  assert!(forest.with_max_depth(1).single_tree(&g).is_none());
+}
+
+#[test]
+fn test_single_tree_cancelled() {
+  use std::sync::atomic::{AtomicBool, Ordering};
+
+  let g: Grammar = "S -> x\nS -> S S".parse().unwrap();
+  let forest = Forest::from(crate::earley::parse_chart(&g, &["x", "x"]));
+  let cancel = Arc::new(AtomicBool::new(false));
+  let options = ParseOptions {
+    cancel: Some(cancel.clone()),
+    ..ParseOptions::default()
+  };
+  let mut scratch = TreeScratch::default();
+  assert!(forest
+    .single_tree_as(&g, Some(&options), &mut scratch)
+    .is_some());
+
+  cancel.store(true, Ordering::Relaxed);
+  assert!(forest
+    .single_tree_as(&g, Some(&options), &mut scratch)
+    .is_none());
+  assert!(matches!(
+    forest.try_iter_trees_with(&g, &options).next(),
+    Some(Err(ForestError::Cancelled))
+  ));
+}
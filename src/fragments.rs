@@ -0,0 +1,157 @@
+//! Parsing inputs that are a sequence of constituents instead of one, like
+//! the non-sentential utterances of dialogue: "yes, the red one" is an
+//! interjection and a noun phrase, neither of which is a sentence. Each
+//! analysis splits the whole input into fragments, each a parse of one of a
+//! set of root symbols.
+
+use std::collections::HashMap;
+
+use crate::earley::parse_chart_everywhere;
+use crate::forest::Forest;
+use crate::options::ParseOptions;
+use crate::rules::{Grammar, GrammarError};
+use crate::{with_tokens, ParsedTree};
+
+impl Grammar {
+  /// Every way to split `input` into parses of the start symbol, fewest
+  /// fragments first, so a parse of the whole input comes before any that
+  /// split it
+  pub fn parse_fragments<I>(&self, input: I) -> Vec<Vec<ParsedTree>>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    let start = self.start.clone();
+    self
+      .parse_fragments_with(input, &[&start], &ParseOptions::default())
+      .expect("grammar missing start rules")
+  }
+
+  /// Like `parse_fragments`, with any of `roots` as fragments, like
+  /// `["S", "NP", "Intj"]`. Fragments are unified like whole parses, except
+  /// for `root_features`, which are for sentences. `best_first`, `packing`
+  /// and the ambiguity warning are ignored, and a tree too large for the
+  /// `max_fs_*` limits is dropped instead of ending the parse. Empty if the
+  /// parse is cancelled. Fails with `GrammarError::UndefinedStart` if
+  /// `ParseOptions::start` has no rules.
+  pub fn parse_fragments_with<I>(
+    &self,
+    input: I,
+    roots: &[&str],
+    options: &ParseOptions,
+  ) -> Result<Vec<Vec<ParsedTree>>, GrammarError>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    with_tokens(input, |input| {
+      let forest = Forest::from(parse_chart_everywhere(self, input, options)?);
+      if input.is_empty() || options.is_cancelled() {
+        return Ok(Vec::new());
+      }
+
+      // the parses of a root symbol at each span, by start
+      let mut cells: HashMap<usize, Vec<ParsedTree>> = HashMap::new();
+      for start in 0..forest.len() {
+        for state in forest.states_from(start) {
+          if state.span().1 == start || !roots.contains(&&*state.rule().symbol) {
+            continue;
+          }
+          let trees = forest.iter_state_trees(state).filter_map(|tree| {
+            let tree = Self::unify_tree_with(tree, options).ok()?;
+            Some(self.rename_children(tree))
+          });
+          cells.entry(start).or_default().extend(trees);
+        }
+      }
+
+      let mut analyses = split(&cells, 0, input.len());
+      analyses.sort_by_key(Vec::len);
+      trace_event!(debug, analyses = analyses.len(), "parsed fragments");
+      Ok(analyses)
+    })
+  }
+}
+
+/// Every sequence of the trees in `cells` that covers `start..end`
+fn split(
+  cells: &HashMap<usize, Vec<ParsedTree>>,
+  start: usize,
+  end: usize,
+) -> Vec<Vec<ParsedTree>> {
+  if start == end {
+    return vec![Vec::new()];
+  }
+  let mut out = Vec::new();
+  for tree in cells.get(&start).into_iter().flatten() {
+    for mut rest in split(cells, tree.0.span().1, end) {
+      rest.insert(0, tree.clone());
+      out.push(rest);
+    }
+  }
+  out
+}
+
+#[test]
+fn test_parse_fragments() {
+  let g: Grammar = r#"
+    S -> NP VP
+    NP -> Det AP N
+    NP -> Det N
+    AP -> Adj
+    VP -> V
+    Intj -> yes
+    Intj -> no
+    Det -> the
+    Adj -> red
+    N[ num: sg ] -> one
+    N[ num: sg ] -> ball
+    V -> rolls
+  "#
+  .parse()
+  .unwrap();
+
+  let whole = g.parse_fragments(["the", "ball", "rolls"]);
+  assert_eq!(whole.len(), 1);
+  assert_eq!(whole[0].len(), 1);
+  assert!(g.parse_fragments(["yes", "the", "red", "one"]).is_empty());
+
+  let roots = ["S", "NP", "Intj"];
+  let options = ParseOptions::default();
+  let analyses = g
+    .parse_fragments_with(["yes", "the", "red", "one"], &roots, &options)
+    .unwrap();
+  assert_eq!(analyses.len(), 1);
+  let symbols = analyses[0]
+    .iter()
+    .map(|(tree, _)| tree.get_branch().unwrap().0.value.as_str())
+    .collect::<Vec<_>>();
+  assert_eq!(symbols, ["Intj", "NP"]);
+  assert_eq!(analyses[0][1].0.span(), (1, 4));
+
+  // "the ball" is a fragment, but "rolls" isn't
+  let analyses = g
+    .parse_fragments_with(["the", "ball", "rolls", "no"], &roots, &options)
+    .unwrap();
+  assert_eq!(analyses.len(), 1);
+  assert_eq!(analyses[0].len(), 2);
+  assert!(g
+    .parse_fragments_with(["rolls"], &roots, &options)
+    .unwrap()
+    .is_empty());
+}
+
+#[test]
+fn test_fragments_undefined_start() {
+  let g: Grammar = "S -> NP\nNP -> x".parse().unwrap();
+  let options = |start: &str| ParseOptions {
+    start: Some(start.to_string()),
+    ..ParseOptions::default()
+  };
+  let analyses = g.parse_fragments_with(["x"], &["NP"], &options("NP"));
+  assert_eq!(analyses.unwrap().len(), 1);
+  assert!(matches!(
+    g.parse_fragments_with(["x"], &["NP"], &options("Np")),
+    Err(GrammarError::UndefinedStart(s)) if s == "Np"
+  ));
+}
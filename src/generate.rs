@@ -0,0 +1,352 @@
+//! Sentence generation, at random or from a feature structure.
+//!
+//! Random sentences are generated from the grammar's rules alone, ignoring
+//! features, so a generated sentence may not survive unification. Use
+//! `Grammar::parse_one` to check, or `sentences` to only get ones that do.
+//! `from_features` generates every tree whose features are compatible with a
+//! given structure, and `inflect` does the same for single words, from the
+//! lexicon.
+
+use std::sync::Arc;
+
+use crate::featurestructure::NodeRef;
+use crate::rules::{Grammar, Rule};
+use crate::syntree::{Constituent, SynTree, Word};
+use crate::ParsedTree;
+
+/// A source of randomness for generation. `XorShiftRng` is the built-in one,
+/// but anything that can produce `u64`s will do, like an adapter for the
+/// `rand` crate's generators.
+pub trait Rng {
+  fn next_u64(&mut self) -> u64;
+
+  /// A number in `0..n`. Panics if n is 0.
+  fn below(&mut self, n: usize) -> usize {
+    assert!(n > 0, "empty range");
+    (self.next_u64() % n as u64) as usize
+  }
+}
+
+/// A small, fast pseudo-random number generator (xorshift64*). Not suitable
+/// for anything security-related, but plenty for picking rules. The same seed
+/// always gives the same sequence, so generated sentences can be reproduced.
+#[derive(Debug, Clone)]
+pub struct XorShiftRng(u64);
+
+impl XorShiftRng {
+  pub fn new(seed: u64) -> Self {
+    // xorshift gets stuck at 0, and small seeds take a while to get going
+    Self((seed ^ 0x9e37_79b9_7f4a_7c15) | 1)
+  }
+}
+
+impl Rng for XorShiftRng {
+  fn next_u64(&mut self) -> u64 {
+    self.0 ^= self.0 >> 12;
+    self.0 ^= self.0 << 25;
+    self.0 ^= self.0 >> 27;
+    self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+  }
+}
+
+/// Generates a random sentence by picking a random rule for each nonterminal,
+/// starting from the start symbol. Returns None if the derivation gets deeper
+/// than `max_depth`, which is how recursive grammars are kept finite.
+pub fn generate<R: Rng + ?Sized>(
+  g: &Grammar,
+  rng: &mut R,
+  max_depth: usize,
+) -> Option<Vec<String>> {
+  let mut out = Vec::new();
+  if expand(g, &g.start, rng, max_depth, &mut out) {
+    Some(out)
+  } else {
+    None
+  }
+}
+
+fn expand<R: Rng + ?Sized>(
+  g: &Grammar,
+  symbol: &str,
+  rng: &mut R,
+  depth: usize,
+  out: &mut Vec<String>,
+) -> bool {
+  if depth == 0 {
+    return false;
+  }
+
+  let rules = match g.rules.get(symbol) {
+    Some(rules) if !rules.is_empty() => rules,
+    _ => return false,
+  };
+  let rule = &rules[rng.below(rules.len())];
+
+  rule.productions.iter().all(|p| {
+    if p.is_terminal() {
+      out.push(p.symbol.to_string());
+      true
+    } else {
+      expand(g, &p.symbol, rng, depth - 1, out)
+    }
+  })
+}
+
+/// An endless iterator of random sentences that parse, including surviving
+/// unification. Each attempt is limited to `max_depth` like `generate`, and the
+/// iterator ends after `max_attempts` failed attempts in a row, so grammars
+/// that rarely generate anything grammatical don't loop forever.
+pub fn sentences<'a, R: Rng + ?Sized>(
+  g: &'a Grammar,
+  rng: &'a mut R,
+  max_depth: usize,
+  max_attempts: usize,
+) -> impl Iterator<Item = Vec<String>> + 'a {
+  std::iter::from_fn(move || {
+    for _ in 0..max_attempts {
+      if let Some(sentence) = generate(g, rng, max_depth) {
+        if g.parse_one(&sentence).is_some() {
+          return Some(sentence);
+        }
+      }
+    }
+    None
+  })
+}
+
+/// Every tree (with its features) that the grammar can build from its start
+/// symbol whose features include everything in `fs`, up to `max_depth` nonterminals deep.
+/// The `child-N` features of `fs` guide the search, so generating from a
+/// parse's features is quick, while a structure without them has to try every
+/// derivation. `fs` isn't changed.
+pub fn from_features(g: &Grammar, fs: &NodeRef, max_depth: usize) -> Vec<ParsedTree> {
+  let mut out = Vec::new();
+  for mut tree in derivations(g, &g.start, Some(fs), max_depth) {
+    tree.respan();
+    if let Ok((tree, features)) = Grammar::unify_tree(tree) {
+      // fs has to subsume the tree's features: unifying it in changes nothing
+      let unified = features.deep_clone();
+      if NodeRef::unify(unified.clone(), fs.deep_clone()).is_ok()
+        && NodeRef::equivalent(&unified, &features)
+      {
+        out.push((tree, features));
+      }
+    }
+  }
+  out
+}
+
+/// The surface forms of the lexical entries (rules with only words on the
+/// right) that have every feature in `fs`, in the order they're defined, like
+/// `went` for `[ lex: go, tense: past ]`. Entries with more than one word give
+/// them joined with spaces. Entries that leave a feature in `fs` unset don't
+/// count, so `go` without a `tense` isn't a past form.
+pub fn inflect(g: &Grammar, fs: &NodeRef) -> Vec<String> {
+  let mut out: Vec<String> = Vec::new();
+  for rule in g.rules_in_order() {
+    if rule.productions.is_empty() || rule.productions.iter().any(|p| p.is_nonterminal()) {
+      continue;
+    }
+    let unified = rule.features.deep_clone();
+    if NodeRef::unify(unified.clone(), fs.deep_clone()).is_err()
+      || !NodeRef::equivalent(&unified, &rule.features)
+    {
+      continue;
+    }
+    let form = rule
+      .productions
+      .iter()
+      .map(|p| &*p.symbol)
+      .collect::<Vec<_>>()
+      .join(" ");
+    if !out.contains(&form) {
+      out.push(form);
+    }
+  }
+  out
+}
+
+type DerivationTree = SynTree<Arc<Rule>, Arc<str>>;
+
+/// The derivations of `symbol` whose rules don't rule out `fs` on their own.
+/// Spans are left for `SynTree::respan`.
+fn derivations(
+  g: &Grammar,
+  symbol: &str,
+  fs: Option<&NodeRef>,
+  depth: usize,
+) -> Vec<DerivationTree> {
+  if depth == 0 {
+    return Vec::new();
+  }
+  let fs = fs.map(|fs| fs.clone().dereference());
+  // one past the highest child-N in fs, which a rule needs at least as many
+  // productions as
+  let min_len = fs.as_ref().map_or(0, |fs| {
+    fs.walk()
+      .filter(|(path, _)| path.len() == 1)
+      .filter_map(|(path, _)| path[0].strip_prefix("child-")?.parse::<usize>().ok())
+      .map(|idx| idx + 1)
+      .max()
+      .unwrap_or(0)
+  });
+
+  let mut out = Vec::new();
+  'rules: for rule in g.rules.get(symbol).into_iter().flatten() {
+    if rule.len() < min_len {
+      continue;
+    }
+    if let Some(fs) = &fs {
+      if NodeRef::unify(rule.features.deep_clone(), fs.deep_clone()).is_err() {
+        continue;
+      }
+    }
+
+    // every combination of the children's derivations
+    let mut partials: Vec<Vec<DerivationTree>> = vec![Vec::new()];
+    for (idx, p) in rule.productions.iter().enumerate() {
+      let child_fs = fs
+        .as_ref()
+        .and_then(|fs| fs.get_path(&format!("child-{}", idx)));
+      let options = if p.is_terminal() {
+        // a word's features are just the word
+        let fits = child_fs.as_ref().is_none_or(|fs| {
+          fs.walk()
+            .filter(|(path, _)| path.len() == 1)
+            .all(|(path, node)| path[0] == "word" && node.get_str().is_none_or(|w| *w == *p.symbol))
+        });
+        if !fits {
+          continue 'rules;
+        }
+        vec![SynTree::Leaf(Word {
+          value: p.symbol.clone(),
+          span: (0, 0),
+        })]
+      } else {
+        derivations(g, &p.symbol, child_fs.as_ref(), depth - 1)
+      };
+      partials = partials
+        .into_iter()
+        .flat_map(|partial| {
+          options.iter().map(move |option| {
+            let mut partial = partial.clone();
+            partial.push(option.clone());
+            partial
+          })
+        })
+        .collect();
+    }
+
+    out.extend(partials.into_iter().map(|children| {
+      SynTree::Branch(
+        Constituent {
+          value: rule.clone(),
+          span: (0, 0),
+          function: None,
+        },
+        children,
+      )
+    }));
+  }
+  out
+}
+
+#[test]
+fn test_generate() {
+  let g: Grammar = r#"
+    S -> N[ num: #1 ] V[ num: #1 ]
+    S -> S and S
+    N[ num: sg ] -> mary
+    N[ num: pl ] -> they
+    V[ num: sg ] -> runs
+    V[ num: pl ] -> run
+  "#
+  .parse()
+  .unwrap();
+
+  let mut rng = XorShiftRng::new(1);
+  let mut generated = 0;
+  for _ in 0..50 {
+    if let Some(sentence) = generate(&g, &mut rng, 4) {
+      generated += 1;
+      assert!(sentence.len() % 3 == 2);
+    }
+  }
+  assert!(generated > 0);
+
+  let mut rng = XorShiftRng::new(2);
+  for sentence in sentences(&g, &mut rng, 4, 100).take(20) {
+    let sentence = sentence.join(" ");
+    assert!(!sentence.contains("mary run ") && !sentence.ends_with("mary run"));
+    assert!(!sentence.contains("they runs"));
+  }
+
+  // the same seed gives the same sentences
+  let a = generate(&g, &mut XorShiftRng::new(3), 10);
+  let b = generate(&g, &mut XorShiftRng::new(3), 10);
+  assert_eq!(a, b);
+
+  // any Rng will do, like one that always picks the first rule
+  struct First;
+  impl Rng for First {
+    fn next_u64(&mut self) -> u64 {
+      0
+    }
+  }
+  assert_eq!(
+    generate(&g, &mut First, 10),
+    Some(vec!["mary".to_string(), "runs".to_string()])
+  );
+}
+
+#[test]
+fn test_from_features() {
+  let g: Grammar = r#"
+    S -> N[ num: #1 ] V[ num: #1 ]
+    S -> S and S
+    N[ num: sg ] -> mary
+    N[ num: pl ] -> they
+    V[ num: sg ] -> runs
+    V[ num: pl ] -> run
+  "#
+  .parse()
+  .unwrap();
+
+  let yields = |trees: Vec<ParsedTree>| {
+    trees
+      .into_iter()
+      .map(|(t, _)| t.to_sexpr())
+      .collect::<Vec<_>>()
+  };
+
+  let (_, fs) = g.parse_one(&["mary", "runs"]).unwrap();
+  assert_eq!(yields(from_features(&g, &fs, 5)), ["(S (N mary) (V runs))"]);
+
+  // without words, only the agreement is left to go on
+  let fs: NodeRef = "[ child-0.num: pl ]".parse().unwrap();
+  assert_eq!(yields(from_features(&g, &fs, 2)), ["(S (N they) (V run))"]);
+  assert!(from_features(&g, &"[ child-0.num: du ]".parse().unwrap(), 3).is_empty());
+}
+
+#[test]
+fn test_inflect() {
+  let g: Grammar = r#"
+    S -> N V
+    N[ num: sg ] -> mary
+    V[ lex: go, tense: pres, num: sg ] -> goes
+    V[ lex: go, tense: pres, num: pl ] -> go
+    V[ lex: go, tense: past ] -> went
+    V[ lex: go, tense: perf ] -> has gone
+    V[ lex: go ] -> go
+    V[ lex: walk, tense: past ] -> walked
+  "#
+  .parse()
+  .unwrap();
+
+  let inflect = |fs: &str| inflect(&g, &fs.parse().unwrap());
+  assert_eq!(inflect("[ lex: go, tense: past ]"), ["went"]);
+  assert_eq!(inflect("[ lex: go, tense: pres ]"), ["goes", "go"]);
+  assert_eq!(inflect("[ lex: go, tense: perf ]"), ["has gone"]);
+  assert_eq!(inflect("[ tense: past ]"), ["went", "walked"]);
+  assert!(inflect("[ lex: go, tense: fut ]").is_empty());
+}
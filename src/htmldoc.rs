@@ -0,0 +1,266 @@
+//! Renders a grammar as a browsable HTML reference, so a language's
+//! documentation can be generated from its `.fgr` source instead of kept in
+//! sync by hand.
+//!
+//! The page has the grammar's metadata, an inventory of its nonterminals, its
+//! phrasal rules with their doc comments (the `//` comment lines directly
+//! above a rule, other than `// test:` lines), the features used on each
+//! symbol and the values they take, and the lexicon (rules that only have
+//! words on the right) sorted by word.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::rules::{Grammar, Rule};
+use crate::utils::escape_html;
+
+const STYLE: &str = "body { font-family: sans-serif; max-width: 60em; margin: auto; padding: 1em; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.5em; text-align: left; vertical-align: top; }
+pre { background: #f6f6f6; padding: 0.5em; overflow-x: auto; }
+.line { color: #888; font-size: smaller; }";
+
+/// Renders `g` as a standalone HTML page. `src` is the grammar's source, for
+/// the doc comments.
+pub fn render(g: &Grammar, src: &str) -> String {
+  let lines = src.lines().collect::<Vec<_>>();
+  let rules = g.rules_in_order();
+  let (lexical, phrasal): (Vec<&Arc<Rule>>, Vec<&Arc<Rule>>) =
+    rules.iter().partition(|r| is_lexical(r));
+
+  // nonterminals in the order they're first defined
+  let mut symbols: Vec<&str> = Vec::new();
+  for rule in rules.iter() {
+    if !symbols.contains(&rule.symbol.as_str()) {
+      symbols.push(&rule.symbol);
+    }
+  }
+
+  let title = g.metadata.name.as_deref().unwrap_or("Grammar");
+  let mut out = String::new();
+  let _ = writeln!(
+    out,
+    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}\n</style>\n</head>\n<body>",
+    escape_html(title),
+    STYLE
+  );
+  let _ = writeln!(out, "<h1>{}</h1>", escape_html(title));
+  if !g.metadata.is_empty() {
+    out += "<dl>\n";
+    for (key, value) in g.metadata.iter().filter(|(k, _)| *k != "name") {
+      let _ = writeln!(
+        out,
+        "<dt>{}</dt><dd>{}</dd>",
+        escape_html(key),
+        escape_html(value)
+      );
+    }
+    out += "</dl>\n";
+  }
+  out += "<ul>\n<li><a href=\"#nonterminals\">Nonterminals</a></li>\n<li><a href=\"#rules\">Rules</a></li>\n<li><a href=\"#features\">Features</a></li>\n<li><a href=\"#lexicon\">Lexicon</a></li>\n</ul>\n";
+
+  out += "<h2 id=\"nonterminals\">Nonterminals</h2>\n<table>\n<tr><th>Symbol</th><th>Rules</th><th>Lexical entries</th><th>Can be empty</th></tr>\n";
+  for symbol in symbols.iter() {
+    let count = |rules: &[&Arc<Rule>]| rules.iter().filter(|r| r.symbol == *symbol).count();
+    let _ = writeln!(
+      out,
+      "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+      symbol_link(symbol, &phrasal),
+      count(&phrasal),
+      count(&lexical),
+      if g.is_nullable(symbol) { "yes" } else { "" }
+    );
+  }
+  out += "</table>\n";
+
+  out += "<h2 id=\"rules\">Rules</h2>\n";
+  for symbol in symbols.iter() {
+    let symbol_rules = phrasal.iter().filter(|r| r.symbol == *symbol);
+    let mut first = true;
+    for rule in symbol_rules {
+      if first {
+        let _ = writeln!(
+          out,
+          "<h3 id=\"{}\">{}</h3>",
+          anchor(symbol),
+          escape_html(symbol)
+        );
+        first = false;
+      }
+      if let Some(doc) = doc_comment(&lines, rule.line) {
+        let _ = writeln!(out, "<p>{}</p>", escape_html(&doc));
+      }
+      let _ = writeln!(
+        out,
+        "<pre>{}</pre>\n<p class=\"line\">line {}</p>",
+        escape_html(&rule.to_string()),
+        rule.line
+      );
+    }
+  }
+
+  out += "<h2 id=\"features\">Features</h2>\n";
+  for (symbol, paths) in feature_geometry(&rules).iter() {
+    let _ = writeln!(
+      out,
+      "<h3>{}</h3>\n<table>\n<tr><th>Feature</th><th>Values</th></tr>",
+      escape_html(symbol)
+    );
+    for (path, values) in paths {
+      let values = values.iter().map(|v| escape_html(v)).collect::<Vec<_>>();
+      let _ = writeln!(
+        out,
+        "<tr><td>{}</td><td>{}</td></tr>",
+        escape_html(path),
+        values.join(", ")
+      );
+    }
+    out += "</table>\n";
+  }
+
+  out += "<h2 id=\"lexicon\">Lexicon</h2>\n<table>\n<tr><th>Word</th><th>Category</th><th>Features</th><th>Line</th></tr>\n";
+  let mut entries = lexical
+    .iter()
+    .map(|r| {
+      let words = r
+        .productions
+        .iter()
+        .map(|p| &*p.symbol)
+        .collect::<Vec<_>>()
+        .join(" ");
+      (words, *r)
+    })
+    .collect::<Vec<_>>();
+  entries.sort_by(|(w1, r1), (w2, r2)| (w1, r1.index).cmp(&(w2, r2.index)));
+  for (words, rule) in entries {
+    let features = own_features(rule)
+      .into_iter()
+      .map(|(path, value)| format!("{}: {}", path, value))
+      .collect::<Vec<_>>()
+      .join(", ");
+    let _ = writeln!(
+      out,
+      "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+      escape_html(&words),
+      symbol_link(&rule.symbol, &phrasal),
+      escape_html(&features),
+      rule.line
+    );
+  }
+  out += "</table>\n</body>\n</html>\n";
+  out
+}
+
+/// Rules with only words on the right, like `N[ num: sg ] -> mary`
+fn is_lexical(rule: &Rule) -> bool {
+  !rule.is_empty() && rule.productions.iter().all(|p| p.is_terminal())
+}
+
+fn anchor(symbol: &str) -> String {
+  format!("rules-{}", symbol)
+}
+
+/// The symbol, linked to its rules if it has any phrasal ones
+fn symbol_link(symbol: &str, phrasal: &[&Arc<Rule>]) -> String {
+  if phrasal.iter().any(|r| r.symbol == symbol) {
+    format!(
+      "<a href=\"#{}\">{}</a>",
+      anchor(symbol),
+      escape_html(symbol)
+    )
+  } else {
+    escape_html(symbol)
+  }
+}
+
+/// The `//` comment lines directly above the (1-based) line `line`, joined
+fn doc_comment(lines: &[&str], line: usize) -> Option<String> {
+  let mut doc = Vec::new();
+  for l in lines[..line.saturating_sub(1).min(lines.len())]
+    .iter()
+    .rev()
+  {
+    match l.trim().strip_prefix("//") {
+      Some(comment) if comment.trim_start().starts_with("test:") => continue,
+      Some(comment) => doc.push(comment.trim_start_matches('/').trim()),
+      None => break,
+    }
+  }
+  doc.reverse();
+  let doc = doc.join(" ");
+  (!doc.trim().is_empty()).then(|| doc.trim().to_string())
+}
+
+/// The string-valued features a rule puts on its own symbol, as `path`, `value`
+fn own_features(rule: &Rule) -> Vec<(String, String)> {
+  rule
+    .features
+    .walk()
+    .filter(|(path, _)| path.first().is_some_and(|l| !l.starts_with("child-")))
+    .filter_map(|(path, node)| Some((path.join("."), node.get_str()?.to_string())))
+    .collect()
+}
+
+/// For every symbol, the feature paths rules use on it (on their own symbol,
+/// or on a child with that symbol), with the string values those take
+fn feature_geometry(rules: &[&Arc<Rule>]) -> BTreeMap<String, BTreeMap<String, BTreeSet<String>>> {
+  let mut geometry: BTreeMap<String, BTreeMap<String, BTreeSet<String>>> = BTreeMap::new();
+  for rule in rules {
+    for (path, node) in rule.features.walk() {
+      let Some(first) = path.first() else { continue };
+      let (symbol, path) = match first
+        .strip_prefix("child-")
+        .and_then(|idx| idx.parse::<usize>().ok())
+      {
+        Some(idx) => match rule.productions.get(idx) {
+          Some(p) if p.is_nonterminal() && path.len() > 1 => (p.symbol.to_string(), &path[1..]),
+          _ => continue,
+        },
+        None => (rule.symbol.clone(), &path[..]),
+      };
+      let values = geometry
+        .entry(symbol)
+        .or_default()
+        .entry(path.join("."))
+        .or_default();
+      if let Some(value) = node.get_str() {
+        values.insert(value.to_string());
+      }
+    }
+  }
+  geometry
+}
+
+#[test]
+fn test_render() {
+  let src = r#"#name Toy <English>
+#version 2
+
+// A sentence is a subject and a verb
+// that agree in number.
+// test: mary runs
+S -> N[ num: #1 ] V[ num: #1 ]
+
+N[ num: sg ] -> mary
+N[ num: pl ] -> cats
+V[ num: sg, tense: pres ] -> runs
+"#;
+  let g: Grammar = src.parse().unwrap();
+  let html = render(&g, src);
+
+  assert!(html.contains("<title>Toy &lt;English&gt;</title>"));
+  assert!(html.contains("<dt>version</dt><dd>2</dd>"));
+  assert!(html.contains("<p>A sentence is a subject and a verb that agree in number.</p>"));
+  assert!(!html.contains("test: mary runs"));
+  assert!(
+    html.contains("<tr><td><a href=\"#rules-S\">S</a></td><td>1</td><td>0</td><td></td></tr>")
+  );
+  assert!(html.contains("<tr><td>N</td><td>0</td><td>2</td><td></td></tr>"));
+  assert!(html.contains("<tr><td>num</td><td>pl, sg</td></tr>"));
+  assert!(html.contains("<tr><td>tense</td><td>pres</td></tr>"));
+  // the lexicon is sorted by word
+  let cats = html.find("<tr><td>cats</td><td>N</td><td>num: pl</td><td>10</td></tr>");
+  let mary = html.find("<tr><td>mary</td>");
+  assert!(cats.is_some() && cats < mary);
+}
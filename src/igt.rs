@@ -0,0 +1,208 @@
+//! Interlinear glossed text, following the
+//! [Leipzig Glossing Rules](https://www.eva.mpg.de/lingua/resources/glossing-rules.php).
+//!
+//! Lexical entries give their glosses with a `gloss` feature, and optionally
+//! their morpheme breakdown with `morph`, like
+//! `V[ morph: walk-ed, gloss: walk-PST ] -> walked`. A `translation` feature on
+//! the root becomes the free translation, with underscores for spaces since
+//! feature values can't have any. Words without a gloss are glossed as
+//! themselves.
+//!
+//! ```text
+//! mary  walked
+//! mary  walk-ed
+//! Mary  walk-PST
+//! 'Mary walked'
+//! ```
+
+use std::fmt;
+
+use crate::featurestructure::NodeRef;
+use crate::syntree::SynTree;
+use crate::ParsedTree;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlossedWord {
+  pub word: String,
+  /// The word split into morphemes with `-` (or `=` for clitics), or None if
+  /// it wasn't split
+  pub morphemes: Option<String>,
+  pub gloss: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Igt {
+  pub words: Vec<GlossedWord>,
+  pub translation: Option<String>,
+}
+
+impl From<&ParsedTree> for Igt {
+  fn from((tree, fs): &ParsedTree) -> Self {
+    let mut words = Vec::new();
+    collect_words(tree, fs, &mut words);
+    Self {
+      words,
+      translation: fs
+        .get_path("translation")
+        .and_then(|n| n.get_str())
+        .map(|s| s.replace('_', " ")),
+    }
+  }
+}
+
+/// Glosses the words under `tree`, where `fs` is its features
+fn collect_words(tree: &SynTree<String, String>, fs: &NodeRef, out: &mut Vec<GlossedWord>) {
+  let children = match tree {
+    SynTree::Leaf(w) => {
+      out.push(GlossedWord {
+        word: w.value.clone(),
+        morphemes: None,
+        gloss: w.value.clone(),
+      });
+      return;
+    }
+    SynTree::Branch(_, children) => children,
+  };
+
+  let feature = |name| fs.get_path(name).and_then(|n| n.get_str());
+  for (idx, child) in children.iter().enumerate() {
+    match (child, fs.get_path(&format!("child-{}", idx))) {
+      // the lexical entry is this constituent, the parent of the word
+      (SynTree::Leaf(w), _) => out.push(GlossedWord {
+        word: w.value.clone(),
+        morphemes: feature("morph").map(|s| s.to_string()),
+        gloss: feature("gloss").map_or_else(|| w.value.clone(), |s| s.to_string()),
+      }),
+      (child, Some(child_fs)) => collect_words(child, &child_fs, out),
+      (child, None) => collect_words(child, &NodeRef::new_top(), out),
+    }
+  }
+}
+
+impl Igt {
+  /// Words whose morphemes and gloss have a different number of `-` and `=`
+  /// separators, which the Leipzig rules say should match
+  pub fn misaligned(&self) -> Vec<&GlossedWord> {
+    let separators = |s: &str| s.chars().filter(|&c| c == '-' || c == '=').count();
+    self
+      .words
+      .iter()
+      .filter(|w| separators(w.morphemes.as_deref().unwrap_or(&w.word)) != separators(&w.gloss))
+      .collect()
+  }
+
+  /// The lines of the example: the words, the morphemes (if any word was
+  /// split), then the glosses, aligned word by word
+  fn lines(&self) -> Vec<Vec<&str>> {
+    let mut lines = vec![self
+      .words
+      .iter()
+      .map(|w| w.word.as_str())
+      .collect::<Vec<_>>()];
+    if self.words.iter().any(|w| w.morphemes.is_some()) {
+      lines.push(
+        self
+          .words
+          .iter()
+          .map(|w| w.morphemes.as_deref().unwrap_or(&w.word))
+          .collect(),
+      );
+    }
+    lines.push(self.words.iter().map(|w| w.gloss.as_str()).collect());
+    lines
+  }
+
+  /// The example for the LaTeX `gb4e` package's `\gll`, which does the
+  /// alignment itself
+  pub fn to_latex(&self) -> String {
+    let lines = self.lines();
+    let mut out = String::from(if lines.len() == 3 {
+      "\\glll "
+    } else {
+      "\\gll "
+    });
+    for line in lines {
+      out += &line
+        .iter()
+        .map(|w| escape_latex(w))
+        .collect::<Vec<_>>()
+        .join(" ");
+      out += "\\\\\n";
+    }
+    if let Some(translation) = &self.translation {
+      out += &format!("\\glt `{}'\n", escape_latex(translation));
+    }
+    out
+  }
+}
+
+fn escape_latex(s: &str) -> String {
+  let mut out = String::with_capacity(s.len());
+  for c in s.chars() {
+    if matches!(c, '_' | '&' | '%' | '$' | '#' | '{' | '}') {
+      out.push('\\');
+    }
+    out.push(c);
+  }
+  out
+}
+
+impl fmt::Display for Igt {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let lines = self.lines();
+    let widths = (0..self.words.len())
+      .map(|idx| {
+        lines
+          .iter()
+          .map(|line| line[idx].chars().count())
+          .max()
+          .unwrap_or(0)
+      })
+      .collect::<Vec<_>>();
+
+    for line in lines.iter() {
+      let mut out = String::new();
+      for (word, width) in line.iter().zip(widths.iter()) {
+        out += &format!("{:<width$}  ", word, width = width);
+      }
+      writeln!(f, "{}", out.trim_end())?;
+    }
+    if let Some(translation) = &self.translation {
+      writeln!(f, "'{}'", translation)?;
+    }
+    Ok(())
+  }
+}
+
+#[test]
+fn test_igt() {
+  use crate::Grammar;
+
+  let g: Grammar = r#"
+    S[ translation: #t ] -> N V[ translation: #t ]
+    N[ gloss: Mary ] -> mary
+    V[ morph: walk-ed, gloss: walk-PST, translation: Mary_walked ] -> walked
+    V[ morph: walk-s, gloss: walk.3SG ] -> walks
+    N -> he
+  "#
+  .parse()
+  .unwrap();
+
+  let igt = Igt::from(&g.parse_one(&["mary", "walked"]).unwrap());
+  assert_eq!(
+    igt.to_string(),
+    "mary  walked\nmary  walk-ed\nMary  walk-PST\n'Mary walked'\n"
+  );
+  assert!(igt.misaligned().is_empty());
+  assert_eq!(
+    igt.to_latex(),
+    "\\glll mary walked\\\\\nmary walk-ed\\\\\nMary walk-PST\\\\\n\\glt `Mary walked'\n"
+  );
+
+  let igt = Igt::from(&g.parse_one(&["he", "walks"]).unwrap());
+  assert_eq!(igt.to_string(), "he  walks\nhe  walk-s\nhe  walk.3SG\n");
+  assert_eq!(igt.misaligned()[0].word, "walks");
+
+  // only glosses can have `.` and `=`, so this is still a typo elsewhere
+  assert!("V[ tense: past. ] -> walked".parse::<Grammar>().is_err());
+}
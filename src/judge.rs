@@ -0,0 +1,222 @@
+//! Grammaticality judgments that say why a sentence was rejected, for tools
+//! (like language-learning apps) that need to explain a mistake instead of
+//! just flagging it.
+
+use std::fmt;
+
+use crate::earley::parse_chart_with;
+use crate::featurestructure::UnificationFailure;
+use crate::forest::Forest;
+use crate::options::ParseOptions;
+use crate::rules::{Grammar, GrammarError};
+use crate::{with_tokens, ParsedTree};
+
+/// Whether a sentence is grammatical, and if not, why not
+#[derive(Debug)]
+pub enum Judgment {
+  /// The sentence parses, with these trees
+  Accept(Vec<ParsedTree>),
+  /// No rules cover the sentence, regardless of features
+  RejectSyntax(SyntaxError),
+  /// The sentence has trees, but every one of them failed unification. Each
+  /// tree's failure is listed once, with the path of the features that
+  /// clashed and the rule they clashed in.
+  RejectFeatures(Vec<UnificationFailure>),
+}
+
+impl Judgment {
+  pub fn is_accept(&self) -> bool {
+    matches!(self, Self::Accept(_))
+  }
+}
+
+impl fmt::Display for Judgment {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Accept(trees) => write!(
+        f,
+        "accepted with {} parse{}",
+        trees.len(),
+        if trees.len() == 1 { "" } else { "s" }
+      ),
+      Self::RejectSyntax(e) => write!(f, "rejected: {}", e),
+      Self::RejectFeatures(failures) => {
+        write!(f, "rejected by features:")?;
+        for failure in failures {
+          write!(f, "\n  {}", failure)?;
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+/// Where the parser got stuck on a sentence the rules don't cover
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+  /// The index of the first token the grammar couldn't continue with, or the
+  /// sentence's length if it ended too early
+  pub position: usize,
+  /// The token at `position`, or None if the sentence ended too early
+  pub found: Option<String>,
+  /// The words the grammar could have continued with at `position`, sorted.
+  /// Empty if the sentence could only have ended there.
+  pub expected: Vec<String>,
+}
+
+impl fmt::Display for SyntaxError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.found {
+      Some(word) => write!(f, "unexpected {:?} at {}", word, self.position)?,
+      None => write!(f, "unexpected end of sentence")?,
+    }
+    match self.expected.len() {
+      0 => write!(f, ", expected the end of the sentence"),
+      1 => write!(f, ", expected {:?}", self.expected[0]),
+      _ => write!(
+        f,
+        ", expected one of {}",
+        self
+          .expected
+          .iter()
+          .map(|w| format!("{:?}", w))
+          .collect::<Vec<_>>()
+          .join(", ")
+      ),
+    }
+  }
+}
+
+impl Grammar {
+  /// Parses `input` and judges whether it's grammatical. Rejections say why:
+  /// where the rules got stuck and what they expected there, or which
+  /// features clashed.
+  pub fn judge<I>(&self, input: I) -> Judgment
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    self
+      .judge_with(input, &ParseOptions::default())
+      .expect("grammar missing start rules")
+  }
+
+  /// Like `judge`, with options, like the words' capitalization from
+  /// `Grammar::fold_case`. Fails with `GrammarError::UndefinedStart` if
+  /// `ParseOptions::start` has no rules.
+  pub fn judge_with<I>(&self, input: I, options: &ParseOptions) -> Result<Judgment, GrammarError>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    with_tokens(input, |input| {
+      let chart = parse_chart_with(self, input, options)?;
+      let forest = Forest::from(&chart);
+
+      let mut trees = Vec::new();
+      let mut failures: Vec<UnificationFailure> = Vec::new();
+      let mut any_tree = false;
+      let mut forest_trees = forest.iter_trees_with(self, options);
+      while !options.has_enough_trees(trees.len()) {
+        let Some(tree) = forest_trees.next() else {
+          break;
+        };
+        any_tree = true;
+        match Self::unify_tree_with(tree, options) {
+          Ok(tree) => trees.push(self.rename_children(tree)),
+          Err(e) => {
+            if let Ok(failure) = e.downcast::<UnificationFailure>() {
+              if !failures.contains(&failure) {
+                failures.push(*failure);
+              }
+            }
+          }
+        }
+      }
+
+      if !trees.is_empty() {
+        return Ok(Judgment::Accept(trees));
+      }
+      if any_tree {
+        return Ok(Judgment::RejectFeatures(failures));
+      }
+
+      // the parser got as far as the last position with any states
+      let position = (0..chart.len())
+        .rev()
+        .find(|&k| chart.len_at(k) > 0)
+        .unwrap_or(0);
+      let mut expected = chart
+        .next_terminals(position)
+        .into_iter()
+        .map(|(t, _)| t)
+        .collect::<Vec<_>>();
+      expected.sort();
+      Ok(Judgment::RejectSyntax(SyntaxError {
+        position,
+        found: input.get(position).map(|w| w.to_string()),
+        expected,
+      }))
+    })
+  }
+}
+
+#[test]
+fn test_judge() {
+  let g: Grammar = r#"
+    S -> N[ num: #1 ] V[ num: #1 ]
+    S -> N[ num: #1 ] V[ num: #1 ] O
+    N[ num: sg ] -> mary
+    N[ num: pl ] -> they
+    V[ num: sg ] -> runs
+    V[ num: pl ] -> run
+    O -> home
+    O -> away
+  "#
+  .parse()
+  .unwrap();
+
+  match g.judge(["mary", "runs"]) {
+    Judgment::Accept(trees) => assert_eq!(trees.len(), 1),
+    j => panic!("{}", j),
+  }
+
+  let j = g.judge(["mary", "runs", "fast"]);
+  assert_eq!(
+    j.to_string(),
+    r#"rejected: unexpected "fast" at 2, expected one of "away", "home""#
+  );
+  match g.judge(["mary"]) {
+    Judgment::RejectSyntax(e) => {
+      assert_eq!(e.position, 1);
+      assert_eq!(e.found, None);
+      assert_eq!(e.expected, ["run", "runs"]);
+    }
+    j => panic!("{}", j),
+  }
+
+  match g.judge(["mary", "run", "home"]) {
+    Judgment::RejectFeatures(failures) => {
+      assert_eq!(failures.len(), 1);
+      assert_eq!(failures[0].path, ["child-1", "num"]);
+      assert_eq!((&*failures[0].left, &*failures[0].right), ("sg", "pl"));
+      assert_eq!(failures[0].rule.as_ref().unwrap().1, (0, 3));
+    }
+    j => panic!("{}", j),
+  }
+  assert!(!g.judge(["they", "runs"]).is_accept());
+}
+
+#[test]
+fn test_judge_undefined_start() {
+  let g: Grammar = "S -> NP\nNP -> x".parse().unwrap();
+  let options = |start: &str| ParseOptions {
+    start: Some(start.to_string()),
+    ..ParseOptions::default()
+  };
+  assert!(g.judge_with(["x"], &options("NP")).unwrap().is_accept());
+  assert!(matches!(
+    g.judge_with(["x"], &options("Np")),
+    Err(GrammarError::UndefinedStart(s)) if s == "Np"
+  ));
+}
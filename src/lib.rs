@@ -23,12 +23,15 @@ number agreement.
 $ cargo run --bin cli examples/reflexives.fgr
 > she likes himself
 Parsed 0 trees
+did you mean: 'she likes herself'?
 
 > her likes herself
 Parsed 0 trees
+did you mean: 'he likes himself'?
 
 > she like herself
 Parsed 0 trees
+did you mean: 'she likes herself'?
 
 > she likes herself
 Parsed 1 tree
@@ -37,23 +40,23 @@ Parsed 1 tree
   (1..2: TV (1..2: likes))
   (2..3: N (2..3: herself)))
 [
-  child-2: [
-    case: acc
-    pron: ref
-    needs_pron: #0 she
-    num: sg
-    child-0: [ word: herself ]
+  child-0: [
+    case: nom
+    child-0: [ word: she ]
+    num: #2 sg
+    pron: #1 she
   ]
   child-1: [
-    tense: nonpast
     child-0: [ word: likes ]
-    num: #1 sg
+    num: #2
+    tense: nonpast
   ]
-  child-0: [
-    child-0: [ word: she ]
-    case: nom
-    pron: #0
-    num: #1
+  child-2: [
+    case: acc
+    child-0: [ word: herself ]
+    needs_pron: #1
+    num: sg
+    pron: ref
   ]
 ]
 ```
@@ -90,6 +93,7 @@ Parsed 1 tree
 
 > boy throw ball nm-raised-eyebrows
 Parsed 0 trees
+did you mean: 'boy throw ball'?
 ```
 
 # Tutorial
@@ -166,12 +170,14 @@ Parsed 1 tree
   (0..1: N (0..1: he))
   (1..2: IV (1..2: falls)))
 [
-  child-1: [ child-0: [ word: falls ] ]
   child-0: [ child-0: [ word: he ] ]
+  child-1: [ child-0: [ word: falls ] ]
 ]
 
 > he falls her
 Parsed 0 trees
+parse stuck after token 2 ('falls'); expected the end of the sentence
+did you mean: 'he likes her'?
 
 > he likes her
 Parsed 1 tree
@@ -180,13 +186,15 @@ Parsed 1 tree
   (1..2: TV (1..2: likes))
   (2..3: N (2..3: her)))
 [
-  child-2: [ child-0: [ word: her ] ]
-  child-1: [ child-0: [ word: likes ] ]
   child-0: [ child-0: [ word: he ] ]
+  child-1: [ child-0: [ word: likes ] ]
+  child-2: [ child-0: [ word: her ] ]
 ]
 
 > he likes
 Parsed 0 trees
+parse stuck after token 2 ('likes'); expected one of: he, her, herself, him, himself, mary, robert, she, …
+did you mean: 'he falls'?
 
 > he said that he likes her
 Parsed 1 tree
@@ -200,17 +208,19 @@ Parsed 1 tree
     (5..6: N (5..6: her))))
 [
   child-0: [ child-0: [ word: he ] ]
-  child-2: [ child-0: [ word: that ] ]
   child-1: [ child-0: [ word: said ] ]
+  child-2: [ child-0: [ word: that ] ]
   child-3: [
-    child-2: [ child-0: [ word: her ] ]
-    child-1: [ child-0: [ word: likes ] ]
     child-0: [ child-0: [ word: he ] ]
+    child-1: [ child-0: [ word: likes ] ]
+    child-2: [ child-0: [ word: her ] ]
   ]
 ]
 
 > he said that he
 Parsed 0 trees
+parse stuck after token 4 ('he'); expected one of: fall, falls, fell, like, liked, likes, said, say, …
+did you mean: 'he said that he falls'?
 ```
 
 This grammar already parses some correct sentences, and blocks some trivially
@@ -226,8 +236,8 @@ Parsed 1 tree
   (2..3: N (2..3: himself)))
 [
   child-0: [ child-0: [ word: she ] ]
-  child-2: [ child-0: [ word: himself ] ]
   child-1: [ child-0: [ word: likes ] ]
+  child-2: [ child-0: [ word: himself ] ]
 ]
 
 > him like her  // incorrect case on the subject pronoun, should be nominative
@@ -250,9 +260,9 @@ Parsed 1 tree
   (1..2: TV (1..2: like))
   (2..3: N (2..3: her)))
 [
-  child-2: [ child-0: [ word: her ] ]
-  child-1: [ child-0: [ word: like ] ]
   child-0: [ child-0: [ word: he ] ]
+  child-1: [ child-0: [ word: like ] ]
+  child-2: [ child-0: [ word: her ] ]
 ]
 ```
 
@@ -373,21 +383,22 @@ Parsed 1 tree
   (0..1: N (0..1: he))
   (1..2: IV (1..2: fell)))
 [
-  child-1: [
-    child-0: [ word: fell ]
-    num: #0 sg
-    tense: past
-  ]
   child-0: [
-    pron: he
     case: nom
-    num: #0
     child-0: [ word: he ]
+    num: #1 sg
+    pron: he
+  ]
+  child-1: [
+    child-0: [ word: fell ]
+    num: #1
+    tense: past
   ]
 ]
 
 > he like him
 Parsed 0 trees
+did you mean: 'he likes him'?
 
 > he likes himself
 Parsed 1 tree
@@ -396,28 +407,29 @@ Parsed 1 tree
   (1..2: TV (1..2: likes))
   (2..3: N (2..3: himself)))
 [
+  child-0: [
+    case: nom
+    child-0: [ word: he ]
+    num: #2 sg
+    pron: #1 he
+  ]
   child-1: [
-    num: #0 sg
     child-0: [ word: likes ]
+    num: #2
     tense: nonpast
   ]
   child-2: [
-    needs_pron: #1 he
-    num: sg
+    case: acc
     child-0: [ word: himself ]
+    needs_pron: #1
+    num: sg
     pron: ref
-    case: acc
-  ]
-  child-0: [
-    child-0: [ word: he ]
-    pron: #1
-    num: #0
-    case: nom
   ]
 ]
 
 > he likes herself
 Parsed 0 trees
+did you mean: 'he likes himself'?
 
 > mary likes herself
 Parsed 1 tree
@@ -427,27 +439,28 @@ Parsed 1 tree
   (2..3: N (2..3: herself)))
 [
   child-0: [
-    pron: #0 she
-    num: #1 sg
     case: nom
     child-0: [ word: mary ]
+    num: #2 sg
+    pron: #1 she
   ]
   child-1: [
-    tense: nonpast
     child-0: [ word: likes ]
-    num: #1
+    num: #2
+    tense: nonpast
   ]
   child-2: [
+    case: acc
     child-0: [ word: herself ]
+    needs_pron: #1
     num: sg
     pron: ref
-    case: acc
-    needs_pron: #0
   ]
 ]
 
 > mary likes themself
 Parsed 0 trees
+did you mean: 'mary likes herself'?
 
 > sue likes themself
 Parsed 1 tree
@@ -457,27 +470,28 @@ Parsed 1 tree
   (2..3: N (2..3: themself)))
 [
   child-0: [
-    pron: #0 they
-    child-0: [ word: sue ]
     case: nom
-    num: #1 sg
+    child-0: [ word: sue ]
+    num: #2 sg
+    pron: #1 they
   ]
   child-1: [
-    tense: nonpast
-    num: #1
     child-0: [ word: likes ]
+    num: #2
+    tense: nonpast
   ]
   child-2: [
-    needs_pron: #0
     case: acc
-    pron: ref
     child-0: [ word: themself ]
+    needs_pron: #1
     num: sg
+    pron: ref
   ]
 ]
 
 > sue likes himself
 Parsed 0 trees
+did you mean: 'sue likes themself'?
 ```
 
 If this is interesting to you and you want to learn more, you can check out
@@ -493,83 +507,228 @@ my first Rust projects (after migrating through F# -> TypeScript -> C in search
 of the right performance/ergonomics tradeoff), and it could use more tests,
 but overall it's not too bad.
 
+`treebender::prelude` has the types most code needs, and stays put as the
+modules behind it move around, so `use treebender::prelude::*;` is the way to
+start.
+
 Basically, the processing pipeline is:
 
 1. Make a `Grammar` struct
-  * `Grammar` is defined in `rules.rs`.
-  * The easiest way to make a `Grammar` is `Grammar::parse_from_file`, which is
-    mostly a hand-written recusive descent parser in `parse_grammar.rs`. Yes,
-    I recognize the irony here.
+  * `Grammar` is defined in `rules.rs`, and there's only the one -- every
+    other type that takes a grammar takes this.
+  * The easiest way to make a `Grammar` is `Grammar::read_from_file`, which is
+    mostly a hand-written recusive descent parser in `fgr/parse_grammar.rs`.
+    Yes, I recognize the irony here.
 2. It takes input (in `Grammar::parse`, which does everything for you, or
    `Grammar::parse_chart`, which just does the chart)
 3. The input is first chart-parsed in `earley.rs`
 4. Then, a forest is built from the chart, in `forest.rs`, using an algorithm
-    I found in a very useful blog series I forget the URL for, because the
-    algorithms in the academic literature for this are... weird.
+   I found in a very useful blog series I forget the URL for, because the
+   algorithms in the academic literature for this are... weird.
 5. Finally, the feature unification is used to prune the forest down to only
    valid trees. It would be more efficient to do this during parsing, but meh.
+   Every way of parsing ends up in the same place for this:
+   `Grammar::unify_tree` (or `unify_tree_with`, to pass `ParseOptions`)
+   turns one of the forest's trees into a `ParsedTree`, or fails with why it
+   didn't unify. If you're walking a `Forest` or `PackedForest` yourself, call
+   that on each tree.
 
 The most interesting thing you can do via code and not via the CLI is probably
 getting at the raw feature DAG, as that would let you do things like pronoun
-coreference. The DAG code is in `featurestructure.rs`, and should be fairly
-approachable -- there's a lot of Rust ceremony around `Rc<RefCell<...>>`
+coreference. The DAG code is in `featurestructure/`, and should be fairly
+approachable -- there's a lot of Rust ceremony around `Arc<RwLock<...>>`
 because using an arena allocation crate seemed ~~too har~~like overkill, but
-that is somewhat mitigated by the `NodeRef` type alias. Hit me up at
+that is somewhat mitigated by the `NodeRef` type. Hit me up at
 https://vgel.me/contact if you need help with anything here!
 */
 
 #[macro_use]
 extern crate lazy_static;
 
+#[macro_use]
+mod trace;
+
+pub mod agenda;
+pub mod align;
+pub mod ambiguity;
+pub mod analysis;
+pub mod anytime;
+#[cfg(feature = "proptest")]
+pub mod arbitrary;
+pub mod backend;
+pub mod corpus;
+pub mod discourse;
 pub mod earley;
 pub mod featurestructure;
 pub mod fgr;
 pub mod forest;
+pub mod fragments;
+pub mod generate;
+pub mod htmldoc;
+pub mod igt;
+pub mod judge;
+pub mod lint;
+pub mod minpair;
+pub mod observer;
+pub mod options;
+pub mod output;
+pub mod overlay;
+pub mod packing;
+pub mod pipeline;
+pub mod prelude;
+pub mod progress;
+pub mod projection;
+pub mod punct;
+pub mod query;
+pub mod regression;
+pub mod report;
+pub mod rewrite;
+pub mod roundtrip;
 pub mod rules;
+pub mod session;
+pub mod sexpr;
+pub mod slots;
+pub mod suggest;
 pub mod syntree;
+pub mod testsuite;
+pub mod tokenize;
+pub mod transfer;
+pub mod treebank;
+pub mod twolevel;
 pub mod utils;
+pub mod valency;
+pub mod whyno;
 
 use std::fs;
 use std::path;
 use std::sync::Arc;
-
-pub use crate::earley::{parse_chart, Chart};
-pub use crate::featurestructure::NodeRef;
-pub use crate::forest::Forest;
-pub use crate::rules::{Grammar, Rule};
-pub use crate::syntree::{Constituent, SynTree};
+use std::time::Instant;
+
+pub use crate::agenda::{parse_chart_best_first, BestFirstParser};
+pub use crate::ambiguity::{AmbiguityClass, AmbiguityWarning};
+pub use crate::backend::{Earley, ParserBackend};
+use crate::earley::parse_chart_into;
+pub use crate::earley::{parse_chart, parse_chart_with, Chart};
+pub use crate::featurestructure::{FeatureStructureTooLarge, NodeRef, UnificationFailure};
+pub use crate::forest::{Forest, ForestError, TreeScratch};
+pub use crate::judge::{Judgment, SyntaxError};
+pub use crate::lint::GrammarWarnings;
+pub use crate::observer::ParseObserver;
+pub use crate::options::{Cancelled, GrammarOptions, ParseOptions};
+pub use crate::output::{ParseError, ParseOutput};
+pub use crate::packing::{PackedForest, Restrictor};
+pub use crate::progress::Progress;
+pub use crate::rules::{Grammar, GrammarError, GrammarMetadata, Rule};
+use crate::session::ParseBuffers;
+pub use crate::syntree::{Constituent, SynTree, Word, Zipper};
+pub use crate::tokenize::Tokenizer;
 pub use crate::utils::Err;
+pub use crate::whyno::WhyNo;
+
+/// A tree that survived unification, with its feature structure
+pub type ParsedTree = (SynTree<String, String>, NodeRef);
+
+/// Calls `f` with the tokens in `input` as a slice of `&str`, so that parse
+/// functions can take `&[&str]`, `&[String]`, `Vec<String>` or any other
+/// iterator of strings
+fn with_tokens<I, R>(input: I, f: impl FnOnce(&[&str]) -> R) -> R
+where
+  I: IntoIterator,
+  I::Item: AsRef<str>,
+{
+  let owned = input.into_iter().collect::<Vec<_>>();
+  let tokens = owned.iter().map(AsRef::as_ref).collect::<Vec<_>>();
+  f(&tokens)
+}
 
 impl Grammar {
   pub fn parse_chart(&self, input: &[&str]) -> Chart {
     parse_chart(self, input)
   }
 
+  pub fn parse_chart_with(
+    &self,
+    input: &[&str],
+    options: &ParseOptions,
+  ) -> Result<Chart, GrammarError> {
+    parse_chart_with(self, input, options)
+  }
+
   pub fn parse_forest(&self, input: &[&str]) -> Forest {
     Forest::from(self.parse_chart(input))
   }
 
-  pub fn unify_tree(
-    tree: SynTree<Arc<Rule>, String>,
-  ) -> Result<(SynTree<String, String>, NodeRef), Err> {
+  pub fn unify_tree(tree: SynTree<Arc<Rule>, Arc<str>>) -> Result<ParsedTree, Err> {
+    Self::unify_tree_with(tree, &ParseOptions::default())
+  }
+
+  /// Like unify_tree, but fails with `FeatureStructureTooLarge` if the
+  /// structure grows past the limits in `options`
+  pub fn unify_tree_with(
+    tree: SynTree<Arc<Rule>, Arc<str>>,
+    options: &ParseOptions,
+  ) -> Result<ParsedTree, Err> {
     match tree {
-      SynTree::Leaf(w) => Ok((SynTree::Leaf(w), NodeRef::new_top())),
+      // the only copy of the word made while building trees
+      SynTree::Leaf(w) => Ok((
+        SynTree::Leaf(Word {
+          value: w.value.to_string(),
+          span: w.span,
+        }),
+        NodeRef::new_top(),
+      )),
       SynTree::Branch(cons, children) => {
-        let features = cons.value.features.deep_clone();
+        let mut features = cons.value.features.deep_clone();
+        let hidden = children
+          .iter()
+          .map(|c| matches!(c, SynTree::Branch(c, _) if c.value.hidden))
+          .collect::<Vec<_>>();
 
         let mut bare_children = Vec::with_capacity(children.len());
         for (idx, child) in children.into_iter().enumerate() {
-          let (child_tree, child_features) = Self::unify_tree(child)?;
+          let (child_tree, mut child_features) = Self::unify_tree_with(child, options)?;
+          if let SynTree::Leaf(w) = &child_tree {
+            let cap = options
+              .capitalization
+              .as_ref()
+              .and_then(|caps| caps.get(w.span.0).copied());
+            child_features = Self::leaf_features(&cons.value.productions[idx], &w.value, cap);
+          }
           bare_children.push(child_tree);
 
           let to_unify = NodeRef::new_with_edges(vec![(format!("child-{}", idx), child_features)])?;
-          NodeRef::unify(features.clone(), to_unify)?;
+          NodeRef::unify_at(features.clone(), to_unify, &mut Vec::new()).map_err(|mut e| {
+            e.rule = Some((cons.value.clone(), cons.span));
+            trace_event!(trace, failure = %e, "tree rejected");
+            if let Some(observer) = &options.observer {
+              observer.unification_failed(&e);
+            }
+            e
+          })?;
+          FeatureStructureTooLarge::check(&features, options.max_fs_nodes, options.max_fs_depth)?;
+        }
+
+        if cons.value.coordination {
+          Self::coordinate(&cons.value, &features).map_err(|mut e| {
+            e.rule = Some((cons.value.clone(), cons.span));
+            e
+          })?;
+        }
+
+        for (child, prod) in bare_children.iter_mut().zip(&cons.value.productions) {
+          if let SynTree::Branch(c, _) = child {
+            c.function = prod.function.clone();
+          }
+        }
+        if hidden.contains(&true) {
+          (bare_children, features) = Self::splice_hidden(bare_children, &features, &hidden)?;
         }
 
         let bare_self = SynTree::Branch(
           Constituent {
             span: cons.span,
             value: cons.value.symbol.clone(),
+            function: None,
           },
           bare_children,
         );
@@ -579,17 +738,430 @@ impl Grammar {
     }
   }
 
-  pub fn parse(&self, input: &[&str]) -> Vec<(SynTree<String, String>, NodeRef)> {
-    let forest = self.parse_forest(input);
-    let trees = forest.trees(self);
-    trees
+  /// Puts the children of `@hidden` children in their place, in the tree and
+  /// in the `child-N` features, renumbering the rest. The hidden children's
+  /// own features are left out.
+  fn splice_hidden(
+    children: Vec<SynTree<String, String>>,
+    features: &NodeRef,
+    hidden: &[bool],
+  ) -> Result<(Vec<SynTree<String, String>>, NodeRef), Err> {
+    let child_features = |fs: &NodeRef, idx: usize| {
+      fs.get_path(&format!("child-{}", idx))
+        .unwrap_or_else(NodeRef::new_top)
+    };
+    let features = features.clone().dereference();
+    let mut edges = features
+      .borrow()
+      .edged()
       .into_iter()
-      .filter_map(|t| Self::unify_tree(t).map(Some).unwrap_or(None))
-      .collect::<Vec<_>>()
+      .flatten()
+      .filter(|(label, _)| !projection::is_child_label(label, projection::CHILD_PREFIX))
+      .map(|(label, value)| (label.clone(), value.clone()))
+      .collect::<Vec<_>>();
+    let mut spliced = Vec::with_capacity(children.len());
+    for (idx, (child, hidden)) in children.into_iter().zip(hidden).enumerate() {
+      let fs = child_features(&features, idx);
+      match child {
+        SynTree::Branch(_, grandchildren) if *hidden => {
+          for (jdx, grandchild) in grandchildren.into_iter().enumerate() {
+            edges.push((format!("child-{}", spliced.len()), child_features(&fs, jdx)));
+            spliced.push(grandchild);
+          }
+        }
+        child => {
+          edges.push((format!("child-{}", spliced.len()), fs));
+          spliced.push(child);
+        }
+      }
+    }
+    Ok((spliced, NodeRef::new_with_edges(edges)?))
+  }
+
+  /// Gives the mother of a `@coord` rule the features its conjuncts agree on,
+  /// except for any the rule sets itself
+  fn coordinate(rule: &Rule, features: &NodeRef) -> Result<(), UnificationFailure> {
+    let shared = rule
+      .productions
+      .iter()
+      .enumerate()
+      .filter(|(_, p)| p.is_nonterminal() && *p.symbol == rule.symbol)
+      .filter_map(|(idx, _)| features.get_path(&format!("child-{}", idx)))
+      .reduce(|a, b| NodeRef::generalize(&a, &b));
+    let shared = match shared {
+      Some(shared) => shared,
+      None => return Ok(()),
+    };
+
+    let edges = shared
+      .borrow()
+      .edged()
+      .into_iter()
+      .flatten()
+      .filter(|(label, _)| {
+        // the conjuncts' own children aren't features of the coordination
+        !label.starts_with("child-") && features.get_path(label).is_none_or(|n| n.is_top())
+      })
+      .map(|(label, value)| (label.clone(), value.clone()))
+      .collect::<Vec<_>>();
+    let edges = NodeRef::new_with_edges(edges).expect("labels from a map are unique");
+    NodeRef::unify_at(features.clone(), edges, &mut Vec::new())
+  }
+
+  /// Parses `input`, returning every tree that survives unification.
+  /// `input` can be any iterator of strings, like `&["a", "b"]` or a
+  /// `Vec<String>`.
+  pub fn parse<I>(&self, input: I) -> Vec<ParsedTree>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    self.parse_with(input, &ParseOptions::default())
+  }
+
+  /// Parses `input` as `start` instead of the grammar's start symbol, like
+  /// `"NP"` to test the noun phrases on their own. Fails with
+  /// `GrammarError::UndefinedStart` if the grammar has no rules for `start`.
+  /// Set `ParseOptions::start` to combine this with other options.
+  pub fn parse_with_start<I>(&self, start: &str, input: I) -> Result<Vec<ParsedTree>, GrammarError>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    self.check_start(start)?;
+    let options = ParseOptions {
+      start: Some(start.to_string()),
+      ..ParseOptions::default()
+    };
+    Ok(self.parse_with(input, &options))
+  }
+
+  /// Splits `s` into tokens with `tokenize::UnicodeWords` and parses them.
+  /// Words aren't lowercased, so a grammar of lowercase terminals won't
+  /// match capitalized words: see `parse_str_folded` for that.
+  pub fn parse_str(&self, s: &str) -> Vec<ParsedTree> {
+    self.parse_str_with(s, &tokenize::UnicodeWords, &ParseOptions::default())
+  }
+
+  /// Like parse_str, but with a different tokenizer and options
+  pub fn parse_str_with(
+    &self,
+    s: &str,
+    tokenizer: &impl Tokenizer,
+    options: &ParseOptions,
+  ) -> Vec<ParsedTree> {
+    self.parse_with(&tokenizer.tokenize(s), options)
+  }
+
+  /// Like parse_str_with, but lowercases the tokens before matching them,
+  /// and gives each word a `cap` feature for how it was written if the
+  /// grammar uses it, see `fold_case`. Trees have the lowercased words.
+  pub fn parse_str_folded(
+    &self,
+    s: &str,
+    tokenizer: &impl Tokenizer,
+    options: &ParseOptions,
+  ) -> Vec<ParsedTree> {
+    let (words, options) = self.fold_case(&tokenizer.tokenize(s), options);
+    self.parse_with(&words, &options)
+  }
+
+  /// Returns the first tree that survives unification, without building or
+  /// unifying the rest of the forest
+  pub fn parse_one<I>(&self, input: I) -> Option<ParsedTree>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    with_tokens(input, |input| {
+      self
+        .parse_forest(input)
+        .iter_trees(self)
+        .find_map(|t| Self::unify_tree(t).ok())
+        .map(|t| self.rename_children(t))
+    })
+  }
+
+  /// Parses best-first, returning the trees that survive unification from the
+  /// first chart that contains a complete parse, instead of every tree.
+  /// Useful when one good parse is needed quickly. See `agenda` for details.
+  pub fn parse_best_first<I>(&self, input: I) -> Vec<ParsedTree>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    let options = ParseOptions {
+      best_first: true,
+      ..ParseOptions::default()
+    };
+    self.parse_with(input, &options)
+  }
+
+  /// Parses with options. If a limit in the options is exceeded or the parse
+  /// is cancelled, no trees are returned: use try_parse_with to get the error.
+  pub fn parse_with<I>(&self, input: I, options: &ParseOptions) -> Vec<ParsedTree>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    self.try_parse_with(input, options).unwrap_or_default()
+  }
+
+  /// Parses each sentence with `parse_with`, calling `on_progress` after each
+  /// one, for applications that want to show progress through a corpus.
+  pub fn parse_all<S, T>(
+    &self,
+    sentences: &[S],
+    options: &ParseOptions,
+    mut on_progress: impl FnMut(&Progress),
+  ) -> Vec<Vec<ParsedTree>>
+  where
+    S: AsRef<[T]>,
+    T: AsRef<str>,
+  {
+    let start = Instant::now();
+    let mut results = Vec::with_capacity(sentences.len());
+    for sentence in sentences {
+      results.push(self.parse_with(sentence.as_ref(), options));
+      on_progress(&Progress {
+        done: results.len(),
+        total: sentences.len(),
+        elapsed: start.elapsed(),
+      });
+    }
+    results
+  }
+
+  /// Like parse_with, but fails with `FeatureStructureTooLarge` if a limit in
+  /// the options is exceeded, or `Cancelled` if the parse is cancelled.
+  /// Trees that fail unification are still dropped, not errors.
+  pub fn try_parse_with<I>(&self, input: I, options: &ParseOptions) -> Result<Vec<ParsedTree>, Err>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    with_tokens(input, |input| self.try_parse_tokens(input, options))
+  }
+
+  fn try_parse_tokens(
+    &self,
+    input: &[&str],
+    options: &ParseOptions,
+  ) -> Result<Vec<ParsedTree>, Err> {
+    self.try_parse_tokens_in(input, options, &mut ParseBuffers::default())
+  }
+
+  /// Where every option is dispatched on, for `try_parse_tokens` and
+  /// `ParseSession`. Earley parses are built in `buffers`, so a caller that
+  /// keeps them can reuse their storage.
+  pub(crate) fn try_parse_tokens_in(
+    &self,
+    input: &[&str],
+    options: &ParseOptions,
+    buffers: &mut ParseBuffers,
+  ) -> Result<Vec<ParsedTree>, Err> {
+    if let Some(start) = &options.start {
+      self.check_start(start)?;
+    }
+    if let Some(punctuation) = &options.punctuation {
+      let (words, marks) = punctuation.split(input);
+      // the words are numbered without the marks now
+      let capitalization = options.capitalization.as_ref().map(|caps| {
+        caps
+          .iter()
+          .enumerate()
+          .filter(|(idx, _)| !marks.iter().any(|(mark, _)| mark == idx))
+          .map(|(_, &cap)| cap)
+          .collect()
+      });
+      let options = ParseOptions {
+        punctuation: None,
+        capitalization,
+        ..options.clone()
+      };
+      let trees = self.try_parse_tokens_in(&words, &options, buffers)?;
+      return Ok(
+        trees
+          .into_iter()
+          .map(|(tree, fs)| (punctuation.attach(tree, &marks), fs))
+          .collect(),
+      );
+    }
+
+    if let Some(rewrites) = &options.rewrites {
+      let options = ParseOptions {
+        rewrites: None,
+        ..options.clone()
+      };
+      let trees = self.try_parse_tokens_in(input, &options, buffers)?;
+      return Ok(rewrites.apply_all(trees));
+    }
+
+    trace_span!(
+      debug_span,
+      "parse",
+      tokens = input.len(),
+      best_first = options.best_first
+    );
+    if !options.best_first {
+      parse_chart_into(self, input, options, &mut buffers.chart)?;
+      buffers.forest.fill_from(&buffers.chart);
+      return self.unify_forest(&buffers.forest, options, &mut buffers.scratch);
+    }
+
+    let mut parser = BestFirstParser::with_options(self, input, options)?;
+    while parser.next_complete().is_some() {
+      buffers.forest.fill_from(parser.chart());
+      let trees = self.unify_forest(&buffers.forest, options, &mut buffers.scratch)?;
+      if !trees.is_empty() {
+        return Ok(trees);
+      }
+    }
+    options.check_cancelled()?;
+    Ok(Vec::new())
+  }
+
+  /// Parses with an alternative backend instead of Earley. `best_first` is
+  /// ignored, as that's a backend in its own right. Fails if the backend
+  /// does, like for an undefined `ParseOptions::start`.
+  pub fn parse_with_backend<B, I>(
+    &self,
+    backend: &B,
+    input: I,
+    options: &ParseOptions,
+  ) -> Result<Vec<ParsedTree>, GrammarError>
+  where
+    B: ParserBackend + ?Sized,
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    with_tokens(input, |input| {
+      let forest = backend.parse_forest(self, input, options)?;
+      Ok(
+        self
+          .unify_forest(&forest, options, &mut TreeScratch::default())
+          .unwrap_or_default(),
+      )
+    })
+  }
+
+  /// Renames the child features of a finished parse to `child_prefix`, if
+  /// it isn't the default
+  pub(crate) fn rename_children(&self, (tree, fs): ParsedTree) -> ParsedTree {
+    if self.child_prefix == projection::CHILD_PREFIX {
+      (tree, fs)
+    } else {
+      (tree, projection::rename_children(&fs, &self.child_prefix))
+    }
+  }
+
+  /// Unifies `ParseOptions::root_features` (if set) into a parse's root
+  fn unify_root(tree: ParsedTree, options: &ParseOptions) -> Result<ParsedTree, Err> {
+    if let Some(root) = &options.root_features {
+      NodeRef::unify_at(tree.1.clone(), root.deep_clone(), &mut Vec::new()).inspect_err(|e| {
+        trace_event!(trace, failure = %e, "tree rejected by root features");
+        if let Some(observer) = &options.observer {
+          observer.unification_failed(e);
+        }
+      })?;
+    }
+    Ok(tree)
+  }
+
+  /// Builds and unifies a forest's trees. An unambiguous forest's tree is
+  /// built directly, in `scratch`.
+  fn unify_forest(
+    &self,
+    forest: &Forest,
+    options: &ParseOptions,
+    scratch: &mut TreeScratch,
+  ) -> Result<Vec<ParsedTree>, Err> {
+    trace_span!(debug_span, "unify_forest");
+    // also catches a cancelled chart, which may have had no trees
+    options.check_cancelled()?;
+    let packed;
+    let mut forest_trees: Box<dyn Iterator<Item = _>> = match &options.packing {
+      Some(restrictor) => {
+        packed = PackedForest::new_as(options.start_symbol(self), forest, restrictor);
+        Box::new(packed.iter_trees().map(Ok))
+      }
+      None => {
+        // most inputs have one tree, which can be built without enumerating,
+        // but building it would only find an ambiguity partway through. A
+        // cancelled build falls through to try_iter_trees_with, which says so.
+        let single = if forest.has_shared_cell() {
+          None
+        } else {
+          forest.single_tree_as(self, Some(options), scratch)
+        };
+        match single {
+          Some(tree) => Box::new(std::iter::once(Ok(tree))),
+          None => Box::new(forest.try_iter_trees_with(self, options)),
+        }
+      }
+    };
+    let mut trees = Vec::new();
+    // checked before building each tree, so no tree is built past the limit
+    while !options.has_enough_trees(trees.len()) {
+      let Some(tree) = forest_trees.next() else {
+        break;
+      };
+      options.check_cancelled()?;
+      let tree = tree.map_err(|e| match e {
+        ForestError::Cancelled => Box::new(Cancelled) as Err,
+        e => e.into(),
+      })?;
+      match Self::unify_tree_with(tree, options).and_then(|tree| Self::unify_root(tree, options)) {
+        Ok(tree) => trees.push(self.rename_children(tree)),
+        Err(e) if e.is::<FeatureStructureTooLarge>() => return Err(e),
+        Err(_) => {}
+      }
+    }
+    trace_event!(debug, trees = trees.len(), "unified forest");
+    match options.max_ambiguity {
+      Some(limit) if trees.len() > limit => {
+        let warning = AmbiguityWarning {
+          parses: trees.len(),
+          limit,
+          span: self.shortest_ambiguity(&trees),
+        };
+        trace_event!(warn, %warning, "too many parses");
+        if let Some(observer) = &options.observer {
+          observer.ambiguity_exceeded(&warning);
+        }
+      }
+      _ => {}
+    }
+    Ok(trees)
   }
 
   pub fn read_from_file<P: AsRef<path::Path>>(path: P) -> Result<Self, Err> {
-    fs::read_to_string(path)?.parse()
+    Self::read_from_file_with(path, &GrammarOptions::default())
+  }
+
+  /// Like `read_from_file`, with options for how strict to be
+  pub fn read_from_file_with<P: AsRef<path::Path>>(
+    path: P,
+    options: &GrammarOptions,
+  ) -> Result<Self, Err> {
+    let options = GrammarOptions {
+      file: Some(path.as_ref().display().to_string()),
+      ..options.clone()
+    };
+    Self::from_str_with(&fs::read_to_string(path)?, &options)
+  }
+
+  /// Like `str::parse`, with options for how strict to be. In strict mode, a
+  /// grammar with any `warnings` fails to load with a `GrammarWarnings` error.
+  pub fn from_str_with(s: &str, options: &GrammarOptions) -> Result<Self, Err> {
+    let g = fgr::parse_grammar(s, options.file.as_deref())?;
+    if options.strict {
+      let warnings = g.warnings();
+      if !warnings.is_empty() {
+        return Err(Box::new(GrammarWarnings(warnings)));
+      }
+    }
+    Ok(g)
   }
 }
 
@@ -613,4 +1185,694 @@ fn test_unification_blocking() {
   assert_eq!(g.parse(&["himself", "likes", "himself"]).len(), 0);
   assert_eq!(g.parse(&["she", "likes", "himself"]).len(), 0);
   assert_eq!(g.parse(&["himself", "likes", "him"]).len(), 0);
+
+  assert_eq!(g.parse_best_first(&["he", "likes", "himself"]).len(), 1);
+  assert!(g.parse_one(&["he", "likes", "himself"]).is_some());
+  assert!(g.parse_one(&["she", "likes", "himself"]).is_none());
+  let owned = vec!["he".to_string(), "likes".to_string(), "him".to_string()];
+  assert_eq!(g.parse(&owned).len(), 1);
+  assert_eq!(g.parse("he likes him".split(' ')).len(), 1);
+  assert_eq!(g.parse_best_first(&["she", "likes", "himself"]).len(), 0);
+
+  let tree = g
+    .parse_forest(&["she", "likes", "himself"])
+    .trees(&g)
+    .remove(0);
+  let err = Grammar::unify_tree(tree).unwrap_err();
+  let failure = err.downcast_ref::<UnificationFailure>().unwrap();
+  assert_eq!(failure.path, vec!["child-2", "needs_pron"]);
+  assert_eq!((&*failure.left, &*failure.right), ("she", "he"));
+  assert_eq!(
+    failure.to_string(),
+    "unification failure at child-2.needs_pron: she & he, in rule S -> N TV N (line 2) over 0..3"
+  );
+
+  let mut calls = Vec::new();
+  let results = g.parse_all(
+    &[vec!["he", "likes", "him"], vec!["she", "likes", "himself"]],
+    &ParseOptions::default(),
+    |p| calls.push((p.done, p.total)),
+  );
+  assert_eq!(results.iter().map(Vec::len).collect::<Vec<_>>(), vec![1, 0]);
+  assert_eq!(calls, vec![(1, 2), (2, 2)]);
+}
+
+#[test]
+fn test_parse_str() {
+  let g: Grammar = "S -> N IV\nN -> mary\nIV -> fell".parse().unwrap();
+  assert_eq!(g.parse_str(" mary\tfell ").len(), 1);
+  assert_eq!(
+    g.parse_str_with("mary fell", &tokenize::Whitespace, &ParseOptions::default())
+      .len(),
+    1
+  );
+  assert!(g.parse_str("mary fell.").is_empty());
+}
+
+#[test]
+fn test_coordination() {
+  let g: Grammar = r#"
+    S -> NP[ case: nom, num: #1 ] IV[ num: #1 ]
+    @coord NP[ num: pl ] -> NP[ case: #1 ] and NP[ case: #1 ]
+    @coord NP -> NP[ case: #1 ] or NP[ case: #1 ]
+    NP[ num: sg, case: nom, pron: he ] -> he
+    NP[ num: sg, case: acc, pron: he ] -> him
+    NP[ num: sg, case: nom, pron: she ] -> she
+    NP[ num: sg, case: acc, pron: she ] -> her
+    NP[ num: sg, pron: she ] -> mary
+    IV[ num: sg ] -> falls
+    IV[ num: pl ] -> fall
+  "#
+  .parse()
+  .unwrap();
+
+  // the rule sets the number, and the case comes from the conjuncts
+  let trees = g.parse(&["he", "and", "she", "fall"]);
+  assert_eq!(trees.len(), 1);
+  let np = trees[0].1.get_path("child-0").unwrap();
+  assert_eq!(np.get_path("num").unwrap().get_str().as_deref(), Some("pl"));
+  assert_eq!(
+    np.get_path("case").unwrap().get_str().as_deref(),
+    Some("nom")
+  );
+  // the conjuncts disagree on pron, so the coordination doesn't have one
+  assert!(np.get_path("pron").is_none());
+
+  assert!(g.parse(&["he", "and", "she", "falls"]).is_empty());
+  assert!(g.parse(&["him", "and", "her", "fall"]).is_empty());
+  assert!(g.parse(&["he", "and", "her", "fall"]).is_empty());
+  assert_eq!(g.parse(&["he", "and", "mary", "fall"]).len(), 1);
+  // without a number on the rule, it comes from the conjuncts
+  assert_eq!(g.parse(&["she", "or", "mary", "falls"]).len(), 1);
+  assert!(g.parse(&["she", "or", "mary", "fall"]).is_empty());
+
+  let rule = "@coord NP -> NP and NP".parse::<Rule>().unwrap();
+  assert!(rule.coordination);
+  assert!(rule.to_string().starts_with("@coord NP"));
+  assert!("@coord(x) NP -> NP and NP".parse::<Rule>().is_err());
+}
+
+#[test]
+fn test_span_constraints() {
+  let g: Grammar = r#"
+    @span(0: <=2)
+    S -> NP VP
+    @span(1)
+    S -> Intj
+    NP -> N
+    NP -> A NP
+    VP -> V
+    A -> big
+    A -> red
+    N -> dogs
+    V -> bark
+    Intj -> oh
+    Intj -> oh Intj
+  "#
+  .parse()
+  .unwrap();
+
+  assert_eq!(g.parse(&["big", "dogs", "bark"]).len(), 1);
+  assert!(g.parse(&["big", "red", "dogs", "bark"]).is_empty());
+  assert_eq!(g.parse(&["oh"]).len(), 1);
+  assert!(g.parse(&["oh", "oh"]).is_empty());
+  assert!(g
+    .parse_best_first(&["big", "red", "dogs", "bark"])
+    .is_empty());
+
+  // X(0, 2) is in the chart, but can't be the first child
+  let g: Grammar = r#"
+    @span(0: 1)
+    S -> X X
+    X -> a
+    X -> a a
+  "#
+  .parse()
+  .unwrap();
+  let trees = g.parse(&["a", "a", "a"]);
+  assert_eq!(trees.len(), 1);
+  let (_, children) = trees[0].0.get_branch().unwrap();
+  assert_eq!(children[0].span(), (0, 1));
+  let options = ParseOptions {
+    packing: Some(packing::Restrictor::new(Vec::<String>::new())),
+    ..ParseOptions::default()
+  };
+  assert_eq!(g.parse_with(&["a", "a", "a"], &options).len(), 1);
+
+  let rule = "@span(0: <=3, 1: 2-4, >=2) S -> A B"
+    .parse::<Rule>()
+    .unwrap();
+  assert!(rule.to_string().starts_with("@span(0: <=3, 1: 2-4, >=2) S"));
+  assert_eq!(Rule::from_json(&rule.to_json()).unwrap().spans, rule.spans);
+  assert!(rule.allows_span(Some(1), 3) && !rule.allows_span(Some(1), 5));
+  assert!(!rule.allows_span(None, 1));
+  assert!("@span(2: 1) S -> A B".parse::<Rule>().is_err());
+  assert!("@span(3-2) S -> A B".parse::<Rule>().is_err());
+}
+
+#[test]
+fn test_hidden_rules() {
+  let g: Grammar = r#"
+    S -> NP[ num: #1 ] VP[ num: #1 ]
+    NP[ num: #1 ] -> Det NBar[ num: #1 ]
+    @hidden
+    NBar[ num: #1 ] -> NBar2[ num: #1 ]
+    @hidden
+    NBar2[ num: #1 ] -> N[ num: #1 ]
+    VP[ num: #1 ] -> V[ num: #1 ]
+    Det -> the
+    N[ num: pl ] -> dogs
+    N[ num: sg ] -> dog
+    V[ num: pl ] -> bark
+  "#
+  .parse()
+  .unwrap();
+
+  assert!(g.parse(&["the", "dog", "bark"]).is_empty());
+  let parsed = g.parse(&["the", "dogs", "bark"]).remove(0);
+  assert_eq!(
+    parsed.0.to_sexpr(),
+    "(S (NP (Det the) (N dogs)) (VP (V bark)))"
+  );
+  let n = parsed.1.get_path("child-0.child-1").unwrap();
+  assert_eq!(n.get_path("num").unwrap().get_str().as_deref(), Some("pl"));
+  assert_eq!(
+    n.get_path("child-0.word").unwrap().get_str().as_deref(),
+    Some("dogs")
+  );
+  let projected = g.project(&parsed);
+  let np = &projected.children()[0];
+  assert_eq!(np.children().len(), 2);
+
+  let rule = "@hidden NBar -> N".parse::<Rule>().unwrap();
+  assert!(rule.hidden);
+  assert!(rule.to_string().starts_with("@hidden NBar"));
+  assert!(Rule::from_json(&rule.to_json()).unwrap().hidden);
+}
+
+#[test]
+fn test_fs_limits() {
+  let g: Grammar = r#"
+    S -> A[ a.b.c.d: x ] B
+    A -> a
+    B -> b
+  "#
+  .parse()
+  .unwrap();
+
+  let mut options = ParseOptions {
+    max_fs_depth: Some(5),
+    ..ParseOptions::default()
+  };
+  assert_eq!(g.try_parse_with(&["a", "b"], &options).unwrap().len(), 1);
+
+  options.max_fs_depth = Some(4);
+  let err = g.try_parse_with(&["a", "b"], &options).unwrap_err();
+  assert!(err.is::<FeatureStructureTooLarge>());
+  assert!(g.parse_with(&["a", "b"], &options).is_empty());
+
+  options.max_fs_depth = None;
+  options.max_fs_nodes = Some(3);
+  assert!(g.try_parse_with(&["a", "b"], &options).is_err());
+}
+
+#[test]
+fn test_cancel() {
+  use std::sync::atomic::{AtomicBool, Ordering};
+
+  let g: Grammar = "S -> x\nS -> S S".parse().unwrap();
+  let cancel = Arc::new(AtomicBool::new(false));
+  let mut options = ParseOptions {
+    cancel: Some(cancel.clone()),
+    ..ParseOptions::default()
+  };
+  let input = ["x"; 6];
+  assert_eq!(g.try_parse_with(input, &options).unwrap().len(), 42);
+
+  cancel.store(true, Ordering::Relaxed);
+  for best_first in [false, true] {
+    options.best_first = best_first;
+    let err = g.try_parse_with(input, &options).unwrap_err();
+    assert!(err.is::<Cancelled>());
+    assert!(g.parse_with(input, &options).is_empty());
+  }
+}
+
+#[test]
+fn test_deadline_stops_tree_expansion() {
+  use std::time::Duration;
+
+  // the chart is quick, but there are 9 million trees
+  let g: Grammar = "S -> X\nX -> X X\nX -> a".parse().unwrap();
+  let options = ParseOptions {
+    deadline: Some(Instant::now() + Duration::from_millis(50)),
+    ..ParseOptions::default()
+  };
+  let start = Instant::now();
+  let err = g.try_parse_with(["a"; 16], &options).unwrap_err();
+  assert!(err.is::<Cancelled>());
+  assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn test_max_trees() {
+  use crate::judge::Judgment;
+
+  // 429 trees, but only the first two are unified
+  let g: Grammar = "S -> X\nX -> X X\nX -> a".parse().unwrap();
+  let options = ParseOptions {
+    max_trees: Some(2),
+    ..ParseOptions::default()
+  };
+  assert_eq!(g.parse(["a"; 8]).len(), 429);
+  assert_eq!(g.parse_with(["a"; 8], &options).len(), 2);
+  assert!(matches!(
+    g.judge_with(["a"; 8], &options),
+    Ok(Judgment::Accept(trees)) if trees.len() == 2
+  ));
+  let options = ParseOptions {
+    max_trees: Some(10),
+    ..ParseOptions::default()
+  };
+  assert_eq!(g.parse_with(["a"; 3], &options).len(), 2);
+}
+
+#[test]
+fn test_beam() {
+  let g: Grammar = r#"
+    S -> NP
+    @weight(0.2)
+    NP -> A
+    @weight(0.8)
+    NP -> B
+    A -> x
+    B -> x
+  "#
+  .parse()
+  .unwrap();
+
+  assert_eq!(g.parse(&["x"]).len(), 2);
+
+  let mut options = ParseOptions {
+    beam: Some(1),
+    ..ParseOptions::default()
+  };
+  // both keep the best, though the exhaustive parser finds NP -> A first and
+  // has to drop it
+  for best_first in [false, true] {
+    options.best_first = best_first;
+    let trees = g.parse_with(&["x"], &options);
+    assert_eq!(trees.len(), 1);
+    assert_eq!(trees[0].0.to_sexpr(), "(S (NP (B x)))");
+  }
+
+  // without weights, it's first come, first served
+  let g: Grammar = "S -> NP\nNP -> A\nNP -> B\nA -> x\nB -> x".parse().unwrap();
+  options.best_first = false;
+  let trees = g.parse_with(&["x"], &options);
+  assert_eq!(trees.len(), 1);
+  assert_eq!(trees[0].0.to_sexpr(), "(S (NP (A x)))");
+}
+
+#[test]
+fn test_unknown_words() {
+  let g: Grammar = r#"
+    S -> NP V
+    NP -> Det N
+    NP -> Det Adj N
+    NP -> Det Adj
+    Det -> the
+    N -> cat
+    V -> sleeps
+    @weight(0.3)
+    Adj -> _unknown
+    @weight(0.7)
+    N -> _unknown
+  "#
+  .parse()
+  .unwrap();
+
+  assert_eq!(g.parse(&["the", "cat", "sleeps"]).len(), 1);
+  // "cat" is known, so it can't be an Adj
+  assert!(g.parse(&["the", "cat", "cat", "sleeps"]).is_empty());
+
+  let trees = g.parse(&["the", "wug", "cat", "sleeps"]);
+  assert_eq!(trees.len(), 1);
+  let (tree, fs) = &trees[0];
+  assert_eq!(tree.yield_tokens(), ["the", "wug", "cat", "sleeps"]);
+  assert_eq!(
+    fs.get_path("child-0.child-1.child-0.word")
+      .unwrap()
+      .get_str()
+      .as_deref(),
+    Some("wug")
+  );
+
+  // "the wug" is Det N or Det Adj, ranked by weight
+  let options = ParseOptions {
+    best_first: true,
+    beam: Some(1),
+    ..ParseOptions::default()
+  };
+  assert_eq!(g.parse(&["the", "wug", "sleeps"]).len(), 2);
+  let trees = g.parse_with(&["the", "wug", "sleeps"], &options);
+  assert_eq!(trees.len(), 1);
+  assert!(trees[0].0.to_string().contains("N (1..2: wug)"));
+
+  let options = ParseOptions {
+    packing: Some(Restrictor::new(Vec::<String>::new())),
+    ..ParseOptions::default()
+  };
+  let trees = g.parse_with(&["the", "wug", "sleeps"], &options);
+  assert_eq!(trees[0].0.yield_tokens(), ["the", "wug", "sleeps"]);
+}
+
+#[test]
+fn test_root_features() {
+  let g: Grammar = r#"
+    S[ register: #r, addressee.num: #n ] -> V[ register: #r, num: #n ]
+    V[ register: formal ] -> sit
+    V[ register: informal, num: sg ] -> sit
+    V[ register: informal, num: pl ] -> sit
+  "#
+  .parse()
+  .unwrap();
+
+  assert_eq!(g.parse(&["sit"]).len(), 3);
+
+  let mut options = ParseOptions {
+    root_features: Some("[ register: informal ]".parse().unwrap()),
+    ..ParseOptions::default()
+  };
+  assert_eq!(g.parse_with(&["sit"], &options).len(), 2);
+
+  options.root_features = Some("[ register: informal, addressee.num: pl ]".parse().unwrap());
+  let trees = g.parse_with(&["sit"], &options);
+  assert_eq!(trees.len(), 1);
+  assert_eq!(
+    trees[0]
+      .1
+      .get_path("child-0.num")
+      .unwrap()
+      .get_str()
+      .as_deref(),
+    Some("pl")
+  );
+
+  // the formal verb agrees with any addressee, and gets the context's number
+  options.root_features = Some("[ register: formal, addressee.num: pl ]".parse().unwrap());
+  let trees = g.parse_with(&["sit"], &options);
+  assert_eq!(trees.len(), 1);
+  assert_eq!(
+    trees[0]
+      .1
+      .get_path("child-0.num")
+      .unwrap()
+      .get_str()
+      .as_deref(),
+    Some("pl")
+  );
+  // the constraints are copied, not changed
+  assert!(options.root_features.unwrap().get_path("child-0").is_none());
+
+  assert!("[ num: sg ] x".parse::<NodeRef>().is_err());
+}
+
+#[test]
+fn test_grammatical_functions() {
+  let g: Grammar = r#"
+    S -> subj:NP VP
+    VP -> V obj:NP
+    NP -> mary
+    NP -> sue
+    V -> likes
+  "#
+  .parse()
+  .unwrap();
+
+  let (tree, _) = g.parse_one(&["mary", "likes", "sue"]).unwrap();
+  let (_, children) = tree.get_branch().unwrap();
+  assert_eq!(
+    children[0].get_branch().unwrap().0.function.as_deref(),
+    Some("subj")
+  );
+  let (vp, vp_children) = children[1].get_branch().unwrap();
+  assert_eq!(vp.function, None);
+  assert_eq!(vp_children[1].to_json()["function"], "obj");
+  assert!(tree.to_string().contains("0..1: NP (subj)"));
+  assert_eq!(g.rules["S"][0].to_string(), "S[] -> subj:NP VP");
+
+  assert!("S -> subj:mary".parse::<Grammar>().is_err());
+  assert!("subj:S -> NP\nNP -> mary".parse::<Grammar>().is_err());
+}
+
+#[test]
+fn test_strict_loading() {
+  let src = r#"
+    S -> N V
+    S -> N V
+    N -> mary
+    V -> runs
+    Adj -> big
+  "#;
+  let strict = GrammarOptions {
+    strict: true,
+    ..GrammarOptions::default()
+  };
+
+  // permissive loading keeps the warnings for later
+  let g = Grammar::from_str_with(src, &GrammarOptions::default()).unwrap();
+  assert_eq!(g.warnings().len(), 2);
+
+  let e = Grammar::from_str_with(src, &strict).unwrap_err();
+  let warnings = e.downcast_ref::<GrammarWarnings>().unwrap();
+  assert_eq!(warnings.0.len(), 2);
+  assert!(e
+    .to_string()
+    .starts_with("grammar has 2 warnings in strict mode\n"));
+
+  assert!(Grammar::from_str_with("S -> N\nN -> mary", &strict).is_ok());
+}
+
+#[test]
+fn test_rule_locations() {
+  let src = r#"S -> N[ num: #1 ] V[ num: #1 ]
+N[ num: sg ] -> mary
+V[ num: pl ] -> run
+V[ num: pl ] -> run
+"#;
+  let options = GrammarOptions {
+    file: Some("agr.fgr".to_string()),
+    ..GrammarOptions::default()
+  };
+  let g = Grammar::from_str_with(src, &options).unwrap();
+  let rule = &g.rules_in_order()[0];
+  assert_eq!(rule.location().as_deref(), Some("agr.fgr:1"));
+  let rule = &g.rules_in_order()[1];
+  assert!(format!("{:#}", rule).ends_with("-> mary  // agr.fgr:2"));
+  // plain Display stays the same, since it's used to compare rules
+  assert!(rule.to_string().ends_with("-> mary"));
+
+  // the duplicate rule
+  assert!(g.warnings()[0].to_string().ends_with("(agr.fgr:4)"));
+  let judgment = g.judge(["mary", "run"]).to_string();
+  assert!(
+    judgment.contains("in rule S -> N V (agr.fgr:1) over 0..2"),
+    "{}",
+    judgment
+  );
+
+  let g: Grammar = src.parse().unwrap();
+  assert_eq!(g.rules_in_order()[0].location().as_deref(), Some("line 1"));
+}
+
+#[test]
+fn test_percolation() {
+  let g: Grammar = r#"
+    #percolate num, agr.per from head
+    S -> NP[ num: #1 ] VP[ num: #1 ]
+    VP -> head:V NP
+    NP -> head:N
+    N[ num: sg, agr.per: 3 ] -> mary
+    N[ num: pl, agr.per: 3 ] -> cats
+    V[ num: sg ] -> likes
+    V[ num: pl ] -> like
+  "#
+  .parse()
+  .unwrap();
+
+  assert_eq!(g.parse(&["mary", "likes", "cats"]).len(), 1);
+  assert_eq!(g.parse(&["cats", "like", "mary"]).len(), 1);
+  assert_eq!(g.parse(&["mary", "like", "cats"]).len(), 0);
+  let (_, fs) = g.parse_one(&["cats", "like", "mary"]).unwrap();
+  assert_eq!(
+    fs.get_path("child-0.agr.per").unwrap().get_str().as_deref(),
+    Some("3")
+  );
+  assert!(g.metadata.get("percolate").is_none());
+  // percolated features become part of the rule
+  assert_eq!(g.rules["NP"][0].to_string().matches("#").count(), 4);
+
+  let err = "#percolate num from head\nS[ num: pl ] -> head:N[ num: sg ]\nN -> x"
+    .parse::<Grammar>()
+    .map(|_| ())
+    .unwrap_err();
+  assert!(err
+    .to_string()
+    .starts_with("line 2: can't percolate num from head"));
+  assert!("#percolate num\nS -> x".parse::<Grammar>().is_err());
+  assert!("#percolate num, from head\nS -> x"
+    .parse::<Grammar>()
+    .is_err());
+}
+
+#[test]
+fn test_lemma() {
+  let g: Grammar = r#"
+    #lemma sem.lex
+    S -> N V
+    N -> mary
+    N -> _unknown
+    V[ sem.lex: go ] -> went
+    V -> goes
+    V -> gave up
+  "#
+  .parse()
+  .unwrap();
+
+  let lemmas = |input: &str| {
+    let (_, fs) = g.parse_one(input.split(' ')).unwrap();
+    ["child-0", "child-1"].map(|child| {
+      fs.get_path(&format!("{}.sem.lex", child))
+        .and_then(|lex| lex.get_str())
+        .map(|lex| lex.to_string())
+        .unwrap_or_default()
+    })
+  };
+  assert_eq!(lemmas("mary goes"), ["mary", "goes"]);
+  assert_eq!(lemmas("mary went"), ["mary", "go"]);
+  assert_eq!(lemmas("mary gave up"), ["mary", "gave up"]);
+  assert_eq!(lemmas("sue went"), ["sue", "go"]);
+  assert!(g.metadata.get("lemma").is_none());
+  assert!(g.rules["S"][0].features.get_path("sem").is_none());
+
+  assert!("#lemma lex, sem\nS -> x".parse::<Grammar>().is_err());
+  assert!("#lemma lex\n#lemma sem\nS -> x".parse::<Grammar>().is_err());
+}
+
+#[test]
+fn test_parse_with_start() {
+  let g: Grammar = r#"
+    S -> NP VP
+    NP -> Det N
+    VP -> V NP
+    Det -> the
+    N -> dog
+    N -> ball
+    V -> chased
+  "#
+  .parse()
+  .unwrap();
+
+  assert_eq!(g.parse_with_start("NP", ["the", "dog"]).unwrap().len(), 1);
+  assert!(g.parse(["the", "dog"]).is_empty());
+  let vp = g.parse_with_start("VP", ["chased", "the", "ball"]).unwrap();
+  assert_eq!(vp.len(), 1);
+  assert!(g
+    .parse_with_start("NP", ["the", "dog", "chased", "the", "ball"])
+    .unwrap()
+    .is_empty());
+  assert!(matches!(
+    g.parse_with_start("Np", ["the", "dog"]),
+    Err(GrammarError::UndefinedStart(s)) if s == "Np"
+  ));
+  assert!(g.parse_with_start("dog", ["dog"]).is_err());
+
+  // the other options still apply
+  let options = ParseOptions {
+    start: Some("NP".to_string()),
+    best_first: true,
+    ..ParseOptions::default()
+  };
+  assert_eq!(g.parse_with(["the", "ball"], &options).len(), 1);
+  let options = ParseOptions {
+    packing: Some(Restrictor::default()),
+    ..options
+  };
+  assert_eq!(g.parse_with(["the", "ball"], &options).len(), 1);
+  let options = ParseOptions {
+    start: Some("PP".to_string()),
+    ..ParseOptions::default()
+  };
+  assert!(g.try_parse_with(["the", "ball"], &options).is_err());
+  assert!(matches!(
+    g.with_start("PP"),
+    Err(GrammarError::UndefinedStart(s)) if s == "PP"
+  ));
+
+  let np = g.with_start("NP").unwrap();
+  let (tree, _) = np.parse_one(["the", "ball"]).unwrap();
+  assert_eq!(tree.to_sexpr(), "(NP (Det the) (N ball))");
+  assert_eq!(g.start, "S");
+}
+
+#[test]
+fn test_capitalization() {
+  use crate::tokenize::Whitespace;
+
+  let g: Grammar = r#"
+    S -> NP V
+    NP -> N
+    NP -> PropN
+    N -> dogs
+    PropN[ child-0.cap: initial ] -> _unknown
+    V -> bark
+  "#
+  .parse()
+  .unwrap();
+
+  let options = ParseOptions::default();
+  let parses = |s: &str| g.parse_str_folded(s, &Whitespace, &options);
+  assert_eq!(parses("Dogs bark").len(), 1);
+  assert_eq!(parses("Rex bark").len(), 1);
+  assert_eq!(parses("rex bark").len(), 0);
+  assert!(g.parse_str("dogs Bark").is_empty());
+
+  let (tree, fs) = parses("Rex BARK").pop().unwrap();
+  assert_eq!(tree.to_sexpr(), "(S (NP (PropN rex)) (V bark))");
+  assert_eq!(
+    fs.get_path("child-1.child-0.cap")
+      .and_then(|cap| cap.get_str())
+      .as_deref(),
+    Some("all")
+  );
+
+  // grammars that don't check cap don't get it
+  let plain: Grammar = "S -> N V\nN -> dogs\nV -> bark".parse().unwrap();
+  assert!(g.uses_capitalization() && !plain.uses_capitalization());
+  let (_, fs) = plain
+    .parse_str_folded("Dogs bark", &Whitespace, &options)
+    .pop()
+    .unwrap();
+  assert!(NodeRef::equivalent(
+    &fs,
+    &plain.parse(["dogs", "bark"]).pop().unwrap().1
+  ));
+  assert!(fs.get_path("child-0.child-0.cap").is_none());
+
+  // marks taken out of the input don't shift the words' capitalization
+  let options = ParseOptions {
+    punctuation: Some(Arc::new(punct::Punctuation::default())),
+    ..ParseOptions::default()
+  };
+  assert_eq!(
+    g.parse_str_folded("¿ Rex bark", &Whitespace, &options)
+      .len(),
+    1
+  );
+  assert_eq!(
+    g.parse_str_folded("¿ rex Bark", &Whitespace, &options)
+      .len(),
+    0
+  );
 }
@@ -0,0 +1,211 @@
+//! Checks for things in a grammar that aren't errors, but are usually mistakes.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::rules::{Grammar, Rule};
+
+/// A suspicious part of a grammar, found by `lint`
+#[derive(Debug, Clone)]
+pub struct Lint {
+  pub message: String,
+  /// The rule the message is about, if it's about a single rule
+  pub rule: Option<Arc<Rule>>,
+}
+
+impl fmt::Display for Lint {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "warning: {}", self.message)?;
+    if let Some(rule) = &self.rule {
+      write!(f, "\n  in rule: {}", rule)?;
+      if let Some(location) = rule.location() {
+        write!(f, " ({})", location)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Error for a grammar loaded in strict mode (see `GrammarOptions`) that has
+/// warnings
+#[derive(Debug, Clone)]
+pub struct GrammarWarnings(pub Vec<Lint>);
+
+impl fmt::Display for GrammarWarnings {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "grammar has {} warning{} in strict mode",
+      self.0.len(),
+      if self.0.len() == 1 { "" } else { "s" }
+    )?;
+    for lint in self.0.iter() {
+      write!(f, "\n{}", lint)?;
+    }
+    Ok(())
+  }
+}
+
+impl std::error::Error for GrammarWarnings {}
+
+/// Checks a grammar for:
+///
+/// * nonterminals that can't be reached from the start symbol
+/// * nonterminals that can never produce a complete constituent (e.g. `A -> A x`
+///   with no other `A` rules)
+/// * rules that are defined twice
+/// * `child-N` features for children a rule doesn't have
+/// * the string value `top`, which is usually meant to be `**top**`
+///
+/// Lints are returned in a stable order: by kind, then by rule order.
+pub fn lint(g: &Grammar) -> Vec<Lint> {
+  let mut lints = Vec::new();
+  let rules = g.rules_in_order();
+
+  let reachable = g.reachable();
+  for symbol in symbols_in_order(&rules, |s| !reachable.contains(s)) {
+    lints.push(Lint {
+      message: format!("nonterminal {} can't be reached from {}", symbol, g.start),
+      rule: None,
+    });
+  }
+
+  let productive = g.productive();
+  for symbol in symbols_in_order(&rules, |s| !productive.contains(s)) {
+    lints.push(Lint {
+      message: format!("nonterminal {} can never be completed", symbol),
+      rule: None,
+    });
+  }
+
+  let mut seen: HashMap<String, &Arc<Rule>> = HashMap::new();
+  for rule in rules.iter() {
+    let key = rule.to_string();
+    if let Some(first) = seen.get(&key) {
+      lints.push(Lint {
+        message: format!("duplicate rule (the first is rule #{})", first.index),
+        rule: Some((*rule).clone()),
+      });
+    } else {
+      seen.insert(key, rule);
+    }
+  }
+
+  for rule in rules.iter() {
+    for (path, _) in rule.features.walk() {
+      let child = path
+        .first()
+        .and_then(|l| l.strip_prefix("child-"))
+        .and_then(|idx| idx.parse::<usize>().ok());
+      if path.len() == 1 && child.is_some_and(|idx| idx >= rule.len()) {
+        lints.push(Lint {
+          message: format!(
+            "{} has features, but the rule only has {} children",
+            path[0],
+            rule.len()
+          ),
+          rule: Some((*rule).clone()),
+        });
+      }
+    }
+  }
+
+  for rule in rules.iter() {
+    for (path, node) in rule.features.walk() {
+      if node.get_str().is_some_and(|s| &*s == "top") {
+        lints.push(Lint {
+          message: format!(
+            "{} is the string \"top\", write **top** for a value that unifies with anything",
+            path.join(".")
+          ),
+          rule: Some((*rule).clone()),
+        });
+      }
+    }
+  }
+
+  lints
+}
+
+/// Each rule symbol matching `pred`, once, in the order they're first defined
+fn symbols_in_order<'a>(rules: &[&'a Arc<Rule>], pred: impl Fn(&str) -> bool) -> Vec<&'a str> {
+  let mut seen = HashSet::new();
+  rules
+    .iter()
+    .map(|r| r.symbol.as_str())
+    .filter(|s| pred(s) && seen.insert(*s))
+    .collect()
+}
+
+#[test]
+fn test_lint() {
+  let g: Grammar = r#"
+    S -> N V
+    N -> mary
+    V -> runs
+    V -> runs
+    Adj -> happy
+    Loop -> Loop x
+    S -> Loop
+  "#
+  .parse()
+  .unwrap();
+
+  let messages = lint(&g).into_iter().map(|l| l.message).collect::<Vec<_>>();
+  assert_eq!(
+    messages,
+    vec![
+      "nonterminal Adj can't be reached from S",
+      "nonterminal Loop can never be completed",
+      "duplicate rule (the first is rule #2)",
+    ]
+  );
+
+  let g: Grammar = "S -> x".parse().unwrap();
+  assert!(lint(&g).is_empty());
+
+  let g: Grammar = r#"
+    S[ child-2.num: sg ] -> N[ num: #1, case: top ] V[ num: #2 ]
+    N -> mary
+    V -> runs
+  "#
+  .parse()
+  .unwrap();
+  let messages = g
+    .warnings()
+    .into_iter()
+    .map(|l| l.message)
+    .collect::<Vec<_>>();
+  assert_eq!(
+    messages,
+    vec![
+      "tag #1 is only used once",
+      "tag #2 is only used once",
+      "child-2 has features, but the rule only has 2 children",
+      "child-0.case is the string \"top\", write **top** for a value that unifies with anything",
+    ]
+  );
+
+  // adding and removing rules keeps the warnings up to date
+  let mut g = g;
+  g.remove_rule(
+    &"S[ child-2.num: sg ] -> N[ num: #1, case: top ] V[ num: #2 ]"
+      .parse()
+      .unwrap(),
+  )
+  .unwrap_err();
+  g.add_rule("S -> N".parse().unwrap()).unwrap();
+  g.remove_rule(
+    &"S[ child-2.num: sg ] -> N[ num: #1, case: top ] V[ num: #2 ]"
+      .parse()
+      .unwrap(),
+  )
+  .unwrap();
+  let messages = g
+    .warnings()
+    .into_iter()
+    .map(|l| l.message)
+    .collect::<Vec<_>>();
+  assert_eq!(messages, vec!["nonterminal V can't be reached from S"]);
+}
@@ -0,0 +1,150 @@
+//! Minimal pairs: variants of a grammatical sentence with one word swapped for
+//! another of the same category, and whether each still parses. The ones that
+//! don't, with the constraint that blocked them, make good teaching examples
+//! and regression tests (`*mary run` for subject agreement, say).
+
+use std::collections::BTreeSet;
+
+use crate::judge::Judgment;
+use crate::options::ParseOptions;
+use crate::rules::{Grammar, GrammarError, Rule};
+
+/// A sentence with one word substituted, and the grammar's judgment of it
+#[derive(Debug)]
+pub struct MinimalPair {
+  /// The index of the substituted word
+  pub position: usize,
+  pub original: String,
+  pub replacement: String,
+  /// The whole variant sentence
+  pub sentence: Vec<String>,
+  pub judgment: Judgment,
+}
+
+impl MinimalPair {
+  /// Whether the substitution made the sentence ungrammatical
+  pub fn is_blocked(&self) -> bool {
+    !self.judgment.is_accept()
+  }
+}
+
+/// Lexical entries with a single word on the right, like `N[ num: sg ] -> mary`
+fn single_word(rule: &Rule) -> Option<&str> {
+  match &rule.productions[..] {
+    [p] if p.is_terminal() && !p.is_unknown_word() => Some(&p.symbol),
+    _ => None,
+  }
+}
+
+/// The other words that share a category with `word` in the lexicon, sorted
+fn substitutes(g: &Grammar, word: &str) -> BTreeSet<String> {
+  let rules = g.rules_in_order();
+  let categories = rules
+    .iter()
+    .filter(|r| single_word(r) == Some(word))
+    .map(|r| r.symbol.as_str())
+    .collect::<BTreeSet<_>>();
+  rules
+    .iter()
+    .filter(|r| categories.contains(r.symbol.as_str()))
+    .filter_map(|r| single_word(r))
+    .filter(|w| *w != word)
+    .map(str::to_string)
+    .collect()
+}
+
+/// Every variant of `sentence` with one word replaced by another word of the
+/// same category (a symbol that has a lexical entry for both), judged by `g`.
+/// Variants are in order of position, then replacement. Words the lexicon
+/// doesn't have a single-word entry for, like ones that are part of a phrasal
+/// rule, aren't substituted.
+pub fn minimal_pairs<S: AsRef<str>>(g: &Grammar, sentence: &[S]) -> Vec<MinimalPair> {
+  minimal_pairs_with(g, sentence, &ParseOptions::default()).expect("grammar missing start rules")
+}
+
+/// Like `minimal_pairs`, judging the variants with `options`, like the
+/// capitalization of the original sentence's words from `Grammar::fold_case`.
+/// Fails like `Grammar::judge_with`.
+pub fn minimal_pairs_with<S: AsRef<str>>(
+  g: &Grammar,
+  sentence: &[S],
+  options: &ParseOptions,
+) -> Result<Vec<MinimalPair>, GrammarError> {
+  let sentence = sentence
+    .iter()
+    .map(|w| w.as_ref().to_string())
+    .collect::<Vec<_>>();
+  let mut pairs = Vec::new();
+  for (position, original) in sentence.iter().enumerate() {
+    for replacement in substitutes(g, original) {
+      let mut variant = sentence.clone();
+      variant[position] = replacement.clone();
+      let judgment = g.judge_with(&variant, options)?;
+      pairs.push(MinimalPair {
+        position,
+        original: original.clone(),
+        replacement,
+        sentence: variant,
+        judgment,
+      });
+    }
+  }
+  Ok(pairs)
+}
+
+#[test]
+fn test_minimal_pairs() {
+  let g: Grammar = r#"
+    S -> N[ num: #1 ] V[ num: #1 ]
+    N[ num: sg ] -> mary
+    N[ num: pl ] -> they
+    N[ num: pl ] -> cats
+    V[ num: sg ] -> runs
+    V[ num: pl ] -> run
+  "#
+  .parse()
+  .unwrap();
+
+  let pairs = minimal_pairs(&g, &["mary", "runs"]);
+  let summary = pairs
+    .iter()
+    .map(|p| format!("{} {}", p.sentence.join(" "), p.is_blocked()))
+    .collect::<Vec<_>>();
+  assert_eq!(
+    summary,
+    ["cats runs true", "they runs true", "mary run true"]
+  );
+  match &pairs[2].judgment {
+    Judgment::RejectFeatures(failures) => assert_eq!(failures[0].path, ["child-1", "num"]),
+    j => panic!("{}", j),
+  }
+  assert!(minimal_pairs(&g, &["they", "run"])
+    .iter()
+    .any(|p| p.sentence == ["cats", "run"] && !p.is_blocked()));
+}
+
+#[test]
+fn test_minimal_pairs_with_capitalization() {
+  let g: Grammar = r#"
+    S -> N V
+    N[ child-0.cap: initial ] -> _unknown
+    N -> it
+    V -> runs
+    V -> sleeps
+  "#
+  .parse()
+  .unwrap();
+
+  let blocked = |sentence: &[&str]| {
+    let (words, options) = g.fold_case(sentence, &ParseOptions::default());
+    minimal_pairs_with(&g, &words, &options)
+      .unwrap()
+      .iter()
+      .filter(|p| p.is_blocked())
+      .map(|p| p.sentence.join(" "))
+      .collect::<Vec<_>>()
+  };
+  // the variants keep the name's capitalization
+  assert!(blocked(&["Élan", "runs"]).is_empty());
+  assert_eq!(blocked(&["élan", "runs"]), ["élan sleeps"]);
+}
@@ -0,0 +1,129 @@
+//! Hooks for watching a parse as it happens, for debuggers and visualizers.
+//! Set `ParseOptions::observer` to receive the events.
+
+use std::fmt;
+
+use crate::ambiguity::AmbiguityWarning;
+use crate::earley::State;
+use crate::featurestructure::UnificationFailure;
+use crate::forest::ForestState;
+
+/// Callbacks for parser events. Every method does nothing by default, so
+/// implementors only need the ones they care about. Parsing takes options by
+/// reference, so observers that record events need interior mutability, like
+/// a `Mutex`.
+pub trait ParseObserver: Send + Sync {
+  /// A state was added to the chart at `k`
+  fn state_added(&self, _k: usize, _state: &State) {}
+
+  /// `state` consumed `token`, the `k`th word
+  fn scanned(&self, _k: usize, _state: &State, _token: &str) {}
+
+  /// A completed state, ending at `k`, is about to advance the states waiting
+  /// on its symbol
+  fn completed(&self, _k: usize, _state: &State) {}
+
+  /// Every state at `k` has been processed, so `states` are all it will
+  /// have. Comes once per position, in order, so a debugger can print the
+  /// chart as it's built (see `earley::format_position`).
+  fn position_finished(&self, _k: usize, _states: &[State]) {}
+
+  /// A completed constituent was added to the forest
+  fn forest_node_created(&self, _state: &ForestState) {}
+
+  /// A tree was rejected because its features didn't unify
+  fn unification_failed(&self, _failure: &UnificationFailure) {}
+
+  /// The sentence had more parses than `ParseOptions::max_ambiguity`
+  fn ambiguity_exceeded(&self, _warning: &AmbiguityWarning) {}
+}
+
+impl fmt::Debug for dyn ParseObserver {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "ParseObserver")
+  }
+}
+
+#[test]
+fn test_observer() {
+  use std::sync::{Arc, Mutex};
+
+  use crate::{Grammar, ParseOptions};
+
+  #[derive(Default)]
+  struct Recorder(Mutex<Vec<String>>);
+
+  impl ParseObserver for Recorder {
+    fn scanned(&self, k: usize, _state: &State, token: &str) {
+      self.0.lock().unwrap().push(format!("scan {} {}", k, token));
+    }
+
+    fn forest_node_created(&self, _state: &ForestState) {
+      self.0.lock().unwrap().push("forest".to_string());
+    }
+
+    fn unification_failed(&self, failure: &UnificationFailure) {
+      self.0.lock().unwrap().push(failure.path.join("."));
+    }
+  }
+
+  let g: Grammar = r#"
+    S -> N[ num: #1 ] IV[ num: #1 ]
+    N[ num: sg ] -> mary
+    IV[ num: pl ] -> fall
+  "#
+  .parse()
+  .unwrap();
+
+  let recorder = Arc::new(Recorder::default());
+  let options = ParseOptions {
+    observer: Some(recorder.clone()),
+    ..ParseOptions::default()
+  };
+  assert!(g.parse_with(&["mary", "fall"], &options).is_empty());
+  assert_eq!(
+    *recorder.0.lock().unwrap(),
+    [
+      "scan 0 mary",
+      "scan 1 fall",
+      "forest",
+      "forest",
+      "forest",
+      "child-1.num",
+    ]
+  );
+}
+
+#[test]
+fn test_observer_best_first() {
+  use std::sync::{Arc, Mutex};
+
+  use crate::{Grammar, ParseOptions};
+
+  #[derive(Default)]
+  struct Recorder(Mutex<Vec<String>>);
+
+  impl ParseObserver for Recorder {
+    fn unification_failed(&self, failure: &UnificationFailure) {
+      self.0.lock().unwrap().push(failure.path.join("."));
+    }
+  }
+
+  // the forest has one tree, so it's built directly
+  let g: Grammar = r#"
+    S -> N[ num: #1 ] IV[ num: #1 ]
+    N[ num: sg ] -> mary
+    IV[ num: pl ] -> fall
+  "#
+  .parse()
+  .unwrap();
+
+  let recorder = Arc::new(Recorder::default());
+  let options = ParseOptions {
+    observer: Some(recorder.clone()),
+    best_first: true,
+    ..ParseOptions::default()
+  };
+  assert!(g.parse_with(&["mary", "fall"], &options).is_empty());
+  assert_eq!(*recorder.0.lock().unwrap(), ["child-1.num"]);
+}
@@ -0,0 +1,126 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::featurestructure::NodeRef;
+use crate::observer::ParseObserver;
+use crate::packing::Restrictor;
+use crate::punct::Punctuation;
+use crate::rewrite::Rewrites;
+use crate::rules::Grammar;
+use crate::tokenize::Capitalization;
+
+/// Options that control how a sentence is parsed. The defaults give an
+/// exhaustive parse that returns every tree.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+  /// Use the best-first parser (see `agenda`) and stop at the first chart
+  /// that contains a surviving parse
+  pub best_first: bool,
+  /// Maximum number of completed states to keep per chart cell (one symbol
+  /// over one span). The highest-scoring states are kept, scoring states by
+  /// the product of their rules' weights, see `Chart::with_beam`.
+  pub beam: Option<usize>,
+  /// Maximum number of nodes in a tree's feature structure. Trees that grow
+  /// past it stop the parse with a `FeatureStructureTooLarge` error.
+  pub max_fs_nodes: Option<usize>,
+  /// Maximum depth of a tree's feature structure, like `max_fs_nodes`
+  pub max_fs_depth: Option<usize>,
+  /// Receives events as the sentence is parsed, see `observer`
+  pub observer: Option<Arc<dyn ParseObserver>>,
+  /// Set this to true from another thread to stop the parse, which then
+  /// fails with `Cancelled`
+  pub cancel: Option<Arc<AtomicBool>>,
+  /// Stop the parse once this time has passed, as if it was cancelled. See
+  /// `anytime` for keeping what was found by then.
+  pub deadline: Option<Instant>,
+  /// Punctuation to take out of the input and attach to the trees afterwards,
+  /// instead of parsing it with the grammar, see `punct`
+  pub punctuation: Option<Arc<Punctuation>>,
+  /// Rewrites to apply to the tree of every parse that survives, see
+  /// `rewrite`. Punctuation is attached after rewriting.
+  pub rewrites: Option<Arc<Rewrites>>,
+  /// Features every parse's root must unify with, like context from outside
+  /// the sentence (`[ addressee.num: pl ]`). Parses that don't are dropped, and
+  /// the rest have these features unified into their roots.
+  pub root_features: Option<NodeRef>,
+  /// Unify the forest bottom-up, packing ambiguity under this restrictor,
+  /// before unpacking trees (see `packing`). Gives the same parses, in a
+  /// different order, with much less unification for very ambiguous
+  /// sentences.
+  pub packing: Option<Restrictor>,
+  /// Warn (through `observer`, and `tracing`) about sentences with more
+  /// parses than this, see `ambiguity`. The parses are still returned.
+  pub max_ambiguity: Option<usize>,
+  /// How each token was capitalized, from `tokenize::fold_case`. Each word's
+  /// leaf then has a `cap` feature of `none`, `initial` or `all`, which rules
+  /// can check like `PropN[ child-0.cap: initial ] -> _unknown`.
+  /// `Grammar::fold_case` only sets this for grammars that check `cap`.
+  pub capitalization: Option<Vec<Capitalization>>,
+  /// Stop once this many trees have survived unification, instead of building
+  /// and unifying the rest. Each tree's subtrees are still all built first,
+  /// so set a `deadline` too for very ambiguous sentences.
+  pub max_trees: Option<usize>,
+  /// Parse as this symbol instead of the grammar's start symbol, see
+  /// `Grammar::parse_with_start`
+  pub start: Option<String>,
+}
+
+impl ParseOptions {
+  pub fn is_cancelled(&self) -> bool {
+    self
+      .cancel
+      .as_ref()
+      .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+      || self
+        .deadline
+        .is_some_and(|deadline| Instant::now() >= deadline)
+  }
+
+  /// `start`, or else the grammar's start symbol
+  pub fn start_symbol<'a>(&'a self, g: &'a Grammar) -> &'a str {
+    self.start.as_deref().unwrap_or(&g.start)
+  }
+
+  /// Whether `trees` surviving trees are as many as `max_trees` allows
+  pub fn has_enough_trees(&self, trees: usize) -> bool {
+    self.max_trees.is_some_and(|max| trees >= max)
+  }
+
+  /// Fails with `Cancelled` if the parse has been cancelled
+  pub fn check_cancelled(&self) -> Result<(), Cancelled> {
+    if self.is_cancelled() {
+      Err(Cancelled)
+    } else {
+      Ok(())
+    }
+  }
+}
+
+/// Options that control how a grammar is loaded, see `Grammar::from_str_with`.
+/// The defaults are permissive: a grammar with warnings still loads, and the
+/// warnings are left for `Grammar::warnings`.
+#[derive(Debug, Clone, Default)]
+pub struct GrammarOptions {
+  /// Fail to load a grammar that has any warnings (unreachable symbols,
+  /// duplicate rules, tags only used once, and the rest of `lint`), with a
+  /// `GrammarWarnings` error listing them. Meant for CI, where a warning is
+  /// probably a mistake.
+  pub strict: bool,
+  /// The file the grammar is from, for rules' locations (see
+  /// `Rule::location`). `Grammar::read_from_file` sets this itself.
+  pub file: Option<String>,
+}
+
+/// Error for when a parse is stopped through `ParseOptions::cancel`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "parse cancelled")
+  }
+}
+
+impl std::error::Error for Cancelled {}
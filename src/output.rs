@@ -0,0 +1,320 @@
+//! A parse API that keeps "ungrammatical" and "couldn't finish" apart.
+//! `Grammar::parse` returns a bare `Vec` that's empty either way, while
+//! `Grammar::try_parse` returns `Ok` with a `ParseOutput` whenever the parse
+//! ran to the end, even with no trees, and a `ParseError` only when it
+//! didn't (it was cancelled, or a structure or tree grew too large).
+
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::ambiguity::AmbiguityWarning;
+use crate::earley::State;
+use crate::featurestructure::{FeatureStructureTooLarge, UnificationFailure};
+use crate::forest::{ForestError, ForestState};
+use crate::observer::ParseObserver;
+use crate::options::{Cancelled, ParseOptions};
+use crate::rules::Grammar;
+use crate::{with_tokens, Err, ParsedTree};
+
+/// Something worth knowing about a parse that didn't stop it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+  /// The sentence had more parses than `ParseOptions::max_ambiguity`
+  Ambiguity(AmbiguityWarning),
+  /// A token no rule has as a terminal. It can only be parsed by `_unknown`
+  /// rules, if the grammar has any.
+  UnknownWord { position: usize, word: String },
+}
+
+impl fmt::Display for ParseWarning {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Ambiguity(warning) => write!(f, "{}", warning),
+      Self::UnknownWord { position, word } => {
+        write!(f, "unknown word {:?} at {}", word, position)
+      }
+    }
+  }
+}
+
+/// Counts of the work a parse did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseStats {
+  pub tokens: usize,
+  /// States added to the chart
+  pub chart_states: usize,
+  /// Completed constituents added to the forest
+  pub forest_nodes: usize,
+  /// Trees unified, whether or not they survived
+  pub trees_tried: usize,
+  pub elapsed: Duration,
+}
+
+/// Everything a finished parse found
+#[derive(Debug, Clone)]
+pub struct ParseOutput {
+  /// The trees that survived unification, like `Grammar::parse` returns
+  pub trees: Vec<ParsedTree>,
+  pub warnings: Vec<ParseWarning>,
+  /// Why each rejected tree was rejected: the features that clashed, and the
+  /// rule and span they clashed in. Failures shared by several trees are
+  /// listed once.
+  pub rejected: Vec<UnificationFailure>,
+  pub stats: ParseStats,
+}
+
+impl ParseOutput {
+  /// Whether any tree survived
+  pub fn is_grammatical(&self) -> bool {
+    !self.trees.is_empty()
+  }
+}
+
+/// Why a parse couldn't finish
+#[derive(Debug)]
+pub enum ParseError {
+  /// Through `ParseOptions::cancel` or `ParseOptions::deadline`
+  Cancelled,
+  /// A tree's features grew past the limits in `ParseOptions`
+  TooLarge(FeatureStructureTooLarge),
+  /// The forest's trees couldn't be built: they nest too deeply, or never
+  /// end
+  Forest(ForestError),
+  /// Anything else, which would be a bug
+  Other(Err),
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Cancelled => write!(f, "{}", Cancelled),
+      Self::TooLarge(e) => write!(f, "{}", e),
+      Self::Forest(e) => write!(f, "{}", e),
+      Self::Other(e) => write!(f, "internal error: {}", e),
+    }
+  }
+}
+
+impl Error for ParseError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match self {
+      Self::Cancelled => None,
+      Self::TooLarge(e) => Some(e),
+      Self::Forest(e) => Some(e),
+      Self::Other(e) => Some(e.as_ref()),
+    }
+  }
+}
+
+impl From<Err> for ParseError {
+  fn from(e: Err) -> Self {
+    if e.is::<Cancelled>() {
+      return Self::Cancelled;
+    }
+    let e = match e.downcast::<FeatureStructureTooLarge>() {
+      Ok(e) => return Self::TooLarge(*e),
+      Err(e) => e,
+    };
+    match e.downcast::<ForestError>() {
+      Ok(e) => Self::Forest(*e),
+      Err(e) => Self::Other(e),
+    }
+  }
+}
+
+/// What the collector has seen so far
+#[derive(Default)]
+struct Collected {
+  chart_states: usize,
+  forest_nodes: usize,
+  trees_tried: usize,
+  rejected: Vec<UnificationFailure>,
+  ambiguity: Option<AmbiguityWarning>,
+}
+
+/// Records the events `ParseOutput` needs, passing them on to the observer
+/// that was already in the options, if any
+struct Collector {
+  inner: Option<Arc<dyn ParseObserver>>,
+  collected: Mutex<Collected>,
+}
+
+impl Collector {
+  fn forward(&self, f: impl FnOnce(&dyn ParseObserver)) {
+    if let Some(inner) = &self.inner {
+      f(inner.as_ref());
+    }
+  }
+}
+
+impl ParseObserver for Collector {
+  fn state_added(&self, k: usize, state: &State) {
+    self.collected.lock().unwrap().chart_states += 1;
+    self.forward(|o| o.state_added(k, state));
+  }
+
+  fn scanned(&self, k: usize, state: &State, token: &str) {
+    self.forward(|o| o.scanned(k, state, token));
+  }
+
+  fn completed(&self, k: usize, state: &State) {
+    self.forward(|o| o.completed(k, state));
+  }
+
+  fn position_finished(&self, k: usize, states: &[State]) {
+    self.forward(|o| o.position_finished(k, states));
+  }
+
+  fn forest_node_created(&self, state: &ForestState) {
+    self.collected.lock().unwrap().forest_nodes += 1;
+    self.forward(|o| o.forest_node_created(state));
+  }
+
+  fn unification_failed(&self, failure: &UnificationFailure) {
+    {
+      let mut collected = self.collected.lock().unwrap();
+      collected.trees_tried += 1;
+      if !collected.rejected.contains(failure) {
+        collected.rejected.push(failure.clone());
+      }
+    }
+    self.forward(|o| o.unification_failed(failure));
+  }
+
+  fn ambiguity_exceeded(&self, warning: &AmbiguityWarning) {
+    self.collected.lock().unwrap().ambiguity = Some(warning.clone());
+    self.forward(|o| o.ambiguity_exceeded(warning));
+  }
+}
+
+impl Grammar {
+  /// Parses `input`, telling a sentence with no parses (`Ok`, with no trees)
+  /// apart from a parse that failed. See `ParseOutput` for what else comes
+  /// back.
+  pub fn try_parse<I>(&self, input: I) -> Result<ParseOutput, ParseError>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    self.try_parse_output(input, &ParseOptions::default())
+  }
+
+  /// Like `try_parse`, with options. The observer in `options` still gets
+  /// every event.
+  pub fn try_parse_output<I>(
+    &self,
+    input: I,
+    options: &ParseOptions,
+  ) -> Result<ParseOutput, ParseError>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    with_tokens(input, |input| {
+      let start = Instant::now();
+      let collector = Arc::new(Collector {
+        inner: options.observer.clone(),
+        collected: Mutex::new(Collected::default()),
+      });
+      let options = ParseOptions {
+        observer: Some(collector.clone()),
+        ..options.clone()
+      };
+      let trees = self.try_parse_tokens(input, &options)?;
+      let elapsed = start.elapsed();
+
+      let collected = std::mem::take(&mut *collector.collected.lock().unwrap());
+      let mut warnings = input
+        .iter()
+        .enumerate()
+        .filter(|(_, word)| !self.is_known_word(word))
+        .map(|(position, word)| ParseWarning::UnknownWord {
+          position,
+          word: word.to_string(),
+        })
+        .collect::<Vec<_>>();
+      warnings.extend(collected.ambiguity.map(ParseWarning::Ambiguity));
+
+      Ok(ParseOutput {
+        stats: ParseStats {
+          tokens: input.len(),
+          chart_states: collected.chart_states,
+          forest_nodes: collected.forest_nodes,
+          trees_tried: collected.trees_tried + trees.len(),
+          elapsed,
+        },
+        trees,
+        warnings,
+        rejected: collected.rejected,
+      })
+    })
+  }
+}
+
+#[test]
+fn test_try_parse() {
+  use std::sync::atomic::AtomicBool;
+
+  let g: Grammar = r#"
+    S -> N[ num: #1 ] V[ num: #1 ]
+    N[ num: sg ] -> mary
+    N[ num: pl ] -> they
+    N -> _unknown
+    V[ num: sg ] -> runs
+    V[ num: pl ] -> run
+  "#
+  .parse()
+  .unwrap();
+
+  let output = g.try_parse(["mary", "runs"]).unwrap();
+  assert!(output.is_grammatical());
+  assert!(output.warnings.is_empty() && output.rejected.is_empty());
+  assert_eq!(output.stats.tokens, 2);
+  assert_eq!(output.stats.trees_tried, 1);
+  assert!(output.stats.chart_states > 0 && output.stats.forest_nodes > 0);
+
+  // ungrammatical isn't an error
+  let output = g.try_parse(["mary", "run"]).unwrap();
+  assert!(!output.is_grammatical());
+  assert_eq!(output.rejected.len(), 1);
+  assert_eq!(output.rejected[0].path, ["child-1", "num"]);
+  assert_eq!(output.stats.trees_tried, 1);
+  let output = g.try_parse(["runs", "mary"]).unwrap();
+  assert!(!output.is_grammatical() && output.rejected.is_empty());
+
+  let output = g.try_parse(["fido", "runs"]).unwrap();
+  assert!(output.is_grammatical());
+  assert_eq!(
+    output.warnings,
+    [ParseWarning::UnknownWord {
+      position: 0,
+      word: "fido".to_string()
+    }]
+  );
+  assert_eq!(
+    output.warnings[0].to_string(),
+    r#"unknown word "fido" at 0"#
+  );
+
+  let options = ParseOptions {
+    max_ambiguity: Some(0),
+    ..ParseOptions::default()
+  };
+  let output = g.try_parse_output(["they", "run"], &options).unwrap();
+  assert!(matches!(output.warnings[..], [ParseWarning::Ambiguity(_)]));
+
+  let options = ParseOptions {
+    cancel: Some(Arc::new(AtomicBool::new(true))),
+    ..ParseOptions::default()
+  };
+  let e = g.try_parse_output(["mary", "runs"], &options).unwrap_err();
+  assert!(matches!(e, ParseError::Cancelled));
+  let options = ParseOptions {
+    max_fs_nodes: Some(1),
+    ..ParseOptions::default()
+  };
+  let e = g.try_parse_output(["mary", "runs"], &options).unwrap_err();
+  assert!(matches!(e, ParseError::TooLarge(_)));
+}
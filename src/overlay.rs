@@ -0,0 +1,128 @@
+//! Overlays: small sets of rules, like a dialect's extra words or a vocabulary
+//! pack, added on top of a loaded grammar at runtime. The base grammar isn't
+//! reparsed or changed, and its rules are shared with every grammar made from
+//! it, so applying an overlay costs about as much as its own rules.
+//!
+//! An overlay is written like a grammar file, but its rules can use the base
+//! grammar's nonterminals without defining them, and its first rule doesn't
+//! change the start symbol:
+//!
+//! ```
+//! use treebender::overlay::Overlay;
+//! use treebender::Grammar;
+//!
+//! let base: Grammar = "S -> N V\nN -> mary\nV -> runs".parse().unwrap();
+//! let pack: Overlay = "N -> fido\nV -> barks".parse().unwrap();
+//! let g = base.with_overlay(&pack).unwrap();
+//! assert_eq!(g.parse(["fido", "barks"]).len(), 1);
+//! assert!(base.parse(["fido", "barks"]).is_empty());
+//! ```
+
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::fgr::parse_grammar_rules;
+use crate::rules::{Grammar, GrammarMetadata, Rule};
+use crate::Err;
+
+/// Rules to add to a grammar, see the module docs
+#[derive(Debug)]
+pub struct Overlay {
+  /// The overlay's own `#key value` lines, which aren't merged into the
+  /// grammar's, for telling overlays apart
+  pub metadata: GrammarMetadata,
+  pub rules: Vec<Rule>,
+  /// The parse warnings for each rule, which the grammar gets with the rules
+  warnings: Vec<Vec<String>>,
+}
+
+impl Overlay {
+  /// Reads an overlay from a file, recording it as the file its rules came
+  /// from
+  pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Self, Err> {
+    let file = path.as_ref().display().to_string();
+    Self::parse_from(&fs::read_to_string(path)?, Some(&file))
+  }
+
+  fn parse_from(s: &str, file: Option<&str>) -> Result<Self, Err> {
+    let (metadata, rules, warnings) = parse_grammar_rules(s, file)?;
+    Ok(Self {
+      metadata,
+      rules,
+      warnings,
+    })
+  }
+}
+
+impl FromStr for Overlay {
+  type Err = Err;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Self::parse_from(s, None)
+  }
+}
+
+impl Grammar {
+  /// A copy of this grammar with `overlay`'s rules after its own. Fails with
+  /// a `GrammarError` if the result wouldn't `validate`, like when the
+  /// overlay uses a nonterminal neither defines. Overlays can be stacked by
+  /// calling this on the result.
+  pub fn with_overlay(&self, overlay: &Overlay) -> Result<Grammar, Err> {
+    let mut g = self.clone();
+    let added = g.append_rules(overlay.rules.iter().map(Rule::deep_clone).collect())?;
+    for (rule, messages) in added.iter().zip(&overlay.warnings) {
+      g.add_parse_warnings(rule, messages.clone());
+    }
+    Ok(g)
+  }
+}
+
+#[test]
+fn test_overlays() {
+  let base: Grammar = r#"
+    S -> N[ num: #1 ] V[ num: #1 ]
+    N[ num: sg ] -> mary
+    V[ num: sg ] -> runs
+  "#
+  .parse()
+  .unwrap();
+
+  let pack: Overlay = r#"
+    #name animals
+    N[ num: sg ] -> fido
+    N[ num: pl ] -> dogs
+    V[ num: pl ] -> bark
+    V -> Adv V
+    Adv -> loudly
+  "#
+  .parse()
+  .unwrap();
+  assert_eq!(pack.metadata.name.as_deref(), Some("animals"));
+
+  let g = base.with_overlay(&pack).unwrap();
+  assert_eq!(g.start, "S");
+  assert_eq!(g.parse(["dogs", "loudly", "bark"]).len(), 1);
+  assert!(g.parse(["fido", "bark"]).is_empty());
+  assert_eq!(g.parse(["mary", "runs"]).len(), 1);
+  assert!(base.parse(["fido", "runs"]).is_empty());
+  let indices = g
+    .rules_in_order()
+    .iter()
+    .map(|r| r.index)
+    .collect::<Vec<_>>();
+  assert_eq!(indices, (0..8).collect::<Vec<_>>());
+  // the base's rules are shared, not copied
+  assert!(std::sync::Arc::ptr_eq(
+    &base.rules["S"][0],
+    &g.rules["S"][0]
+  ));
+
+  let slang: Overlay = "V[ num: sg ] -> zooms".parse().unwrap();
+  let stacked = g.with_overlay(&slang).unwrap();
+  assert_eq!(stacked.parse(["fido", "loudly", "zooms"]).len(), 1);
+
+  let broken: Overlay = "N -> Det cat".parse().unwrap();
+  assert!(base.with_overlay(&broken).is_err());
+  assert_eq!(base.rules_in_order().len(), 3);
+}
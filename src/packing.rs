@@ -0,0 +1,422 @@
+//! Ambiguity packing under restriction, the approach the LKB and PET use to
+//! keep large grammars tractable.
+//!
+//! `Forest::iter_trees` builds every tree before unifying it, so a sentence
+//! with a few ambiguous spans does exponentially much unification. A
+//! `PackedForest` instead unifies bottom-up, once per constituent, and packs
+//! the analyses of a span whose *restricted* feature structures are
+//! equivalent into one node: rules above the span can't tell them apart, so
+//! they only need to be unified with one of them. The restricted structure
+//! leaves out the constituent's children (which rules rarely look into) and
+//! whatever features the `Restrictor` names.
+//!
+//! Restricting only ever loses information, so a packed forest contains every
+//! tree that would survive unification, and maybe some that won't (if a rule
+//! looks at a restricted feature). Trees are unpacked lazily, and still need
+//! to be unified in full, which `ParseOptions::packing` does.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::featurestructure::NodeRef;
+use crate::forest::Forest;
+use crate::projection::{is_child_label, CHILD_PREFIX};
+use crate::rules::{Grammar, Rule};
+use crate::syntree::{Constituent, SynTree, Word};
+use crate::utils::Combinations;
+
+/// Which features to leave out when comparing analyses for packing, besides
+/// the `child-N` ones, which are always left out
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Restrictor {
+  /// Top-level features, like `sem` or `gap`
+  pub features: Vec<String>,
+}
+
+impl Restrictor {
+  pub fn new<I, S>(features: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    Self {
+      features: features.into_iter().map(Into::into).collect(),
+    }
+  }
+
+  fn keeps(&self, label: &str) -> bool {
+    !is_child_label(label, CHILD_PREFIX) && !self.features.iter().any(|f| f == label)
+  }
+
+  /// A copy of `fs` without the restricted features
+  pub fn restrict(&self, fs: &NodeRef) -> NodeRef {
+    let fs = fs.clone().dereference();
+    let edges = fs.borrow().edged().map(|edges| {
+      edges
+        .iter()
+        .filter(|(label, _)| self.keeps(label))
+        .map(|(l, v)| (l.clone(), v.clone()))
+        .collect::<Vec<_>>()
+    });
+    match edges {
+      // copied together, so features shared between them stay shared
+      Some(edges) => NodeRef::new_with_edges(edges).unwrap().deep_clone(),
+      None => fs.deep_clone(),
+    }
+  }
+}
+
+/// A child in an `Analysis`: a word, or a node of the forest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackedChild {
+  /// A terminal and its position in the input
+  Word(Arc<str>, usize),
+  /// The index of a node in `PackedForest::nodes`
+  Node(usize),
+}
+
+/// One way of building a node: a rule, and a choice of node for each of its
+/// children
+#[derive(Debug, Clone)]
+pub struct Analysis {
+  pub rule: Arc<Rule>,
+  pub children: Vec<PackedChild>,
+}
+
+/// A symbol over a span, with every analysis of it that has the same
+/// restricted features
+#[derive(Debug, Clone)]
+pub struct PackedNode {
+  pub symbol: String,
+  pub span: (usize, usize),
+  /// The restricted features of the first analysis, which the others are
+  /// equivalent to
+  pub features: NodeRef,
+  /// In the order they were found. Never empty.
+  pub analyses: Vec<Analysis>,
+}
+
+/// A forest with its ambiguity packed, see the module docs
+#[derive(Debug, Clone)]
+pub struct PackedForest {
+  nodes: Vec<PackedNode>,
+  roots: Vec<usize>,
+}
+
+impl PackedForest {
+  /// Unifies `forest` bottom-up, packing analyses whose features are
+  /// equivalent under `restrictor`
+  pub fn new(g: &Grammar, forest: &Forest, restrictor: &Restrictor) -> Self {
+    Self::new_as(&g.start, forest, restrictor)
+  }
+
+  /// Like `new`, packing trees of `start` instead of the grammar's start
+  /// symbol
+  pub(crate) fn new_as(start: &str, forest: &Forest, restrictor: &Restrictor) -> Self {
+    trace_span!(debug_span, "pack_forest");
+    let mut packer = Packer {
+      forest,
+      restrictor,
+      nodes: Vec::new(),
+      cells: HashMap::new(),
+    };
+    let roots = if forest.states_from(0).is_empty() {
+      Vec::new()
+    } else {
+      packer.cell(start, 0, forest.len())
+    };
+    trace_event!(debug, nodes = packer.nodes.len(), "packed forest");
+    Self {
+      nodes: packer.nodes,
+      roots,
+    }
+  }
+
+  /// Every node, children before their parents
+  pub fn nodes(&self) -> &[PackedNode] {
+    &self.nodes
+  }
+
+  /// The nodes of the start symbol over the whole input
+  pub fn roots(&self) -> &[usize] {
+    &self.roots
+  }
+
+  /// The number of analyses in the forest, which is roughly how many
+  /// unifications building it took
+  pub fn analyses(&self) -> usize {
+    self.nodes.iter().map(|n| n.analyses.len()).sum()
+  }
+
+  /// Lazily unpacks the trees of the root nodes, in the same form as
+  /// `Forest::iter_trees`, to be unified with `Grammar::unify_tree`
+  pub fn iter_trees(&self) -> impl Iterator<Item = SynTree<Arc<Rule>, Arc<str>>> + '_ {
+    self
+      .roots
+      .iter()
+      .flat_map(move |&id| self.iter_node_trees(id))
+  }
+
+  /// Lazily unpacks the trees of one node
+  pub fn iter_node_trees(
+    &self,
+    id: usize,
+  ) -> impl Iterator<Item = SynTree<Arc<Rule>, Arc<str>>> + '_ {
+    let node = &self.nodes[id];
+    node.analyses.iter().flat_map(move |analysis| {
+      let child_sets = analysis
+        .children
+        .iter()
+        .map(|child| self.child_trees(child))
+        .collect::<Vec<_>>();
+      combinations(child_sets).map(move |children| {
+        SynTree::Branch(
+          Constituent {
+            value: analysis.rule.clone(),
+            span: node.span,
+            function: None,
+          },
+          children,
+        )
+      })
+    })
+  }
+
+  fn child_trees(&self, child: &PackedChild) -> Vec<SynTree<Arc<Rule>, Arc<str>>> {
+    match child {
+      PackedChild::Word(w, pos) => vec![SynTree::Leaf(Word {
+        value: w.clone(),
+        span: (*pos, pos + 1),
+      })],
+      PackedChild::Node(id) => self.iter_node_trees(*id).collect(),
+    }
+  }
+}
+
+/// Like `Combinations`, but with one empty set for no choices, for rules
+/// with no productions
+fn combinations<T: Clone + 'static>(choices: Vec<Vec<T>>) -> Box<dyn Iterator<Item = Vec<T>>> {
+  if choices.is_empty() {
+    Box::new(std::iter::once(Vec::new()))
+  } else {
+    Box::new(Combinations::new(choices))
+  }
+}
+
+/// A production matched over part of a span, before its node is chosen
+enum Slot {
+  Word(Arc<str>, usize),
+  Cell(Arc<str>, usize, usize),
+}
+
+struct Packer<'a> {
+  forest: &'a Forest,
+  restrictor: &'a Restrictor,
+  nodes: Vec<PackedNode>,
+  /// The nodes of each symbol over each span, or None while they're being
+  /// built (so unary cycles end)
+  cells: HashMap<(String, usize, usize), Option<Vec<usize>>>,
+}
+
+impl Packer<'_> {
+  fn cell(&mut self, symbol: &str, start: usize, end: usize) -> Vec<usize> {
+    let key = (symbol.to_string(), start, end);
+    match self.cells.get(&key) {
+      Some(Some(ids)) => return ids.clone(),
+      Some(None) => return Vec::new(),
+      None => {}
+    }
+    self.cells.insert(key.clone(), None);
+
+    let rules = self
+      .forest
+      .states_from(start)
+      .iter()
+      .filter(|s| s.span().1 == end && s.rule().symbol == symbol)
+      .map(|s| s.rule().clone())
+      .collect::<Vec<_>>();
+    let mut ids: Vec<usize> = Vec::new();
+    for rule in rules {
+      for slots in self.sequences(&rule, 0, start, end) {
+        let child_sets = slots
+          .into_iter()
+          .map(|slot| match slot {
+            Slot::Word(w, pos) => vec![PackedChild::Word(w, pos)],
+            Slot::Cell(symbol, start, end) => self
+              .cell(&symbol, start, end)
+              .into_iter()
+              .map(PackedChild::Node)
+              .collect(),
+          })
+          .collect::<Vec<_>>();
+        for children in combinations(child_sets) {
+          let Some(features) = self.unify(&rule, &children) else {
+            continue;
+          };
+          let features = self.restrictor.restrict(&features);
+          let analysis = Analysis {
+            rule: rule.clone(),
+            children,
+          };
+          match ids
+            .iter()
+            .find(|&&id| NodeRef::equivalent(&self.nodes[id].features, &features))
+          {
+            Some(&id) => self.nodes[id].analyses.push(analysis),
+            None => {
+              self.nodes.push(PackedNode {
+                symbol: symbol.to_string(),
+                span: (start, end),
+                features,
+                analyses: vec![analysis],
+              });
+              ids.push(self.nodes.len() - 1);
+            }
+          }
+        }
+      }
+    }
+
+    self.cells.insert(key, Some(ids.clone()));
+    ids
+  }
+
+  /// The ways `rule`'s productions from `prod_idx` on can cover
+  /// `start..end`, like `Forest::extend_out` but by symbol instead of by
+  /// state, since nodes pack every rule for their symbol
+  fn sequences(&self, rule: &Rule, prod_idx: usize, start: usize, end: usize) -> Vec<Vec<Slot>> {
+    let Some(production) = rule.productions.get(prod_idx) else {
+      return if start == end {
+        vec![Vec::new()]
+      } else {
+        Vec::new()
+      };
+    };
+
+    if production.is_nonterminal() {
+      let mut ends = self
+        .forest
+        .states_from(start)
+        .iter()
+        .filter(|s| {
+          s.span().1 <= end
+            && *production.symbol == s.rule().symbol
+            && rule.allows_span(Some(prod_idx), s.span().1 - start)
+        })
+        .map(|s| s.span().1)
+        .collect::<Vec<_>>();
+      ends.dedup();
+      ends
+        .into_iter()
+        .flat_map(|mid| {
+          self
+            .sequences(rule, prod_idx + 1, mid, end)
+            .into_iter()
+            .map(move |mut seq| {
+              seq.insert(0, Slot::Cell(production.symbol.clone(), start, mid));
+              seq
+            })
+        })
+        .collect()
+    } else if start == end || !rule.allows_span(Some(prod_idx), 1) {
+      Vec::new()
+    } else {
+      self
+        .sequences(rule, prod_idx + 1, start + 1, end)
+        .into_iter()
+        .map(|mut seq| {
+          seq.insert(0, Slot::Word(self.forest.word(production, start), start));
+          seq
+        })
+        .collect()
+    }
+  }
+
+  /// `rule`'s features unified with its children's restricted ones, like
+  /// `Grammar::unify_tree` does with the full ones
+  fn unify(&self, rule: &Rule, children: &[PackedChild]) -> Option<NodeRef> {
+    let features = rule.features.deep_clone();
+    for (idx, child) in children.iter().enumerate() {
+      let child_features = match child {
+        PackedChild::Word(w, _) => Grammar::leaf_features(&rule.productions[idx], w, None),
+        PackedChild::Node(id) => self.nodes[*id].features.deep_clone(),
+      };
+      let to_unify =
+        NodeRef::new_with_edges(vec![(format!("{}{}", CHILD_PREFIX, idx), child_features)]).ok()?;
+      NodeRef::unify_at(features.clone(), to_unify, &mut Vec::new()).ok()?;
+    }
+    if rule.coordination {
+      Grammar::coordinate(rule, &features).ok()?;
+    }
+    Some(features)
+  }
+}
+
+#[test]
+fn test_packing() {
+  let g: Grammar = r#"
+    S -> NP[ num: #1 ] VP[ num: #1 ]
+    VP[ num: #1 ] -> V[ num: #1 ] NP
+    VP[ num: #1 ] -> VP[ num: #1 ] PP
+    NP[ num: #1 ] -> NP[ num: #1 ] PP
+    NP[ num: #1 ] -> Det N[ num: #1 ]
+    NP[ num: #1 ] -> N[ num: #1 ]
+    PP -> P NP
+    Det -> the
+    N[ num: sg ] -> i
+    N[ num: sg ] -> man
+    N[ num: sg ] -> park
+    N[ num: sg ] -> fish
+    N[ num: pl ] -> fish
+    V[ num: sg ] -> saw
+    P -> in
+    P -> with
+  "#
+  .parse()
+  .unwrap();
+
+  let input = [
+    "i", "saw", "the", "man", "in", "the", "park", "with", "the", "fish",
+  ];
+  let forest = g.parse_forest(&input);
+  let packed = PackedForest::new(&g, &forest, &Restrictor::default());
+  // the attachments all give the same S, VP and NP features, so they're
+  // packed into one root
+  assert_eq!(packed.roots().len(), 1);
+  let unpacked = packed.iter_trees().collect::<Vec<_>>();
+  assert_eq!(unpacked.len(), forest.trees(&g).len());
+
+  let sexprs = |options: &crate::ParseOptions| {
+    let mut trees = g
+      .parse_with(input, options)
+      .iter()
+      .map(|(t, _)| t.to_sexpr())
+      .collect::<Vec<_>>();
+    trees.sort();
+    trees
+  };
+  let packing = crate::ParseOptions {
+    packing: Some(Restrictor::default()),
+    ..Default::default()
+  };
+  assert_eq!(sexprs(&packing), sexprs(&Default::default()));
+
+  // "the fish" has two NPs over it, unless num is restricted away
+  let nps = |packed: &PackedForest| {
+    packed
+      .nodes()
+      .iter()
+      .filter(|n| n.symbol == "NP" && n.span == (8, 10))
+      .count()
+  };
+  assert_eq!(nps(&packed), 2);
+  let restricted = PackedForest::new(&g, &forest, &Restrictor::new(["num"]));
+  assert_eq!(nps(&restricted), 1);
+  assert!(restricted.analyses() < packed.analyses());
+  // restricting too much only adds trees that fail to unify in full
+  let restricting = crate::ParseOptions {
+    packing: Some(Restrictor::new(["num"])),
+    ..Default::default()
+  };
+  assert_eq!(sexprs(&restricting), sexprs(&Default::default()));
+}
@@ -0,0 +1,227 @@
+//! Translation between two grammars: parse with one, rewrite the parses'
+//! features with `transfer` rules, and generate from the results with the
+//! other. Each stage can also be run on its own, to compose them differently.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::featurestructure::NodeRef;
+use crate::generate::from_features;
+use crate::judge::Judgment;
+use crate::rules::Grammar;
+use crate::transfer::Transfer;
+use crate::ParsedTree;
+
+/// A source grammar, transfer rules, and a target grammar
+#[derive(Debug)]
+pub struct Pipeline {
+  pub source: Grammar,
+  pub transfer: Transfer,
+  pub target: Grammar,
+  /// How many nonterminals deep to look for trees in the target grammar, see
+  /// `generate::from_features`
+  pub max_depth: usize,
+}
+
+/// One translation of a sentence, with the structures it went through
+#[derive(Debug, Clone)]
+pub struct Translation {
+  /// The parse in the source grammar
+  pub parse: ParsedTree,
+  /// Its features after transfer
+  pub transferred: NodeRef,
+  /// The tree generated from them in the target grammar
+  pub generated: ParsedTree,
+}
+
+impl Translation {
+  /// The words of the generated sentence
+  pub fn words(&self) -> Vec<&str> {
+    self
+      .generated
+      .0
+      .yield_tokens()
+      .into_iter()
+      .map(String::as_str)
+      .collect()
+  }
+}
+
+/// The stage a translation failed at, and why
+#[derive(Debug)]
+pub enum PipelineError {
+  /// The source grammar rejected the sentence
+  Parse(Judgment),
+  /// No transfer rule matched any of the sentence's parses
+  Transfer { parses: usize },
+  /// The target grammar has no tree with the transferred features
+  Generate { transferred: Vec<NodeRef> },
+}
+
+impl fmt::Display for PipelineError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Parse(judgment) => write!(f, "couldn't parse: {}", judgment),
+      Self::Transfer { parses } => write!(
+        f,
+        "no transfer rule matched the sentence's {} parse{}",
+        parses,
+        if *parses == 1 { "" } else { "s" }
+      ),
+      Self::Generate { transferred } => {
+        write!(f, "couldn't generate from the transferred features:")?;
+        for fs in transferred {
+          write!(f, "\n{}", fs)?;
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+impl Error for PipelineError {}
+
+impl Pipeline {
+  pub fn new(source: Grammar, transfer: Transfer, target: Grammar) -> Self {
+    Self {
+      source,
+      transfer,
+      target,
+      max_depth: 10,
+    }
+  }
+
+  pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+    self.max_depth = max_depth;
+    self
+  }
+
+  /// Parses `input` with the source grammar
+  pub fn parse<I>(&self, input: I) -> Result<Vec<ParsedTree>, PipelineError>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    match self.source.judge(input) {
+      Judgment::Accept(trees) => Ok(trees),
+      judgment => Err(PipelineError::Parse(judgment)),
+    }
+  }
+
+  /// The outputs of every transfer rule that matches each parse, paired with
+  /// the parse
+  pub fn transfer(
+    &self,
+    parses: Vec<ParsedTree>,
+  ) -> Result<Vec<(ParsedTree, NodeRef)>, PipelineError> {
+    let count = parses.len();
+    let transferred = parses
+      .into_iter()
+      .flat_map(|parse| {
+        self
+          .transfer
+          .apply_all(&parse.1)
+          .into_iter()
+          .map(move |fs| (parse.clone(), fs))
+      })
+      .collect::<Vec<_>>();
+    if transferred.is_empty() {
+      Err(PipelineError::Transfer { parses: count })
+    } else {
+      Ok(transferred)
+    }
+  }
+
+  /// Generates every tree in the target grammar for each transferred
+  /// structure
+  pub fn generate(
+    &self,
+    transferred: Vec<(ParsedTree, NodeRef)>,
+  ) -> Result<Vec<Translation>, PipelineError> {
+    let mut translations = Vec::new();
+    for (parse, fs) in transferred.iter() {
+      for generated in from_features(&self.target, fs, self.max_depth) {
+        translations.push(Translation {
+          parse: parse.clone(),
+          transferred: fs.clone(),
+          generated,
+        });
+      }
+    }
+    if translations.is_empty() {
+      Err(PipelineError::Generate {
+        transferred: transferred.into_iter().map(|(_, fs)| fs).collect(),
+      })
+    } else {
+      Ok(translations)
+    }
+  }
+
+  /// Runs all three stages on `input`, returning every translation of every
+  /// parse
+  pub fn translate<I>(&self, input: I) -> Result<Vec<Translation>, PipelineError>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    let parses = self.parse(input)?;
+    let transferred = self.transfer(parses)?;
+    self.generate(transferred)
+  }
+}
+
+#[test]
+fn test_pipeline() {
+  let source: Grammar = r#"
+    S[ agent: #a, pred: #p ] -> N[ sem: #a ] V[ sem: #p ]
+    N[ sem: mary ] -> mary
+    N[ sem: cat ] -> cats
+    V[ sem: run ] -> runs
+    V[ sem: sleep ] -> sleeps
+  "#
+  .parse()
+  .unwrap();
+  // verb-initial, and without a word for cats
+  let target: Grammar = r#"
+    S[ agent: #a, pred: #p ] -> V[ sem: #p ] N[ sem: #a ]
+    N[ sem: mary ] -> mari
+    V[ sem: run ] -> kuru
+    V[ sem: sleep ] -> neru
+  "#
+  .parse()
+  .unwrap();
+  let transfer: Transfer = r#"
+    [ agent: #a, pred: run ] => [ agent: #a, pred: run ]
+  "#
+  .parse()
+  .unwrap();
+  let pipeline = Pipeline::new(source, transfer, target).with_max_depth(3);
+
+  let translations = pipeline.translate(["mary", "runs"]).unwrap();
+  assert_eq!(translations.len(), 1);
+  assert_eq!(translations[0].words(), ["kuru", "mari"]);
+
+  let e = pipeline.translate(["runs", "mary"]).unwrap_err();
+  assert!(matches!(e, PipelineError::Parse(_)));
+  assert!(e.to_string().starts_with("couldn't parse: rejected"));
+
+  let e = pipeline.translate(["mary", "sleeps"]).unwrap_err();
+  assert_eq!(
+    e.to_string(),
+    "no transfer rule matched the sentence's 1 parse"
+  );
+
+  match pipeline.translate(["cats", "runs"]).unwrap_err() {
+    PipelineError::Generate { transferred } => {
+      assert_eq!(
+        transferred[0]
+          .get_path("agent")
+          .unwrap()
+          .get_str()
+          .as_deref(),
+        Some("cat")
+      )
+    }
+    e => panic!("{}", e),
+  }
+}
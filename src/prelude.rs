@@ -0,0 +1,33 @@
+//! The types most applications need, for `use treebender::prelude::*`:
+//!
+//! ```
+//! use treebender::prelude::*;
+//!
+//! let g: Grammar = "S -> N V\nN -> mary\nV -> runs".parse()?;
+//! let trees: Vec<ParsedTree> = g.parse_str_with("mary runs", &Whitespace, &ParseOptions::default());
+//! let (tree, fs): &(SynTree<String, String>, NodeRef) = &trees[0];
+//! assert_eq!(tree.to_string(), "(0..2: S\n  (0..1: N (0..1: mary))\n  (1..2: V (1..2: runs)))");
+//! let serialized: Option<SerializedNode> = fs.into();
+//! assert!(serialized.unwrap().get_path(&["child-0"]).is_some());
+//! # Ok::<(), Err>(())
+//! ```
+//!
+//! Everything here stays importable from here as the modules behind it are
+//! reorganized, so code that only uses the prelude doesn't have to chase
+//! moved items between versions.
+
+pub use crate::featurestructure::{
+  DisplayOptions, FeatureStructureTooLarge, NodeRef, SerializedNode, UnificationFailure,
+};
+pub use crate::forest::ForestError;
+pub use crate::judge::SyntaxError;
+pub use crate::lint::GrammarWarnings;
+pub use crate::observer::ParseObserver;
+pub use crate::options::{Cancelled, GrammarOptions, ParseOptions};
+pub use crate::output::{ParseError, ParseOutput};
+pub use crate::packing::Restrictor;
+pub use crate::rules::{Grammar, GrammarError, GrammarMetadata};
+pub use crate::syntree::{Constituent, SynTree, Word};
+pub use crate::tokenize::{Tokenizer, UnicodeWords, Whitespace};
+pub use crate::utils::Err;
+pub use crate::ParsedTree;
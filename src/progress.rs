@@ -0,0 +1,69 @@
+//! Progress reporting for parsing many sentences, see `Grammar::parse_all`.
+
+use std::fmt;
+use std::time::Duration;
+
+/// How far through a batch of sentences parsing is
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+  /// Sentences parsed so far
+  pub done: usize,
+  pub total: usize,
+  /// Time since the batch started
+  pub elapsed: Duration,
+}
+
+impl Progress {
+  /// Sentences parsed per second so far
+  pub fn per_second(&self) -> f64 {
+    let secs = self.elapsed.as_secs_f64();
+    if secs > 0.0 {
+      self.done as f64 / secs
+    } else {
+      0.0
+    }
+  }
+
+  /// Estimated time left, assuming the rest parse at the same rate. None until
+  /// at least one sentence is done.
+  pub fn eta(&self) -> Option<Duration> {
+    if self.done == 0 {
+      return None;
+    }
+    let left = self.total.saturating_sub(self.done) as u32;
+    Some(self.elapsed / self.done as u32 * left)
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.done >= self.total
+  }
+}
+
+impl fmt::Display for Progress {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{}/{} sentences, {:.1}/s",
+      self.done,
+      self.total,
+      self.per_second()
+    )?;
+    if let Some(eta) = self.eta() {
+      write!(f, ", ETA {}s", eta.as_secs())?;
+    }
+    Ok(())
+  }
+}
+
+#[test]
+fn test_progress() {
+  let p = Progress {
+    done: 10,
+    total: 40,
+    elapsed: Duration::from_secs(2),
+  };
+  assert_eq!(p.per_second(), 5.0);
+  assert_eq!(p.eta(), Some(Duration::from_secs(6)));
+  assert_eq!(p.to_string(), "10/40 sentences, 5.0/s, ETA 6s");
+  assert!(!p.is_finished());
+}
@@ -0,0 +1,208 @@
+//! Views of parse results that don't depend on the `child-N` features the
+//! parser uses to build them.
+//!
+//! A parse's feature structure nests each constituent's features under its
+//! position in its parent (`child-0.child-1.num`), so reading a node's
+//! features means knowing where it is in the tree. `Grammar::project` instead
+//! pairs every constituent with just its own features, and
+//! `Grammar::child_prefix` renames the `child-` features in parse results for
+//! consumers that expect something else.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::featurestructure::NodeRef;
+use crate::rules::Grammar;
+use crate::syntree::{Constituent, SynTree};
+use crate::ParsedTree;
+
+/// The prefix of the features the parser puts a rule's children under, and
+/// the default for `Grammar::child_prefix`
+pub const CHILD_PREFIX: &str = "child-";
+
+/// A constituent with its own features, without its children's
+#[derive(Debug, Clone)]
+pub struct Projected {
+  pub symbol: String,
+  pub features: NodeRef,
+}
+
+impl fmt::Display for Projected {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.symbol)
+  }
+}
+
+impl Grammar {
+  /// The tree of a parse, with each constituent's own features (like `num`
+  /// and `case`) instead of the whole structure at the root. The features are
+  /// copies, so changing them doesn't change the parse.
+  pub fn project(&self, parsed: &ParsedTree) -> SynTree<Projected, String> {
+    let (tree, fs) = parsed;
+    project_node(tree, fs, &self.child_prefix)
+  }
+}
+
+fn project_node(
+  tree: &SynTree<String, String>,
+  fs: &NodeRef,
+  prefix: &str,
+) -> SynTree<Projected, String> {
+  match tree {
+    SynTree::Leaf(w) => SynTree::Leaf(w.clone()),
+    SynTree::Branch(c, children) => {
+      let fs = fs.clone().dereference();
+      let children = children
+        .iter()
+        .enumerate()
+        .map(|(idx, child)| {
+          let child_fs = fs
+            .get_path(&format!("{}{}", prefix, idx))
+            .unwrap_or_else(NodeRef::new_top);
+          project_node(child, &child_fs, prefix)
+        })
+        .collect();
+      SynTree::Branch(
+        Constituent {
+          value: Projected {
+            symbol: c.value.clone(),
+            features: own_features(&fs, prefix),
+          },
+          span: c.span,
+          function: c.function.clone(),
+        },
+        children,
+      )
+    }
+  }
+}
+
+pub(crate) fn is_child_label(label: &str, prefix: &str) -> bool {
+  label
+    .strip_prefix(prefix)
+    .is_some_and(|idx| !idx.is_empty() && idx.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// A copy of `fs` without its child features
+fn own_features(fs: &NodeRef, prefix: &str) -> NodeRef {
+  let edges = fs.borrow().edged().map(|edges| {
+    edges
+      .iter()
+      .filter(|(label, _)| !is_child_label(label, prefix))
+      .map(|(l, v)| (l.clone(), v.clone()))
+      .collect::<Vec<_>>()
+  });
+  match edges {
+    // copied together, so features shared between them stay shared
+    Some(edges) => NodeRef::new_with_edges(edges).unwrap().deep_clone(),
+    None => fs.deep_clone(),
+  }
+}
+
+/// A copy of `fs` with every `child-N` feature renamed to `{prefix}N`
+pub(crate) fn rename_children(fs: &NodeRef, prefix: &str) -> NodeRef {
+  rename_children_in(fs, prefix, &mut HashMap::new())
+}
+
+fn rename_children_in(
+  fs: &NodeRef,
+  prefix: &str,
+  copies: &mut HashMap<NodeRef, NodeRef>,
+) -> NodeRef {
+  let fs = fs.clone().dereference();
+  if let Some(copy) = copies.get(&fs) {
+    return copy.clone();
+  }
+
+  let edges = fs.borrow().edged().map(|edges| {
+    edges
+      .iter()
+      .map(|(l, v)| (l.clone(), v.clone()))
+      .collect::<Vec<_>>()
+  });
+  let copy = match edges {
+    Some(edges) => NodeRef::new_with_edges(edges.into_iter().map(|(label, value)| {
+      let label = match label.strip_prefix(CHILD_PREFIX) {
+        Some(idx) if is_child_label(&label, CHILD_PREFIX) => format!("{}{}", prefix, idx),
+        _ => label,
+      };
+      (label, rename_children_in(&value, prefix, copies))
+    }))
+    .unwrap(),
+    None => fs.deep_clone(),
+  };
+  copies.insert(fs, copy.clone());
+  copy
+}
+
+#[test]
+fn test_project() {
+  let src = r#"
+    S -> N[ num: #1 ] V[ num: #1 ]
+    N[ num: sg, case: nom ] -> mary
+    V[ num: sg ] -> runs
+  "#;
+  let g: Grammar = src.parse().unwrap();
+  let parsed = g.parse_one(&["mary", "runs"]).unwrap();
+  let projected = g.project(&parsed);
+
+  let (s, children) = projected.get_branch().unwrap();
+  assert_eq!(s.value.symbol, "S");
+  assert!(s.value.features.get_path("child-0").is_none());
+  let (n, _) = children[0].get_branch().unwrap();
+  assert_eq!(n.value.symbol, "N");
+  assert_eq!(
+    n.value
+      .features
+      .get_path("num")
+      .unwrap()
+      .get_str()
+      .as_deref(),
+    Some("sg")
+  );
+  assert_eq!(
+    n.value
+      .features
+      .get_path("case")
+      .unwrap()
+      .get_str()
+      .as_deref(),
+    Some("nom")
+  );
+  assert!(n.value.features.get_path("child-0").is_none());
+  assert_eq!(projected.to_sexpr(), "(S (N mary) (V runs))");
+
+  let mut g: Grammar = src.parse().unwrap();
+  g.child_prefix = "dtr".to_string();
+  let parsed = g.parse_one(&["mary", "runs"]).unwrap();
+  assert!(parsed.1.get_path("child-0").is_none());
+  assert_eq!(
+    parsed.1.get_path("dtr1.num").unwrap().get_str().as_deref(),
+    Some("sg")
+  );
+  assert_eq!(
+    parsed
+      .1
+      .get_path("dtr0.dtr0.word")
+      .unwrap()
+      .get_str()
+      .as_deref(),
+    Some("mary")
+  );
+  // agreement is still shared after renaming
+  assert!(
+    parsed.1.get_path("dtr0.num").unwrap().dereference()
+      == parsed.1.get_path("dtr1.num").unwrap().dereference()
+  );
+  let (_, children) = g.project(&parsed).into_branch().unwrap();
+  let (v, _) = children[1].get_branch().unwrap();
+  assert_eq!(
+    v.value
+      .features
+      .get_path("num")
+      .unwrap()
+      .get_str()
+      .as_deref(),
+    Some("sg")
+  );
+}
@@ -0,0 +1,402 @@
+//! Punctuation that attaches loosely to constituents, so grammars don't need a
+//! rule for every place a comma can go.
+//!
+//! When `ParseOptions::punctuation` is set, punctuation marks are taken out of
+//! the input before parsing, and put back into each tree afterwards as leaves
+//! of the constituent the specification says they belong to. They aren't in
+//! the feature structure, so a constituent's `child-N` features only count its
+//! other children.
+//!
+//! A specification has one mark per line, with `//` comments:
+//!
+//! ```text
+//! , left      // the biggest constituent that ends just before it
+//! ¿ right     // the biggest constituent that starts just after it
+//! . root      // the whole sentence
+//! ( pair )    // the constituent between it and the closing mark, if there
+//!             // is one, or else the smallest one that contains both marks
+//! ```
+//!
+//! A mark never splits a constituent: if one goes around where the mark would
+//! be added, the mark goes inside it instead.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::syntree::{Constituent, SynTree, Word};
+use crate::utils::Err;
+
+const DEFAULT_SPEC: &str = r#"
+, left
+; left
+: left
+. root
+! root
+? root
+… root
+¿ right
+¡ right
+( pair )
+[ pair ]
+" pair "
+“ pair ”
+‘ pair ’
+"#;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Attach {
+  Left,
+  Right,
+  Root,
+  /// Opens a pair closed by the given mark, which can be the same one
+  Pair(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Punctuation {
+  pub marks: HashMap<String, Attach>,
+}
+
+impl Default for Punctuation {
+  /// Common English punctuation, see `DEFAULT_SPEC`
+  fn default() -> Self {
+    DEFAULT_SPEC.parse().unwrap()
+  }
+}
+
+impl FromStr for Punctuation {
+  type Err = Err;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut marks = HashMap::new();
+    for line in s.lines() {
+      let line = match line.find("//") {
+        Some(idx) => &line[..idx],
+        None => line,
+      };
+      let fields = line.split_whitespace().collect::<Vec<_>>();
+      let attach = match fields.as_slice() {
+        [] => continue,
+        [_, "left"] => Attach::Left,
+        [_, "right"] => Attach::Right,
+        [_, "root"] => Attach::Root,
+        [_, "pair", close] => Attach::Pair(close.to_string()),
+        _ => return Err(format!("bad punctuation: {}", line.trim()).into()),
+      };
+      marks.insert(fields[0].to_string(), attach);
+    }
+    Ok(Self { marks })
+  }
+}
+
+/// Where a mark was decided to go, for pairs once they're matched up
+#[derive(Debug, Clone, Copy)]
+enum Place {
+  Left,
+  Right,
+  Root,
+  /// Either mark of a pair, with both positions
+  Pair(usize, usize),
+}
+
+impl Punctuation {
+  /// The marks that close pairs
+  fn is_close(&self, token: &str) -> bool {
+    self
+      .marks
+      .values()
+      .any(|a| matches!(a, Attach::Pair(close) if close == token))
+  }
+
+  pub fn is_mark(&self, token: &str) -> bool {
+    self.marks.contains_key(token) || self.is_close(token)
+  }
+
+  /// Splits tokens into the words to parse, and the marks with their positions
+  pub fn split<'a>(&self, tokens: &[&'a str]) -> (Vec<&'a str>, Vec<(usize, &'a str)>) {
+    let mut words = Vec::new();
+    let mut marks = Vec::new();
+    for (idx, &token) in tokens.iter().enumerate() {
+      if self.is_mark(token) {
+        marks.push((idx, token));
+      } else {
+        words.push(token);
+      }
+    }
+    (words, marks)
+  }
+
+  /// Matches up pairs, and decides where every mark goes
+  fn places(&self, marks: &[(usize, &str)]) -> Vec<Place> {
+    let mut places = vec![Place::Root; marks.len()];
+    // (index in marks, the mark that closes it)
+    let mut open: Vec<(usize, &str)> = Vec::new();
+    for (idx, &(pos, mark)) in marks.iter().enumerate() {
+      // a mark that closes the innermost open pair, even if it could open one
+      if let Some(&(open_idx, close)) = open.last() {
+        if close == mark {
+          open.pop();
+          let open_pos = marks[open_idx].0;
+          places[open_idx] = Place::Pair(open_pos, pos);
+          places[idx] = Place::Pair(open_pos, pos);
+          continue;
+        }
+      }
+      places[idx] = match self.marks.get(mark) {
+        Some(Attach::Left) => Place::Left,
+        Some(Attach::Right) => Place::Right,
+        Some(Attach::Pair(close)) => {
+          open.push((idx, close));
+          // if it's never closed, it goes with what follows it
+          Place::Right
+        }
+        // a closing mark that was never opened
+        Some(Attach::Root) | None => {
+          if self.is_close(mark) {
+            Place::Left
+          } else {
+            Place::Root
+          }
+        }
+      };
+    }
+    places
+  }
+
+  /// Puts marks taken out by `split` back into a tree parsed from the words,
+  /// renumbering its spans to count them
+  pub fn attach(
+    &self,
+    tree: SynTree<String, String>,
+    marks: &[(usize, &str)],
+  ) -> SynTree<String, String> {
+    if marks.is_empty() {
+      return tree;
+    }
+
+    let word_count = tree.get_branch().map_or(0, |(c, _)| c.span.1);
+    let mut word_pos = Vec::with_capacity(word_count);
+    let mut mark_iter = marks.iter().peekable();
+    let mut pos = 0;
+    while word_pos.len() < word_count {
+      if mark_iter.peek().is_some_and(|(p, _)| *p == pos) {
+        mark_iter.next();
+      } else {
+        word_pos.push(pos);
+      }
+      pos += 1;
+    }
+    let mut tree = tree;
+    remap(&mut tree, &word_pos);
+
+    let places = self.places(marks);
+    // left and root marks (and pairs) are attached left to right, so a mark
+    // can attach to a constituent that's been extended by the one before it,
+    // then right marks right to left, for the same reason
+    let order = (0..marks.len())
+      .filter(|&idx| !matches!(places[idx], Place::Right))
+      .chain(
+        (0..marks.len())
+          .rev()
+          .filter(|&idx| matches!(places[idx], Place::Right)),
+      )
+      .collect::<Vec<_>>();
+
+    for idx in order {
+      let (pos, mark) = marks[idx];
+      let target = match places[idx] {
+        Place::Left => find_highest(&tree, &|c| c.span.1 == pos && c.span.0 < pos),
+        Place::Right => find_highest(&tree, &|c| c.span.0 == pos + 1 && c.span.1 > pos + 1),
+        Place::Root => None,
+        // the closing mark was attached with the opening one
+        Place::Pair(_, close) if close == pos => continue,
+        Place::Pair(open, close) => {
+          let target = find_highest(&tree, &|c| c.span.0 == open + 1 && c.span.1 == close)
+            .or_else(|| find_lowest(&tree, &|c| c.span.0 <= open && c.span.1 > close))
+            .unwrap_or_default();
+          let (_, close_mark) = marks.iter().find(|(p, _)| *p == close).unwrap();
+          let node = at_path(&mut tree, &target);
+          insert(node, open, mark);
+          insert(node, close, close_mark);
+          continue;
+        }
+      };
+      insert(at_path(&mut tree, &target.unwrap_or_default()), pos, mark);
+    }
+    tree
+  }
+}
+
+/// Changes word indexes in spans to positions in the input with marks
+fn remap(tree: &mut SynTree<String, String>, word_pos: &[usize]) {
+  let start = |s: usize| {
+    word_pos
+      .get(s)
+      .copied()
+      .unwrap_or_else(|| word_pos.last().map_or(0, |p| p + 1))
+  };
+  match tree {
+    SynTree::Leaf(Word { span, .. }) => *span = (word_pos[span.0], word_pos[span.0] + 1),
+    SynTree::Branch(Constituent { span, .. }, children) => {
+      *span = if span.0 == span.1 {
+        (start(span.0), start(span.0))
+      } else {
+        (word_pos[span.0], word_pos[span.1 - 1] + 1)
+      };
+      for child in children.iter_mut() {
+        remap(child, word_pos);
+      }
+    }
+  }
+}
+
+/// The path (child indexes) to the first constituent matching `pred` in
+/// pre-order, which is the highest one
+fn find_highest(
+  tree: &SynTree<String, String>,
+  pred: &impl Fn(&Constituent<String>) -> bool,
+) -> Option<Vec<usize>> {
+  let (cons, children) = tree.get_branch()?;
+  if pred(cons) {
+    return Some(Vec::new());
+  }
+  children.iter().enumerate().find_map(|(idx, child)| {
+    let mut path = find_highest(child, pred)?;
+    path.insert(0, idx);
+    Some(path)
+  })
+}
+
+/// The path to the lowest constituent matching `pred`, where `pred` holds for
+/// the parents of anything it holds for
+fn find_lowest(
+  tree: &SynTree<String, String>,
+  pred: &impl Fn(&Constituent<String>) -> bool,
+) -> Option<Vec<usize>> {
+  let (cons, children) = tree.get_branch()?;
+  if !pred(cons) {
+    return None;
+  }
+  children
+    .iter()
+    .enumerate()
+    .find_map(|(idx, child)| {
+      let mut path = find_lowest(child, pred)?;
+      path.insert(0, idx);
+      Some(path)
+    })
+    .or(Some(Vec::new()))
+}
+
+fn at_path<'a>(
+  tree: &'a mut SynTree<String, String>,
+  path: &[usize],
+) -> &'a mut SynTree<String, String> {
+  match (path.split_first(), tree) {
+    (Some((&idx, rest)), SynTree::Branch(_, children)) => at_path(&mut children[idx], rest),
+    (_, tree) => tree,
+  }
+}
+
+/// Adds a mark as a leaf of `tree` in order, growing its span to cover it. If
+/// a child's span goes around the mark, it goes in that child instead, so spans
+/// stay nested: the marks of a pair that straddles constituents, like in
+/// "mary ( fell and sue ) fell", end up in different ones.
+fn insert(tree: &mut SynTree<String, String>, pos: usize, mark: &str) {
+  if let SynTree::Branch(cons, children) = tree {
+    let around = children.iter().position(|c| {
+      let (start, end) = span_of(c);
+      c.is_branch() && start < pos && pos < end
+    });
+    if let Some(idx) = around {
+      insert(&mut children[idx], pos, mark);
+      return;
+    }
+
+    let idx = children
+      .iter()
+      .position(|c| span_of(c).0 > pos)
+      .unwrap_or(children.len());
+    children.insert(
+      idx,
+      SynTree::Leaf(Word {
+        value: mark.to_string(),
+        span: (pos, pos + 1),
+      }),
+    );
+    cons.span = (cons.span.0.min(pos), cons.span.1.max(pos + 1));
+  }
+}
+
+fn span_of<T, U>(tree: &SynTree<T, U>) -> (usize, usize) {
+  match tree {
+    SynTree::Leaf(w) => w.span,
+    SynTree::Branch(c, _) => c.span,
+  }
+}
+
+#[test]
+fn test_punctuation() {
+  use crate::{Grammar, ParseOptions};
+  use std::sync::Arc;
+
+  let g: Grammar = r#"
+    S -> NP VP
+    S -> S and S
+    NP -> mary
+    NP -> sue
+    VP -> fell
+    VP -> said S
+  "#
+  .parse()
+  .unwrap();
+  let options = ParseOptions {
+    punctuation: Some(Arc::new(Punctuation::default())),
+    ..ParseOptions::default()
+  };
+  let parse = |s: &str| {
+    g.parse_with(s.split(' '), &options)
+      .into_iter()
+      .map(|(t, _)| t.to_sexpr())
+      .collect::<Vec<_>>()
+  };
+
+  assert_eq!(parse("mary fell ."), ["(S (NP mary) (VP fell) .)"]);
+  assert_eq!(
+    parse("mary fell , and sue fell !"),
+    ["(S (S (NP mary) (VP fell) ,) and (S (NP sue) (VP fell)) !)"]
+  );
+  assert_eq!(
+    parse("sue said “ mary fell ” ."),
+    ["(S (NP sue) (VP said (S “ (NP mary) (VP fell) ”)) .)"]
+  );
+  // the pair doesn't enclose a constituent, so it goes in the smallest one
+  // around it
+  assert_eq!(
+    parse("( mary fell and sue fell )"),
+    [r#"(S "(" (S (NP mary) (VP fell)) and (S (NP sue) (VP fell)) ")")"#]
+  );
+  // the pair straddles constituents, so each mark goes inside the one it
+  // falls in
+  assert_eq!(
+    parse("mary ( fell and sue ) fell"),
+    [r#"(S (S (NP mary) "(" (VP fell)) and (S (NP sue) ")" (VP fell)))"#]
+  );
+  let tree = &g.parse_with("mary ( fell and sue ) fell".split(' '), &options)[0].0;
+  assert_eq!(
+    tree.to_sexpr_with_spans(),
+    r#"(S@0..7 (S@0..3 (NP@0..1 mary@0..1) "("@1..2 (VP@2..3 fell@2..3)) and@3..4 (S@4..7 (NP@4..5 sue@4..5) ")"@5..6 (VP@6..7 fell@6..7)))"#
+  );
+  assert_eq!(parse("¿ mary fell ?"), ["(S ¿ (NP mary) (VP fell) ?)"]);
+  assert_eq!(parse("mary fell ) ,"), [r#"(S (NP mary) (VP fell) ")" ,)"#]);
+
+  let tree = &g.parse_with(["mary", ",", "fell"], &options)[0].0;
+  assert_eq!(
+    tree.to_sexpr_with_spans(),
+    "(S@0..3 (NP@0..2 mary@0..1 ,@1..2) (VP@2..3 fell@2..3))"
+  );
+
+  let spec: Punctuation = "// just commas\n, left".parse().unwrap();
+  assert_eq!(spec.marks.len(), 1);
+  assert!("- sideways".parse::<Punctuation>().is_err());
+}
@@ -0,0 +1,181 @@
+//! A small pattern language for picking parses by their features, like
+//! `child-1.tense = past & child-0.num = #x & child-2.num = #x`.
+//!
+//! A query is one or more conditions joined by `&`, and any number of those
+//! joined by `|`, where `&` binds tighter. The conditions are:
+//!
+//! - `path = value`: the feature at `path` is the string `value`
+//! - `path != value`: it isn't, or there's no feature at `path`
+//! - `path = #name`: every path with the same `#name` (in the same `|` branch)
+//!   has the same value, which can be a whole feature structure
+//! - `path`: there's some feature at `path`
+
+use std::str::FromStr;
+
+use crate::featurestructure::NodeRef;
+use crate::utils::Err;
+use crate::ParsedTree;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+  Equals(String, String),
+  NotEquals(String, String),
+  Var(String, String),
+  Exists(String),
+}
+
+impl Condition {
+  fn path(&self) -> &str {
+    match self {
+      Self::Equals(path, _)
+      | Self::NotEquals(path, _)
+      | Self::Var(path, _)
+      | Self::Exists(path) => path,
+    }
+  }
+}
+
+impl FromStr for Condition {
+  type Err = Err;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (path, value, negated) = if let Some((path, value)) = s.split_once("!=") {
+      (path, Some(value), true)
+    } else if let Some((path, value)) = s.split_once('=') {
+      (path, Some(value), false)
+    } else {
+      (s, None, false)
+    };
+
+    let path = path.trim();
+    let valid = |p: &str| {
+      !p.is_empty()
+        && p.split('.').all(|l| {
+          !l.is_empty()
+            && l
+              .chars()
+              .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        })
+    };
+    if !valid(path) {
+      return Err(format!("bad path in query: {:?}", s.trim()).into());
+    }
+
+    match value.map(str::trim) {
+      None => Ok(Self::Exists(path.to_string())),
+      Some("") => Err(format!("missing value in query: {:?}", s.trim()).into()),
+      Some(value) if value.contains(char::is_whitespace) => {
+        Err(format!("bad value in query: {:?}", s.trim()).into())
+      }
+      Some(value) => match value.strip_prefix('#') {
+        Some(_) if negated => Err(format!("can't negate a variable: {:?}", s.trim()).into()),
+        Some("") => Err(format!("missing variable name in query: {:?}", s.trim()).into()),
+        Some(var) => Ok(Self::Var(path.to_string(), var.to_string())),
+        None if negated => Ok(Self::NotEquals(path.to_string(), value.to_string())),
+        None => Ok(Self::Equals(path.to_string(), value.to_string())),
+      },
+    }
+  }
+}
+
+/// A parsed query, see the module docs for the syntax
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+  /// Alternatives (joined by `|`), each a list of conditions that all have to
+  /// hold
+  pub alternatives: Vec<Vec<Condition>>,
+}
+
+impl FromStr for Query {
+  type Err = Err;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let alternatives = s
+      .split('|')
+      .map(|alt| alt.split('&').map(str::parse).collect())
+      .collect::<Result<Vec<Vec<_>>, _>>()?;
+    Ok(Self { alternatives })
+  }
+}
+
+impl Query {
+  pub fn matches(&self, fs: &NodeRef) -> bool {
+    self
+      .alternatives
+      .iter()
+      .any(|conditions| Self::all_hold(conditions, fs))
+  }
+
+  fn all_hold(conditions: &[Condition], fs: &NodeRef) -> bool {
+    let mut vars: Vec<(&str, NodeRef)> = Vec::new();
+    conditions.iter().all(|cond| {
+      let found = fs.get_path(cond.path());
+      let value = found.as_ref().and_then(NodeRef::get_str);
+      match cond {
+        Condition::Equals(_, v) => value.is_some_and(|s| &*s == v),
+        Condition::NotEquals(_, v) => value.is_none_or(|s| &*s != v),
+        Condition::Exists(_) => found.is_some(),
+        Condition::Var(_, var) => {
+          let Some(found) = found else { return false };
+          match vars.iter().find(|(name, _)| name == var) {
+            Some((_, bound)) => *bound == found || NodeRef::equivalent(bound, &found),
+            None => {
+              vars.push((var, found));
+              true
+            }
+          }
+        }
+      }
+    })
+  }
+
+  /// The parses that match, in order
+  pub fn select(&self, trees: Vec<ParsedTree>) -> Vec<ParsedTree> {
+    trees.into_iter().filter(|t| self.matches(&t.1)).collect()
+  }
+
+  /// The indexes of the parses that match
+  pub fn positions(&self, trees: &[ParsedTree]) -> Vec<usize> {
+    trees
+      .iter()
+      .enumerate()
+      .filter(|(_, t)| self.matches(&t.1))
+      .map(|(idx, _)| idx)
+      .collect()
+  }
+}
+
+#[test]
+fn test_query() {
+  use crate::Grammar;
+
+  let g: Grammar = r#"
+    S -> N[ num: #1 ] V[ num: #1 ] N
+    N[ num: sg ] -> fish
+    N[ num: pl ] -> fish
+    V[ num: pl, tense: nonpast ] -> eat
+  "#
+  .parse()
+  .unwrap();
+  let trees = g.parse(&["fish", "eat", "fish"]);
+  assert_eq!(trees.len(), 2);
+
+  let count = |q: &str| q.parse::<Query>().unwrap().select(trees.clone()).len();
+  assert_eq!(count("child-1.tense = nonpast"), 2);
+  assert_eq!(count("child-0.num = #x & child-1.num = #x"), 2);
+  assert_eq!(count("child-0.num = #x & child-2.num = #x"), 1);
+  assert_eq!(count("child-2.num = sg"), 1);
+  assert_eq!(count("child-2.num != sg & child-1.tense"), 1);
+  assert_eq!(count("child-1.tense = past | child-2.num = pl"), 1);
+  assert_eq!(count("child-1.mood"), 0);
+  assert_eq!(count("child-1.mood != x"), 2);
+
+  let query: Query = "child-2.num=pl".parse().unwrap();
+  assert_eq!(query.positions(&trees).len(), 1);
+
+  for bad in [
+    "", "a = ", "a b = c", "a..b = c", "a != #x", "a = #", "a = b c",
+  ] {
+    assert!(bad.parse::<Query>().is_err(), "{:?}", bad);
+  }
+}
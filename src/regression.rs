@@ -0,0 +1,232 @@
+//! Differential testing: parsing the same sentences with two versions of a
+//! grammar, and reporting the sentences whose parse counts, bracketings, or
+//! chosen features changed, for CI to fail on or render.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use serde_json::{json, Value};
+
+use crate::rules::Grammar;
+use crate::ParsedTree;
+
+/// A feature whose values (over all the parses) changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeatureChange {
+  pub path: String,
+  /// Sorted and deduplicated. Strings as they are, complex values in their
+  /// canonical form (see `NodeRef::normalize`), and parses without the
+  /// feature (or with **top**) left out.
+  pub old: Vec<String>,
+  pub new: Vec<String>,
+}
+
+/// How one sentence's parses changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentenceDiff {
+  pub tokens: Vec<String>,
+  pub old_parses: usize,
+  pub new_parses: usize,
+  /// Bracketings (see `SynTree::to_sexpr`) only the old grammar found
+  pub lost: Vec<String>,
+  /// Bracketings only the new grammar found
+  pub gained: Vec<String>,
+  pub features: Vec<FeatureChange>,
+}
+
+impl SentenceDiff {
+  pub fn to_json(&self) -> Value {
+    json!({
+      "tokens": self.tokens,
+      "old_parses": self.old_parses,
+      "new_parses": self.new_parses,
+      "lost": self.lost,
+      "gained": self.gained,
+      "features": self.features.iter().map(|f| json!({
+        "path": f.path,
+        "old": f.old,
+        "new": f.new,
+      })).collect::<Vec<_>>(),
+    })
+  }
+}
+
+impl fmt::Display for SentenceDiff {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{}: {} -> {} parses",
+      self.tokens.join(" "),
+      self.old_parses,
+      self.new_parses
+    )?;
+    for tree in self.lost.iter() {
+      write!(f, "\n  - {}", tree)?;
+    }
+    for tree in self.gained.iter() {
+      write!(f, "\n  + {}", tree)?;
+    }
+    for change in self.features.iter() {
+      write!(
+        f,
+        "\n  {}: {{{}}} -> {{{}}}",
+        change.path,
+        change.old.join(", "),
+        change.new.join(", ")
+      )?;
+    }
+    Ok(())
+  }
+}
+
+/// The sentences that changed, out of how many were compared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegressionReport {
+  pub sentences: usize,
+  pub changed: Vec<SentenceDiff>,
+}
+
+impl RegressionReport {
+  pub fn is_empty(&self) -> bool {
+    self.changed.is_empty()
+  }
+
+  pub fn to_json(&self) -> Value {
+    json!({
+      "sentences": self.sentences,
+      "changed": self.changed.iter().map(SentenceDiff::to_json).collect::<Vec<_>>(),
+    })
+  }
+}
+
+impl fmt::Display for RegressionReport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{} of {} sentence{} changed",
+      self.changed.len(),
+      self.sentences,
+      if self.sentences == 1 { "" } else { "s" }
+    )?;
+    for diff in self.changed.iter() {
+      write!(f, "\n{}", diff)?;
+    }
+    Ok(())
+  }
+}
+
+/// Parses each sentence with `old` and `new`, and reports the ones where the
+/// number of parses, the set of bracketings, or the values of any of
+/// `features` (paths in the parses' features) differ
+pub fn compare<S, T>(
+  old: &Grammar,
+  new: &Grammar,
+  sentences: &[S],
+  features: &[&str],
+) -> RegressionReport
+where
+  S: AsRef<[T]>,
+  T: AsRef<str>,
+{
+  let mut changed = Vec::new();
+  for sentence in sentences {
+    let tokens = sentence
+      .as_ref()
+      .iter()
+      .map(|t| t.as_ref().to_string())
+      .collect::<Vec<_>>();
+    let (old_trees, new_trees) = (old.parse(&tokens), new.parse(&tokens));
+    let (old_brackets, new_brackets) = (bracketings(&old_trees), bracketings(&new_trees));
+    let changes = features
+      .iter()
+      .filter_map(|path| {
+        let (old, new) = (values(&old_trees, path), values(&new_trees, path));
+        (old != new).then(|| FeatureChange {
+          path: path.to_string(),
+          old,
+          new,
+        })
+      })
+      .collect::<Vec<_>>();
+
+    let diff = SentenceDiff {
+      tokens,
+      old_parses: old_trees.len(),
+      new_parses: new_trees.len(),
+      lost: old_brackets.difference(&new_brackets).cloned().collect(),
+      gained: new_brackets.difference(&old_brackets).cloned().collect(),
+      features: changes,
+    };
+    if diff.old_parses != diff.new_parses
+      || !diff.lost.is_empty()
+      || !diff.gained.is_empty()
+      || !diff.features.is_empty()
+    {
+      changed.push(diff);
+    }
+  }
+  RegressionReport {
+    sentences: sentences.len(),
+    changed,
+  }
+}
+
+fn bracketings(trees: &[ParsedTree]) -> BTreeSet<String> {
+  trees.iter().map(|(tree, _)| tree.to_sexpr()).collect()
+}
+
+fn values(trees: &[ParsedTree], path: &str) -> Vec<String> {
+  trees
+    .iter()
+    .filter_map(|(_, fs)| fs.get_path(path))
+    .filter(|value| !value.is_top())
+    .map(|value| match value.get_str() {
+      Some(s) => s.to_string(),
+      None => value.normalize().canonical,
+    })
+    .collect::<BTreeSet<_>>()
+    .into_iter()
+    .collect()
+}
+
+#[test]
+fn test_regression() {
+  let old: Grammar = r#"
+    S[ tense: #t ] -> N V[ tense: #t ]
+    N -> mary
+    N -> fish
+    V[ tense: pres ] -> runs
+    V[ tense: past ] -> ran
+  "#
+  .parse()
+  .unwrap();
+  let new: Grammar = r#"
+    S[ tense: #t ] -> N V[ tense: #t ]
+    N -> mary
+    N -> fish
+    V -> fish
+    V[ tense: pres ] -> runs
+    V[ tense: pres ] -> ran
+  "#
+  .parse()
+  .unwrap();
+
+  let sentences = [
+    vec!["mary", "runs"],
+    vec!["mary", "ran"],
+    vec!["mary", "fish"],
+  ];
+  let report = compare(&old, &new, &sentences, &["tense"]);
+  assert_eq!(report.sentences, 3);
+  assert_eq!(
+    report.to_string(),
+    r#"2 of 3 sentences changed
+mary ran: 1 -> 1 parses
+  tense: {past} -> {pres}
+mary fish: 0 -> 1 parses
+  + (S (N mary) (V fish))"#
+  );
+  assert_eq!(report.to_json()["changed"][1]["new_parses"], 1);
+
+  assert!(compare(&old, &old, &sentences, &["tense"]).is_empty());
+}
@@ -0,0 +1,266 @@
+//! The JSON format for parse results, shared by the CLI's `--jsonl`, the
+//! server's `parse` method, and `ParseReport::to_json`, so programs reading
+//! them only have one format to follow. Version 1 is:
+//!
+//! ```text
+//! {
+//!   "version": 1,
+//!   "tokens": ["mary", "runs"],
+//!   "parses": 1,                  // how many trees survived unification
+//!   "trees": [{                   // may be fewer than "parses", if limited
+//!     "tree": "(S (N mary) (V runs))",
+//!     "structure": { "label", "span", "children", "function"? }, // SynTree::to_json
+//!     "features": { ... },        // NodeRef::to_json
+//!     "reentrancies": [["child-0.num", "child-1.num"], ...]
+//!   }],
+//!   "diagnostics": [...],         // why the sentence was rejected, if it was
+//!   "ms": 0.25                    // time taken to parse
+//! }
+//! ```
+//!
+//! `reentrancies` lists the paths that lead to the same node, for each node
+//! that more than one path does, since `features` writes shared structures out
+//! once per path. Each diagnostic has a `kind` and a human-readable
+//! `message`. `"syntax"` diagnostics (see `judge::SyntaxError`) also have
+//! `position`, `found` (null at the end of the sentence) and `expected`, and
+//! `"unification"` ones (see `UnificationFailure`) have `path`, `values`, and
+//! `rule`, `line`, `file` (null if unknown) and `span` for the rule they
+//! happened in.
+//!
+//! Fields are only added within a version; anything else bumps
+//! `SCHEMA_VERSION`.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use serde_json::{json, Value};
+
+use crate::featurestructure::{NodeRef, UnificationFailure};
+use crate::judge::Judgment;
+use crate::options::ParseOptions;
+use crate::rules::{Grammar, GrammarError};
+use crate::ParsedTree;
+
+pub const SCHEMA_VERSION: u64 = 1;
+
+/// A sentence's parse, for writing as JSON
+#[derive(Debug)]
+pub struct ParseReport {
+  pub tokens: Vec<String>,
+  /// How many trees survived unification. Trees can be taken out of
+  /// `judgment` (to only report some of them) without changing this.
+  pub parses: usize,
+  pub judgment: Judgment,
+  /// Time taken to parse, in milliseconds
+  pub ms: f64,
+}
+
+impl ParseReport {
+  /// Parses (and judges) `input` with `g`
+  pub fn new<I>(g: &Grammar, input: I) -> Self
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    Self::new_with(g, input, &ParseOptions::default()).expect("grammar missing start rules")
+  }
+
+  /// Like `new`, with options, failing like `Grammar::judge_with`
+  pub fn new_with<I>(g: &Grammar, input: I, options: &ParseOptions) -> Result<Self, GrammarError>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    let tokens = input
+      .into_iter()
+      .map(|t| t.as_ref().to_string())
+      .collect::<Vec<_>>();
+    let start = Instant::now();
+    let judgment = g.judge_with(&tokens, options)?;
+    let ms = start.elapsed().as_secs_f64() * 1000.0;
+    let parses = match &judgment {
+      Judgment::Accept(trees) => trees.len(),
+      _ => 0,
+    };
+    Ok(Self {
+      tokens,
+      parses,
+      judgment,
+      ms,
+    })
+  }
+
+  /// The trees, or an empty slice if the sentence was rejected
+  pub fn trees(&self) -> &[ParsedTree] {
+    match &self.judgment {
+      Judgment::Accept(trees) => trees,
+      _ => &[],
+    }
+  }
+
+  /// Keeps only the trees `f` returns, like `Selection::apply`
+  pub fn retain_trees(&mut self, f: impl FnOnce(Vec<ParsedTree>) -> Vec<ParsedTree>) {
+    if let Judgment::Accept(trees) = &mut self.judgment {
+      *trees = f(std::mem::take(trees));
+    }
+  }
+
+  pub fn to_json(&self) -> Value {
+    json!({
+      "version": SCHEMA_VERSION,
+      "tokens": self.tokens,
+      "parses": self.parses,
+      "trees": self.trees().iter().map(tree_to_json).collect::<Vec<_>>(),
+      "diagnostics": diagnostics(&self.judgment),
+      "ms": self.ms,
+    })
+  }
+}
+
+/// A single parse in the format of the `trees` list
+pub fn tree_to_json((tree, fs): &ParsedTree) -> Value {
+  json!({
+    "tree": tree.to_sexpr(),
+    "structure": tree.to_json(),
+    "features": fs.to_json(),
+    "reentrancies": reentrancies(fs),
+  })
+}
+
+fn diagnostics(judgment: &Judgment) -> Vec<Value> {
+  match judgment {
+    Judgment::Accept(_) => Vec::new(),
+    Judgment::RejectSyntax(e) => vec![json!({
+      "kind": "syntax",
+      "message": e.to_string(),
+      "position": e.position,
+      "found": e.found,
+      "expected": e.expected,
+    })],
+    Judgment::RejectFeatures(failures) => failures.iter().map(unification_failure).collect(),
+  }
+}
+
+fn unification_failure(e: &UnificationFailure) -> Value {
+  let mut json = json!({
+    "kind": "unification",
+    "message": e.to_string(),
+    "path": e.path.join("."),
+    "values": [e.left, e.right],
+  });
+  if let Some((rule, span)) = &e.rule {
+    json["rule"] = rule.skeleton().into();
+    json["line"] = rule.line.into();
+    json["file"] = json!(rule.file.as_deref());
+    json["span"] = json!([span.0, span.1]);
+  }
+  json
+}
+
+/// For every node more than one path leads to, those paths, sorted. Paths
+/// that only share a node because they go through an already shared one
+/// (like `a.x` and `b.x` when `a` and `b` are shared) aren't listed.
+fn reentrancies(fs: &NodeRef) -> Vec<Vec<String>> {
+  let walked = fs.walk().collect::<Vec<_>>();
+  let by_path = walked
+    .iter()
+    .map(|(path, node)| (path.clone(), node.clone()))
+    .collect::<HashMap<_, _>>();
+
+  // for each node, the (parent, label) edges leading to it, and their paths
+  struct Paths {
+    node: NodeRef,
+    edges: Vec<(NodeRef, String)>,
+    paths: Vec<String>,
+  }
+  let mut nodes: Vec<Paths> = Vec::new();
+  for (path, node) in walked.iter() {
+    let Some((label, parent)) = path.split_last() else {
+      continue;
+    };
+    let edge = (by_path[parent].clone(), label.clone());
+    let idx = match nodes.iter().position(|n| n.node == *node) {
+      Some(idx) => idx,
+      None => {
+        nodes.push(Paths {
+          node: node.clone(),
+          edges: Vec::new(),
+          paths: Vec::new(),
+        });
+        nodes.len() - 1
+      }
+    };
+    let entry = &mut nodes[idx];
+    if !entry.edges.contains(&edge) {
+      entry.edges.push(edge);
+      entry.paths.push(path.join("."));
+    }
+  }
+
+  let mut out = nodes
+    .into_iter()
+    .filter(|n| n.paths.len() > 1)
+    .map(|mut n| {
+      n.paths.sort();
+      n.paths
+    })
+    .collect::<Vec<_>>();
+  out.sort();
+  out
+}
+
+#[test]
+fn test_parse_report() {
+  let g: Grammar = r#"
+    S -> N[ agr: #1 ] V[ agr: #1 ]
+    N[ agr.num: sg ] -> mary
+    V[ agr.num: sg ] -> runs
+    V[ agr.num: pl ] -> run
+  "#
+  .parse()
+  .unwrap();
+
+  let report = ParseReport::new(&g, ["mary", "runs"]);
+  let json = report.to_json();
+  assert_eq!(json["version"], 1);
+  assert_eq!(json["tokens"], json!(["mary", "runs"]));
+  assert_eq!(json["parses"], 1);
+  assert_eq!(json["diagnostics"], json!([]));
+  assert!(json["ms"].is_f64());
+  let tree = &json["trees"][0];
+  assert_eq!(tree["tree"], "(S (N mary) (V runs))");
+  assert_eq!(tree["features"]["child-1"]["agr"]["num"], "sg");
+  // the shared agr, but not agr.num under it
+  assert_eq!(
+    tree["reentrancies"],
+    json!([["child-0.agr", "child-1.agr"]])
+  );
+
+  let mut report = ParseReport::new(&g, ["mary", "runs"]);
+  report.retain_trees(|_| Vec::new());
+  assert_eq!(report.to_json()["parses"], 1);
+  assert_eq!(report.to_json()["trees"], json!([]));
+
+  let json = ParseReport::new(&g, ["mary", "run"]).to_json();
+  assert_eq!(json["parses"], 0);
+  let diagnostic = &json["diagnostics"][0];
+  assert_eq!(diagnostic["kind"], "unification");
+  assert_eq!(diagnostic["path"], "child-1.agr.num");
+  assert_eq!(diagnostic["values"], json!(["sg", "pl"]));
+  assert_eq!(diagnostic["rule"], "S -> N V");
+  assert_eq!(diagnostic["line"], 2);
+  assert_eq!(diagnostic["file"], Value::Null);
+  assert_eq!(diagnostic["span"], json!([0, 2]));
+
+  let json = ParseReport::new(&g, ["mary"]).to_json();
+  assert_eq!(
+    json["diagnostics"],
+    json!([{
+      "kind": "syntax",
+      "message": "unexpected end of sentence, expected one of \"run\", \"runs\"",
+      "position": 1,
+      "found": null,
+      "expected": ["run", "runs"],
+    }])
+  );
+}
@@ -0,0 +1,465 @@
+//! Rewriting parsed trees, to clean up structure that's only there for the
+//! grammar's sake, like helper nonterminals for lists or optional
+//! constituents. A rewrite is a pattern and a replacement, as S-expressions
+//! with variables:
+//!
+//! ```text
+//! // splice the items of a helper list into whatever contains it
+//! (?label ...before (AdjList ...items) ...after) => (?label ...before ...items ...after)
+//! // drop a unary helper node
+//! (OptDet ?x) => ?x
+//! ```
+//!
+//! In patterns, `?x` matches any subtree, `...xs` any run of children
+//! (including none), `_` anything without binding it, and other atoms are
+//! words. A label can be `?x` or `_` too. The replacement can use anything
+//! the pattern bound. Rewrites are tried in order at every node, bottom-up,
+//! until none match. Only the tree changes: the features still have the
+//! grammar's `child-N` structure.
+//!
+//! Set `ParseOptions::rewrites` to rewrite every parse, or call
+//! `Rewrites::apply` on trees directly.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::sexpr::{tokenize, Token};
+use crate::syntree::{Constituent, SynTree, Word};
+use crate::utils::Err;
+use crate::ParsedTree;
+
+/// How many times rewrites can apply at one node, in case some undo each
+/// other
+const MAX_REWRITES_AT_NODE: usize = 100;
+
+type Tree = SynTree<String, String>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Label {
+  Name(String),
+  Var(String),
+  Any,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Pattern {
+  /// `?x`
+  Var(String),
+  /// `...xs`
+  Rest(String),
+  /// `_`
+  Any,
+  Word(String),
+  Branch(Label, Vec<Pattern>),
+}
+
+#[derive(Debug, Clone)]
+enum Binding {
+  Tree(Tree),
+  Trees(Vec<Tree>),
+  Label(String),
+}
+
+type Bindings = HashMap<String, Binding>;
+
+fn atom_pattern(atom: &str) -> Result<Pattern, Err> {
+  Ok(if atom == "_" {
+    Pattern::Any
+  } else if let Some(name) = atom.strip_prefix("...") {
+    if name.is_empty() {
+      return Err("... needs a name, like ...rest".into());
+    }
+    Pattern::Rest(name.to_string())
+  } else if let Some(name) = atom.strip_prefix('?') {
+    if name.is_empty() {
+      return Err("? needs a name, like ?x".into());
+    }
+    Pattern::Var(name.to_string())
+  } else {
+    Pattern::Word(atom.to_string())
+  })
+}
+
+fn parse_pattern(tokens: &[Token], pos: &mut usize) -> Result<Pattern, Err> {
+  match tokens.get(*pos) {
+    None => Err("unexpected end of pattern".into()),
+    Some(Token::Close) => Err("unexpected )".into()),
+    Some(Token::Atom(atom, _)) => {
+      *pos += 1;
+      atom_pattern(atom)
+    }
+    Some(Token::Open) => {
+      *pos += 1;
+      let label = match tokens.get(*pos) {
+        Some(Token::Atom(label, _)) => match atom_pattern(label)? {
+          Pattern::Any => Label::Any,
+          Pattern::Var(name) => Label::Var(name),
+          Pattern::Word(name) => Label::Name(name),
+          _ => return Err(format!("{} can't be a label", label).into()),
+        },
+        _ => return Err("expected a label after (".into()),
+      };
+      *pos += 1;
+      let mut children = Vec::new();
+      loop {
+        match tokens.get(*pos) {
+          Some(Token::Close) => {
+            *pos += 1;
+            break;
+          }
+          None => return Err("unclosed (".into()),
+          _ => children.push(parse_pattern(tokens, pos)?),
+        }
+      }
+      Ok(Pattern::Branch(label, children))
+    }
+  }
+}
+
+impl Pattern {
+  fn has_any(&self) -> bool {
+    match self {
+      Self::Any => true,
+      Self::Branch(label, children) => *label == Label::Any || children.iter().any(Self::has_any),
+      _ => false,
+    }
+  }
+
+  /// Every variable, with whether it's a `...` one, in order
+  fn vars(&self, out: &mut Vec<(String, &'static str)>) {
+    match self {
+      Self::Var(name) => out.push((name.clone(), "?")),
+      Self::Rest(name) => out.push((name.clone(), "...")),
+      Self::Branch(label, children) => {
+        if let Label::Var(name) = label {
+          out.push((name.clone(), "label"));
+        }
+        for child in children {
+          child.vars(out);
+        }
+      }
+      Self::Any | Self::Word(_) => {}
+    }
+  }
+
+  fn matches(&self, tree: &Tree, bindings: &mut Bindings) -> bool {
+    match (self, tree) {
+      (Self::Any, _) => true,
+      (Self::Var(name), tree) => {
+        bindings.insert(name.clone(), Binding::Tree(tree.clone()));
+        true
+      }
+      (Self::Word(word), SynTree::Leaf(w)) => *word == w.value,
+      (Self::Branch(label, patterns), SynTree::Branch(cons, children)) => {
+        let label_matches = match label {
+          Label::Any => true,
+          Label::Name(name) => *name == cons.value,
+          Label::Var(name) => {
+            bindings.insert(name.clone(), Binding::Label(cons.value.clone()));
+            true
+          }
+        };
+        label_matches && Self::matches_seq(patterns, children, bindings)
+      }
+      _ => false,
+    }
+  }
+
+  /// Matches a list of child patterns, trying every split for `...` ones
+  fn matches_seq(patterns: &[Pattern], trees: &[Tree], bindings: &mut Bindings) -> bool {
+    match patterns.split_first() {
+      None => trees.is_empty(),
+      Some((Self::Rest(name), rest)) => (0..=trees.len()).any(|n| {
+        let mut tried = bindings.clone();
+        tried.insert(name.clone(), Binding::Trees(trees[..n].to_vec()));
+        if Self::matches_seq(rest, &trees[n..], &mut tried) {
+          *bindings = tried;
+          true
+        } else {
+          false
+        }
+      }),
+      Some((first, rest)) => match trees.split_first() {
+        Some((tree, trees)) => {
+          first.matches(tree, bindings) && Self::matches_seq(rest, trees, bindings)
+        }
+        None => false,
+      },
+    }
+  }
+
+  /// Builds the replacement's trees. Only a `...` one can give more (or
+  /// fewer) than one.
+  fn build(&self, bindings: &Bindings) -> Vec<Tree> {
+    match self {
+      Self::Var(name) | Self::Rest(name) => match &bindings[name] {
+        Binding::Tree(tree) => vec![tree.clone()],
+        Binding::Trees(trees) => trees.clone(),
+        Binding::Label(label) => vec![SynTree::Leaf(Word {
+          value: label.clone(),
+          span: (0, 0),
+        })],
+      },
+      Self::Word(word) => vec![SynTree::Leaf(Word {
+        value: word.clone(),
+        span: (0, 0),
+      })],
+      Self::Branch(label, patterns) => {
+        let value = match label {
+          Label::Name(name) => name.clone(),
+          Label::Var(name) => match &bindings[name] {
+            Binding::Label(label) => label.clone(),
+            Binding::Tree(SynTree::Branch(cons, _)) => cons.value.clone(),
+            Binding::Tree(SynTree::Leaf(w)) => w.value.clone(),
+            Binding::Trees(_) => name.clone(),
+          },
+          Label::Any => unreachable!("replacements can't have _"),
+        };
+        let children = patterns.iter().flat_map(|p| p.build(bindings)).collect();
+        vec![SynTree::Branch(
+          Constituent {
+            span: (0, 0),
+            value,
+            function: None,
+          },
+          children,
+        )]
+      }
+      Self::Any => unreachable!("replacements can't have _"),
+    }
+  }
+}
+
+/// Sets every branch's span to cover its children, leaving leaves' alone.
+/// Branches without children start where `start` says.
+fn fix_spans(tree: &mut Tree, start: usize) -> usize {
+  match tree {
+    SynTree::Leaf(w) => w.span.1,
+    SynTree::Branch(cons, children) => {
+      let mut end = start;
+      let mut first = None;
+      for child in children.iter_mut() {
+        end = fix_spans(child, end);
+        first.get_or_insert(child.span().0);
+      }
+      cons.span = (first.unwrap_or(start), end);
+      end
+    }
+  }
+}
+
+/// A pattern and its replacement, see the module docs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rewrite {
+  pattern: Pattern,
+  replacement: Pattern,
+  /// The rewrite's source, for messages
+  pub source: String,
+  /// The (1-based) line it was on, or 0 if it wasn't read from a file
+  pub line: usize,
+}
+
+impl FromStr for Rewrite {
+  type Err = Err;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (pattern, replacement) = s
+      .split_once("=>")
+      .ok_or_else(|| format!("expected => in rewrite: {}", s.trim()))?;
+    let parse = |s: &str| -> Result<Pattern, Err> {
+      let tokens = tokenize(s, false)?;
+      let mut pos = 0;
+      let pattern = parse_pattern(&tokens, &mut pos)?;
+      if pos != tokens.len() {
+        return Err(format!("expected one pattern, found more in {}", s.trim()).into());
+      }
+      Ok(pattern)
+    };
+    let (pattern, replacement) = (parse(pattern)?, parse(replacement)?);
+    if matches!(pattern, Pattern::Rest(_)) || matches!(replacement, Pattern::Rest(_)) {
+      return Err("a whole pattern can't be a ... variable".into());
+    }
+    if replacement.has_any() {
+      return Err("_ can only be in the pattern".into());
+    }
+
+    let mut bound = Vec::new();
+    pattern.vars(&mut bound);
+    for (idx, (name, _)) in bound.iter().enumerate() {
+      if bound[..idx].iter().any(|(n, _)| n == name) {
+        return Err(format!("{} is bound twice", name).into());
+      }
+    }
+    let mut used = Vec::new();
+    replacement.vars(&mut used);
+    for (name, kind) in used {
+      match bound.iter().find(|(n, _)| *n == name) {
+        None => return Err(format!("{} isn't bound by the pattern", name).into()),
+        Some((_, "...")) if kind != "..." => {
+          return Err(format!("{} is a ... variable, so it needs ... in front", name).into())
+        }
+        _ => {}
+      }
+    }
+
+    Ok(Self {
+      pattern,
+      replacement,
+      source: s.trim().to_string(),
+      line: 0,
+    })
+  }
+}
+
+impl fmt::Display for Rewrite {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.source)
+  }
+}
+
+impl Rewrite {
+  /// The replacement for `tree`, if the pattern matches it. A replacement
+  /// of a single branch keeps `tree`'s grammatical function.
+  pub fn apply_at(&self, tree: &Tree) -> Option<Vec<Tree>> {
+    let mut bindings = Bindings::new();
+    if !self.pattern.matches(tree, &mut bindings) {
+      return None;
+    }
+    let mut replacement = self.replacement.build(&bindings);
+    if let (Some((cons, _)), [SynTree::Branch(new, _)]) = (tree.get_branch(), &mut replacement[..])
+    {
+      if matches!(self.replacement, Pattern::Branch(..)) {
+        new.function = cons.function.clone();
+      }
+    }
+    Some(replacement)
+  }
+}
+
+/// A list of rewrites, see the module docs
+#[derive(Debug, Clone, Default)]
+pub struct Rewrites {
+  pub rewrites: Vec<Rewrite>,
+}
+
+impl FromStr for Rewrites {
+  type Err = Err;
+
+  /// Reads one rewrite per line, skipping blank lines and `//` comments
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut rewrites = Vec::new();
+    for (idx, line) in s.lines().enumerate() {
+      let line = match line.find("//") {
+        Some(pos) => &line[..pos],
+        None => line,
+      };
+      if line.trim().is_empty() {
+        continue;
+      }
+      let mut rewrite: Rewrite = line
+        .parse()
+        .map_err(|e| format!("line {}: {}", idx + 1, e))?;
+      rewrite.line = idx + 1;
+      rewrites.push(rewrite);
+    }
+    Ok(Self { rewrites })
+  }
+}
+
+impl Rewrites {
+  /// Rewrites `tree` bottom-up. If the root is rewritten into something
+  /// other than a single tree, it's returned unchanged.
+  pub fn apply(&self, tree: Tree) -> Tree {
+    let start = tree.span().0;
+    match self.rewrite(tree.clone()).as_mut_slice() {
+      [root] => {
+        fix_spans(root, start);
+        root.clone()
+      }
+      _ => tree,
+    }
+  }
+
+  /// Rewrites the trees of parses, leaving their features alone
+  pub fn apply_all(&self, parses: Vec<ParsedTree>) -> Vec<ParsedTree> {
+    parses
+      .into_iter()
+      .map(|(tree, fs)| (self.apply(tree), fs))
+      .collect()
+  }
+
+  fn rewrite(&self, tree: Tree) -> Vec<Tree> {
+    let mut trees = match tree {
+      SynTree::Branch(cons, children) => {
+        let children = children.into_iter().flat_map(|c| self.rewrite(c)).collect();
+        vec![SynTree::Branch(cons, children)]
+      }
+      leaf => vec![leaf],
+    };
+    for _ in 0..MAX_REWRITES_AT_NODE {
+      let [tree] = &trees[..] else {
+        break;
+      };
+      match self.rewrites.iter().find_map(|r| r.apply_at(tree)) {
+        Some(replacement) => trees = replacement,
+        None => break,
+      }
+    }
+    trees
+  }
+}
+
+#[test]
+fn test_rewrites() {
+  use crate::rules::Grammar;
+
+  let g: Grammar = r#"
+    S -> NP VP
+    NP -> OptDet AdjList N
+    OptDet -> Det
+    OptDet ->
+    AdjList -> A AdjList
+    AdjList ->
+    VP -> V
+    Det -> the
+    A -> big
+    A -> red
+    N -> dogs
+    V -> bark
+  "#
+  .parse()
+  .unwrap();
+  let rewrites: Rewrites = r#"
+    // flatten the recursive helper into its parent
+    (?label ...before (AdjList ...items) ...after) => (?label ...before ...items ...after)
+    (OptDet ?x) => ?x
+    (?label ...before (OptDet) ...after) => (?label ...before ...after)
+  "#
+  .parse()
+  .unwrap();
+
+  let (tree, _) = g.parse_one(["the", "big", "red", "dogs", "bark"]).unwrap();
+  let tree = rewrites.apply(tree);
+  assert_eq!(
+    tree.to_sexpr(),
+    "(S (NP (Det the) (A big) (A red) (N dogs)) (VP (V bark)))"
+  );
+  assert_eq!(tree.children()[0].span(), (0, 4));
+
+  let options = crate::ParseOptions {
+    rewrites: Some(std::sync::Arc::new(rewrites)),
+    ..crate::ParseOptions::default()
+  };
+  let (tree, _) = g.parse_with(["dogs", "bark"], &options).remove(0);
+  assert_eq!(tree.to_sexpr(), "(S (NP (N dogs)) (VP (V bark)))");
+  assert_eq!(tree.span(), (0, 2));
+
+  assert!("(A ?x) => (B ?y)".parse::<Rewrite>().is_err());
+  assert!("(A ...xs) => (B ?xs)".parse::<Rewrite>().is_err());
+  assert!("(A ?x ?x) => (B ?x)".parse::<Rewrite>().is_err());
+  assert!("...xs => (B)".parse::<Rewrite>().is_err());
+  assert!("(A ?x) => (_ ?x)".parse::<Rewrite>().is_err());
+  let e = "(A ?x)\n(B) => (C)".parse::<Rewrites>().unwrap_err();
+  assert!(e.to_string().starts_with("line 1: expected =>"));
+}
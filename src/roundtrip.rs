@@ -0,0 +1,217 @@
+//! Round-trip checks: parse a sentence, generate from each parse's features
+//! (see `generate::from_features`), and parse what was generated again. In a
+//! reversible grammar, every parse should give back just the sentence it came
+//! from, with the same features.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::featurestructure::NodeRef;
+use crate::generate::from_features;
+use crate::rules::Grammar;
+
+#[derive(Debug, Clone)]
+pub struct RoundTripOptions {
+  /// How many nonterminals deep generated trees can be
+  pub max_depth: usize,
+  /// Leave the `word` features out before generating, so the check shows
+  /// which other sentences the rest of the features allow, like a verb that
+  /// doesn't agree because its entry is missing a feature
+  pub strip_words: bool,
+}
+
+impl Default for RoundTripOptions {
+  fn default() -> Self {
+    Self {
+      max_depth: 12,
+      strip_words: false,
+    }
+  }
+}
+
+/// Something that didn't survive the round trip. `parse` is the index of the
+/// original parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+  /// Generating from the parse's features didn't give back the sentence
+  NotRegenerated { parse: usize },
+  /// Generating from the parse's features gave another sentence too
+  OtherSentence { parse: usize, sentence: Vec<String> },
+  /// A generated sentence has no parse with the same features as the original
+  FeaturesDiffer { parse: usize, sentence: Vec<String> },
+}
+
+impl fmt::Display for Mismatch {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::NotRegenerated { parse } => {
+        write!(f, "parse {}: didn't generate the sentence back", parse + 1)
+      }
+      Self::OtherSentence { parse, sentence } => {
+        write!(
+          f,
+          "parse {}: also generated \"{}\"",
+          parse + 1,
+          sentence.join(" ")
+        )
+      }
+      Self::FeaturesDiffer { parse, sentence } => write!(
+        f,
+        "parse {}: \"{}\" doesn't parse back to the same features",
+        parse + 1,
+        sentence.join(" ")
+      ),
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct RoundTrip {
+  pub sentence: Vec<String>,
+  pub parses: usize,
+  pub mismatches: Vec<Mismatch>,
+}
+
+impl RoundTrip {
+  /// True if the sentence parsed, and every parse made the round trip
+  pub fn is_ok(&self) -> bool {
+    self.parses > 0 && self.mismatches.is_empty()
+  }
+}
+
+impl fmt::Display for RoundTrip {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: ", self.sentence.join(" "))?;
+    if self.parses == 0 {
+      return write!(f, "no parses");
+    }
+    if self.mismatches.is_empty() {
+      return write!(f, "ok");
+    }
+    for m in self.mismatches.iter() {
+      write!(f, "\n  {}", m)?;
+    }
+    Ok(())
+  }
+}
+
+/// Parses `sentence`, and checks every parse against what generating from its
+/// features gives back
+pub fn check<I>(g: &Grammar, sentence: I, options: &RoundTripOptions) -> RoundTrip
+where
+  I: IntoIterator,
+  I::Item: AsRef<str>,
+{
+  let sentence = sentence
+    .into_iter()
+    .map(|w| w.as_ref().to_string())
+    .collect::<Vec<_>>();
+  let parses = g.parse(&sentence);
+
+  let mut mismatches = Vec::new();
+  for (idx, (_, fs)) in parses.iter().enumerate() {
+    let target = if options.strip_words {
+      without_words(fs, &mut HashMap::new())
+    } else {
+      fs.clone()
+    };
+
+    let mut generated = from_features(g, &target, options.max_depth)
+      .into_iter()
+      .map(|(tree, _)| tree.yield_tokens().into_iter().cloned().collect())
+      .collect::<Vec<_>>();
+    generated.sort();
+    generated.dedup();
+
+    if !generated.contains(&sentence) {
+      mismatches.push(Mismatch::NotRegenerated { parse: idx });
+    }
+    for other in generated {
+      let reparses = g.parse(&other);
+      if other != sentence {
+        mismatches.push(Mismatch::OtherSentence {
+          parse: idx,
+          sentence: other.clone(),
+        });
+      }
+      if !options.strip_words && !reparses.iter().any(|(_, r)| NodeRef::equivalent(r, fs)) {
+        mismatches.push(Mismatch::FeaturesDiffer {
+          parse: idx,
+          sentence: other,
+        });
+      }
+    }
+  }
+
+  RoundTrip {
+    sentence,
+    parses: parses.len(),
+    mismatches,
+  }
+}
+
+/// A copy of `fs` without any `word` features
+fn without_words(fs: &NodeRef, copies: &mut HashMap<NodeRef, NodeRef>) -> NodeRef {
+  let fs = fs.clone().dereference();
+  if let Some(copy) = copies.get(&fs) {
+    return copy.clone();
+  }
+
+  let edges = fs.borrow().edged().map(|edges| {
+    edges
+      .iter()
+      .map(|(l, v)| (l.clone(), v.clone()))
+      .collect::<Vec<_>>()
+  });
+  let copy = match edges {
+    Some(edges) => NodeRef::new_with_edges(
+      edges
+        .into_iter()
+        .filter(|(label, _)| label != "word")
+        .map(|(label, value)| (label, without_words(&value, copies))),
+    )
+    .unwrap(),
+    None => fs.deep_clone(),
+  };
+  copies.insert(fs, copy.clone());
+  copy
+}
+
+#[test]
+fn test_round_trip() {
+  let g: Grammar = r#"
+    S -> N[ num: #1 ] V[ num: #1 ]
+    N[ num: sg ] -> mary
+    N[ num: pl ] -> they
+    V[ num: sg ] -> runs
+    V[ num: pl ] -> run
+    // missing its number
+    V -> ran
+  "#
+  .parse()
+  .unwrap();
+
+  let report = check(&g, ["mary", "runs"], &RoundTripOptions::default());
+  assert!(report.is_ok(), "{}", report);
+  assert_eq!(report.to_string(), "mary runs: ok");
+  assert!(!check(&g, ["mary", "run"], &RoundTripOptions::default()).is_ok());
+
+  let options = RoundTripOptions {
+    strip_words: true,
+    ..RoundTripOptions::default()
+  };
+  // ran's entry doesn't say it's plural, so it can stand in for runs
+  let report = check(&g, ["mary", "runs"], &options);
+  assert_eq!(
+    report.mismatches,
+    [Mismatch::OtherSentence {
+      parse: 0,
+      sentence: vec!["mary".to_string(), "ran".to_string()]
+    }]
+  );
+  assert_eq!(
+    report.to_string(),
+    "mary runs: \n  parse 1: also generated \"mary ran\""
+  );
+  assert_eq!(check(&g, ["they", "ran"], &options).mismatches.len(), 1);
+}
@@ -2,9 +2,93 @@ use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::sync::Arc;
 
-use crate::featurestructure::NodeRef;
+use crate::featurestructure::{Interner, NodeRef};
+use crate::lint::{lint, Lint};
+use crate::options::ParseOptions;
+use crate::projection::CHILD_PREFIX;
+use crate::syntree::SynTree;
+use crate::tokenize::{self, Capitalization};
 use crate::utils::Err;
 
+/// A terminal that matches any word no other terminal in the grammar does,
+/// for guessing the categories of words missing from the lexicon, like
+/// `N -> _unknown`. Give fallback categories `@weight`s to rank the guesses.
+/// The word it matched is the leaf's `word` feature.
+pub const UNKNOWN_WORD: &str = "_unknown";
+
+/// A limit on how many tokens a rule, or one of its children, can cover, set
+/// with `@span` in the grammar: `@span(0: <=3, 2: 1)` for a first child of at
+/// most 3 tokens and a third of exactly 1, or `@span(2-4)` for the rule
+/// itself. Checked while filling the chart, so a constituent that breaks it is
+/// never built.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SpanConstraint {
+  /// The index of the child it limits, or None for the whole rule
+  pub child: Option<usize>,
+  pub min: usize,
+  pub max: Option<usize>,
+}
+
+impl SpanConstraint {
+  pub fn allows(&self, len: usize) -> bool {
+    len >= self.min && self.max.is_none_or(|max| len <= max)
+  }
+}
+
+impl std::str::FromStr for SpanConstraint {
+  type Err = Err;
+
+  /// Parses `N: length` or `length`, where the length is `3`, `<=3`, `>=3` or
+  /// `2-4`
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (child, length) = match s.split_once(':') {
+      Some((child, length)) => (
+        Some(
+          child
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| format!("bad @span child: {}", child.trim()))?,
+        ),
+        length.trim(),
+      ),
+      None => (None, s.trim()),
+    };
+    let number = |n: &str| -> Result<usize, Err> {
+      n.trim()
+        .parse()
+        .map_err(|_| format!("bad @span length: {}", length).into())
+    };
+    let (min, max) = if let Some(max) = length.strip_prefix("<=") {
+      (0, Some(number(max)?))
+    } else if let Some(min) = length.strip_prefix(">=") {
+      (number(min)?, None)
+    } else if let Some((min, max)) = length.split_once('-') {
+      (number(min)?, Some(number(max)?))
+    } else {
+      let n = number(length)?;
+      (n, Some(n))
+    };
+    if max.is_some_and(|max| max < min) {
+      return Err(format!("empty @span length: {}", length).into());
+    }
+    Ok(Self { child, min, max })
+  }
+}
+
+impl fmt::Display for SpanConstraint {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(child) = self.child {
+      write!(f, "{}: ", child)?;
+    }
+    match (self.min, self.max) {
+      (min, Some(max)) if min == max => write!(f, "{}", min),
+      (0, Some(max)) => write!(f, "<={}", max),
+      (min, Some(max)) => write!(f, "{}-{}", min, max),
+      (min, None) => write!(f, ">={}", min),
+    }
+  }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ProductionKind {
   Terminal,
@@ -14,21 +98,28 @@ pub enum ProductionKind {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Production {
   pub kind: ProductionKind,
-  pub symbol: String,
+  /// Shared, so parsed trees can point at it instead of copying it for every
+  /// leaf
+  pub symbol: Arc<str>,
+  /// The grammatical function of this child, like `subj` in `S -> subj:NP VP`.
+  /// Copied to the child's `Constituent` in parsed trees.
+  pub function: Option<String>,
 }
 
 impl Production {
-  pub fn new_terminal(symbol: String) -> Self {
+  pub fn new_terminal(symbol: impl Into<Arc<str>>) -> Self {
     Self {
       kind: ProductionKind::Terminal,
-      symbol,
+      symbol: symbol.into(),
+      function: None,
     }
   }
 
-  pub fn new_nonterminal(symbol: String) -> Self {
+  pub fn new_nonterminal(symbol: impl Into<Arc<str>>) -> Self {
     Self {
       kind: ProductionKind::Nonterminal,
-      symbol,
+      symbol: symbol.into(),
+      function: None,
     }
   }
 
@@ -39,21 +130,56 @@ impl Production {
   pub fn is_nonterminal(&self) -> bool {
     self.kind == ProductionKind::Nonterminal
   }
+
+  /// Whether this is the `UNKNOWN_WORD` terminal
+  pub fn is_unknown_word(&self) -> bool {
+    self.is_terminal() && *self.symbol == *UNKNOWN_WORD
+  }
 }
 
 impl fmt::Display for Production {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(function) = &self.function {
+      write!(f, "{}:", function)?;
+    }
     write!(f, "{}", self.symbol)
   }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq)]
 pub struct Rule {
   pub symbol: String,
   pub features: NodeRef,
   pub productions: Vec<Production>,
+  /// Probability of this rule, from 0 to 1, set with `@weight(x)` in the
+  /// grammar. Only used to order work in best-first parsing (and rank
+  /// suggestions), defaults to 1.0
+  pub weight: f64,
+  /// Position of this rule in the grammar, assigned by `Grammar::new`. Used to
+  /// order trees deterministically.
+  pub index: usize,
+  /// The (1-based) line in the grammar source the rule starts on, or 0 for
+  /// rules that weren't parsed from a full grammar
+  pub line: usize,
+  /// The file the rule was read from, if it was read from a file (see
+  /// `GrammarOptions::file`)
+  pub file: Option<Arc<str>>,
+  /// Set with `@coord`. The rule's symbol gets the features that all its
+  /// children with the same symbol (its conjuncts) agree on, unless the rule
+  /// sets them itself, like `@coord NP[ num: pl ] -> NP and NP`.
+  pub coordination: bool,
+  /// Limits on how many tokens the rule and its children can cover, set
+  /// with `@span`
+  pub spans: Vec<SpanConstraint>,
+  /// Set with `@hidden`. The rule's nodes are left out of parsed trees, with
+  /// their children in their place, for rules that are only there for the
+  /// grammar's bookkeeping. Their features are still unified.
+  pub hidden: bool,
 }
 
+// weights are checked to be from 0 to 1 when parsed, so this is fine
+impl Eq for Rule {}
+
 impl Rule {
   pub fn len(&self) -> usize {
     self.productions.len()
@@ -62,42 +188,332 @@ impl Rule {
   pub fn is_empty(&self) -> bool {
     self.len() == 0
   }
+
+  /// A copy of the rule that doesn't share its features with this one
+  pub fn deep_clone(&self) -> Self {
+    Self {
+      symbol: self.symbol.clone(),
+      features: self.features.deep_clone(),
+      productions: self.productions.clone(),
+      weight: self.weight,
+      index: self.index,
+      line: self.line,
+      file: self.file.clone(),
+      coordination: self.coordination,
+      spans: self.spans.clone(),
+      hidden: self.hidden,
+    }
+  }
+
+  /// Where the rule was defined, like `grammar.fgr:12`, or just `line 12`
+  /// if the file isn't known. None for rules that weren't parsed from a
+  /// grammar.
+  pub fn location(&self) -> Option<String> {
+    match (&self.file, self.line) {
+      (None, 0) => None,
+      (None, line) => Some(format!("line {}", line)),
+      (Some(file), 0) => Some(file.to_string()),
+      (Some(file), line) => Some(format!("{}:{}", file, line)),
+    }
+  }
+
+  /// Whether the `@span` constraints let `child` (or the rule itself, for
+  /// None) cover `len` tokens
+  pub fn allows_span(&self, child: Option<usize>, len: usize) -> bool {
+    self
+      .spans
+      .iter()
+      .filter(|c| c.child == child)
+      .all(|c| c.allows(len))
+  }
+
+  /// The rule without its features, like `S -> N IV`
+  pub fn skeleton(&self) -> String {
+    let mut out = format!("{} ->", self.symbol);
+    for p in self.productions.iter() {
+      out.push(' ');
+      out += &p.symbol;
+    }
+    out
+  }
+
+  /// The rule as JSON, with its features as in `NodeRef::to_json`, for
+  /// `Chart::to_json`. Complex features shared between paths are copied, so
+  /// they're no longer shared when read back.
+  pub fn to_json(&self) -> serde_json::Value {
+    serde_json::json!({
+      "index": self.index,
+      "line": self.line,
+      "file": self.file.as_deref(),
+      "symbol": self.symbol,
+      "weight": self.weight,
+      "coord": self.coordination,
+      "hidden": self.hidden,
+      "spans": self.spans.iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+      "features": self.features.to_json(),
+      "productions": self.productions.iter().map(|p| serde_json::json!({
+        "symbol": &*p.symbol,
+        "terminal": p.is_terminal(),
+        "function": p.function,
+      })).collect::<Vec<_>>(),
+    })
+  }
+
+  /// Reads a rule written by `to_json`
+  pub fn from_json(value: &serde_json::Value) -> Result<Self, Err> {
+    let field = |name: &str| {
+      value
+        .get(name)
+        .ok_or_else(|| -> Err { format!("rule is missing {}: {}", name, value).into() })
+    };
+    let number = |name: &str| -> Result<usize, Err> {
+      Ok(
+        field(name)?
+          .as_u64()
+          .ok_or_else(|| format!("bad rule {}", name))? as usize,
+      )
+    };
+
+    let mut productions = Vec::new();
+    for p in field("productions")?
+      .as_array()
+      .ok_or("bad rule productions")?
+    {
+      let symbol = p["symbol"].as_str().ok_or("bad production symbol")?;
+      let mut production = if p["terminal"].as_bool().unwrap_or(false) {
+        Production::new_terminal(symbol)
+      } else {
+        Production::new_nonterminal(symbol)
+      };
+      production.function = p["function"].as_str().map(str::to_string);
+      productions.push(production);
+    }
+
+    Ok(Self {
+      symbol: field("symbol")?
+        .as_str()
+        .ok_or("bad rule symbol")?
+        .to_string(),
+      features: NodeRef::from_json(field("features")?)?,
+      productions,
+      weight: field("weight")?
+        .as_f64()
+        .filter(|w| (0.0..=1.0).contains(w))
+        .ok_or("bad rule weight")?,
+      index: number("index")?,
+      line: number("line")?,
+      file: value.get("file").and_then(|f| f.as_str()).map(Arc::from),
+      coordination: field("coord")?.as_bool().ok_or("bad rule coord")?,
+      spans: match value.get("spans").and_then(|s| s.as_array()) {
+        Some(spans) => spans
+          .iter()
+          .map(|c| c.as_str().ok_or("bad rule span")?.parse())
+          .collect::<Result<_, _>>()?,
+        None => Vec::new(),
+      },
+      hidden: value
+        .get("hidden")
+        .and_then(|h| h.as_bool())
+        .unwrap_or(false),
+    })
+  }
 }
 
 impl std::fmt::Display for Rule {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    if self.weight != 1.0 {
+      write!(f, "@weight({}) ", self.weight)?;
+    }
+    if self.coordination {
+      write!(f, "@coord ")?;
+    }
+    if self.hidden {
+      write!(f, "@hidden ")?;
+    }
+    if !self.spans.is_empty() {
+      let spans = self.spans.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+      write!(f, "@span({}) ", spans.join(", "))?;
+    }
     write!(f, "{}{} ->", self.symbol, self.features)?;
     for p in self.productions.iter() {
       write!(f, " {}", p)?;
     }
+    // with {:#}, where it was defined, as a comment so it still parses
+    if f.alternate() {
+      if let Some(location) = self.location() {
+        write!(f, "  // {}", location)?;
+      }
+    }
     Ok(())
   }
 }
 
-#[derive(Debug)]
+impl<U> SynTree<Arc<Rule>, U> {
+  /// The rules used to build this tree, in the order of a leftmost derivation
+  /// (parents before their children, children left to right)
+  pub fn derivation(&self) -> Vec<&Arc<Rule>> {
+    let mut out = Vec::new();
+    self.collect_derivation(&mut out);
+    out
+  }
+
+  fn collect_derivation<'a>(&'a self, out: &mut Vec<&'a Arc<Rule>>) {
+    if let Some((c, children)) = self.get_branch() {
+      out.push(&c.value);
+      for child in children {
+        child.collect_derivation(out);
+      }
+    }
+  }
+}
+
+/// The `#key value` lines at the top of a grammar file, like
+/// `#version 1.2`, for telling which grammar a parse came from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GrammarMetadata {
+  pub name: Option<String>,
+  pub version: Option<String>,
+  pub author: Option<String>,
+  pub description: Option<String>,
+  /// Any other keys, in the order they were written
+  pub other: Vec<(String, String)>,
+}
+
+impl GrammarMetadata {
+  pub fn get(&self, key: &str) -> Option<&str> {
+    self.iter().find(|(k, _)| *k == key).map(|(_, v)| v)
+  }
+
+  /// Sets `key`, erroring if it's already set
+  pub fn insert(&mut self, key: &str, value: String) -> Result<(), Err> {
+    if self.get(key).is_some() {
+      return Err(format!("duplicate metadata key: #{}", key).into());
+    }
+    match key {
+      "name" => self.name = Some(value),
+      "version" => self.version = Some(value),
+      "author" => self.author = Some(value),
+      "description" => self.description = Some(value),
+      _ => self.other.push((key.to_string(), value)),
+    }
+    Ok(())
+  }
+
+  /// Every key that's set and its value, the well-known ones first
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+    [
+      ("name", &self.name),
+      ("version", &self.version),
+      ("author", &self.author),
+      ("description", &self.description),
+    ]
+    .into_iter()
+    .filter_map(|(k, v)| v.as_deref().map(|v| (k, v)))
+    .chain(self.other.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.iter().next().is_none()
+  }
+
+  /// An object with a string for every key that's set
+  pub fn to_json(&self) -> serde_json::Value {
+    serde_json::Value::Object(
+      self
+        .iter()
+        .map(|(k, v)| (k.to_string(), serde_json::Value::from(v)))
+        .collect(),
+    )
+  }
+}
+
+/// Something that makes a grammar unusable, from `Grammar::validate`
+#[derive(Debug, Clone)]
+pub enum GrammarError {
+  /// The grammar has no rules
+  Empty,
+  /// The start symbol has no rules
+  UndefinedStart(String),
+  /// `rule` uses `symbol`, which has no rules
+  MissingNonterminal { symbol: String, rule: Arc<Rule> },
+  /// These nonterminals can derive themselves without consuming any input,
+  /// in this order, ending with the first one again. Examples are `A -> A`,
+  /// or `A -> B` and `B -> A C` with a nullable `C`. Every sentence with one
+  /// of them would have infinitely many trees.
+  Cycle(Vec<String>),
+}
+
+impl fmt::Display for GrammarError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Empty => write!(f, "empty ruleset"),
+      Self::UndefinedStart(symbol) => write!(f, "missing rules for start symbol {}", symbol),
+      Self::MissingNonterminal { symbol, rule } => {
+        write!(f, "missing rules for nonterminal {}", symbol)?;
+        if let Some(location) = rule.location() {
+          write!(f, " (used at {})", location)?;
+        }
+        Ok(())
+      }
+      Self::Cycle(symbols) => write!(
+        f,
+        "nonterminals derive themselves without consuming input: {}",
+        symbols.join(" -> ")
+      ),
+    }
+  }
+}
+
+impl std::error::Error for GrammarError {}
+
+#[derive(Debug, Clone)]
 pub struct Grammar {
   pub start: String,
   pub rules: HashMap<String, Vec<Arc<Rule>>>,
+  pub metadata: GrammarMetadata,
+  /// What the features of a constituent's children are called in parse
+  /// results, followed by the child's index: `child-0`, `child-1`... by
+  /// default. Rules are always written and unified with `child-`; this only
+  /// renames the features of finished parses, so helpers that read them (like
+  /// `igt` and `generate::from_features`) expect the default.
+  pub child_prefix: String,
+  /// Warnings found while parsing the grammar, which can't be found from the
+  /// parsed rules, see `warnings`
+  parse_warnings: Vec<Lint>,
   nullables: HashSet<String>,
+  /// For each nullable symbol, the rules that predicting it can complete
+  /// without consuming any input, see `empty_completions`
+  empty_completions: HashMap<String, Vec<Arc<Rule>>>,
   nonterminals: HashSet<String>,
+  /// Every terminal in the rules, other than `UNKNOWN_WORD`
+  terminals: HashSet<String>,
+  /// Whether any rule has a `cap` feature, see `uses_capitalization`
+  uses_cap: bool,
 }
 
 impl std::fmt::Display for Grammar {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    for (key, value) in self.metadata.iter() {
+      writeln!(f, "#{} {}", key, value)?;
+    }
     writeln!(f, "//** start: {}", self.start)?;
     write!(f, "//** nonterminals:")?;
-    for nt in self.nonterminals.iter() {
+    let mut nonterminals = self.nonterminals.iter().collect::<Vec<_>>();
+    nonterminals.sort();
+    for nt in nonterminals {
       write!(f, " {}", nt)?;
     }
     writeln!(f)?;
 
     write!(f, "//** nullables:")?;
-    for nt in self.nullables.iter() {
+    let mut nullables = self.nullables.iter().collect::<Vec<_>>();
+    nullables.sort();
+    for nt in nullables {
       write!(f, " {}", nt)?;
     }
     writeln!(f)?;
 
-    for rule in self.rules.values().flatten() {
+    for rule in self.rules_in_order() {
       writeln!(f, "{}\n", rule)?;
     }
 
@@ -106,51 +522,404 @@ impl std::fmt::Display for Grammar {
 }
 
 impl Grammar {
+  /// Makes a grammar from `rules`, with the first rule's symbol as the start
+  /// symbol. Fails with a `GrammarError` if it doesn't `validate`.
   pub fn new(rules: Vec<Rule>) -> Result<Self, Err> {
-    assert!(!rules.is_empty());
-
+    let Some(first) = rules.first() else {
+      return Err(Box::new(GrammarError::Empty));
+    };
+    let start = first.symbol.clone();
     let nonterminals: HashSet<String> = rules.iter().map(|r| r.symbol.clone()).collect();
-    let start = rules[0].symbol.clone();
-
-    for r in rules.iter() {
-      for p in r.productions.iter() {
-        if p.is_nonterminal() && !nonterminals.contains(&p.symbol) {
-          return Err(format!("missing rules for nonterminal {}", p.symbol).into());
-        }
-      }
-    }
 
     let rules: HashMap<String, Vec<Arc<Rule>>> =
-      rules.into_iter().fold(HashMap::new(), |mut map, rule| {
-        map
-          .entry(rule.symbol.clone())
-          .or_insert_with(Vec::new)
-          .push(Arc::new(rule));
-        map
-      });
+      rules
+        .into_iter()
+        .enumerate()
+        .fold(HashMap::new(), |mut map, (index, mut rule)| {
+          rule.index = index;
+          map
+            .entry(rule.symbol.clone())
+            .or_insert_with(Vec::new)
+            .push(Arc::new(rule));
+          map
+        });
 
-    let nullables = Self::find_nullables(&rules);
+    // lexicons repeat the same few values (sg, nom, ...) and bundles of them
+    // over and over
+    let mut interner = Interner::default();
+    for rule in rules.values().flatten() {
+      rule.features.intern(&mut interner);
+    }
 
-    Ok(Self {
+    let mut g = Self {
       start,
       rules,
+      metadata: GrammarMetadata::default(),
+      child_prefix: CHILD_PREFIX.to_string(),
+      parse_warnings: Vec::new(),
       nonterminals,
-      nullables,
-    })
+      nullables: HashSet::new(),
+      empty_completions: HashMap::new(),
+      terminals: HashSet::new(),
+      uses_cap: false,
+    };
+    g.update_analyses();
+    g.validate()?;
+    Ok(g)
+  }
+
+  /// A copy of the grammar with `start` as its start symbol, failing with
+  /// `GrammarError::UndefinedStart` if there are no rules for it. To parse
+  /// as another symbol without copying the grammar, see `parse_with_start`.
+  pub fn with_start(&self, start: &str) -> Result<Grammar, GrammarError> {
+    self.check_start(start)?;
+    let mut g = self.clone();
+    g.start = start.to_string();
+    Ok(g)
+  }
+
+  /// Fails with `GrammarError::UndefinedStart` if there are no rules for
+  /// `start`
+  pub(crate) fn check_start(&self, start: &str) -> Result<(), GrammarError> {
+    if self.rules.get(start).is_none_or(|r| r.is_empty()) {
+      return Err(GrammarError::UndefinedStart(start.to_string()));
+    }
+    Ok(())
+  }
+
+  /// Checks that the grammar can be parsed with: it has rules, including for
+  /// the start symbol and every nonterminal they use, and no nonterminal can
+  /// derive itself without consuming input. Every constructor and `add_rule`
+  /// checks this; call it after changing `start` or `rules` directly.
+  pub fn validate(&self) -> Result<(), GrammarError> {
+    let rules = self.rules_in_order();
+    if rules.is_empty() {
+      return Err(GrammarError::Empty);
+    }
+    self.check_start(&self.start)?;
+    for rule in rules.iter() {
+      for p in rule.productions.iter() {
+        if p.is_nonterminal() && !self.rules.contains_key(&*p.symbol) {
+          return Err(GrammarError::MissingNonterminal {
+            symbol: p.symbol.to_string(),
+            rule: (*rule).clone(),
+          });
+        }
+      }
+    }
+    match self.find_cycle(&rules) {
+      Some(cycle) => Err(GrammarError::Cycle(cycle)),
+      None => Ok(()),
+    }
+  }
+
+  /// A nonterminal that derives itself through rules whose other productions
+  /// are all nullable, and the path it takes, found in rule order
+  fn find_cycle(&self, rules: &[&Arc<Rule>]) -> Option<Vec<String>> {
+    // symbol -> the symbols it can derive alone, without consuming input
+    let mut edges: HashMap<&str, Vec<&str>> = HashMap::new();
+    for rule in rules.iter() {
+      for (idx, p) in rule.productions.iter().enumerate() {
+        let others_nullable = rule
+          .productions
+          .iter()
+          .enumerate()
+          .all(|(i, o)| i == idx || (o.is_nonterminal() && self.is_nullable(&o.symbol)));
+        if p.is_nonterminal() && others_nullable {
+          edges.entry(&rule.symbol).or_default().push(&p.symbol);
+        }
+      }
+    }
+
+    fn visit<'a>(
+      symbol: &'a str,
+      edges: &HashMap<&'a str, Vec<&'a str>>,
+      path: &mut Vec<&'a str>,
+      done: &mut HashSet<&'a str>,
+    ) -> Option<Vec<String>> {
+      if let Some(pos) = path.iter().position(|s| *s == symbol) {
+        let mut cycle = path[pos..]
+          .iter()
+          .map(|s| s.to_string())
+          .collect::<Vec<_>>();
+        cycle.push(symbol.to_string());
+        return Some(cycle);
+      }
+      if !done.insert(symbol) {
+        return None;
+      }
+      path.push(symbol);
+      for next in edges.get(symbol).into_iter().flatten() {
+        if let Some(cycle) = visit(next, edges, path, done) {
+          return Some(cycle);
+        }
+      }
+      path.pop();
+      None
+    }
+
+    let mut done = HashSet::new();
+    rules
+      .iter()
+      .find_map(|rule| visit(&rule.symbol, &edges, &mut Vec::new(), &mut done))
+  }
+
+  /// Whether any rule has a `cap` feature, so parses need to know how each
+  /// word was capitalized, see `fold_case`
+  pub fn uses_capitalization(&self) -> bool {
+    self.uses_cap
+  }
+
+  /// Lowercases `tokens` with `tokenize::fold_case`, returning them with
+  /// `options` plus their capitalization (see `ParseOptions::capitalization`).
+  /// Grammars that don't use `cap` don't get it, so their parses are the same
+  /// as for the lowercased tokens.
+  pub fn fold_case<S: AsRef<str>>(
+    &self,
+    tokens: &[S],
+    options: &ParseOptions,
+  ) -> (Vec<String>, ParseOptions) {
+    let (words, caps) = tokenize::fold_case(tokens);
+    let options = ParseOptions {
+      capitalization: self.uses_cap.then_some(caps),
+      ..options.clone()
+    };
+    (words, options)
   }
 
   pub fn is_nullable(&self, s: &str) -> bool {
     self.nullables.contains(s)
   }
+
+  /// The nonterminals that can be empty, see `is_nullable`
+  pub fn nullables(&self) -> &HashSet<String> {
+    &self.nullables
+  }
+
+  /// Whether some terminal in the grammar is `word`, so `UNKNOWN_WORD`
+  /// doesn't match it
+  pub fn is_known_word(&self, word: &str) -> bool {
+    self.terminals.contains(word)
+  }
+
+  /// Whether a `terminal` production can scan `token`
+  pub fn terminal_matches(&self, terminal: &str, token: &str) -> bool {
+    terminal == token || (terminal == UNKNOWN_WORD && !self.is_known_word(token))
+  }
+
+  /// The features a leaf gives its parent's `child-N` for `production`: its
+  /// word for `UNKNOWN_WORD`, since the rule can't know it, and its
+  /// capitalization, if that's known. The rule already has any other word.
+  pub(crate) fn leaf_features(
+    production: &Production,
+    word: &str,
+    cap: Option<Capitalization>,
+  ) -> NodeRef {
+    let mut edges = Vec::new();
+    if production.is_unknown_word() {
+      edges.push(("word".to_string(), NodeRef::new_str(word.to_string())));
+    }
+    if let Some(cap) = cap {
+      edges.push((
+        "cap".to_string(),
+        NodeRef::new_str(cap.as_str().to_string()),
+      ));
+    }
+    if edges.is_empty() {
+      NodeRef::new_top()
+    } else {
+      NodeRef::new_with_edges(edges).unwrap()
+    }
+  }
+
+  /// Things in the grammar that aren't errors, but are probably mistakes:
+  /// what `lint` finds, plus anything suspicious found while parsing it.
+  /// Parse warnings come first, in rule order.
+  pub fn warnings(&self) -> Vec<Lint> {
+    let mut warnings = self.parse_warnings.clone();
+    warnings.extend(lint(self));
+    warnings
+  }
+
+  pub(crate) fn add_parse_warnings(&mut self, rule: &Arc<Rule>, messages: Vec<String>) {
+    self
+      .parse_warnings
+      .extend(messages.into_iter().map(|message| Lint {
+        message,
+        rule: Some(rule.clone()),
+      }));
+  }
+
+  /// The rules that can be completed over empty input once `s` is predicted:
+  /// the nullable rules for `s`, and those for the nullable symbols in them.
+  /// Empty if `s` isn't nullable.
+  pub(crate) fn empty_completions(&self, s: &str) -> &[Arc<Rule>] {
+    self.empty_completions.get(s).map_or(&[], Vec::as_slice)
+  }
+
+  /// Adds a rule after the existing ones, updating the nullable set. Fails
+  /// with a `GrammarError`, leaving the grammar as it was, if the grammar
+  /// with the rule wouldn't `validate`.
+  pub fn add_rule(&mut self, mut rule: Rule) -> Result<Arc<Rule>, Err> {
+    rule.index = self
+      .rules
+      .values()
+      .flatten()
+      .map(|r| r.index + 1)
+      .max()
+      .unwrap_or(0);
+    let rule = Arc::new(rule);
+    self.nonterminals.insert(rule.symbol.clone());
+    self
+      .rules
+      .entry(rule.symbol.clone())
+      .or_default()
+      .push(rule.clone());
+    self.update_analyses();
+
+    if let Err(e) = self.validate() {
+      let rules = self.rules.get_mut(&rule.symbol).unwrap();
+      rules.pop();
+      if rules.is_empty() {
+        self.rules.remove(&rule.symbol);
+        self.nonterminals.remove(&rule.symbol);
+      }
+      self.update_analyses();
+      return Err(Box::new(e));
+    }
+    Ok(rule)
+  }
+
+  /// Adds `rules` after the existing ones, updating the analyses once for all
+  /// of them. Unlike `add_rule`, the grammar is left with the rules even if it
+  /// doesn't `validate`, so callers should work on a copy.
+  pub(crate) fn append_rules(&mut self, rules: Vec<Rule>) -> Result<Vec<Arc<Rule>>, Err> {
+    let next = self
+      .rules
+      .values()
+      .flatten()
+      .map(|r| r.index + 1)
+      .max()
+      .unwrap_or(0);
+    let mut interner = Interner::default();
+    let mut added = Vec::with_capacity(rules.len());
+    for (offset, mut rule) in rules.into_iter().enumerate() {
+      rule.index = next + offset;
+      rule.features.intern(&mut interner);
+      let rule = Arc::new(rule);
+      self.nonterminals.insert(rule.symbol.clone());
+      self
+        .rules
+        .entry(rule.symbol.clone())
+        .or_default()
+        .push(rule.clone());
+      added.push(rule);
+    }
+    self.update_analyses();
+    self.validate()?;
+    Ok(added)
+  }
+
+  /// Removes the rule that prints the same as `rule` (ignoring which rule
+  /// index it has), updating the nullable set. Errors if there's no such rule,
+  /// or if removing it would leave a nonterminal that's still used (or the
+  /// start symbol) without any rules.
+  pub fn remove_rule(&mut self, rule: &Rule) -> Result<Arc<Rule>, Err> {
+    let text = rule.to_string();
+    let rules = self
+      .rules
+      .get(&rule.symbol)
+      .ok_or_else(|| format!("no rules for nonterminal {}", rule.symbol))?;
+    let position = rules
+      .iter()
+      .position(|r| r.to_string() == text)
+      .ok_or_else(|| format!("no such rule: {}", text))?;
+
+    if rules.len() == 1 {
+      let used = rule.symbol == self.start
+        || self.rules.values().flatten().any(|r| {
+          r.symbol != rule.symbol && r.productions.iter().any(|p| *p.symbol == rule.symbol)
+        });
+      if used {
+        return Err(
+          format!(
+            "can't remove the last rule for {}, it's still used",
+            rule.symbol
+          )
+          .into(),
+        );
+      }
+    }
+
+    let rules = self.rules.get_mut(&rule.symbol).unwrap();
+    let removed = rules.remove(position);
+    self
+      .parse_warnings
+      .retain(|w| !w.rule.as_ref().is_some_and(|r| Arc::ptr_eq(r, &removed)));
+    if rules.is_empty() {
+      self.rules.remove(&rule.symbol);
+      self.nonterminals.remove(&rule.symbol);
+    }
+    self.update_analyses();
+    Ok(removed)
+  }
+
+  /// All rules, in the order they were defined
+  pub fn rules_in_order(&self) -> Vec<&Arc<Rule>> {
+    let mut rules = self.rules.values().flatten().collect::<Vec<_>>();
+    rules.sort_by_key(|r| r.index);
+    rules
+  }
 }
 
 impl Grammar {
+  fn update_analyses(&mut self) {
+    self.terminals = self
+      .rules
+      .values()
+      .flatten()
+      .flat_map(|r| r.productions.iter())
+      .filter(|p| p.is_terminal() && !p.is_unknown_word())
+      .map(|p| p.symbol.to_string())
+      .collect();
+    self.uses_cap = self.rules.values().flatten().any(|r| {
+      r.features
+        .walk()
+        .any(|(path, _)| path.last().is_some_and(|label| label == "cap"))
+    });
+    self.nullables = Self::find_nullables(&self.rules);
+    self.empty_completions = self
+      .nullables
+      .iter()
+      .map(|s| (s.clone(), self.find_empty_completions(s)))
+      .collect();
+  }
+
+  fn find_empty_completions(&self, symbol: &str) -> Vec<Arc<Rule>> {
+    let mut found: Vec<Arc<Rule>> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![symbol];
+    while let Some(symbol) = stack.pop() {
+      if !seen.insert(symbol) {
+        continue;
+      }
+      for rule in self.rules.get(symbol).into_iter().flatten() {
+        if Self::rule_is_nullable(&self.nullables, rule) {
+          found.push(rule.clone());
+          stack.extend(rule.productions.iter().map(|p| &*p.symbol));
+        }
+      }
+    }
+    found.sort_by_key(|r| r.index);
+    found
+  }
+
   fn rule_is_nullable(nullables: &HashSet<String>, rule: &Rule) -> bool {
     rule.is_empty()
       || rule
         .productions
         .iter()
-        .all(|p| p.is_nonterminal() && nullables.contains(&p.symbol))
+        .all(|p| p.is_nonterminal() && nullables.contains(&*p.symbol))
   }
 
   fn find_nullables(rules: &HashMap<String, Vec<Arc<Rule>>>) -> HashSet<String> {
@@ -203,6 +972,54 @@ fn test_parse_grammar() {
   assert!(g.rules.get("mary").is_none());
 }
 
+#[test]
+fn test_rule_lines() {
+  let g: Grammar = r#"
+       S -> N[ case: nom, num: #1 ] IV[ num: #1 ]
+       S -> N[ case: nom, pron: #1, num: #2 ] TV[ num: #2 ] N[ case: acc, needs_pron: #1 ]
+       S -> N[ case: nom, num: #1 ] CV[ num: #num ] Comp S
+
+       N[ num: sg, pron: she ]     -> mary
+       IV[ num: top, tense: past ] -> fell
+       TV[ num: top, tense: past ] -> kissed
+       CV[ num: top, tense: past ] -> said
+       Comp -> that
+     "#
+  .parse()
+  .unwrap();
+
+  let lines = g
+    .rules_in_order()
+    .iter()
+    .map(|r| r.line)
+    .collect::<Vec<_>>();
+  assert_eq!(lines, vec![2, 3, 4, 6, 7, 8, 9, 10]);
+  assert_eq!(g.rules["S"][2].skeleton(), "S -> N CV Comp S");
+}
+
+#[test]
+fn test_rule_weights() {
+  let g: Grammar = r#"
+    @weight(0.5)
+    S -> x
+    S -> y
+  "#
+  .parse()
+  .unwrap();
+
+  assert_eq!(g.rules["S"][0].weight, 0.5);
+  assert_eq!(g.rules["S"][1].weight, 1.0);
+  assert!(g.rules["S"][0].to_string().starts_with("@weight(0.5) S"));
+  assert!(g.rules["S"][1].to_string().starts_with("S"));
+  assert!("@weight(inf) S -> x".parse::<Grammar>().is_err());
+  // a weight over 1 would let a state's score grow, so best-first parsing
+  // couldn't find the best parse first
+  let err = "@weight(10) S -> x".parse::<Grammar>().unwrap_err();
+  assert!(err.to_string().contains("bad rule weight: 10"));
+  assert!("@weight(-0.5) S -> x".parse::<Grammar>().is_err());
+  assert!("@weight(1) S -> x".parse::<Grammar>().is_ok());
+}
+
 #[test]
 fn test_find_nullables() {
   let g: Grammar = r#"
@@ -217,3 +1034,132 @@ fn test_find_nullables() {
   let nl: HashSet<String> = ["B", "D"].iter().map(|&s| s.to_string()).collect();
   assert_eq!(g.nullables, nl);
 }
+
+#[test]
+fn test_empty_completions() {
+  let g: Grammar = r#"
+      S -> A B
+      A -> c
+      B -> D D
+      D ->
+    "#
+  .parse()
+  .unwrap();
+
+  let skeletons = |s| {
+    g.empty_completions(s)
+      .iter()
+      .map(|r| r.skeleton())
+      .collect::<Vec<_>>()
+  };
+  assert_eq!(skeletons("B"), ["B -> D D", "D ->"]);
+  assert_eq!(skeletons("D"), ["D ->"]);
+  assert!(skeletons("A").is_empty());
+}
+
+#[test]
+fn test_add_remove_rule() {
+  let mut g: Grammar = r#"
+      S -> A
+      A -> x
+    "#
+  .parse()
+  .unwrap();
+
+  assert!(g.add_rule("S -> B".parse().unwrap()).is_err());
+  g.add_rule("A ->".parse().unwrap()).unwrap();
+  assert!(g.is_nullable("S"));
+  assert_eq!(g.rules_in_order().last().unwrap().index, 2);
+
+  g.remove_rule(&"A ->".parse().unwrap()).unwrap();
+  assert!(!g.is_nullable("S"));
+  assert!(g.remove_rule(&"A ->".parse().unwrap()).is_err());
+  assert!(g.remove_rule(&"A -> x".parse().unwrap()).is_err());
+
+  g.add_rule("B -> y".parse().unwrap()).unwrap();
+  g.remove_rule(&"B -> y".parse().unwrap()).unwrap();
+  assert!(!g.rules.contains_key("B"));
+  assert!(!g.nonterminals.contains("B"));
+}
+
+#[test]
+fn test_validate() {
+  fn error(src: &str) -> String {
+    let e = src.parse::<Grammar>().unwrap_err();
+    assert!(e.downcast_ref::<GrammarError>().is_some(), "{}", e);
+    e.to_string()
+  }
+
+  assert_eq!(error(""), "empty ruleset");
+  assert_eq!(
+    error("S -> A\nA -> B"),
+    "missing rules for nonterminal B (used at line 2)"
+  );
+  assert_eq!(
+    error("S -> A\nA -> A"),
+    "nonterminals derive themselves without consuming input: A -> A"
+  );
+  // through an empty rule
+  assert_eq!(
+    error("S -> A x\nA -> B C\nB -> A\nB -> y\nC ->"),
+    "nonterminals derive themselves without consuming input: A -> B -> A"
+  );
+  // recursion that consumes input is fine
+  let mut g: Grammar = "S -> S x\nS -> x".parse().unwrap();
+  assert!(g.validate().is_ok());
+
+  g.start = "T".to_string();
+  assert!(matches!(
+    g.validate(),
+    Err(GrammarError::UndefinedStart(s)) if s == "T"
+  ));
+  g.start = "S".to_string();
+
+  // a failed add leaves the grammar as it was
+  let e = g.add_rule("S -> S".parse().unwrap()).unwrap_err();
+  assert!(matches!(
+    e.downcast_ref::<GrammarError>(),
+    Some(GrammarError::Cycle(_))
+  ));
+  assert_eq!(g.rules["S"].len(), 2);
+  assert!(g.add_rule("T -> T".parse().unwrap()).is_err());
+  assert!(!g.rules.contains_key("T"));
+  assert!(!g.nonterminals.contains("T"));
+  assert!(g.validate().is_ok());
+}
+
+#[test]
+fn test_grammar_metadata() {
+  let g: Grammar = r#"
+    // a header comment
+    #name Toy English
+    #version 1.2.0
+    #license CC-BY // not a well-known key
+
+    S -> N
+    N -> mary
+  "#
+  .parse()
+  .unwrap();
+
+  assert_eq!(g.metadata.name.as_deref(), Some("Toy English"));
+  assert_eq!(g.metadata.get("version"), Some("1.2.0"));
+  assert_eq!(g.metadata.get("license"), Some("CC-BY"));
+  assert_eq!(g.metadata.author, None);
+  assert_eq!(
+    g.metadata.to_json(),
+    serde_json::json!({ "name": "Toy English", "version": "1.2.0", "license": "CC-BY" })
+  );
+  // rule lines still count from the top of the file
+  assert_eq!(g.rules["S"][0].line, 7);
+  assert!(g
+    .to_string()
+    .starts_with("#name Toy English\n#version 1.2.0\n"));
+
+  let plain: Grammar = "S -> x".parse().unwrap();
+  assert!(plain.metadata.is_empty());
+
+  assert!("#name a\n#name b\nS -> x".parse::<Grammar>().is_err());
+  assert!("#version\nS -> x".parse::<Grammar>().is_err());
+  assert!("S -> x\n#version 1\n".parse::<Grammar>().is_err());
+}
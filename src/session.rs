@@ -0,0 +1,143 @@
+//! Parse sessions, for parsing many sentences with the same grammar and
+//! options (like a server does) without allocating a new chart and forest for
+//! each one.
+//!
+//! Feature structures are handed back to the caller with the trees, so they're
+//! still allocated per parse; it's the chart and forest storage, which is most
+//! of the churn for long sentences, that gets reused.
+//!
+//! A session can also carry a `Discourse` from sentence to sentence, so
+//! pronouns can refer back to what earlier sentences mentioned.
+
+use crate::discourse::{Discourse, Resolution};
+use crate::earley::Chart;
+use crate::forest::{Forest, TreeScratch};
+use crate::options::ParseOptions;
+use crate::rules::Grammar;
+use crate::utils::Err;
+use crate::{with_tokens, ParsedTree};
+
+/// The chart and forest a parse is built in, and the scratch space its
+/// tree is built with when there's only one
+#[derive(Debug, Clone)]
+pub(crate) struct ParseBuffers {
+  pub(crate) chart: Chart,
+  pub(crate) forest: Forest,
+  pub(crate) scratch: TreeScratch,
+}
+
+impl Default for ParseBuffers {
+  fn default() -> Self {
+    Self {
+      chart: Chart::new(0),
+      forest: Forest::from(&Chart::new(0)),
+      scratch: TreeScratch::default(),
+    }
+  }
+}
+
+pub struct ParseSession<'g> {
+  pub grammar: &'g Grammar,
+  pub options: ParseOptions,
+  /// When set, each parse updates it (see `Discourse::update`): with the
+  /// first tree whose antecedents can all be resolved, or else the first
+  /// tree
+  pub discourse: Option<Discourse>,
+  /// How the last parse's antecedents were resolved, if there's a discourse
+  pub resolutions: Vec<Resolution>,
+  buffers: ParseBuffers,
+}
+
+impl<'g> ParseSession<'g> {
+  pub fn new(grammar: &'g Grammar) -> Self {
+    Self::with_options(grammar, ParseOptions::default())
+  }
+
+  pub fn with_options(grammar: &'g Grammar, options: ParseOptions) -> Self {
+    Self {
+      grammar,
+      options,
+      discourse: None,
+      resolutions: Vec::new(),
+      buffers: ParseBuffers::default(),
+    }
+  }
+
+  /// Like `Grammar::parse_with`, with this session's options
+  pub fn parse<I>(&mut self, input: I) -> Vec<ParsedTree>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    self.try_parse(input).unwrap_or_default()
+  }
+
+  /// Like `Grammar::try_parse_with`, with this session's options
+  pub fn try_parse<I>(&mut self, input: I) -> Result<Vec<ParsedTree>, Err>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    let trees = with_tokens(input, |input| {
+      self
+        .grammar
+        .try_parse_tokens_in(input, &self.options, &mut self.buffers)
+    })?;
+
+    self.resolutions.clear();
+    if let Some(discourse) = &mut self.discourse {
+      let chosen = trees
+        .iter()
+        .find(|(_, fs)| discourse.can_resolve(fs))
+        .or(trees.first());
+      if let Some((_, fs)) = chosen {
+        self.resolutions = discourse.update(fs);
+      }
+    }
+    Ok(trees)
+  }
+
+  /// Empties the chart and forest from the last parse, keeping their
+  /// allocations. Parsing does this anyway, so this is only needed to drop
+  /// the last parse's states between sentences.
+  pub fn reset(&mut self) {
+    self.buffers.chart.reset(0);
+    self.buffers.forest.fill_from(&self.buffers.chart);
+  }
+}
+
+#[test]
+fn test_parse_session() {
+  let g: Grammar = r#"
+    S -> N[ num: #1 ] V[ num: #1 ]
+    N[ num: sg ] -> mary
+    N[ num: pl ] -> they
+    V[ num: sg ] -> runs
+    V[ num: pl ] -> run
+  "#
+  .parse()
+  .unwrap();
+
+  let mut session = ParseSession::new(&g);
+  for sentence in [
+    &["mary", "runs"][..],
+    &["they", "run"],
+    &["mary", "run"],
+    &["they"],
+  ] {
+    let expected = g.parse(sentence);
+    let got = session.parse(sentence);
+    assert_eq!(got.len(), expected.len(), "{:?}", sentence);
+    for ((t1, fs1), (t2, fs2)) in got.iter().zip(expected.iter()) {
+      assert_eq!(t1.to_string(), t2.to_string());
+      assert!(crate::featurestructure::NodeRef::equivalent(fs1, fs2));
+    }
+  }
+
+  // the chart from a longer sentence doesn't leak into a shorter one
+  session.parse(["mary", "runs"]);
+  assert_eq!(session.parse(["runs"]).len(), 0);
+  session.reset();
+  assert_eq!(session.buffers.chart.len(), 0);
+  assert_eq!(session.parse(["mary", "runs"]).len(), 1);
+}
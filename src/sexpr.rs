@@ -0,0 +1,252 @@
+//! Reading and writing trees as S-expressions, the bracketed format used by
+//! most treebank tools: `(S (N mary) (IV fell))`.
+//!
+//! Spans can be written after labels with `@`, like `(S@0..2 (N@0..1 mary@0..1) ...)`.
+//! They're optional when reading, and are worked out from the words if left
+//! out. Labels containing spaces, brackets, `"` or `@` are written in double
+//! quotes, with `\"` and `\\` escapes.
+
+use std::fmt;
+
+use crate::syntree::{Constituent, SynTree, Word};
+use crate::utils::Err;
+
+impl<T, U> SynTree<T, U>
+where
+  T: fmt::Display,
+  U: fmt::Display,
+{
+  /// Renders this tree on one line as an S-expression, without spans, like
+  /// `(S (N mary) (IV fell))`
+  pub fn to_sexpr(&self) -> String {
+    let mut out = String::new();
+    self.write_sexpr(false, &mut out);
+    out
+  }
+
+  /// Like `to_sexpr`, but with the span of every node, like
+  /// `(S@0..2 (N@0..1 mary@0..1) (IV@1..2 fell@1..2))`
+  pub fn to_sexpr_with_spans(&self) -> String {
+    let mut out = String::new();
+    self.write_sexpr(true, &mut out);
+    out
+  }
+
+  fn write_sexpr(&self, spans: bool, out: &mut String) {
+    let write_atom = |s: &str, span: (usize, usize), out: &mut String| {
+      *out += &quote(s);
+      if spans {
+        *out += &format!("@{}..{}", span.0, span.1);
+      }
+    };
+
+    match self {
+      Self::Leaf(w) => write_atom(&w.value.to_string(), w.span, out),
+      Self::Branch(c, children) => {
+        out.push('(');
+        write_atom(&c.value.to_string(), c.span, out);
+        for child in children {
+          out.push(' ');
+          child.write_sexpr(spans, out);
+        }
+        out.push(')');
+      }
+    }
+  }
+}
+
+impl SynTree<String, String> {
+  /// Reads a tree from an S-expression, like `(S (N mary) (IV fell))`. Bare
+  /// atoms are words, and every list must start with a label.
+  pub fn from_sexpr(s: &str) -> Result<Self, Err> {
+    let tokens = tokenize(s, true)?;
+    let mut pos = 0;
+    let mut next_word = 0;
+    let tree = parse_tree(&tokens, &mut pos, &mut next_word)?;
+    if pos != tokens.len() {
+      return Err(format!("unexpected {} after the end of the tree", tokens[pos]).into());
+    }
+    Ok(tree)
+  }
+}
+
+fn needs_quotes(s: &str) -> bool {
+  s.is_empty()
+    || s
+      .chars()
+      .any(|c| c.is_whitespace() || matches!(c, '(' | ')' | '"' | '@' | '\\'))
+}
+
+fn quote(s: &str) -> String {
+  if needs_quotes(s) {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+  } else {
+    s.to_string()
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token {
+  Open,
+  Close,
+  /// An atom, with its span if it had one
+  Atom(String, Option<(usize, usize)>),
+}
+
+impl fmt::Display for Token {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Open => write!(f, "("),
+      Self::Close => write!(f, ")"),
+      Self::Atom(s, _) => write!(f, "{}", quote(s)),
+    }
+  }
+}
+
+/// Parses the `@i..j` after an atom
+fn parse_span(s: &str) -> Option<(usize, usize)> {
+  let (start, end) = s.split_once("..")?;
+  Some((start.parse().ok()?, end.parse().ok()?))
+}
+
+/// Splits `s` into brackets and atoms. With `spans` off, `@` is just part of
+/// an atom.
+pub(crate) fn tokenize(s: &str, spans: bool) -> Result<Vec<Token>, Err> {
+  let mut tokens = Vec::new();
+  let mut chars = s.chars().peekable();
+  while let Some(&c) = chars.peek() {
+    if c.is_whitespace() {
+      chars.next();
+      continue;
+    }
+    if c == '(' || c == ')' {
+      chars.next();
+      tokens.push(if c == '(' { Token::Open } else { Token::Close });
+      continue;
+    }
+
+    let mut atom = String::new();
+    if c == '"' {
+      chars.next();
+      loop {
+        match chars.next() {
+          Some('"') => break,
+          Some('\\') => atom.extend(chars.next()),
+          Some(c) => atom.push(c),
+          None => return Err(format!("unclosed quote in {}", s).into()),
+        }
+      }
+    } else {
+      while let Some(&c) = chars.peek() {
+        if c.is_whitespace() || matches!(c, '(' | ')' | '"') || (spans && c == '@') {
+          break;
+        }
+        atom.push(c);
+        chars.next();
+      }
+    }
+
+    let mut span = None;
+    if spans && chars.peek() == Some(&'@') {
+      chars.next();
+      let mut text = String::new();
+      while let Some(&c) = chars.peek() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+          break;
+        }
+        text.push(c);
+        chars.next();
+      }
+      span = Some(parse_span(&text).ok_or_else(|| format!("bad span: @{}", text))?);
+    }
+    tokens.push(Token::Atom(atom, span));
+  }
+  Ok(tokens)
+}
+
+/// Parses the tree starting at `tokens[*pos]`, leaving `pos` after it.
+/// `next_word` is where the next word without a span starts.
+pub(crate) fn parse_tree(
+  tokens: &[Token],
+  pos: &mut usize,
+  next_word: &mut usize,
+) -> Result<SynTree<String, String>, Err> {
+  match tokens.get(*pos) {
+    None => Err("unexpected end of tree".into()),
+    Some(Token::Close) => Err("unexpected )".into()),
+    Some(Token::Atom(word, span)) => {
+      *pos += 1;
+      let span = span.unwrap_or((*next_word, *next_word + 1));
+      *next_word = span.1;
+      Ok(SynTree::Leaf(Word {
+        value: word.clone(),
+        span,
+      }))
+    }
+    Some(Token::Open) => {
+      *pos += 1;
+      let (label, span) = match tokens.get(*pos) {
+        Some(Token::Atom(label, span)) => (label.clone(), *span),
+        _ => return Err("expected a label after (".into()),
+      };
+      *pos += 1;
+
+      let start = *next_word;
+      let mut children = Vec::new();
+      loop {
+        match tokens.get(*pos) {
+          Some(Token::Close) => {
+            *pos += 1;
+            break;
+          }
+          None => return Err(format!("unclosed ({}", label).into()),
+          _ => children.push(parse_tree(tokens, pos, next_word)?),
+        }
+      }
+
+      let span = span.unwrap_or_else(|| match (children.first(), children.last()) {
+        (Some(first), Some(last)) => (span_of(first).0, span_of(last).1),
+        _ => (start, start),
+      });
+      Ok(SynTree::Branch(
+        Constituent {
+          value: label,
+          span,
+          function: None,
+        },
+        children,
+      ))
+    }
+  }
+}
+
+fn span_of<T, U>(t: &SynTree<T, U>) -> (usize, usize) {
+  match t {
+    SynTree::Leaf(w) => w.span,
+    SynTree::Branch(c, _) => c.span,
+  }
+}
+
+#[test]
+fn test_sexpr() {
+  let s = "(S (N mary) (VP (V \"likes\\\"\") (N) (N sue)))";
+  let tree = SynTree::from_sexpr(s).unwrap();
+  assert_eq!(tree.to_sexpr(), s);
+  assert_eq!(
+    tree.to_sexpr_with_spans(),
+    "(S@0..3 (N@0..1 mary@0..1) (VP@1..3 (V@1..2 \"likes\\\"\"@1..2) (N@2..2) (N@2..3 sue@2..3)))"
+  );
+  assert_eq!(
+    SynTree::from_sexpr(&tree.to_sexpr_with_spans()).unwrap(),
+    tree
+  );
+
+  let g: crate::Grammar = "S -> N fell\nN -> mary".parse().unwrap();
+  let (parsed, _) = g.parse(&["mary", "fell"]).remove(0);
+  assert_eq!(SynTree::from_sexpr(&parsed.to_sexpr()).unwrap(), parsed);
+
+  assert!(SynTree::from_sexpr("(S (N mary)").is_err());
+  assert!(SynTree::from_sexpr("(S) x").is_err());
+  assert!(SynTree::from_sexpr("(S@x mary)").is_err());
+  assert!(SynTree::from_sexpr("(())").is_err());
+}
@@ -0,0 +1,277 @@
+//! Slot filling: matching parses against templates to pull out the parts an
+//! application acts on, like the verb, object and recipient of a command.
+//!
+//! A `Template` picks constituents by their symbol and a feature pattern
+//! (matched like a transfer rule's, see `transfer`), and names paths from
+//! them to fill its slots. A path steps down the tree through its `child-N`
+//! segments, or through grammatical functions (`obj` for `VP -> V obj:NP`),
+//! and the rest of it, if any, is a feature path in the constituent it got
+//! to:
+//!
+//! ```
+//! use treebender::slots::{Filler, Template, Templates};
+//! use treebender::Grammar;
+//!
+//! let g: Grammar = r#"
+//!   VP[ verb: #v ] -> V[ lex: #v ] obj:NP
+//!   V[ lex: take ] -> take
+//!   NP -> the N
+//!   N -> lamp
+//! "#
+//! .parse()
+//! .unwrap();
+//!
+//! let mut templates = Templates::default();
+//! templates.add(
+//!   Template::new("take", "VP", "[ verb: take ]")
+//!     .unwrap()
+//!     .with_slot("object", "obj"),
+//! );
+//! let parse = g.parse_one(["take", "the", "lamp"]).unwrap();
+//! let filled = templates.fill(&g, &parse);
+//! assert_eq!(filled[0].template, "take");
+//! assert_eq!(filled[0].get("object").unwrap().to_string(), "the lamp");
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::featurestructure::NodeRef;
+use crate::rules::Grammar;
+use crate::syntree::SynTree;
+use crate::transfer::matches;
+use crate::utils::Err;
+use crate::ParsedTree;
+
+/// What a slot was filled with
+#[derive(Debug, Clone)]
+pub enum Filler {
+  /// A constituent or word, by its span and words
+  Span {
+    span: (usize, usize),
+    words: Vec<String>,
+  },
+  /// A string feature
+  Value(String),
+  /// Any other feature structure
+  Features(NodeRef),
+}
+
+impl fmt::Display for Filler {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Span { words, .. } => write!(f, "{}", words.join(" ")),
+      Self::Value(value) => write!(f, "{}", value),
+      Self::Features(fs) => write!(f, "{}", fs),
+    }
+  }
+}
+
+/// Constituents to look for, and the slots to fill from each, see the module
+/// docs
+#[derive(Debug, Clone)]
+pub struct Template {
+  pub name: String,
+  pub symbol: String,
+  pub pattern: NodeRef,
+  /// Slot names and their paths, in order
+  pub slots: Vec<(String, String)>,
+}
+
+impl Template {
+  /// A template for `symbol` constituents whose features match `pattern`,
+  /// like `[ verb: give ]`
+  pub fn new(
+    name: impl Into<String>,
+    symbol: impl Into<String>,
+    pattern: &str,
+  ) -> Result<Self, Err> {
+    Ok(Self {
+      name: name.into(),
+      symbol: symbol.into(),
+      pattern: pattern.parse()?,
+      slots: Vec::new(),
+    })
+  }
+
+  pub fn with_slot(mut self, name: impl Into<String>, path: impl Into<String>) -> Self {
+    self.slots.push((name.into(), path.into()));
+    self
+  }
+}
+
+/// A template that matched, with its slots
+#[derive(Debug, Clone)]
+pub struct Filled {
+  pub template: String,
+  /// The span of the constituent it matched
+  pub span: (usize, usize),
+  /// The slots whose paths were there, in the template's order. Slots whose
+  /// paths weren't are left out, so templates can have optional slots.
+  pub slots: Vec<(String, Filler)>,
+}
+
+impl Filled {
+  pub fn get(&self, slot: &str) -> Option<&Filler> {
+    self
+      .slots
+      .iter()
+      .find(|(name, _)| name == slot)
+      .map(|(_, filler)| filler)
+  }
+}
+
+/// Registered templates, tried in order
+#[derive(Debug, Clone, Default)]
+pub struct Templates {
+  pub templates: Vec<Template>,
+}
+
+impl Templates {
+  pub fn add(&mut self, template: Template) {
+    self.templates.push(template);
+  }
+
+  /// Every template that matches a constituent of `parsed`, by constituent
+  /// (outermost first, then left to right), then template
+  pub fn fill(&self, g: &Grammar, parsed: &ParsedTree) -> Vec<Filled> {
+    let mut out = Vec::new();
+    self.fill_node(g, &parsed.0, &parsed.1, &mut out);
+    out
+  }
+
+  fn fill_node(
+    &self,
+    g: &Grammar,
+    tree: &SynTree<String, String>,
+    fs: &NodeRef,
+    out: &mut Vec<Filled>,
+  ) {
+    let SynTree::Branch(cons, children) = tree else {
+      return;
+    };
+    for template in self.templates.iter() {
+      if cons.value != template.symbol || !matches(&template.pattern, fs, &mut HashMap::new()) {
+        continue;
+      }
+      let slots = template
+        .slots
+        .iter()
+        .filter_map(|(name, path)| Some((name.clone(), resolve(g, tree, fs, path)?)))
+        .collect();
+      out.push(Filled {
+        template: template.name.clone(),
+        span: cons.span,
+        slots,
+      });
+    }
+    for (idx, child) in children.iter().enumerate() {
+      if let Some(child_fs) = fs.get_path(&format!("{}{}", g.child_prefix, idx)) {
+        self.fill_node(g, child, &child_fs, out);
+      }
+    }
+  }
+}
+
+/// Follows `path` from a constituent, see the module docs
+fn resolve(
+  g: &Grammar,
+  tree: &SynTree<String, String>,
+  fs: &NodeRef,
+  path: &str,
+) -> Option<Filler> {
+  let (mut tree, mut fs) = (tree, fs.clone());
+  let mut rest = path.split('.').peekable();
+  while let Some(label) = rest.peek() {
+    let SynTree::Branch(_, children) = tree else {
+      break;
+    };
+    let idx = match label.strip_prefix(g.child_prefix.as_str()) {
+      Some(idx) => idx.parse::<usize>().ok(),
+      None => children.iter().position(|c| match c {
+        SynTree::Branch(c, _) => c.function.as_deref() == Some(*label),
+        SynTree::Leaf(_) => false,
+      }),
+    };
+    let Some(idx) = idx.filter(|&idx| idx < children.len()) else {
+      break;
+    };
+    tree = &children[idx];
+    fs = fs.get_path(&format!("{}{}", g.child_prefix, idx))?;
+    rest.next();
+  }
+
+  let rest = rest.collect::<Vec<_>>();
+  if rest.is_empty() {
+    return Some(Filler::Span {
+      span: tree.span(),
+      words: tree.yield_tokens().into_iter().cloned().collect(),
+    });
+  }
+  let value = fs.get_path(&rest.join("."))?;
+  Some(match value.get_str() {
+    Some(s) => Filler::Value(s.to_string()),
+    None => Filler::Features(value),
+  })
+}
+
+#[test]
+fn test_slots() {
+  let g: Grammar = r#"
+    S -> VP
+    VP[ verb: #v ] -> V[ lex: #v ] obj:NP
+    VP[ verb: #v ] -> V[ lex: #v ] obj:NP rcpt:PP
+    PP -> P NP
+    NP[ thing: #t ] -> Det N[ thing: #t ]
+    Det -> the
+    N[ thing: lamp ] -> lamp
+    N[ thing: troll ] -> troll
+    V[ lex: give ] -> give
+    V[ lex: take ] -> take
+    P -> to
+  "#
+  .parse()
+  .unwrap();
+
+  let mut templates = Templates::default();
+  templates.add(
+    Template::new("give", "VP", "[ verb: give ]")
+      .unwrap()
+      .with_slot("command", "verb")
+      .with_slot("object", "obj")
+      .with_slot("recipient", "rcpt.child-1")
+      .with_slot("who", "rcpt.child-1.thing"),
+  );
+  templates.add(Template::new("act", "VP", "[ verb: #v ]").unwrap());
+
+  let parse = g
+    .parse_one(["give", "the", "lamp", "to", "the", "troll"])
+    .unwrap();
+  let filled = templates.fill(&g, &parse);
+  assert_eq!(
+    filled.iter().map(|f| &f.template).collect::<Vec<_>>(),
+    ["give", "act"]
+  );
+  let give = &filled[0];
+  assert_eq!(give.span, (0, 6));
+  assert_eq!(give.get("command").unwrap().to_string(), "give");
+  assert!(matches!(
+    give.get("object"),
+    Some(Filler::Span { span: (1, 3), .. })
+  ));
+  assert_eq!(give.get("recipient").unwrap().to_string(), "the troll");
+  assert_eq!(give.get("who").unwrap().to_string(), "troll");
+
+  // the recipient is optional
+  let parse = g.parse_one(["give", "the", "lamp"]).unwrap();
+  let filled = templates.fill(&g, &parse);
+  assert_eq!(
+    filled[0].slots.iter().map(|(n, _)| n).collect::<Vec<_>>(),
+    ["command", "object"]
+  );
+
+  let parse = g.parse_one(["take", "the", "lamp"]).unwrap();
+  let filled = templates.fill(&g, &parse);
+  assert_eq!(filled.len(), 1);
+  assert_eq!(filled[0].template, "act");
+}
@@ -0,0 +1,499 @@
+//! Suggestions for the next word of an incomplete sentence, for autocomplete
+//! in command inputs and REPLs, and for saying where a sentence that didn't
+//! parse went wrong and what it might have meant instead.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+use std::fmt;
+
+use crate::earley::{parse_chart, Chart};
+use crate::rules::Grammar;
+use crate::with_tokens;
+
+/// A word that could come next, and how likely it is
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+  pub token: String,
+  /// The weights (`@weight(x)`) of the rules waiting for the word, summed,
+  /// as a share of the total for every suggestion, so they add up to 1
+  pub confidence: f64,
+}
+
+/// Where the parser stopped on a sentence it couldn't parse, and the words it
+/// would have taken there
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stuck {
+  /// How many tokens were read before the parser stopped
+  pub position: usize,
+  /// The last token read, if any
+  pub after: Option<String>,
+  /// What could have come next, sorted. Empty if the sentence was complete
+  /// but couldn't go on, with tokens left over.
+  pub expected: Vec<String>,
+}
+
+impl Stuck {
+  /// How many of `expected` Display lists before trailing off
+  pub const MAX_SHOWN: usize = 8;
+}
+
+impl fmt::Display for Stuck {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.after {
+      Some(token) => write!(f, "parse stuck after token {} ('{}')", self.position, token)?,
+      None => write!(f, "parse stuck at the start")?,
+    }
+    if self.expected.is_empty() {
+      return write!(f, "; expected the end of the sentence");
+    }
+    write!(f, "; expected one of: ")?;
+    let shown = self.expected.len().min(Self::MAX_SHOWN);
+    write!(f, "{}", self.expected[..shown].join(", "))?;
+    if shown < self.expected.len() {
+      write!(f, ", …")?;
+    }
+    Ok(())
+  }
+}
+
+/// One change `Grammar::corrections` made to an input. Positions are in the
+/// input, so an insertion at 1 goes before its second token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+  Insert {
+    position: usize,
+    token: String,
+  },
+  Delete {
+    position: usize,
+    token: String,
+  },
+  Replace {
+    position: usize,
+    from: String,
+    to: String,
+  },
+}
+
+/// A variant of an input that parses, and how it was made
+#[derive(Debug, Clone, PartialEq)]
+pub struct Correction {
+  pub tokens: Vec<String>,
+  /// In input order
+  pub edits: Vec<Edit>,
+  /// How far the variant is from the input: 1 for each word inserted or
+  /// deleted, and for each word replaced, the share of its letters that had
+  /// to change, so fixing a typo costs less than swapping in another word
+  pub cost: f64,
+}
+
+impl Correction {
+  /// The most `Grammar::corrections` will change an input
+  pub const MAX_COST: f64 = 2.0;
+  /// How many edited prefixes `Grammar::corrections` will try before giving
+  /// up on finding more
+  const MAX_STEPS: usize = 2000;
+}
+
+impl fmt::Display for Correction {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "did you mean: '{}'?", self.tokens.join(" "))
+  }
+}
+
+/// A partial correction: the first `position` tokens of the input, edited
+/// into `tokens`. Ordered so the cheapest comes out of a heap first, then the
+/// first one found.
+struct Candidate {
+  cost: f64,
+  order: usize,
+  position: usize,
+  tokens: Vec<String>,
+  edits: Vec<Edit>,
+}
+
+impl Ord for Candidate {
+  fn cmp(&self, other: &Self) -> Ordering {
+    other
+      .cost
+      .total_cmp(&self.cost)
+      .then_with(|| other.order.cmp(&self.order))
+  }
+}
+
+impl PartialOrd for Candidate {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl PartialEq for Candidate {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for Candidate {}
+
+/// The edit distance between `a` and `b`, as a share of the longer's letters
+fn word_distance(a: &str, b: &str) -> f64 {
+  let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+  let mut row = (0..=b.len()).collect::<Vec<_>>();
+  for (i, ca) in a.iter().enumerate() {
+    let mut diagonal = row[0];
+    row[0] = i + 1;
+    for (j, cb) in b.iter().enumerate() {
+      let above = row[j + 1];
+      row[j + 1] = (diagonal + usize::from(ca != cb))
+        .min(row[j] + 1)
+        .min(above + 1);
+      diagonal = above;
+    }
+  }
+  row[b.len()] as f64 / a.len().max(b.len()).max(1) as f64
+}
+
+impl Chart {
+  /// The terminals the states at `k` are waiting for, with the summed weight
+  /// of their rules, in the order they're first found. `UNKNOWN_WORD` isn't
+  /// one, since it stands for words the grammar doesn't have.
+  pub fn next_terminals(&self, k: usize) -> Vec<(String, f64)> {
+    let mut out: Vec<(String, f64)> = Vec::new();
+    for state in self.states_at(k) {
+      let Some(p) = state.lr0.next_production() else {
+        continue;
+      };
+      if !p.is_terminal() || p.is_unknown_word() {
+        continue;
+      }
+      match out.iter_mut().find(|(t, _)| **t == *p.symbol) {
+        Some((_, weight)) => *weight += state.lr0.rule.weight,
+        None => out.push((p.symbol.to_string(), state.lr0.rule.weight)),
+      }
+    }
+    out
+  }
+}
+
+impl Grammar {
+  /// The words that could come after `prefix`, sorted. Empty if the grammar
+  /// can't continue it, including when `prefix` already goes wrong.
+  pub fn next_tokens<I>(&self, prefix: I) -> Vec<String>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    let mut tokens = self
+      .suggest(prefix)
+      .into_iter()
+      .map(|s| s.token)
+      .collect::<Vec<_>>();
+    tokens.sort();
+    tokens
+  }
+
+  /// Whether `chart` has a parse of all `len` tokens as the start symbol,
+  /// before features are checked
+  fn chart_parses(&self, chart: &Chart, len: usize) -> bool {
+    chart.states_at(len).iter().any(|state| {
+      state.origin == 0 && !state.lr0.is_active() && state.lr0.rule.symbol == self.start
+    })
+  }
+
+  /// Up to `limit` variants of `input` that parse, fewest changes first (see
+  /// `Correction::cost`), for "did you mean" messages. Words are inserted or
+  /// replaced with ones the grammar could take at that point, so no more
+  /// than `Correction::MAX_COST` of edits are tried. If `input` parses, it
+  /// comes first, unedited.
+  pub fn corrections<I>(&self, input: I, limit: usize) -> Vec<Correction>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    with_tokens(input, |input| {
+      let mut out: Vec<Correction> = Vec::new();
+      let mut queue = BinaryHeap::new();
+      let mut seen = HashSet::new();
+      let mut order = 0;
+      queue.push(Candidate {
+        cost: 0.0,
+        order,
+        position: 0,
+        tokens: Vec::new(),
+        edits: Vec::new(),
+      });
+
+      while let Some(candidate) = queue.pop() {
+        if out.len() >= limit || seen.len() >= Correction::MAX_STEPS {
+          break;
+        }
+        if !seen.insert((candidate.position, candidate.tokens.clone())) {
+          continue;
+        }
+        let tokens = candidate
+          .tokens
+          .iter()
+          .map(String::as_str)
+          .collect::<Vec<_>>();
+        let chart = parse_chart(self, &tokens);
+        let k = tokens.len();
+        if candidate.position == input.len()
+          && self.chart_parses(&chart, k)
+          && !self.parse(&tokens).is_empty()
+        {
+          out.push(Correction {
+            tokens: candidate.tokens.clone(),
+            edits: candidate.edits.clone(),
+            cost: candidate.cost,
+          });
+        }
+
+        let terminals = chart
+          .next_terminals(k)
+          .into_iter()
+          .map(|(token, _)| token)
+          .collect::<Vec<_>>();
+        let takes_unknown = chart.states_at(k).iter().any(|state| {
+          state
+            .lr0
+            .next_production()
+            .is_some_and(|p| p.is_unknown_word())
+        });
+        let mut push = |cost: f64, position: usize, token: Option<&str>, edit: Option<Edit>| {
+          let cost = candidate.cost + cost;
+          if cost > Correction::MAX_COST {
+            return;
+          }
+          let mut tokens = candidate.tokens.clone();
+          tokens.extend(token.map(str::to_string));
+          let mut edits = candidate.edits.clone();
+          edits.extend(edit);
+          order += 1;
+          queue.push(Candidate {
+            cost,
+            order,
+            position,
+            tokens,
+            edits,
+          });
+        };
+
+        let position = candidate.position;
+        if let Some(&token) = input.get(position) {
+          if terminals.iter().any(|t| t == token) || (takes_unknown && !self.is_known_word(token)) {
+            push(0.0, position + 1, Some(token), None);
+          }
+          for t in terminals.iter().filter(|t| *t != token) {
+            let edit = Edit::Replace {
+              position,
+              from: token.to_string(),
+              to: t.clone(),
+            };
+            push(word_distance(token, t), position + 1, Some(t), Some(edit));
+          }
+          let edit = Edit::Delete {
+            position,
+            token: token.to_string(),
+          };
+          push(1.0, position + 1, None, Some(edit));
+        }
+        for t in &terminals {
+          let edit = Edit::Insert {
+            position,
+            token: t.clone(),
+          };
+          push(1.0, position, Some(t), Some(edit));
+        }
+      }
+      trace_event!(
+        debug,
+        corrections = out.len(),
+        tried = seen.len(),
+        "corrected input"
+      );
+      out
+    })
+  }
+
+  /// Where parsing `input` got stuck: the furthest token the chart reached,
+  /// and the words it was waiting for there. None if the chart has a parse of
+  /// the whole sentence, which means it failed in unification instead, or
+  /// there was no failure. Features aren't checked, as with `next_tokens`.
+  pub fn stuck<I>(&self, input: I) -> Option<Stuck>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    with_tokens(input, |input| {
+      let chart = parse_chart(self, input);
+      if self.chart_parses(&chart, input.len()) {
+        return None;
+      }
+
+      let position = (0..=input.len())
+        .rev()
+        .find(|&k| chart.len_at(k) > 0)
+        .unwrap_or(0);
+      let mut expected = chart
+        .next_terminals(position)
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect::<Vec<_>>();
+      expected.sort();
+      Some(Stuck {
+        position,
+        after: position.checked_sub(1).map(|k| input[k].to_string()),
+        expected,
+      })
+    })
+  }
+
+  /// Like `next_tokens`, ranked by the weights of the rules that could scan
+  /// them: most likely first, then alphabetically. Features aren't checked,
+  /// so a suggestion may not lead to a parse that unifies.
+  pub fn suggest<I>(&self, prefix: I) -> Vec<Suggestion>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    with_tokens(prefix, |prefix| {
+      let chart = parse_chart(self, prefix);
+      let terminals = chart.next_terminals(prefix.len());
+      let total = terminals.iter().map(|(_, w)| w).sum::<f64>();
+      let mut suggestions = terminals
+        .into_iter()
+        .map(|(token, weight)| Suggestion {
+          token,
+          confidence: if total > 0.0 { weight / total } else { 0.0 },
+        })
+        .collect::<Vec<_>>();
+      suggestions.sort_by(|a, b| {
+        b.confidence
+          .total_cmp(&a.confidence)
+          .then_with(|| a.token.cmp(&b.token))
+      });
+      suggestions
+    })
+  }
+}
+
+#[test]
+fn test_next_tokens() {
+  let g: Grammar = r#"
+    S -> go Dir
+    S -> take Obj
+    @weight(0.75)
+    Dir -> north
+    @weight(0.25)
+    Dir -> south
+    Obj -> Det lamp
+    Det -> the
+    Det ->
+  "#
+  .parse()
+  .unwrap();
+
+  assert_eq!(g.next_tokens(Vec::<&str>::new()), ["go", "take"]);
+  assert_eq!(g.next_tokens(["take"]), ["lamp", "the"]);
+  assert!(g.next_tokens(["go", "north"]).is_empty());
+  assert!(g.next_tokens(["north"]).is_empty());
+
+  assert_eq!(g.stuck(["take", "the", "lamp"]), None);
+  let stuck = g.stuck(["take", "the", "north"]).unwrap();
+  assert_eq!(stuck.position, 2);
+  assert_eq!(stuck.expected, ["lamp"]);
+  assert_eq!(
+    stuck.to_string(),
+    "parse stuck after token 2 ('the'); expected one of: lamp"
+  );
+  assert_eq!(
+    g.stuck(["north"]).unwrap().to_string(),
+    "parse stuck at the start; expected one of: go, take"
+  );
+  assert_eq!(
+    g.stuck(["go", "north", "north"]).unwrap().to_string(),
+    "parse stuck after token 2 ('north'); expected the end of the sentence"
+  );
+  let stuck = g.stuck(["take"]).unwrap();
+  assert_eq!((stuck.position, stuck.expected.len()), (1, 2));
+
+  assert_eq!(
+    g.suggest(["go"]),
+    [
+      Suggestion {
+        token: "north".to_string(),
+        confidence: 0.75
+      },
+      Suggestion {
+        token: "south".to_string(),
+        confidence: 0.25
+      },
+    ]
+  );
+}
+
+#[test]
+fn test_corrections() {
+  let g: Grammar = r#"
+    S -> N[ g: #1 ] V N[ refl: y, g: #1 ]
+    N[ g: f ] -> she
+    N[ g: m ] -> he
+    N[ refl: y, g: f ] -> herself
+    N[ refl: y, g: m ] -> himself
+    V -> likes
+    V -> sees
+  "#
+  .parse()
+  .unwrap();
+
+  let corrections = g.corrections(["she", "like", "herself"], 3);
+  assert_eq!(corrections[0].tokens, ["she", "likes", "herself"]);
+  assert_eq!(
+    corrections[0].edits,
+    [Edit::Replace {
+      position: 1,
+      from: "like".to_string(),
+      to: "likes".to_string()
+    }]
+  );
+  assert!((corrections[0].cost - 0.2).abs() < 1e-9);
+  assert_eq!(
+    corrections[0].to_string(),
+    "did you mean: 'she likes herself'?"
+  );
+  assert!(corrections.windows(2).all(|w| w[0].cost <= w[1].cost));
+
+  // the chart takes "she likes himself", but unification doesn't
+  let corrections = g.corrections(["she", "likes", "himself"], 2);
+  let tokens = corrections
+    .iter()
+    .map(|c| c.tokens.join(" "))
+    .collect::<Vec<_>>();
+  assert_eq!(tokens, ["she likes herself", "he likes himself"]);
+
+  let corrections = g.corrections(["she", "herself"], 2);
+  let tokens = corrections
+    .iter()
+    .map(|c| c.tokens.join(" "))
+    .collect::<Vec<_>>();
+  assert_eq!(tokens, ["she likes herself", "she sees herself"]);
+  assert!(corrections.iter().all(|c| c.cost == 1.0));
+  assert!(matches!(
+    corrections[0].edits[..],
+    [Edit::Insert { position: 1, .. }]
+  ));
+
+  let corrections = g.corrections(["she", "likes", "herself", "now"], 1);
+  assert_eq!(corrections[0].tokens, ["she", "likes", "herself"]);
+  assert!(matches!(
+    corrections[0].edits[..],
+    [Edit::Delete { position: 3, .. }]
+  ));
+
+  let corrections = g.corrections(["he", "sees", "himself"], 1);
+  assert_eq!((corrections[0].cost, corrections[0].edits.len()), (0.0, 0));
+  assert!(g
+    .corrections(["the", "cat", "sat", "on", "mats"], 1)
+    .is_empty());
+  assert_eq!(word_distance("kitten", "sitting"), 3.0 / 7.0);
+}
@@ -1,9 +1,15 @@
 use std::fmt;
+use std::fmt::Write;
+
+use crate::utils::escape_mermaid;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Constituent<T> {
   pub value: T,
   pub span: (usize, usize),
+  /// The grammatical function (like `subj`) the parent rule gave this
+  /// constituent, if any. Only set in unified trees.
+  pub function: Option<String>,
 }
 
 impl<T> fmt::Display for Constituent<T>
@@ -11,7 +17,11 @@ where
   T: fmt::Display,
 {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    write!(f, "{}..{}: {}", self.span.0, self.span.1, self.value)
+    write!(f, "{}..{}: {}", self.span.0, self.span.1, self.value)?;
+    if let Some(function) = &self.function {
+      write!(f, " ({})", function)?;
+    }
+    Ok(())
   }
 }
 
@@ -37,6 +47,34 @@ pub enum SynTree<T, U> {
 }
 
 impl<T, U> SynTree<T, U> {
+  pub fn leaf(value: U, span: (usize, usize)) -> Self {
+    Self::Leaf(Word { value, span })
+  }
+
+  /// A constituent spanning its children, from the start of the first to the
+  /// end of the last ((0, 0) if it has none)
+  pub fn branch(value: T, children: Vec<Self>) -> Self {
+    let span = match (children.first(), children.last()) {
+      (Some(first), Some(last)) => (first.span().0, last.span().1),
+      _ => (0, 0),
+    };
+    Self::Branch(
+      Constituent {
+        value,
+        span,
+        function: None,
+      },
+      children,
+    )
+  }
+
+  pub fn span(&self) -> (usize, usize) {
+    match self {
+      Self::Branch(c, _) => c.span,
+      Self::Leaf(w) => w.span,
+    }
+  }
+
   pub fn is_leaf(&self) -> bool {
     matches!(self, Self::Leaf(_))
   }
@@ -60,6 +98,125 @@ impl<T, U> SynTree<T, U> {
     }
   }
 
+  pub fn get_leaf_mut(&mut self) -> Option<&mut Word<U>> {
+    match self {
+      Self::Leaf(w) => Some(w),
+      _ => None,
+    }
+  }
+
+  #[allow(clippy::type_complexity)] // TODO
+  pub fn get_branch_mut(&mut self) -> Option<(&mut Constituent<T>, &mut Vec<SynTree<T, U>>)> {
+    match self {
+      Self::Branch(c, cs) => Some((c, cs)),
+      _ => None,
+    }
+  }
+
+  /// The children of a branch, or an empty slice for a leaf
+  pub fn children(&self) -> &[SynTree<T, U>] {
+    match self {
+      Self::Branch(_, cs) => cs,
+      Self::Leaf(_) => &[],
+    }
+  }
+
+  /// The children of a branch, to add, remove or edit them. None for a leaf.
+  pub fn children_mut(&mut self) -> Option<&mut Vec<SynTree<T, U>>> {
+    match self {
+      Self::Branch(_, cs) => Some(cs),
+      Self::Leaf(_) => None,
+    }
+  }
+
+  /// The node at `path`, a list of child indices from this node
+  pub fn get(&self, path: &[usize]) -> Option<&Self> {
+    path
+      .iter()
+      .try_fold(self, |node, &idx| node.children().get(idx))
+  }
+
+  pub fn get_mut(&mut self, path: &[usize]) -> Option<&mut Self> {
+    path
+      .iter()
+      .try_fold(self, |node, &idx| node.children_mut()?.get_mut(idx))
+  }
+
+  /// Replaces the node at `path` with `tree`, returning the old node, or
+  /// giving `tree` back if there's no node there
+  pub fn replace(&mut self, path: &[usize], tree: Self) -> Result<Self, Self> {
+    match self.get_mut(path) {
+      Some(node) => Ok(std::mem::replace(node, tree)),
+      None => Err(tree),
+    }
+  }
+
+  /// The words under this node, left to right
+  pub fn yield_tokens(&self) -> Vec<&U> {
+    let mut out = Vec::new();
+    self.collect_tokens(&mut out);
+    out
+  }
+
+  fn collect_tokens<'a>(&'a self, out: &mut Vec<&'a U>) {
+    match self {
+      Self::Leaf(w) => out.push(&w.value),
+      Self::Branch(_, children) => children.iter().for_each(|c| c.collect_tokens(out)),
+    }
+  }
+
+  /// The smallest constituent (not word) whose span covers the tokens
+  /// `start..end`, like the lowest phrase a token is part of with
+  /// `find_spanning(k, k + 1)`. None if even this node doesn't cover them.
+  pub fn find_spanning(&self, start: usize, end: usize) -> Option<&Self> {
+    match self {
+      Self::Branch(c, children) if c.span.0 <= start && end <= c.span.1 => Some(
+        children
+          .iter()
+          .find_map(|child| child.find_spanning(start, end))
+          .unwrap_or(self),
+      ),
+      _ => None,
+    }
+  }
+
+  /// The text of `source` this node covers, given the byte span of each token
+  /// in it (from `Tokenizer::token_spans`), including any whitespace or
+  /// punctuation between its words. None if the node's span is empty or
+  /// outside `token_spans`.
+  pub fn span_text<'s>(&self, source: &'s str, token_spans: &[(usize, usize)]) -> Option<&'s str> {
+    let (start, end) = self.span();
+    if start >= end {
+      return None;
+    }
+    let first = token_spans.get(start)?;
+    let last = token_spans.get(end - 1)?;
+    source.get(first.0..last.1)
+  }
+
+  /// Renumbers the words from 0, left to right, and makes every
+  /// constituent's span cover its words. Editing a tree doesn't update spans,
+  /// so call this after adding or removing words.
+  pub fn respan(&mut self) {
+    self.respan_from(&mut 0);
+  }
+
+  fn respan_from(&mut self, next_word: &mut usize) {
+    match self {
+      Self::Leaf(w) => {
+        w.span = (*next_word, *next_word + 1);
+        *next_word += 1;
+      }
+      Self::Branch(c, children) => {
+        let start = *next_word;
+        for child in children.iter_mut() {
+          child.respan_from(next_word);
+        }
+        c.span = (start, *next_word);
+      }
+    }
+  }
+
   #[allow(clippy::type_complexity)] // TODO
   pub fn into_branch(self) -> Option<(Constituent<T>, Vec<SynTree<T, U>>)> {
     match self {
@@ -83,6 +240,7 @@ impl<T, U> SynTree<T, U> {
           Constituent {
             span: t.span,
             value: map_branch(t),
+            function: t.function.clone(),
           },
           children,
         )
@@ -95,6 +253,73 @@ impl<T, U> SynTree<T, U> {
   }
 }
 
+impl<T, U> SynTree<T, U>
+where
+  T: fmt::Display,
+  U: fmt::Display,
+{
+  /// Renders this tree as a [mermaid](https://mermaid.js.org/) flowchart,
+  /// with words in rounded boxes
+  pub fn to_mermaid(&self) -> String {
+    let mut out = String::from("graph TD\n");
+    self.write_mermaid(&mut 0, &mut out);
+    out
+  }
+
+  /// The tree as JSON: constituents as `{ "label", "span", "children" }`, plus
+  /// `"function"` if they have one, and words as `{ "word", "span" }`
+  pub fn to_json(&self) -> serde_json::Value {
+    match self {
+      Self::Leaf(w) => serde_json::json!({
+        "word": w.value.to_string(),
+        "span": [w.span.0, w.span.1],
+      }),
+      Self::Branch(c, children) => {
+        let mut json = serde_json::json!({
+          "label": c.value.to_string(),
+          "span": [c.span.0, c.span.1],
+          "children": children.iter().map(Self::to_json).collect::<Vec<_>>(),
+        });
+        if let Some(function) = &c.function {
+          json["function"] = function.as_str().into();
+        }
+        json
+      }
+    }
+  }
+
+  /// Writes this node and its children, returning this node's id
+  fn write_mermaid(&self, next_id: &mut usize, out: &mut String) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+    match self {
+      Self::Leaf(w) => {
+        writeln!(
+          out,
+          "  n{}(\"{}\")",
+          id,
+          escape_mermaid(&w.value.to_string())
+        )
+        .unwrap();
+      }
+      Self::Branch(c, children) => {
+        writeln!(
+          out,
+          "  n{}[\"{}\"]",
+          id,
+          escape_mermaid(&c.value.to_string())
+        )
+        .unwrap();
+        for child in children {
+          let child_id = child.write_mermaid(next_id, out);
+          writeln!(out, "  n{} --> n{}", id, child_id).unwrap();
+        }
+      }
+    }
+    id
+  }
+}
+
 impl<T, U> fmt::Display for SynTree<T, U>
 where
   T: fmt::Display,
@@ -121,3 +346,276 @@ where
     }
   }
 }
+
+/// A zipper over a tree: a focused node that can move around the tree and be
+/// edited in place, for passes that restructure trees (like reattaching
+/// punctuation) without tracking paths by hand. Spans aren't updated by edits;
+/// call `SynTree::respan` on the result if words were added or removed.
+#[derive(Debug, Clone)]
+pub struct Zipper<T, U> {
+  tree: SynTree<T, U>,
+  /// Child indices from the root to the focus
+  path: Vec<usize>,
+}
+
+impl<T, U> Zipper<T, U> {
+  /// A zipper focused on the root of `tree`
+  pub fn new(tree: SynTree<T, U>) -> Self {
+    Self {
+      tree,
+      path: Vec::new(),
+    }
+  }
+
+  pub fn path(&self) -> &[usize] {
+    &self.path
+  }
+
+  pub fn focus(&self) -> &SynTree<T, U> {
+    self.tree.get(&self.path).expect("zipper path is valid")
+  }
+
+  pub fn focus_mut(&mut self) -> &mut SynTree<T, U> {
+    self.tree.get_mut(&self.path).expect("zipper path is valid")
+  }
+
+  pub fn is_root(&self) -> bool {
+    self.path.is_empty()
+  }
+
+  /// Moves to the parent. Returns false (without moving) at the root.
+  pub fn up(&mut self) -> bool {
+    self.path.pop().is_some()
+  }
+
+  /// Moves to the root
+  pub fn top(&mut self) {
+    self.path.clear();
+  }
+
+  /// Moves to child `idx`. Returns false (without moving) if there's no such
+  /// child.
+  pub fn down(&mut self, idx: usize) -> bool {
+    if idx < self.focus().children().len() {
+      self.path.push(idx);
+      true
+    } else {
+      false
+    }
+  }
+
+  /// Moves to the previous sibling. Returns false (without moving) if there
+  /// isn't one.
+  pub fn left(&mut self) -> bool {
+    match self.path.last_mut() {
+      Some(idx) if *idx > 0 => {
+        *idx -= 1;
+        true
+      }
+      _ => false,
+    }
+  }
+
+  /// Moves to the next sibling. Returns false (without moving) if there isn't
+  /// one.
+  pub fn right(&mut self) -> bool {
+    let siblings = match self.path.split_last() {
+      Some((_, parent)) => self.tree.get(parent).map_or(0, |p| p.children().len()),
+      None => return false,
+    };
+    match self.path.last_mut() {
+      Some(idx) if *idx + 1 < siblings => {
+        *idx += 1;
+        true
+      }
+      _ => false,
+    }
+  }
+
+  /// Moves to the next node in pre-order (parents before their children,
+  /// left to right), so a pass can visit every node with
+  /// `while z.advance() { ... }` after looking at the root. Returns false at the
+  /// last node, leaving the zipper at the root.
+  pub fn advance(&mut self) -> bool {
+    if self.down(0) {
+      return true;
+    }
+    loop {
+      if self.right() {
+        return true;
+      }
+      if !self.up() {
+        return false;
+      }
+    }
+  }
+
+  /// Replaces the focused node, returning the old one
+  pub fn replace(&mut self, tree: SynTree<T, U>) -> SynTree<T, U> {
+    std::mem::replace(self.focus_mut(), tree)
+  }
+
+  /// Adds `tree` as the focus's previous sibling, keeping the focus on the
+  /// same node. Gives `tree` back at the root, which can't have siblings.
+  pub fn insert_left(&mut self, tree: SynTree<T, U>) -> Result<(), SynTree<T, U>> {
+    let Some((idx, parent)) = self.path.split_last() else {
+      return Err(tree);
+    };
+    let idx = *idx;
+    let siblings = self.tree.get_mut(parent).and_then(SynTree::children_mut);
+    siblings.expect("zipper path is valid").insert(idx, tree);
+    *self.path.last_mut().unwrap() += 1;
+    Ok(())
+  }
+
+  /// Adds `tree` as the focus's next sibling, keeping the focus on the same
+  /// node. Gives `tree` back at the root, which can't have siblings.
+  pub fn insert_right(&mut self, tree: SynTree<T, U>) -> Result<(), SynTree<T, U>> {
+    let Some((idx, parent)) = self.path.split_last() else {
+      return Err(tree);
+    };
+    let idx = *idx;
+    let siblings = self.tree.get_mut(parent).and_then(SynTree::children_mut);
+    siblings
+      .expect("zipper path is valid")
+      .insert(idx + 1, tree);
+    Ok(())
+  }
+
+  /// Removes the focused node and moves to its parent. Returns None (without
+  /// removing anything) at the root.
+  pub fn remove(&mut self) -> Option<SynTree<T, U>> {
+    let idx = self.path.pop()?;
+    let siblings = self.focus_mut().children_mut();
+    Some(siblings.expect("zipper path is valid").remove(idx))
+  }
+
+  /// The whole tree, with any edits
+  pub fn into_tree(self) -> SynTree<T, U> {
+    self.tree
+  }
+}
+
+impl<T, U> From<SynTree<T, U>> for Zipper<T, U> {
+  fn from(tree: SynTree<T, U>) -> Self {
+    Self::new(tree)
+  }
+}
+
+#[test]
+fn test_to_mermaid() {
+  let tree: SynTree<String, String> = SynTree::Branch(
+    Constituent {
+      value: "S".to_string(),
+      span: (0, 1),
+      function: None,
+    },
+    vec![SynTree::Leaf(Word {
+      value: "\"hi\"".to_string(),
+      span: (0, 1),
+    })],
+  );
+
+  assert_eq!(tree.to_sexpr(), r#"(S "\"hi\"")"#);
+  assert_eq!(
+    tree.to_mermaid(),
+    "graph TD\n  n0[\"S\"]\n  n1(\"#quot;hi#quot;\")\n  n0 --> n1\n"
+  );
+}
+
+#[test]
+fn test_editing() {
+  let leaf = |w: &str| SynTree::leaf(w.to_string(), (0, 0));
+  let branch = |l: &str, cs| SynTree::branch(l.to_string(), cs);
+  let mut tree: SynTree<String, String> = branch(
+    "S",
+    vec![
+      branch("N", vec![leaf("mary")]),
+      branch("V", vec![leaf("runs")]),
+    ],
+  );
+  tree.respan();
+  assert_eq!(
+    tree.to_sexpr_with_spans(),
+    "(S@0..2 (N@0..1 mary@0..1) (V@1..2 runs@1..2))"
+  );
+  assert_eq!(
+    tree.get(&[1, 0]).and_then(|t| t.get_leaf()).unwrap().value,
+    "runs"
+  );
+  assert!(tree.get(&[2]).is_none());
+
+  let old = tree.replace(&[1, 0], leaf("walks")).unwrap();
+  assert_eq!(old.get_leaf().unwrap().value, "runs");
+  tree
+    .children_mut()
+    .unwrap()
+    .push(branch("Adv", vec![leaf("home")]));
+  tree.respan();
+  assert_eq!(tree.to_sexpr(), "(S (N mary) (V walks) (Adv home))");
+  assert_eq!(tree.span(), (0, 3));
+  assert!(tree.get_mut(&[0, 0, 0]).is_none());
+}
+
+#[test]
+fn test_yield_and_spans() {
+  use crate::tokenize::{Tokenizer, Whitespace};
+
+  let source = "the  cat sat";
+  let tree = SynTree::from_sexpr("(S (NP (D the) (N cat)) (VP (V sat)))").unwrap();
+  assert_eq!(tree.yield_tokens(), ["the", "cat", "sat"]);
+  assert_eq!(tree.children()[1].yield_tokens(), ["sat"]);
+
+  let label =
+    |t: Option<&SynTree<String, String>>| t.unwrap().get_branch().unwrap().0.value.clone();
+  assert_eq!(label(tree.find_spanning(1, 2)), "N");
+  assert_eq!(label(tree.find_spanning(0, 2)), "NP");
+  assert_eq!(label(tree.find_spanning(1, 3)), "S");
+  assert!(tree.find_spanning(2, 4).is_none());
+
+  let spans = Whitespace.token_spans(source);
+  assert_eq!(spans, [(0, 3), (5, 8), (9, 12)]);
+  assert_eq!(tree.span_text(source, &spans), Some("the  cat sat"));
+  assert_eq!(
+    tree.children()[0].span_text(source, &spans),
+    Some("the  cat")
+  );
+  assert_eq!(tree.span_text(source, &spans[..1]), None);
+}
+
+#[test]
+fn test_zipper() {
+  let tree: SynTree<String, String> =
+    SynTree::from_sexpr("(S (NP (D the) (N cat)) (VP (V sat)))").unwrap();
+  let mut z = Zipper::new(tree);
+
+  // visit every node in pre-order
+  let mut labels = vec![z.focus().to_sexpr()];
+  while z.advance() {
+    if let Some((c, _)) = z.focus().get_branch() {
+      labels.push(c.value.clone());
+    }
+  }
+  assert!(z.is_root());
+  assert_eq!(labels[1..], ["NP", "D", "N", "VP", "V"]);
+
+  assert!(z.down(0) && z.down(1));
+  assert_eq!(z.path(), [0, 1]);
+  assert!(!z.right() && z.left() && !z.left());
+  z.insert_left(SynTree::leaf(",".to_string(), (0, 0)))
+    .unwrap();
+  assert_eq!(z.path(), [0, 1]);
+  z.insert_right(SynTree::branch("Adj".to_string(), Vec::new()))
+    .unwrap();
+  assert!(z.up() && z.right() && z.down(0));
+  let v = z.remove().unwrap();
+  assert_eq!(v.to_sexpr(), "(V sat)");
+  assert_eq!(z.path(), [1]);
+  z.top();
+  assert!(z.insert_left(v).is_err());
+  assert!(z.remove().is_none());
+  assert_eq!(
+    z.into_tree().to_sexpr(),
+    "(S (NP , (D the) (Adj) (N cat)) (VP))"
+  );
+}
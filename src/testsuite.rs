@@ -0,0 +1,115 @@
+//! Test sentences for grammars.
+//!
+//! A test file has one sentence per line. Sentences that shouldn't parse are
+//! starred, as is traditional in linguistics. Blank lines and `//` comments
+//! are skipped:
+//!
+//! ```text
+//! // reflexives need an antecedent
+//! mary likes herself
+//! *herself likes mary
+//! ```
+//!
+//! Grammar files can also carry their own tests, as `// test:` comments:
+//!
+//! ```text
+//! // test: mary likes herself
+//! // test: *herself likes mary
+//! S -> N[ case: nom ] TV N[ case: acc ]
+//! ```
+
+use std::fmt;
+
+use crate::rules::Grammar;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestCase {
+  pub sentence: String,
+  /// false for starred sentences
+  pub should_parse: bool,
+  /// The (1-based) line the test was on
+  pub line: usize,
+}
+
+impl TestCase {
+  fn from_line(text: &str, line: usize) -> Self {
+    let text = text.trim();
+    let (sentence, should_parse) = match text.strip_prefix('*') {
+      Some(rest) => (rest.trim(), false),
+      None => (text, true),
+    };
+    Self {
+      sentence: sentence.to_string(),
+      should_parse,
+      line,
+    }
+  }
+
+  pub fn tokens(&self) -> Vec<&str> {
+    self.sentence.split_whitespace().collect()
+  }
+
+  /// Whether the sentence parses (or doesn't) as expected
+  pub fn passes(&self, g: &Grammar) -> bool {
+    g.parse_one(&self.tokens()).is_some() == self.should_parse
+  }
+}
+
+impl fmt::Display for TestCase {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if !self.should_parse {
+      write!(f, "*")?;
+    }
+    write!(f, "{}", self.sentence)
+  }
+}
+
+/// Reads the test cases from a test file
+pub fn parse_test_file(s: &str) -> Vec<TestCase> {
+  s.lines()
+    .enumerate()
+    .filter(|(_, l)| {
+      let l = l.trim();
+      !l.is_empty() && !l.starts_with("//")
+    })
+    .map(|(idx, l)| TestCase::from_line(l, idx + 1))
+    .collect()
+}
+
+/// Reads the `// test:` comments from the source of a grammar
+pub fn inline_tests(grammar_src: &str) -> Vec<TestCase> {
+  grammar_src
+    .lines()
+    .enumerate()
+    .filter_map(|(idx, l)| {
+      let comment = l.trim().strip_prefix("//")?;
+      let test = comment.trim_start().strip_prefix("test:")?;
+      Some(TestCase::from_line(test, idx + 1))
+    })
+    .collect()
+}
+
+#[test]
+fn test_testsuite() {
+  let src = r#"
+    // test: mary runs
+    // test: * runs mary
+    // tests are useful
+    S -> N V
+    N -> mary
+    V -> runs
+  "#;
+  let g: Grammar = src.parse().unwrap();
+
+  let tests = inline_tests(src);
+  let from_file = parse_test_file("// comment\nmary runs\n\n*runs mary");
+  assert_eq!(tests.len(), 2);
+  assert_eq!(from_file.len(), 2);
+  for (a, b) in tests.iter().zip(from_file.iter()) {
+    assert_eq!((&a.sentence, a.should_parse), (&b.sentence, b.should_parse));
+  }
+  assert_eq!((tests[0].line, from_file[1].line), (2, 4));
+  assert!(!tests[1].should_parse);
+  assert_eq!(tests[1].to_string(), "*runs mary");
+  assert!(tests.iter().all(|t| t.passes(&g)));
+}
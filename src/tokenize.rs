@@ -0,0 +1,134 @@
+//! Splitting text into the tokens a grammar's terminals are matched against.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+pub trait Tokenizer {
+  fn tokenize<'a>(&self, s: &'a str) -> Vec<&'a str>;
+
+  /// The byte span of each token in `s`, for `SynTree::span_text`. Tokens
+  /// that are slices of `s` (like all of this module's tokenizers return) are
+  /// located exactly, and any others are searched for after the last one.
+  fn token_spans(&self, s: &str) -> Vec<(usize, usize)> {
+    let base = s.as_ptr() as usize;
+    let mut next = 0;
+    self
+      .tokenize(s)
+      .into_iter()
+      .map(|t| {
+        let ptr = t.as_ptr() as usize;
+        let start = if ptr >= base && ptr + t.len() <= base + s.len() {
+          ptr - base
+        } else {
+          s[next..].find(t).map_or(next, |idx| next + idx)
+        };
+        next = start + t.len();
+        (start, next)
+      })
+      .collect()
+  }
+}
+
+/// Splits on whitespace only, so `"hello, world!"` is `["hello,", "world!"]`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Whitespace;
+
+impl Tokenizer for Whitespace {
+  fn tokenize<'a>(&self, s: &'a str) -> Vec<&'a str> {
+    s.split_whitespace().collect()
+  }
+}
+
+/// Splits on Unicode word boundaries ([UAX #29]), dropping whitespace. Each
+/// punctuation mark is its own token, contractions like "don't" and numbers
+/// like "3.14" stay whole, and ideographs (which are written without spaces)
+/// are one token each. This is the default for `Grammar::parse_str`.
+///
+/// [UAX #29]: https://www.unicode.org/reports/tr29/
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UnicodeWords;
+
+impl Tokenizer for UnicodeWords {
+  fn tokenize<'a>(&self, s: &'a str) -> Vec<&'a str> {
+    s.split_word_bounds()
+      .filter(|t| !t.chars().all(char::is_whitespace))
+      .collect()
+  }
+}
+
+/// How a token was capitalized before `fold_case` lowercased it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capitalization {
+  /// No uppercase first letter, including tokens without letters
+  None,
+  /// An uppercase first letter, like "Mary", "McCoy" or "I"
+  Initial,
+  /// More than one letter, all uppercase, like "NASA"
+  All,
+}
+
+impl Capitalization {
+  pub fn of(token: &str) -> Self {
+    let mut letters = token.chars().filter(|c| c.is_alphabetic());
+    match letters.next() {
+      Some(first) if first.is_uppercase() => {
+        let mut rest = letters.peekable();
+        if rest.peek().is_some() && rest.all(char::is_uppercase) {
+          Self::All
+        } else {
+          Self::Initial
+        }
+      }
+      _ => Self::None,
+    }
+  }
+
+  /// The value of the `cap` feature, see `ParseOptions::capitalization`
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::None => "none",
+      Self::Initial => "initial",
+      Self::All => "all",
+    }
+  }
+}
+
+/// Lowercases `tokens`, so they match a grammar's lowercase terminals, and
+/// returns how each was capitalized, for `ParseOptions::capitalization`
+pub fn fold_case<S: AsRef<str>>(tokens: &[S]) -> (Vec<String>, Vec<Capitalization>) {
+  tokens
+    .iter()
+    .map(|t| (t.as_ref().to_lowercase(), Capitalization::of(t.as_ref())))
+    .unzip()
+}
+
+#[test]
+fn test_tokenize() {
+  let s = "\"Don't,\" she said—at 3.14 p.m. 我们走吧!";
+  assert_eq!(
+    Whitespace.tokenize(s),
+    ["\"Don't,\"", "she", "said—at", "3.14", "p.m.", "我们走吧!"]
+  );
+  assert_eq!(
+    UnicodeWords.tokenize(s),
+    [
+      "\"", "Don't", ",", "\"", "she", "said", "—", "at", "3.14", "p.m", ".", "我", "们", "走",
+      "吧", "!"
+    ]
+  );
+}
+
+#[test]
+fn test_fold_case() {
+  let (words, caps) = fold_case(&[
+    "Mary", "and", "I", "saw", "NASA", "launch", "3", "R2-D2", "ÉTÉ",
+  ]);
+  assert_eq!(
+    words,
+    ["mary", "and", "i", "saw", "nasa", "launch", "3", "r2-d2", "été"]
+  );
+  use Capitalization::*;
+  assert_eq!(
+    caps,
+    [Initial, None, Initial, None, All, None, None, All, All]
+  );
+}
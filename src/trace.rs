@@ -0,0 +1,20 @@
+//! Wrappers around `tracing`'s macros that compile to nothing unless the
+//! `tracing` feature is enabled, so instrumentation doesn't cost anything (or
+//! pull in the dependency) by default.
+
+/// Emits an event at the given level, like `trace_event!(debug, states = n, "parsed chart")`
+macro_rules! trace_event {
+  ($level:ident, $($arg:tt)+) => {
+    #[cfg(feature = "tracing")]
+    tracing::$level!($($arg)+);
+  };
+}
+
+/// Enters a span at the given level until the end of the enclosing block, like
+/// `trace_span!(debug_span, "parse", tokens = input.len())`
+macro_rules! trace_span {
+  ($level:ident, $($arg:tt)+) => {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::$level!($($arg)+).entered();
+  };
+}
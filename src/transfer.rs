@@ -0,0 +1,258 @@
+//! Transfer rules, which match a feature structure (like a parse's) and
+//! rewrite it into another one, for mapping syntax to semantics or one
+//! language's structures to another's.
+//!
+//! Rules are written one per line as `PATTERN => OUTPUT`, with `//` comments,
+//! in the grammar's feature structure syntax:
+//!
+//! ```text
+//! [ child-0.child-0.word: #agent, child-1.child-0.word: #verb ] => [ frame: #verb, agent: #agent ]
+//! ```
+//!
+//! A pattern matches a structure if every feature it has is there, with the
+//! same value for strings. A tag in the pattern matches whatever is at its
+//! paths (which have to be the same value, if it's used on several), and a tag
+//! in the output is replaced by a copy of what it matched. Everything else in
+//! the output is copied as written.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::featurestructure::{Feature, NodeRef};
+use crate::fgr::parse_features;
+use crate::utils::Err;
+
+#[derive(Debug)]
+pub struct TransferRule {
+  pub pattern: NodeRef,
+  /// Shares nodes with `pattern` where they have a tag in common
+  pub output: NodeRef,
+  /// The (1-based) line in the rules source the rule is on
+  pub line: usize,
+}
+
+impl FromStr for TransferRule {
+  type Err = Err;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (pattern, rest) = parse_features(s)?;
+    let rest = rest
+      .trim_start()
+      .strip_prefix("=>")
+      .ok_or_else(|| format!("expected => in transfer rule: {}", s.trim()))?;
+    let (output, rest) = parse_features(rest)?;
+    if !rest.trim().is_empty() {
+      return Err(format!("expected a single transfer rule, found more at {}", rest).into());
+    }
+
+    // parsed as one structure, so tags are shared between the sides
+    let features = (pattern.into_iter().map(|f| ("in", f)))
+      .chain(output.into_iter().map(|f| ("out", f)))
+      .map(|(side, f)| Feature {
+        path: format!("{}.{}", side, f.path),
+        ..f
+      });
+    let both = NodeRef::new_from_paths(features)?;
+    let side = |name| {
+      both
+        .get_path(name)
+        .map_or_else(|| NodeRef::new_with_edges(Vec::new()), Ok)
+    };
+
+    Ok(Self {
+      pattern: side("in")?,
+      output: side("out")?,
+      line: 0,
+    })
+  }
+}
+
+impl TransferRule {
+  /// The rewritten structure, if the pattern matches `fs`. `fs` isn't changed.
+  pub fn apply(&self, fs: &NodeRef) -> Option<NodeRef> {
+    let mut bindings = HashMap::new();
+    if !matches(&self.pattern, fs, &mut bindings) {
+      return None;
+    }
+    build(&self.output, &bindings, &mut HashMap::new()).ok()
+  }
+}
+
+/// Checks that `pattern` matches `fs`, binding pattern nodes to the nodes of
+/// `fs` they matched
+pub(crate) fn matches(
+  pattern: &NodeRef,
+  fs: &NodeRef,
+  bindings: &mut HashMap<NodeRef, NodeRef>,
+) -> bool {
+  let pattern = pattern.clone().dereference();
+  let fs = fs.clone().dereference();
+
+  if let Some(bound) = bindings.get(&pattern) {
+    return *bound == fs || NodeRef::equivalent(bound, &fs);
+  }
+  bindings.insert(pattern.clone(), fs.clone());
+
+  if pattern.is_top() {
+    return true;
+  }
+  if let Some(s) = pattern.get_str() {
+    return fs.get_str() == Some(s);
+  }
+
+  let edges = pattern
+    .borrow()
+    .edged()
+    .map(|edges| {
+      edges
+        .iter()
+        .map(|(l, v)| (l.clone(), v.clone()))
+        .collect::<Vec<_>>()
+    })
+    .unwrap_or_default();
+  edges.into_iter().all(|(label, value)| {
+    let target = fs.borrow().edged().and_then(|e| e.get(&label).cloned());
+    target.is_some_and(|target| matches(&value, &target, bindings))
+  })
+}
+
+/// Copies `output`, replacing nodes bound by the pattern with copies of what
+/// they matched. `copies` keeps shared nodes shared.
+fn build(
+  output: &NodeRef,
+  bindings: &HashMap<NodeRef, NodeRef>,
+  copies: &mut HashMap<NodeRef, NodeRef>,
+) -> Result<NodeRef, Err> {
+  let output = output.clone().dereference();
+  if let Some(copy) = copies.get(&output) {
+    return Ok(copy.clone());
+  }
+
+  let copy = if let Some(bound) = bindings.get(&output) {
+    bound.deep_clone()
+  } else if output.is_top() {
+    NodeRef::new_top()
+  } else if let Some(s) = output.get_str() {
+    NodeRef::new_str(s.to_string())
+  } else {
+    let edges = output
+      .borrow()
+      .edged()
+      .map(|edges| {
+        edges
+          .iter()
+          .map(|(l, v)| (l.clone(), v.clone()))
+          .collect::<Vec<_>>()
+      })
+      .unwrap_or_default();
+    let edges = edges
+      .into_iter()
+      .map(|(label, value)| Ok((label, build(&value, bindings, copies)?)))
+      .collect::<Result<Vec<_>, Err>>()?;
+    NodeRef::new_with_edges(edges)?
+  };
+  copies.insert(output, copy.clone());
+  Ok(copy)
+}
+
+/// A list of transfer rules, tried in order
+#[derive(Debug, Default)]
+pub struct Transfer {
+  pub rules: Vec<TransferRule>,
+}
+
+impl FromStr for Transfer {
+  type Err = Err;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut rules = Vec::new();
+    for (idx, line) in s.lines().enumerate() {
+      let line = match line.find("//") {
+        Some(pos) => &line[..pos],
+        None => line,
+      };
+      if line.trim().is_empty() {
+        continue;
+      }
+      let mut rule: TransferRule = line
+        .parse()
+        .map_err(|e| format!("line {}: {}", idx + 1, e))?;
+      rule.line = idx + 1;
+      rules.push(rule);
+    }
+    Ok(Self { rules })
+  }
+}
+
+impl Transfer {
+  /// The output of the first rule that matches `fs`
+  pub fn apply(&self, fs: &NodeRef) -> Option<NodeRef> {
+    self.rules.iter().find_map(|r| r.apply(fs))
+  }
+
+  /// The outputs of every rule that matches `fs`, in order
+  pub fn apply_all(&self, fs: &NodeRef) -> Vec<NodeRef> {
+    self.rules.iter().filter_map(|r| r.apply(fs)).collect()
+  }
+}
+
+#[test]
+fn test_transfer() {
+  use crate::Grammar;
+
+  let g: Grammar = r#"
+    S -> N[ num: #1 ] V[ num: #1 ]
+    S -> N[ num: #1 ] V[ num: #1, trans: yes ] N
+    N[ num: sg ] -> mary
+    N[ num: pl ] -> cats
+    V[ num: sg ] -> sleeps
+    V[ num: pl, trans: yes ] -> see
+  "#
+  .parse()
+  .unwrap();
+
+  let transfer: Transfer = r#"
+    // transitive verbs first, as their pattern is more specific
+    [ child-0.child-0.word: #a, child-1.trans: yes, child-1.child-0.word: #v, child-2.child-0.word: #p ] => [ frame: #v, agent: #a, patient: #p ]
+    [ child-0.child-0.word: #a, child-0.num: #n, child-1.child-0.word: #v ] => [ frame: #v, agent: #a, agent_num: #n, kind: event ]
+  "#
+  .parse()
+  .unwrap();
+  assert_eq!(transfer.rules[1].line, 4);
+
+  let json = NodeRef::to_json;
+
+  let (_, fs) = g.parse_one(&["mary", "sleeps"]).unwrap();
+  let out = transfer.apply(&fs).unwrap();
+  assert_eq!(
+    json(&out),
+    serde_json::json!({ "frame": "sleeps", "agent": "mary", "agent_num": "sg", "kind": "event" })
+  );
+  // the parse isn't changed
+  assert!(fs.get_path("frame").is_none());
+
+  let (_, fs) = g.parse_one(&["cats", "see", "mary"]).unwrap();
+  assert_eq!(transfer.apply_all(&fs).len(), 2);
+  assert_eq!(
+    json(&transfer.apply(&fs).unwrap()),
+    serde_json::json!({ "frame": "see", "agent": "cats", "patient": "mary" })
+  );
+
+  // tags used twice in a pattern need the same value at both paths
+  let same: TransferRule = "[ a: #x, b: #x ] => [ c: #x ]".parse().unwrap();
+  let fs: NodeRef = "[ a: x, b: x ]".parse().unwrap();
+  assert_eq!(
+    json(&same.apply(&fs).unwrap()),
+    serde_json::json!({ "c": "x" })
+  );
+  assert!(same.apply(&"[ a: x, b: y ]".parse().unwrap()).is_none());
+  assert!(same.apply(&"[ a: x ]".parse().unwrap()).is_none());
+
+  assert!("[ a: x ]".parse::<TransferRule>().is_err());
+  assert!("[ a: x ] => [ b: y ] extra"
+    .parse::<TransferRule>()
+    .is_err());
+  assert!("[ a: x ] =>\n[ a: y ] => [ b: y ]"
+    .parse::<Transfer>()
+    .is_err());
+}
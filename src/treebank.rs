@@ -0,0 +1,294 @@
+//! Reading gold trees from Penn Treebank style files, and scoring parses
+//! against them with labeled brackets (like evalb).
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::sexpr::{parse_tree, tokenize, Token};
+use crate::syntree::{Constituent, SynTree, Word};
+use crate::utils::Err;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreebankOptions {
+  /// Turn labels like `NP-SBJ-1` or `NP=2` into `NP`
+  pub strip_function_tags: bool,
+  /// Remove `-NONE-` empty elements (traces and null complementizers), and
+  /// any constituents left with no words
+  pub remove_empty: bool,
+  /// Lowercase the words, like the CLI does to its input
+  pub lowercase: bool,
+}
+
+impl Default for TreebankOptions {
+  fn default() -> Self {
+    Self {
+      strip_function_tags: true,
+      remove_empty: true,
+      lowercase: false,
+    }
+  }
+}
+
+/// Reads every tree in `s`. Trees can be wrapped in an unlabeled outer
+/// bracket, like `( (S ...) )`, as in the Penn Treebank. Unlike `from_sexpr`,
+/// `@` isn't special, since spans are worked out from the words.
+pub fn read_treebank(s: &str, opts: &TreebankOptions) -> Result<Vec<SynTree<String, String>>, Err> {
+  let tokens = tokenize(s, false)?;
+  let mut trees = Vec::new();
+  let mut pos = 0;
+  while pos < tokens.len() {
+    let wrapped = tokens[pos] == Token::Open && tokens.get(pos + 1) == Some(&Token::Open);
+    if wrapped {
+      pos += 1;
+    }
+    let tree = parse_tree(&tokens, &mut pos, &mut 0)?;
+    if wrapped {
+      if tokens.get(pos) != Some(&Token::Close) {
+        return Err(format!("expected ) after {}", tree.to_sexpr()).into());
+      }
+      pos += 1;
+    }
+
+    match clean(tree, opts) {
+      Some(mut tree) => {
+        respan(&mut tree, &mut 0);
+        trees.push(tree);
+      }
+      None => return Err("tree has no words".into()),
+    }
+  }
+  Ok(trees)
+}
+
+fn strip_function_tags(label: &str) -> &str {
+  // -NONE-, -LRB- and friends are whole labels
+  if label.starts_with('-') {
+    return label;
+  }
+  match label.find(['-', '=']) {
+    Some(0) | None => label,
+    Some(idx) => &label[..idx],
+  }
+}
+
+/// Applies `opts` to a tree, returning `None` if nothing is left of it
+fn clean(tree: SynTree<String, String>, opts: &TreebankOptions) -> Option<SynTree<String, String>> {
+  match tree {
+    SynTree::Leaf(mut w) => {
+      if opts.lowercase {
+        w.value = w.value.to_lowercase();
+      }
+      Some(SynTree::Leaf(w))
+    }
+    SynTree::Branch(mut c, children) => {
+      if opts.remove_empty && c.value == "-NONE-" {
+        return None;
+      }
+      let children = children
+        .into_iter()
+        .filter_map(|child| clean(child, opts))
+        .collect::<Vec<_>>();
+      if opts.remove_empty && children.is_empty() {
+        return None;
+      }
+      if opts.strip_function_tags {
+        c.value = strip_function_tags(&c.value).to_string();
+      }
+      Some(SynTree::Branch(c, children))
+    }
+  }
+}
+
+/// Numbers the words from `next_word`, and sets every span to cover its words
+fn respan<T, U>(tree: &mut SynTree<T, U>, next_word: &mut usize) {
+  match tree {
+    SynTree::Leaf(Word { span, .. }) => {
+      *span = (*next_word, *next_word + 1);
+      *next_word += 1;
+    }
+    SynTree::Branch(Constituent { span, .. }, children) => {
+      let start = *next_word;
+      for child in children.iter_mut() {
+        respan(child, next_word);
+      }
+      *span = (start, *next_word);
+    }
+  }
+}
+
+/// The labeled brackets of a tree, skipping preterminals (constituents with
+/// a single word under them)
+pub fn brackets<T, U>(tree: &SynTree<T, U>) -> Vec<(String, (usize, usize))>
+where
+  T: fmt::Display,
+{
+  let mut out = Vec::new();
+  collect_brackets(tree, &mut out);
+  out
+}
+
+fn collect_brackets<T, U>(tree: &SynTree<T, U>, out: &mut Vec<(String, (usize, usize))>)
+where
+  T: fmt::Display,
+{
+  if let SynTree::Branch(c, children) = tree {
+    if !matches!(children.as_slice(), [SynTree::Leaf(_)]) {
+      out.push((c.value.to_string(), c.span));
+    }
+    for child in children {
+      collect_brackets(child, out);
+    }
+  }
+}
+
+/// Labeled bracket counts for one or more parses. Add scores together to get
+/// corpus-level numbers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BracketScore {
+  /// Brackets in both trees
+  pub matched: usize,
+  pub gold: usize,
+  pub test: usize,
+}
+
+impl BracketScore {
+  pub fn compare<T, U, V, W>(gold: &SynTree<T, U>, test: &SynTree<V, W>) -> Self
+  where
+    T: fmt::Display,
+    V: fmt::Display,
+  {
+    let (gold, test) = (brackets(gold), brackets(test));
+    let mut counts = HashMap::new();
+    for b in gold.iter() {
+      *counts.entry(b).or_insert(0) += 1;
+    }
+    let mut matched = 0;
+    for b in test.iter() {
+      if let Some(n) = counts.get_mut(b).filter(|n| **n > 0) {
+        *n -= 1;
+        matched += 1;
+      }
+    }
+    Self {
+      matched,
+      gold: gold.len(),
+      test: test.len(),
+    }
+  }
+
+  pub fn precision(&self) -> f64 {
+    ratio(self.matched, self.test)
+  }
+
+  pub fn recall(&self) -> f64 {
+    ratio(self.matched, self.gold)
+  }
+
+  pub fn f1(&self) -> f64 {
+    let (p, r) = (self.precision(), self.recall());
+    if p + r == 0.0 {
+      0.0
+    } else {
+      2.0 * p * r / (p + r)
+    }
+  }
+}
+
+/// Empty sets of brackets match perfectly
+fn ratio(n: usize, d: usize) -> f64 {
+  if d == 0 {
+    1.0
+  } else {
+    n as f64 / d as f64
+  }
+}
+
+impl std::ops::Add for BracketScore {
+  type Output = Self;
+
+  fn add(self, other: Self) -> Self {
+    Self {
+      matched: self.matched + other.matched,
+      gold: self.gold + other.gold,
+      test: self.test + other.test,
+    }
+  }
+}
+
+impl fmt::Display for BracketScore {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "P {:.2} R {:.2} F1 {:.2} ({}/{} gold, {} test)",
+      self.precision() * 100.0,
+      self.recall() * 100.0,
+      self.f1() * 100.0,
+      self.matched,
+      self.gold,
+      self.test
+    )
+  }
+}
+
+#[test]
+fn test_treebank() {
+  let src = "
+    ( (S (NP-SBJ-1 (NNP Mary))
+         (VP (VBD fell)
+             (NP (-NONE- *-1)))
+         (. .)) )
+    (S (NP (DT the) (NN email@example)) (VP (VBD bounced)))
+  ";
+  let trees = read_treebank(
+    src,
+    &TreebankOptions {
+      lowercase: true,
+      ..TreebankOptions::default()
+    },
+  )
+  .unwrap();
+  assert_eq!(trees.len(), 2);
+  assert_eq!(
+    trees[0].to_sexpr_with_spans(),
+    "(S@0..3 (NP@0..1 (NNP@0..1 mary@0..1)) (VP@1..2 (VBD@1..2 fell@1..2)) (.@2..3 .@2..3))"
+  );
+  assert_eq!(
+    trees[1].to_sexpr(),
+    "(S (NP (DT the) (NN \"email@example\")) (VP (VBD bounced)))"
+  );
+
+  let kept = read_treebank(
+    src,
+    &TreebankOptions {
+      strip_function_tags: false,
+      remove_empty: false,
+      lowercase: false,
+    },
+  )
+  .unwrap();
+  assert_eq!(
+    kept[0].to_sexpr(),
+    "(S (NP-SBJ-1 (NNP Mary)) (VP (VBD fell) (NP (-NONE- *-1))) (. .))"
+  );
+  assert!(read_treebank("( (S (N x))", &TreebankOptions::default()).is_err());
+
+  let gold = &trees[1];
+  // `@` starts a span in from_sexpr, so it needs quotes there
+  let test = SynTree::from_sexpr("(S (DT the) (VP (NN \"email@example\") (VBD bounced)))").unwrap();
+  // only S matches: gold has NP 0..2 and VP 2..3, test has VP 1..3
+  let score = BracketScore::compare(gold, &test);
+  assert_eq!(
+    score,
+    BracketScore {
+      matched: 1,
+      gold: 3,
+      test: 2
+    }
+  );
+  assert_eq!(
+    score.to_string(),
+    "P 50.00 R 33.33 F1 40.00 (1/3 gold, 2 test)"
+  );
+  let total = score + BracketScore::compare(gold, gold);
+  assert_eq!((total.matched, total.gold, total.test), (4, 6, 5));
+}
@@ -0,0 +1,563 @@
+//! Two-level (Koskenniemi-style) spelling rules, for grammars whose words
+//! are built from morphemes that change at the seams, like `happy+er` being
+//! spelled `happier`. Each rule constrains how a lexical symbol may be
+//! realized on the surface, and the two sides are aligned symbol by symbol,
+//! with `0` standing for nothing, so `happy+er` and `happier` line up as
+//! `h a p p y:i +:0 e r`.
+//!
+//! A rules file has one rule or set per line, with `//` comments:
+//!
+//! ```text
+//! C = b c d f g h k l m n p r s t v z
+//! // y is spelled i before a suffix, and only there
+//! y:i <=> C _ +:0
+//! // a silent e drops before a suffix starting with a vowel
+//! e:0 <=> _ +:0 V
+//! ```
+//!
+//! A rule is a pair, an operator, and contexts (separated by `|`) with `_`
+//! where the pair goes:
+//!
+//! - `a:b => ...` a:b only occurs in one of the contexts
+//! - `a:b <= ...` in one of the contexts, a is always realized as b
+//! - `a:b <=> ...` both
+//! - `a:b /<= ...` a:b never occurs in the contexts
+//!
+//! In contexts, `a:b` is a pair, `a:` (or just `a`) is a lexical `a` with any
+//! realization, `:b` is anything realized as `b`, `?` is any pair, and `#` is
+//! the edge of the word. Either side can be a set. Every symbol can also be
+//! realized as itself, and the morpheme boundary `+` as nothing, without a
+//! rule saying so.
+//!
+//! The grammar sees morphemes as tokens, with `-` in front of every one after
+//! the first (`happy -er`), so `Grammar::parse_surface` parses surface words
+//! and `TwoLevel::realize` spells out the tokens of generated trees.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::rules::Grammar;
+use crate::utils::Err;
+use crate::ParsedTree;
+
+/// Separates morphemes in lexical forms
+pub const MORPHEME_BOUNDARY: char = '+';
+/// Marks the morphemes after the first in a word as the grammar's tokens
+pub const BOUND_PREFIX: &str = "-";
+
+/// How many symbols in a row can be inserted (`0:x`) while generating
+const MAX_INSERTIONS: usize = 1;
+/// How many symbols in a row can be deleted (`x:0`), like `e:0 +:0`
+const MAX_DELETIONS: usize = 2;
+
+/// A lexical symbol and its realization, None for `0`
+pub type Pair = (Option<char>, Option<char>);
+
+/// One side of a pattern in a context
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Side {
+  Any,
+  Symbol(Option<char>),
+  Set(Vec<char>),
+}
+
+impl Side {
+  fn matches(&self, c: Option<char>) -> bool {
+    match self {
+      Self::Any => true,
+      Self::Symbol(s) => *s == c,
+      Self::Set(set) => c.is_some_and(|c| set.contains(&c)),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ContextItem {
+  Pair(Side, Side),
+  /// `#`
+  Edge,
+}
+
+/// The symbols to the left and right of a rule's pair
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Context {
+  left: Vec<ContextItem>,
+  right: Vec<ContextItem>,
+}
+
+impl Context {
+  /// Whether `pairs[at]` is in this context
+  fn matches(&self, pairs: &[Pair], at: usize) -> bool {
+    let item_matches = |item: &ContextItem, pair: &Pair| match item {
+      ContextItem::Pair(l, s) => l.matches(pair.0) && s.matches(pair.1),
+      ContextItem::Edge => false,
+    };
+
+    let mut idx = at;
+    for item in self.left.iter().rev() {
+      if *item == ContextItem::Edge {
+        if idx != 0 {
+          return false;
+        }
+        continue;
+      }
+      if idx == 0 || !item_matches(item, &pairs[idx - 1]) {
+        return false;
+      }
+      idx -= 1;
+    }
+    let mut idx = at + 1;
+    for item in &self.right {
+      if *item == ContextItem::Edge {
+        if idx != pairs.len() {
+          return false;
+        }
+        continue;
+      }
+      if idx >= pairs.len() || !item_matches(item, &pairs[idx]) {
+        return false;
+      }
+      idx += 1;
+    }
+    true
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+  /// `=>`
+  OnlyIn,
+  /// `<=`
+  AlwaysIn,
+  /// `<=>`
+  Both,
+  /// `/<=`
+  NeverIn,
+}
+
+impl fmt::Display for Operator {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::OnlyIn => write!(f, "=>"),
+      Self::AlwaysIn => write!(f, "<="),
+      Self::Both => write!(f, "<=>"),
+      Self::NeverIn => write!(f, "/<="),
+    }
+  }
+}
+
+/// One rule, see the module docs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TwoLevelRule {
+  pub pair: Pair,
+  pub operator: Operator,
+  contexts: Vec<Context>,
+  /// The rule's source, for messages
+  pub source: String,
+  /// The (1-based) line it was on
+  pub line: usize,
+}
+
+impl TwoLevelRule {
+  fn in_context(&self, pairs: &[Pair], at: usize) -> bool {
+    self.contexts.iter().any(|c| c.matches(pairs, at))
+  }
+
+  /// Whether an alignment obeys the rule
+  pub fn allows(&self, pairs: &[Pair]) -> bool {
+    (0..pairs.len()).all(|at| {
+      let pair = pairs[at];
+      let only_in = matches!(self.operator, Operator::OnlyIn | Operator::Both);
+      let always_in = matches!(self.operator, Operator::AlwaysIn | Operator::Both);
+      if pair == self.pair {
+        match self.operator {
+          Operator::NeverIn => !self.in_context(pairs, at),
+          _ => !only_in || self.in_context(pairs, at),
+        }
+      } else {
+        !(always_in && pair.0 == self.pair.0 && self.in_context(pairs, at))
+      }
+    })
+  }
+}
+
+impl fmt::Display for TwoLevelRule {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.source)
+  }
+}
+
+/// A set of rules, see the module docs
+#[derive(Debug, Clone, Default)]
+pub struct TwoLevel {
+  pub rules: Vec<TwoLevelRule>,
+  /// The pairs besides identities that an alignment can use: every rule's
+  /// pair, and `+:0`
+  pairs: Vec<Pair>,
+}
+
+fn parse_symbol(s: &str) -> Result<Option<char>, Err> {
+  let mut chars = s.chars();
+  match (chars.next(), chars.next()) {
+    (Some('0'), None) => Ok(None),
+    (Some(c), None) => Ok(Some(c)),
+    _ => Err(format!("expected a single symbol, found {:?}", s).into()),
+  }
+}
+
+fn parse_side(s: &str, sets: &HashMap<String, Vec<char>>) -> Result<Side, Err> {
+  if s.is_empty() {
+    Ok(Side::Any)
+  } else if let Some(set) = sets.get(s) {
+    Ok(Side::Set(set.clone()))
+  } else {
+    parse_symbol(s)
+      .map(Side::Symbol)
+      .map_err(|e| format!("{} (or a set)", e).into())
+  }
+}
+
+fn parse_item(s: &str, sets: &HashMap<String, Vec<char>>) -> Result<ContextItem, Err> {
+  match s {
+    "#" => Ok(ContextItem::Edge),
+    "?" => Ok(ContextItem::Pair(Side::Any, Side::Any)),
+    _ => match s.split_once(':') {
+      Some((l, r)) => Ok(ContextItem::Pair(
+        parse_side(l, sets)?,
+        parse_side(r, sets)?,
+      )),
+      None => Ok(ContextItem::Pair(parse_side(s, sets)?, Side::Any)),
+    },
+  }
+}
+
+fn parse_context(s: &str, sets: &HashMap<String, Vec<char>>) -> Result<Context, Err> {
+  let tokens = s.split_whitespace().collect::<Vec<_>>();
+  let center = tokens
+    .iter()
+    .position(|t| *t == "_")
+    .ok_or_else(|| format!("expected _ in context {:?}", s.trim()))?;
+  let left = tokens[..center]
+    .iter()
+    .map(|t| parse_item(t, sets))
+    .collect::<Result<Vec<_>, _>>()?;
+  let right = tokens[center + 1..]
+    .iter()
+    .map(|t| parse_item(t, sets))
+    .collect::<Result<Vec<_>, _>>()?;
+  let edge_inside = left.iter().skip(1).any(|i| *i == ContextItem::Edge)
+    || right.iter().rev().skip(1).any(|i| *i == ContextItem::Edge);
+  if edge_inside {
+    return Err(format!("# can only be at the ends of a context: {:?}", s.trim()).into());
+  }
+  Ok(Context { left, right })
+}
+
+fn parse_rule(s: &str, sets: &HashMap<String, Vec<char>>) -> Result<TwoLevelRule, Err> {
+  let mut parts = s.trim().splitn(3, char::is_whitespace);
+  let (pair, operator, contexts) = match (parts.next(), parts.next(), parts.next()) {
+    (Some(pair), Some(op), Some(contexts)) => (pair, op, contexts),
+    _ => return Err(format!("expected a pair, an operator and contexts: {:?}", s.trim()).into()),
+  };
+  let (l, r) = pair
+    .split_once(':')
+    .ok_or_else(|| format!("expected a pair like a:b, found {:?}", pair))?;
+  let pair = (parse_symbol(l)?, parse_symbol(r)?);
+  if pair == (None, None) {
+    return Err("0:0 isn't a pair".into());
+  }
+  let operator = match operator {
+    "=>" => Operator::OnlyIn,
+    "<=" => Operator::AlwaysIn,
+    "<=>" => Operator::Both,
+    "/<=" => Operator::NeverIn,
+    op => return Err(format!("unknown operator {:?}", op).into()),
+  };
+  if pair.0.is_none() && matches!(operator, Operator::AlwaysIn | Operator::Both) {
+    return Err(format!("{} can't force an insertion, use =>", operator).into());
+  }
+  Ok(TwoLevelRule {
+    pair,
+    operator,
+    contexts: contexts
+      .split('|')
+      .map(|c| parse_context(c, sets))
+      .collect::<Result<_, _>>()?,
+    source: s.trim().to_string(),
+    line: 0,
+  })
+}
+
+impl FromStr for TwoLevel {
+  type Err = Err;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut sets = HashMap::new();
+    let mut rules = Vec::new();
+    for (idx, line) in s.lines().enumerate() {
+      let line = match line.find("//") {
+        Some(pos) => &line[..pos],
+        None => line,
+      };
+      if line.trim().is_empty() {
+        continue;
+      }
+      let in_line = |e: Err| -> Err { format!("line {}: {}", idx + 1, e).into() };
+      if let Some((name, members)) = line.split_once(" = ") {
+        let members = members
+          .split_whitespace()
+          .map(parse_symbol)
+          .map(|s| s.and_then(|s| s.ok_or_else(|| "0 can't be in a set".into())))
+          .collect::<Result<Vec<_>, _>>()
+          .map_err(in_line)?;
+        sets.insert(name.trim().to_string(), members);
+      } else {
+        let mut rule = parse_rule(line, &sets).map_err(in_line)?;
+        rule.line = idx + 1;
+        rules.push(rule);
+      }
+    }
+    Ok(Self::new(rules))
+  }
+}
+
+impl TwoLevel {
+  pub fn new(rules: Vec<TwoLevelRule>) -> Self {
+    let mut pairs = vec![(Some(MORPHEME_BOUNDARY), None)];
+    for rule in &rules {
+      if !pairs.contains(&rule.pair) && rule.pair.0 != rule.pair.1 {
+        pairs.push(rule.pair);
+      }
+    }
+    Self { rules, pairs }
+  }
+
+  /// Whether an alignment obeys every rule
+  pub fn allows(&self, pairs: &[Pair]) -> bool {
+    self.rules.iter().all(|r| r.allows(pairs))
+  }
+
+  /// The pairs that can realize lexical `c`, or that `c` can be the surface
+  /// of, identity first
+  fn pairs_for(&self, c: char, lexical: bool) -> Vec<Pair> {
+    let mut out = Vec::new();
+    if c != MORPHEME_BOUNDARY {
+      out.push((Some(c), Some(c)));
+    }
+    let side = |p: &Pair| if lexical { p.0 } else { p.1 };
+    out.extend(self.pairs.iter().filter(|p| side(p) == Some(c)));
+    out
+  }
+
+  /// Every alignment of `input` (one side of it) that `keep` doesn't prune,
+  /// before checking the rules
+  fn align(
+    &self,
+    input: &[char],
+    lexical: bool,
+    keep: &dyn Fn(&[Pair]) -> bool,
+    pairs: &mut Vec<Pair>,
+    empty_run: usize,
+    out: &mut Vec<Vec<Pair>>,
+  ) {
+    if !keep(pairs) {
+      return;
+    }
+    let pos = pairs
+      .iter()
+      .filter(|p| if lexical { p.0 } else { p.1 }.is_some())
+      .count();
+    if pos == input.len() {
+      out.push(pairs.clone());
+    }
+    if let Some(&c) = input.get(pos) {
+      for pair in self.pairs_for(c, lexical) {
+        pairs.push(pair);
+        self.align(input, lexical, keep, pairs, 0, out);
+        pairs.pop();
+      }
+    }
+    let max_run = if lexical {
+      MAX_INSERTIONS
+    } else {
+      MAX_DELETIONS
+    };
+    if empty_run < max_run {
+      let empty = self.pairs.iter().filter(|p| {
+        if lexical {
+          p.0.is_none()
+        } else {
+          p.1.is_none()
+        }
+      });
+      for &pair in empty {
+        pairs.push(pair);
+        self.align(input, lexical, keep, pairs, empty_run + 1, out);
+        pairs.pop();
+      }
+    }
+  }
+
+  /// The surface forms of a lexical form like `happy+er`
+  pub fn generate(&self, lexical: &str) -> Vec<String> {
+    let input = lexical.chars().collect::<Vec<_>>();
+    let mut alignments = Vec::new();
+    self.align(&input, true, &|_| true, &mut Vec::new(), 0, &mut alignments);
+    let mut out: Vec<String> = Vec::new();
+    for pairs in alignments.iter().filter(|p| self.allows(p)) {
+      let surface = pairs.iter().filter_map(|p| p.1).collect::<String>();
+      if !out.contains(&surface) {
+        out.push(surface);
+      }
+    }
+    out
+  }
+
+  /// The lexical forms of a surface word whose morphemes are all in
+  /// `lexicon`, which is asked about them as tokens (with `BOUND_PREFIX` in
+  /// front of all but the first)
+  pub fn analyze(&self, surface: &str, lexicon: impl Fn(&str) -> bool) -> Vec<String> {
+    let input = surface.chars().collect::<Vec<_>>();
+    let known = |lexical: &str| {
+      Self::tokens(lexical)
+        .last()
+        .is_some_and(|t| !t.is_empty() && t != BOUND_PREFIX && lexicon(t))
+    };
+    // checks each morpheme as soon as its boundary is placed
+    let keep = |pairs: &[Pair]| match pairs.last() {
+      Some((Some(MORPHEME_BOUNDARY), _)) => {
+        let lexical = pairs.iter().filter_map(|p| p.0).collect::<String>();
+        known(&lexical[..lexical.len() - 1])
+      }
+      _ => true,
+    };
+    let mut alignments = Vec::new();
+    self.align(&input, false, &keep, &mut Vec::new(), 0, &mut alignments);
+    let mut out: Vec<String> = Vec::new();
+    for pairs in alignments.iter().filter(|p| self.allows(p)) {
+      let lexical = pairs.iter().filter_map(|p| p.0).collect::<String>();
+      if known(&lexical) && !out.contains(&lexical) {
+        out.push(lexical);
+      }
+    }
+    out
+  }
+
+  /// Splits a lexical form into the grammar's tokens: `happy+er` into
+  /// `happy` and `-er`
+  pub fn tokens(lexical: &str) -> Vec<String> {
+    lexical
+      .split(MORPHEME_BOUNDARY)
+      .enumerate()
+      .map(|(idx, m)| {
+        if idx == 0 {
+          m.to_string()
+        } else {
+          format!("{}{}", BOUND_PREFIX, m)
+        }
+      })
+      .collect()
+  }
+
+  /// Spells out a sequence of the grammar's tokens, like the leaves of a
+  /// generated tree, joining bound morphemes onto the word before them. Each
+  /// word gets its first surface form, or its lexical form if the rules
+  /// allow none.
+  pub fn realize<S: AsRef<str>>(&self, tokens: &[S]) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    for token in tokens {
+      let token = token.as_ref();
+      match (token.strip_prefix(BOUND_PREFIX), words.last_mut()) {
+        (Some(bound), Some(word)) if !bound.is_empty() => {
+          word.push(MORPHEME_BOUNDARY);
+          word.push_str(bound);
+        }
+        _ => words.push(token.to_string()),
+      }
+    }
+    words
+      .into_iter()
+      .map(|w| self.generate(&w).into_iter().next().unwrap_or(w))
+      .collect()
+  }
+}
+
+impl Grammar {
+  /// Parses surface words, analyzing each with `rules` into morphemes the
+  /// grammar has (see `twolevel`). A word with several analyses gives the
+  /// parses of each.
+  pub fn parse_surface<I>(&self, rules: &TwoLevel, words: I) -> Vec<ParsedTree>
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    let mut sentences: Vec<Vec<String>> = vec![Vec::new()];
+    for word in words {
+      let analyses = rules.analyze(word.as_ref(), |t| self.is_known_word(t));
+      sentences = sentences
+        .iter()
+        .flat_map(|prefix| {
+          analyses.iter().map(move |lexical| {
+            let mut tokens = prefix.clone();
+            tokens.extend(TwoLevel::tokens(lexical));
+            tokens
+          })
+        })
+        .collect();
+    }
+    sentences
+      .into_iter()
+      .flat_map(|tokens| self.parse(tokens))
+      .collect()
+  }
+}
+
+#[test]
+fn test_twolevel() {
+  let rules: TwoLevel = r#"
+    C = b c d f g h k l m n p r s t v z
+    V = a e i o u
+    // y is spelled i after a consonant, before a suffix
+    y:i <=> C _ +:0
+    // a silent e drops before a suffix starting with a vowel
+    e:0 <=> C _ +:0 V
+  "#
+  .parse()
+  .unwrap();
+
+  assert_eq!(rules.generate("happy+er"), ["happier"]);
+  assert_eq!(rules.generate("play+er"), ["player"]);
+  assert_eq!(rules.generate("move+ing"), ["moving"]);
+  assert_eq!(rules.generate("move+s"), ["moves"]);
+  assert_eq!(rules.generate("happy"), ["happy"]);
+
+  let lexicon = ["happy", "move", "-er", "-ing", "-s", "mover"];
+  let lexicon = |t: &str| lexicon.contains(&t);
+  assert_eq!(rules.analyze("happier", lexicon), ["happy+er"]);
+  assert_eq!(rules.analyze("moving", lexicon), ["move+ing"]);
+  assert_eq!(rules.analyze("mover", lexicon), ["mover", "move+er"]);
+  assert!(rules.analyze("happyer", lexicon).is_empty());
+
+  let g: Grammar = r#"
+    S -> N V
+    N -> mary
+    V[ tense: prog ] -> Aux Vstem Prog
+    Aux -> is
+    Vstem -> move
+    Prog -> -ing
+  "#
+  .parse()
+  .unwrap();
+  let trees = g.parse_surface(&rules, ["mary", "is", "moving"]);
+  assert_eq!(trees.len(), 1);
+  let leaves = trees[0].0.yield_tokens();
+  assert_eq!(leaves, ["mary", "is", "move", "-ing"]);
+  assert_eq!(rules.realize(&leaves), ["mary", "is", "moving"]);
+
+  let e = "y:i <=> Cons _".parse::<TwoLevel>().unwrap_err();
+  assert!(e
+    .to_string()
+    .starts_with("line 1: expected a single symbol"));
+  assert!("0:e <= a _ b".parse::<TwoLevel>().is_err());
+}
@@ -1,10 +1,14 @@
+//! Helpers shared across the crate. `Err` is part of the interface (and in
+//! the prelude); the rest are here for the crate's own use, and may change.
+
 use std::error::Error;
 
 /// Boxed static error type
 pub type Err = Box<dyn Error + 'static>;
 
 /// Takes a list where each element is a set of choices, and returns all the possible sets
-/// generated. Will clone the elements.
+/// generated. Will clone the elements. This builds every set at once, see
+/// `Combinations` to build them as they're needed.
 ///
 /// ```
 /// let v = vec![
@@ -27,24 +31,109 @@ pub fn combinations<T>(list: &[Vec<T>]) -> Vec<Vec<T>>
 where
   T: Clone,
 {
-  if list.is_empty() {
-    Vec::new()
-  } else if list.len() == 1 {
-    list[0].iter().map(|e| vec![e.clone()]).collect()
-  } else {
-    let (head, tail) = list.split_at(1);
-    let head = &head[0];
-
-    combinations(tail)
-      .into_iter()
-      .flat_map(|subseq| {
-        // prepend every element of the head to every possible subseq
-        head.iter().map(move |v| {
-          let mut newseq = subseq.clone();
-          newseq.insert(0, v.clone());
-          newseq
-        })
-      })
-      .collect()
+  Combinations::new(list.to_vec()).collect()
+}
+
+/// Lazy version of `combinations`, yielding the sets in the same order (the
+/// first element changes fastest) without building them all up front
+#[derive(Debug, Clone)]
+pub struct Combinations<T> {
+  choices: Vec<Vec<T>>,
+  /// Which choice of each element the next set uses, or None when done
+  indices: Option<Vec<usize>>,
+}
+
+impl<T: Clone> Combinations<T> {
+  pub fn new(choices: Vec<Vec<T>>) -> Self {
+    let done = choices.is_empty() || choices.iter().any(Vec::is_empty);
+    Self {
+      indices: (!done).then(|| vec![0; choices.len()]),
+      choices,
+    }
+  }
+}
+
+impl<T: Clone> Iterator for Combinations<T> {
+  type Item = Vec<T>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let indices = self.indices.as_mut()?;
+    let set = indices
+      .iter()
+      .zip(self.choices.iter())
+      .map(|(&idx, choices)| choices[idx].clone())
+      .collect();
+
+    // advance like an odometer, carrying into later elements
+    let mut pos = 0;
+    loop {
+      if pos == indices.len() {
+        self.indices = None;
+        break;
+      }
+      indices[pos] += 1;
+      if indices[pos] < self.choices[pos].len() {
+        break;
+      }
+      indices[pos] = 0;
+      pos += 1;
+    }
+
+    Some(set)
+  }
+}
+
+/// Escapes a string for use in HTML text or attribute values
+/// Empties every row and resizes `rows` to `length`. Rows past `length` are
+/// kept in `spare` instead of being dropped, and rows are taken from it when
+/// growing, so a buffer that's reset over and over keeps its allocations.
+pub(crate) fn reset_rows<T>(rows: &mut Vec<Vec<T>>, spare: &mut Vec<Vec<T>>, length: usize) {
+  for row in rows.iter_mut() {
+    row.clear();
+  }
+  while rows.len() > length {
+    spare.extend(rows.pop());
+  }
+  while rows.len() < length {
+    rows.push(spare.pop().unwrap_or_default());
+  }
+}
+
+pub(crate) fn escape_html(s: &str) -> String {
+  let mut out = String::with_capacity(s.len());
+  for c in s.chars() {
+    match c {
+      '&' => out += "&amp;",
+      '<' => out += "&lt;",
+      '>' => out += "&gt;",
+      '"' => out += "&quot;",
+      '\'' => out += "&#39;",
+      c => out.push(c),
+    }
   }
+  out
+}
+
+/// Escapes a string for use as a quoted mermaid node label
+pub(crate) fn escape_mermaid(s: &str) -> String {
+  s.replace('"', "#quot;")
+}
+
+#[test]
+fn test_lazy_combinations() {
+  let v = vec![vec![1, 2], vec![3], vec![4, 5, 6]];
+  assert_eq!(Combinations::new(v.clone()).count(), 6);
+  assert_eq!(
+    Combinations::new(v).take(3).collect::<Vec<_>>(),
+    vec![vec![1, 3, 4], vec![2, 3, 4], vec![1, 3, 5]]
+  );
+  assert_eq!(Combinations::new(vec![vec![1], vec![]]).next(), None);
+  assert_eq!(Combinations::<u8>::new(Vec::new()).next(), None);
+
+  // only what's asked for gets built
+  let huge = vec![(0..1000).collect::<Vec<_>>(); 10];
+  assert_eq!(
+    Combinations::new(huge).nth(1),
+    Some(vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 0])
+  );
 }
@@ -0,0 +1,280 @@
+//! Importing valency dictionaries: lists of verbs and the case frames they
+//! take, turned into lexical entries with a list-valued subcat feature, so
+//! they don't have to be written by hand. A dictionary has a verb per line,
+//! with its frames separated by `|`, each a list of the cases of its
+//! arguments (subject first). `_` is an argument of any case, and `?` makes
+//! an argument optional. Blank lines and `//` comments are ignored:
+//!
+//! ```text
+//! sleeps: nom
+//! gives: nom dat acc | nom acc
+//! eats: nom acc?
+//! ```
+//!
+//! `gives` becomes two entries, the first of them
+//!
+//! ```text
+//! V[ lex: gives, subcat.first.case: nom, subcat.rest.first.case: dat,
+//!    subcat.rest.rest.first.case: acc, subcat.rest.rest.rest: nil ] -> gives
+//! ```
+//!
+//! (on one line), which a grammar can consume an argument at a time. See
+//! `ValencyOptions` for the names used.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::overlay::Overlay;
+use crate::Err;
+
+/// One argument of a case frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Argument {
+  /// The case the argument needs, or None for `_`
+  pub case: Option<String>,
+  pub optional: bool,
+}
+
+/// A verb and the frames it takes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValencyEntry {
+  pub word: String,
+  pub frames: Vec<Vec<Argument>>,
+  /// The (1-based) line of the dictionary the entry was on
+  pub line: usize,
+}
+
+impl ValencyEntry {
+  /// The entry's frames with optional arguments expanded, in order and
+  /// without duplicates: `nom acc?` is `nom acc` and `nom`
+  pub fn expanded_frames(&self) -> Vec<Vec<Option<&str>>> {
+    let mut out: Vec<Vec<Option<&str>>> = Vec::new();
+    for frame in &self.frames {
+      let mut expanded: Vec<Vec<Option<&str>>> = vec![Vec::new()];
+      for arg in frame {
+        let case = arg.case.as_deref();
+        let mut next = Vec::with_capacity(expanded.len() * 2);
+        for prefix in expanded {
+          if arg.optional {
+            next.push(prefix.clone());
+          }
+          let mut with = prefix;
+          with.push(case);
+          next.push(with);
+        }
+        expanded = next;
+      }
+      // longest first, so the full frame is the first entry
+      expanded.sort_by_key(|frame| std::cmp::Reverse(frame.len()));
+      for frame in expanded {
+        if !out.contains(&frame) {
+          out.push(frame);
+        }
+      }
+    }
+    out
+  }
+}
+
+/// The names generated entries use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValencyOptions {
+  /// The entries' symbol, `V` by default
+  pub symbol: String,
+  /// The list of arguments, `subcat` by default. Lists are `first` and
+  /// `rest` features, ending with `nil`.
+  pub feature: String,
+  /// The feature each argument's case goes in, `case` by default
+  pub case_feature: String,
+  /// The value that ends a list, `nil` by default
+  pub nil: String,
+  /// A feature to put the verb itself in, `lex` by default, like
+  /// `generate::inflect` expects
+  pub lex_feature: Option<String>,
+}
+
+impl Default for ValencyOptions {
+  fn default() -> Self {
+    Self {
+      symbol: "V".to_string(),
+      feature: "subcat".to_string(),
+      case_feature: "case".to_string(),
+      nil: "nil".to_string(),
+      lex_feature: Some("lex".to_string()),
+    }
+  }
+}
+
+/// A valency dictionary, see the module docs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValencyLexicon {
+  pub entries: Vec<ValencyEntry>,
+}
+
+/// Whether `s` can be written as a word or value in a grammar
+fn is_name(s: &str) -> bool {
+  !s.is_empty()
+    && s
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl FromStr for ValencyLexicon {
+  type Err = Err;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut entries = Vec::new();
+    for (idx, line) in s.lines().enumerate() {
+      let line_no = idx + 1;
+      let text = line.split("//").next().unwrap_or_default().trim();
+      if text.is_empty() {
+        continue;
+      }
+      let error = |msg: String| -> Err { format!("line {}: {}", line_no, msg).into() };
+
+      let (word, frames) = text
+        .split_once(':')
+        .ok_or_else(|| error(format!("expected word: frames, found {}", text)))?;
+      let word = word.trim();
+      if !is_name(word) {
+        return Err(error(format!("bad word {:?}", word)));
+      }
+
+      let mut parsed = Vec::new();
+      for frame in frames.split('|') {
+        let mut args = Vec::new();
+        for arg in frame.split_whitespace() {
+          let (case, optional) = match arg.strip_suffix('?') {
+            Some(case) => (case, true),
+            None => (arg, false),
+          };
+          let case = match case {
+            "_" => None,
+            case if is_name(case) => Some(case.to_string()),
+            _ => return Err(error(format!("bad argument {:?} for {}", arg, word))),
+          };
+          args.push(Argument { case, optional });
+        }
+        if args.is_empty() {
+          return Err(error(format!("empty frame for {}", word)));
+        }
+        parsed.push(args);
+      }
+
+      entries.push(ValencyEntry {
+        word: word.to_string(),
+        frames: parsed,
+        line: line_no,
+      });
+    }
+    Ok(Self { entries })
+  }
+}
+
+impl ValencyLexicon {
+  pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Self, Err> {
+    fs::read_to_string(path)?.parse()
+  }
+
+  /// The lexical entries as grammar rules, one per line, with each verb's
+  /// frames in order
+  pub fn to_fgr(&self, options: &ValencyOptions) -> String {
+    let mut out = String::new();
+    for entry in &self.entries {
+      for frame in entry.expanded_frames() {
+        let mut features = Vec::new();
+        if let Some(lex) = &options.lex_feature {
+          features.push(format!("{}: {}", lex, entry.word));
+        }
+        let mut path = options.feature.clone();
+        for case in frame {
+          match case {
+            Some(case) => {
+              features.push(format!("{}.first.{}: {}", path, options.case_feature, case))
+            }
+            None => features.push(format!("{}.first: **top**", path)),
+          }
+          path += ".rest";
+        }
+        features.push(format!("{}: {}", path, options.nil));
+        writeln!(
+          out,
+          "{}[ {} ] -> {}",
+          options.symbol,
+          features.join(", "),
+          entry.word
+        )
+        .unwrap();
+      }
+    }
+    out
+  }
+
+  /// The lexical entries as an overlay, to add to a grammar with
+  /// `Grammar::with_overlay`
+  pub fn to_overlay(&self, options: &ValencyOptions) -> Result<Overlay, Err> {
+    self.to_fgr(options).parse()
+  }
+}
+
+#[test]
+fn test_valency() {
+  use crate::Grammar;
+
+  let lexicon: ValencyLexicon = r#"
+    // a few verbs
+    sleeps: nom
+    sees: nom acc
+    helps: nom dat
+    gives: nom dat acc | nom acc
+    eats: nom acc?
+  "#
+  .parse()
+  .unwrap();
+  assert_eq!(lexicon.entries.len(), 5);
+  assert_eq!(lexicon.entries[3].line, 6);
+  assert_eq!(
+    lexicon.entries[4].expanded_frames(),
+    [vec![Some("nom"), Some("acc")], vec![Some("nom")]]
+  );
+  assert_eq!(
+    lexicon.to_fgr(&ValencyOptions::default()).lines().next(),
+    Some("V[ lex: sleeps, subcat.first.case: nom, subcat.rest: nil ] -> sleeps")
+  );
+
+  let base: Grammar = r#"
+    S -> NP[ case: #1 ] VP[ subcat.first.case: #1, subcat.rest: nil ]
+    VP[ subcat: #1 ] -> V[ subcat: #1 ]
+    VP[ subcat.first: #1, subcat.rest: #2 ] -> VP[ subcat.first: #1, subcat.rest.first.case: #3, subcat.rest.rest: #2 ] NP[ case: #3 ]
+    NP[ case: nom ] -> er
+    NP[ case: acc ] -> ihn
+    NP[ case: dat ] -> ihm
+    NP[ case: nom ] -> es
+    NP[ case: acc ] -> es
+    // the verbs come from the lexicon
+    V -> is
+  "#
+  .parse()
+  .unwrap();
+  let g = base
+    .with_overlay(&lexicon.to_overlay(&ValencyOptions::default()).unwrap())
+    .unwrap();
+  let parses = |s: &str| g.parse(s.split(' ')).len();
+  assert_eq!(parses("er sleeps"), 1);
+  assert_eq!(parses("er sleeps ihn"), 0);
+  assert_eq!(parses("er sees ihn"), 1);
+  assert_eq!(parses("er sees ihm"), 0);
+  assert_eq!(parses("er helps ihm"), 1);
+  assert_eq!(parses("er gives ihm es"), 1);
+  assert_eq!(parses("er gives es"), 1);
+  assert_eq!(parses("er gives es ihm"), 0);
+  assert_eq!(parses("er eats"), 1);
+  assert_eq!(parses("er eats es"), 1);
+
+  assert!("sleeps nom".parse::<ValencyLexicon>().is_err());
+  assert!("sleeps: nom |".parse::<ValencyLexicon>().is_err());
+  let e = "ok: nom\nbad: n.om".parse::<ValencyLexicon>().unwrap_err();
+  assert_eq!(e.to_string(), r#"line 2: bad argument "n.om" for bad"#);
+}
@@ -0,0 +1,197 @@
+//! Chart debugging: why a constituent you expected never formed. Given a
+//! symbol and a span, `Chart::why_no` finds the rules for that symbol the
+//! parser predicted at the start of the span, how far each one got, and what
+//! it was stuck waiting for.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::earley::{Chart, LR0};
+use crate::rules::{Grammar, Production, Rule};
+use crate::with_tokens;
+
+/// How far one rule got toward a constituent
+#[derive(Debug, Clone)]
+pub struct Attempt {
+  pub rule: Arc<Rule>,
+  /// How many of the rule's productions were matched
+  pub matched: usize,
+  /// The position the matched productions end at
+  pub reached: usize,
+}
+
+impl Attempt {
+  /// The production the rule was stuck on, or None if it was complete
+  pub fn waiting_for(&self) -> Option<&Production> {
+    self.rule.productions.get(self.matched)
+  }
+
+  pub fn is_complete(&self) -> bool {
+    self.matched == self.rule.len()
+  }
+}
+
+/// The rules that tried to build `symbol` over `start..end`, from
+/// `Chart::why_no`
+#[derive(Debug, Clone)]
+pub struct WhyNo {
+  pub symbol: String,
+  pub start: usize,
+  pub end: usize,
+  /// The furthest each predicted rule got without going past `end`, in the
+  /// grammar's order. Empty if nothing predicted `symbol` at `start`.
+  pub attempts: Vec<Attempt>,
+}
+
+impl WhyNo {
+  pub fn was_predicted(&self) -> bool {
+    !self.attempts.is_empty()
+  }
+
+  /// Whether some rule did build the constituent. If it did but there's
+  /// still no tree with it, its features failed to unify (see
+  /// `Grammar::judge`), or nothing above it used it.
+  pub fn formed(&self) -> bool {
+    self
+      .attempts
+      .iter()
+      .any(|a| a.is_complete() && a.reached == self.end)
+  }
+}
+
+impl fmt::Display for WhyNo {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} over {}..{}", self.symbol, self.start, self.end)?;
+    if !self.was_predicted() {
+      return write!(f, ": never predicted at {}", self.start);
+    }
+    if self.formed() {
+      write!(f, ": formed")?;
+    }
+    for attempt in self.attempts.iter() {
+      let lr0 = LR0 {
+        rule: attempt.rule.clone(),
+        pos: attempt.matched,
+      };
+      write!(f, "\n  {}  ({}..{}, ", lr0, self.start, attempt.reached)?;
+      match attempt.waiting_for() {
+        Some(p) => write!(f, "waiting for {} at {}", p, attempt.reached)?,
+        None if attempt.reached == self.end => write!(f, "complete")?,
+        None => write!(f, "complete, but ends before {}", self.end)?,
+      }
+      if let Some(location) = attempt.rule.location() {
+        write!(f, ", {}", location)?;
+      }
+      write!(f, ")")?;
+    }
+    Ok(())
+  }
+}
+
+impl Chart {
+  /// Why there's no `symbol` over `start..end`: which of its rules were
+  /// predicted at `start`, and for each, the furthest it got toward `end`
+  /// (the state that reached furthest, then matched the most) and what it
+  /// needed next.
+  pub fn why_no(&self, symbol: &str, start: usize, end: usize) -> WhyNo {
+    let mut attempts: Vec<Attempt> = Vec::new();
+    for k in start..=end.min(self.len().saturating_sub(1)) {
+      for state in self.states_at(k) {
+        if state.origin != start || state.lr0.rule.symbol != symbol {
+          continue;
+        }
+        let attempt = Attempt {
+          rule: state.lr0.rule.clone(),
+          matched: state.lr0.pos,
+          reached: k,
+        };
+        match attempts
+          .iter_mut()
+          .find(|a| Arc::ptr_eq(&a.rule, &attempt.rule))
+        {
+          Some(a) if (a.reached, a.matched) < (k, state.lr0.pos) => *a = attempt,
+          Some(_) => {}
+          None => attempts.push(attempt),
+        }
+      }
+    }
+    attempts.sort_by_key(|a| a.rule.index);
+    WhyNo {
+      symbol: symbol.to_string(),
+      start,
+      end,
+      attempts,
+    }
+  }
+}
+
+impl Grammar {
+  /// Parses `input` and asks the chart `Chart::why_no`
+  pub fn why_no<I>(&self, input: I, symbol: &str, start: usize, end: usize) -> WhyNo
+  where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+  {
+    with_tokens(input, |input| {
+      self.parse_chart(input).why_no(symbol, start, end)
+    })
+  }
+}
+
+#[test]
+fn test_why_no() {
+  let g: Grammar = r#"
+    S -> NP V NP
+    NP -> Det N
+    NP -> Det Adj N
+    NP -> N
+    Det -> the
+    Adj -> big
+    N -> cat
+    N -> mouse
+    V -> sees
+  "#
+  .parse()
+  .unwrap();
+
+  // "the big big mouse": Adj can't repeat
+  let input = ["the", "cat", "sees", "the", "big", "big", "mouse"];
+  let why = g.why_no(input, "NP", 3, 7);
+  assert!(why.was_predicted());
+  assert!(!why.formed());
+  let summary = why
+    .attempts
+    .iter()
+    .map(|a| {
+      (
+        a.rule.skeleton(),
+        a.matched,
+        a.reached,
+        a.waiting_for().map(|p| p.symbol.to_string()),
+      )
+    })
+    .collect::<Vec<_>>();
+  assert_eq!(
+    summary,
+    [
+      ("NP -> Det N".to_string(), 1, 4, Some("N".to_string())),
+      ("NP -> Det Adj N".to_string(), 2, 5, Some("N".to_string())),
+      ("NP -> N".to_string(), 0, 3, Some("N".to_string())),
+    ]
+  );
+  assert_eq!(
+    why.to_string(),
+    "NP over 3..7\n  \
+       NP → Det ・ N  (3..4, waiting for N at 4, line 3)\n  \
+       NP → Det Adj ・ N  (3..5, waiting for N at 5, line 4)\n  \
+       NP → ・ N  (3..3, waiting for N at 3, line 5)"
+  );
+
+  // nothing predicts an NP right after the verb's object starts
+  let why = g.why_no(input, "NP", 4, 7);
+  assert!(!why.was_predicted());
+  assert_eq!(why.to_string(), "NP over 4..7: never predicted at 4");
+
+  let why = g.why_no(["the", "cat", "sees", "the", "mouse"], "NP", 0, 2);
+  assert!(why.formed());
+}